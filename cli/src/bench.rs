@@ -0,0 +1,151 @@
+//! `bench`, the compute units InitEscrow, Exchange, ExchangeV2 and Cancel take, compared to a baseline
+//! so a change that makes any of them more expensive doesn't go unnoticed. It makes its own mints and
+//! escrows, so it wants a local validator with the program deployed and its config initialized (init-config),
+//! like init does. It fails if any instruction goes over its baseline by more than the threshold, `--save`
+//! writes what it measured as the new baseline instead
+
+use std::fs;
+
+use bpf_program_template::client;
+use clap::ArgMatches;
+use ed25519_dalek::Keypair;
+use serde_json::{Map, Value};
+use solana_program::{
+	instruction::Instruction,
+	message::VersionedMessage,
+	program_pack::Pack,
+	pubkey::Pubkey,
+	system_instruction,
+};
+use spl_token::state::Mint;
+
+use crate::{
+	cancel_instructions, client_error, create_associated_token_account_if_missing, exchange_instructions, init_instructions,
+	parse_amount, pubkey_of, send, sign, CliResult, Context,
+};
+
+/// What every escrow deposits and expects, in raw units of 0 decimals mints
+const DEPOSIT_AMOUNT: u64 = 1_000;
+const EXPECTED_AMOUNT: u64 = 500;
+
+pub fn run(context: &Context, matches: &ArgMatches) -> CliResult<()> {
+	let baseline_path = matches.value_of("baseline").unwrap();
+	let threshold = parse_amount(matches.value_of("threshold").unwrap())?;
+	let measured = measure_all(context)?;
+
+	if matches.is_present("save") {
+		let baseline = measured.iter().map(|(name, units)| (name.to_string(), Value::from(*units))).collect::<Map<_, _>>();
+		let baseline = serde_json::to_string_pretty(&baseline).map_err(|error| error.to_string())?;
+		fs::write(baseline_path, baseline + "\n").map_err(|error| format!("{}: {}", baseline_path, error))?;
+		for (name, units) in &measured {
+			println!("{:<12} {:>7} units", name, units);
+		}
+		println!("Wrote {}", baseline_path);
+		return Ok(());
+	}
+
+	let baseline = fs::read_to_string(baseline_path)
+		.map_err(|error| format!("{}: {}, --save writes one", baseline_path, error))?;
+	let baseline: Map<String, Value> = serde_json::from_str(&baseline).map_err(|error| format!("{}: {}", baseline_path, error))?;
+	let mut regressions = Vec::new();
+	for (name, units) in &measured {
+		match baseline.get(*name).and_then(Value::as_u64) {
+			Some(baseline_units) => {
+				let change = (*units as f64 - baseline_units as f64) * 100.0 / baseline_units as f64;
+				println!("{:<12} {:>7} units, baseline {} ({:+.1}%)", name, units, baseline_units, change);
+				if *units > baseline_units + baseline_units * threshold / 100 {
+					regressions.push(*name);
+				}
+			},
+			None => println!("{:<12} {:>7} units, not in the baseline", name, units),
+		}
+	}
+	if !regressions.is_empty() {
+		return Err(format!("{} over the baseline by more than {}%", regressions.join(", "), threshold));
+	}
+	Ok(())
+}
+
+/// Sets up the mints and the escrows and measures each instruction on an escrow of its own
+fn measure_all(context: &Context) -> CliResult<Vec<(&'static str, u64)>> {
+	let payer = pubkey_of(&context.payer);
+	// The payer pays for everything, the taker only signs
+	let taker = Keypair::generate(&mut rand::rngs::OsRng);
+	let deposit_mint = create_mint(context)?;
+	let receive_mint = create_mint(context)?;
+	mint_to(context, &deposit_mint, &payer, 3 * DEPOSIT_AMOUNT)?;
+	mint_to(context, &receive_mint, &pubkey_of(&taker), 2 * EXPECTED_AMOUNT)?;
+
+	let mut measured = Vec::new();
+	let (instructions, temp_token_account, escrow_account) =
+		init_instructions(context, &deposit_mint, DEPOSIT_AMOUNT, &receive_mint, EXPECTED_AMOUNT)?;
+	measured.push(("InitEscrow", measure(context, &instructions, &[&temp_token_account, &escrow_account])?));
+	let instructions = exchange_instructions(context, &pubkey_of(&escrow_account), &pubkey_of(&taker), false)?;
+	measured.push(("Exchange", measure(context, &instructions, &[&taker])?));
+
+	let escrow_account = init_escrow(context, &deposit_mint, &receive_mint)?;
+	let instructions = exchange_instructions(context, &escrow_account, &pubkey_of(&taker), true)?;
+	measured.push(("ExchangeV2", measure(context, &instructions, &[&taker])?));
+
+	let escrow_account = init_escrow(context, &deposit_mint, &receive_mint)?;
+	measured.push(("Cancel", measure(context, &cancel_instructions(context, &escrow_account)?, &[])?));
+	Ok(measured)
+}
+
+/// Simulates `instructions` and sends them, returns the units the escrow program's instruction took.
+/// That's from its "Program <id> consumed" log, so the rest of the transaction (creating accounts, ..)
+/// doesn't count. A CPI back into the program logs its line before the instruction's, the last one is it
+fn measure(context: &Context, instructions: &[Instruction], signers: &[&Keypair]) -> CliResult<u64> {
+	// No compute budget instructions in front, they don't change what the program takes
+	let message = client::message(&context.rpc, instructions, &pubkey_of(&context.payer)).map_err(client_error)?;
+	let message = VersionedMessage::Legacy(message);
+	let consumed = format!("Program {} consumed ", context.program_id);
+	let units = context
+		.rpc
+		.simulate_logs(&message)?
+		.iter()
+		.rev()
+		.find_map(|log| log.strip_prefix(&consumed)?.split(' ').next()?.parse::<u64>().ok())
+		.ok_or_else(|| format!("the logs don't say what {} consumed", context.program_id))?;
+	context.rpc.send(&sign(&message, &[&[&context.payer], signers].concat())?)?;
+	Ok(units)
+}
+
+/// An escrow for the instructions after InitEscrow, sent without measuring
+fn init_escrow(context: &Context, deposit_mint: &Pubkey, receive_mint: &Pubkey) -> CliResult<Pubkey> {
+	let (instructions, temp_token_account, escrow_account) =
+		init_instructions(context, deposit_mint, DEPOSIT_AMOUNT, receive_mint, EXPECTED_AMOUNT)?;
+	send(context, &instructions, &[&temp_token_account, &escrow_account])?;
+	Ok(pubkey_of(&escrow_account))
+}
+
+/// A new spl-token mint with 0 decimals, the payer is its mint authority
+fn create_mint(context: &Context) -> CliResult<Pubkey> {
+	let payer = pubkey_of(&context.payer);
+	let mint = Keypair::generate(&mut rand::rngs::OsRng);
+	let instructions = [
+		system_instruction::create_account(
+			&payer,
+			&pubkey_of(&mint),
+			context.rpc.get_minimum_balance_for_rent_exemption(Mint::LEN)?,
+			Mint::LEN as u64,
+			&spl_token::id(),
+		),
+		spl_token::instruction::initialize_mint(&spl_token::id(), &pubkey_of(&mint), &payer, None, 0)
+			.map_err(|error| error.to_string())?,
+	];
+	send(context, &instructions, &[&mint])?;
+	Ok(pubkey_of(&mint))
+}
+
+/// Mints `amount` to `wallet`'s associated token account, which is created first if needed
+fn mint_to(context: &Context, mint: &Pubkey, wallet: &Pubkey, amount: u64) -> CliResult<()> {
+	let mut instructions = Vec::new();
+	let account = create_associated_token_account_if_missing(context, wallet, mint, &spl_token::id(), &mut instructions)?;
+	instructions.push(
+		spl_token::instruction::mint_to(&spl_token::id(), mint, &account, &pubkey_of(&context.payer), &[], amount)
+			.map_err(|error| error.to_string())?,
+	);
+	send(context, &instructions, &[])?;
+	Ok(())
+}
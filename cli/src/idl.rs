@@ -0,0 +1,293 @@
+//! `escrow-cli idl`, generates the program's IDL in Shank's format from its source like `shank idl`
+//! does: the instruction enum with its `#[account(..)]` attributes, the ShankAccount and ShankType
+//! types of state.rs and instruction.rs, and EscrowError.
+//!
+//! Accounts marked optional are left out when they don't apply, the program doesn't take the program
+//! id in their place like Shank's optional accounts. The trailing accounts the instruction docs talk
+//! about (trackers, transfer hook accounts, pNFT accounts, ..) aren't in the IDL
+//!
+//! The discriminants are the borsh tags (see BORSH_TAG_FLAG), so clients generated from the IDL
+//! serialize the arguments in borsh like they do anyway. Versioned instructions like ExchangeV2 get
+//! their own tags, not their index in the enum
+
+use std::{collections::HashMap, fs, path::Path};
+
+use bpf_program_template::instruction::{EscrowInstruction, BORSH_TAG_FLAG};
+use serde_json::{json, Map, Value};
+use solana_program::pubkey::Pubkey;
+use syn::{
+	punctuated::Punctuated, Attribute, Expr, ExprLit, Fields, GenericArgument, Item, ItemEnum, Lit, Meta, PathArguments, Token,
+	Type,
+};
+
+/// The files the IDL comes from, in `src`
+const SOURCES: [&str; 3] = ["instruction.rs", "state.rs", "error.rs"];
+
+pub fn generate(src: &Path, program_id: &Pubkey) -> Result<Value, String> {
+	let mut items = Vec::new();
+	for source in SOURCES {
+		let path = src.join(source);
+		let code = fs::read_to_string(&path).map_err(|error| format!("{}: {}", path.display(), error))?;
+		let file = syn::parse_file(&code).map_err(|error| format!("{}: {}", path.display(), error))?;
+		items.extend(file.items);
+	}
+	let version = package_version(&src.join("../Cargo.toml"))?;
+
+	let aliases = items
+		.iter()
+		.filter_map(|item| match item {
+			Item::Type(alias) => Some((alias.ident.to_string(), (*alias.ty).clone())),
+			_ => None,
+		})
+		.collect::<HashMap<_, _>>();
+	let discriminators = items.iter().filter_map(discriminator).collect::<HashMap<_, _>>();
+
+	let mut instructions = Vec::new();
+	let mut accounts = Vec::new();
+	let mut types = Vec::new();
+	let mut errors = Vec::new();
+	for item in &items {
+		match item {
+			Item::Enum(item) if derives(&item.attrs, "ShankInstruction") => instructions = instructions_of(item, &aliases)?,
+			Item::Enum(item) if derives(&item.attrs, "ShankType") => types.push(json!({
+				"name": item.ident.to_string(),
+				"type": {
+					"kind": "enum",
+					"variants": item.variants.iter().map(|variant| json!({"name": variant.ident.to_string()})).collect::<Vec<_>>(),
+				},
+			})),
+			Item::Enum(item) if item.ident == "EscrowError" => errors = errors_of(item)?,
+			Item::Struct(item) if derives(&item.attrs, "ShankAccount") || derives(&item.attrs, "ShankType") => {
+				let name = item.ident.to_string();
+				let mut definition = json!({
+					"name": name,
+					"type": {"kind": "struct", "fields": fields_of(&item.fields, &aliases)},
+				});
+				if derives(&item.attrs, "ShankAccount") {
+					if let Some(discriminator) = discriminators.get(&name) {
+						definition["discriminator"] = json!(discriminator);
+					}
+					accounts.push(definition);
+				} else {
+					types.push(definition);
+				}
+			},
+			_ => {},
+		}
+	}
+
+	Ok(json!({
+		"version": version,
+		"name": "escrow",
+		"instructions": instructions,
+		"accounts": accounts,
+		"types": types,
+		"errors": errors,
+		"metadata": {"origin": "shank", "address": program_id.to_string()},
+	}))
+}
+
+fn instructions_of(item: &ItemEnum, aliases: &HashMap<String, Type>) -> Result<Vec<Value>, String> {
+	item.variants
+		.iter()
+		.enumerate()
+		.map(|(index, variant)| {
+			let accounts = variant
+				.attrs
+				.iter()
+				.filter(|attr| attr.path().is_ident("account"))
+				.map(account_of)
+				.collect::<Result<Vec<_>, _>>()?;
+			Ok(json!({
+				"name": camel_case(&variant.ident.to_string()),
+				"accounts": accounts,
+				"args": fields_of(&variant.fields, aliases),
+				"discriminant": {"type": "u8", "value": EscrowInstruction::tag_of_variant(index as u8) | BORSH_TAG_FLAG},
+			}))
+		})
+		.collect()
+}
+
+/// `#[account(index, writable, signer, optional, name = "..", desc = "..")]`
+fn account_of(attr: &Attribute) -> Result<Value, String> {
+	let args = attr
+		.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+		.or_else(|_| {
+			// The index is a bare literal, which isn't a Meta
+			attr.parse_args_with(|input: syn::parse::ParseStream| {
+				input.parse::<syn::LitInt>()?;
+				input.parse::<Token![,]>()?;
+				Punctuated::<Meta, Token![,]>::parse_terminated(input)
+			})
+		})
+		.map_err(|error| format!("#[account]: {}", error))?;
+
+	let mut account = Map::new();
+	let (mut writable, mut signer, mut optional) = (false, false, false);
+	for arg in args {
+		match arg {
+			Meta::Path(path) if path.is_ident("writable") => writable = true,
+			Meta::Path(path) if path.is_ident("signer") => signer = true,
+			Meta::Path(path) if path.is_ident("optional") => optional = true,
+			Meta::NameValue(name_value) => {
+				let value = match &name_value.value {
+					Expr::Lit(ExprLit { lit: Lit::Str(value), .. }) => value.value(),
+					_ => return Err("#[account]: name and desc have to be strings".to_string()),
+				};
+				if name_value.path.is_ident("name") {
+					account.insert("name".to_string(), json!(camel_case(&value)));
+				} else if name_value.path.is_ident("desc") {
+					account.insert("desc".to_string(), json!(value));
+				}
+			},
+			_ => return Err("#[account]: unknown argument".to_string()),
+		}
+	}
+	account.insert("isMut".to_string(), json!(writable));
+	account.insert("isSigner".to_string(), json!(signer));
+	if optional {
+		account.insert("isOptional".to_string(), json!(true));
+	}
+	Ok(Value::Object(account))
+}
+
+fn fields_of(fields: &Fields, aliases: &HashMap<String, Type>) -> Vec<Value> {
+	fields
+		.iter()
+		.filter_map(|field| field.ident.as_ref().map(|ident| (ident, &field.ty)))
+		.map(|(ident, ty)| json!({"name": camel_case(&ident.to_string()), "type": type_of(ty, aliases)}))
+		.collect()
+}
+
+fn type_of(ty: &Type, aliases: &HashMap<String, Type>) -> Value {
+	match ty {
+		Type::Array(array) => {
+			let len = match &array.len {
+				Expr::Lit(ExprLit { lit: Lit::Int(len), .. }) => json!(len.base10_parse::<usize>().unwrap_or_default()),
+				len => json!(quote_expr(len)),
+			};
+			json!({"array": [type_of(&array.elem, aliases), len]})
+		},
+		Type::Tuple(tuple) => json!({"tuple": tuple.elems.iter().map(|elem| type_of(elem, aliases)).collect::<Vec<_>>()}),
+		Type::Path(path) => {
+			let segment = match path.path.segments.last() {
+				Some(segment) => segment,
+				None => return json!("unknown"),
+			};
+			let name = segment.ident.to_string();
+			let inner = match &segment.arguments {
+				PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+					GenericArgument::Type(ty) => Some(type_of(ty, aliases)),
+					_ => None,
+				}),
+				_ => None,
+			};
+			match (name.as_str(), inner) {
+				("Option", Some(inner)) => json!({"option": inner}),
+				("Vec", Some(inner)) => json!({"vec": inner}),
+				("Pubkey", _) => json!("publicKey"),
+				// One byte that's 0 or 1, like a bool
+				("PodBool", _) | ("bool", _) => json!("bool"),
+				("String", _) => json!("string"),
+				("u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16" | "i32" | "i64" | "i128", _) => json!(name),
+				_ => match aliases.get(&name) {
+					Some(alias) => type_of(alias, aliases),
+					None => json!({"defined": name}),
+				},
+			}
+		},
+		_ => json!("unknown"),
+	}
+}
+
+/// Array lengths that are constants stay their name
+fn quote_expr(expr: &Expr) -> String {
+	match expr {
+		Expr::Path(path) => path.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>().join("::"),
+		_ => "unknown".to_string(),
+	}
+}
+
+/// Codes are the variants' indices, which is what the program returns as ProgramError::Custom
+fn errors_of(item: &ItemEnum) -> Result<Vec<Value>, String> {
+	item.variants
+		.iter()
+		.enumerate()
+		.map(|(code, variant)| {
+			let msg = variant
+				.attrs
+				.iter()
+				.find(|attr| attr.path().is_ident("error"))
+				.and_then(|attr| attr.parse_args::<syn::LitStr>().ok())
+				.ok_or_else(|| format!("EscrowError::{} has no #[error(\"..\")]", variant.ident))?;
+			Ok(json!({"code": code, "name": variant.ident.to_string(), "msg": msg.value()}))
+		})
+		.collect()
+}
+
+/// `impl Discriminator for T { const DISCRIMINATOR: [u8; 8] = [..]; }` as T and the bytes
+fn discriminator(item: &Item) -> Option<(String, Vec<u8>)> {
+	let item = match item {
+		Item::Impl(item) => item,
+		_ => return None,
+	};
+	let (_, trait_path, _) = item.trait_.as_ref()?;
+	if !trait_path.is_ident("Discriminator") {
+		return None;
+	}
+	let name = match &*item.self_ty {
+		Type::Path(path) => path.path.segments.last()?.ident.to_string(),
+		_ => return None,
+	};
+	let bytes = item.items.iter().find_map(|item| match item {
+		syn::ImplItem::Const(constant) if constant.ident == "DISCRIMINATOR" => match &constant.expr {
+			Expr::Array(array) => array
+				.elems
+				.iter()
+				.map(|elem| match elem {
+					Expr::Lit(ExprLit { lit: Lit::Int(byte), .. }) => byte.base10_parse::<u8>().ok(),
+					_ => None,
+				})
+				.collect::<Option<Vec<u8>>>(),
+			_ => None,
+		},
+		_ => None,
+	})?;
+	Some((name, bytes))
+}
+
+/// The program's version from its manifest, the first `version = ".."` is [package]'s
+fn package_version(manifest: &Path) -> Result<String, String> {
+	let manifest = fs::read_to_string(manifest).map_err(|error| format!("{}: {}", manifest.display(), error))?;
+	manifest
+		.lines()
+		.find_map(|line| line.strip_prefix("version = "))
+		.map(|version| version.trim_matches('"').to_string())
+		.ok_or_else(|| "Cargo.toml: no version".to_string())
+}
+
+fn derives(attrs: &[Attribute], derive: &str) -> bool {
+	attrs.iter().filter(|attr| attr.path().is_ident("derive")).any(|attr| {
+		attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+			.map(|paths| paths.iter().any(|path| path.is_ident(derive)))
+			.unwrap_or(false)
+	})
+}
+
+fn camel_case(name: &str) -> String {
+	let mut camel = String::with_capacity(name.len());
+	let mut upper = false;
+	for (i, c) in name.chars().enumerate() {
+		if c == '_' {
+			upper = true;
+		} else if i == 0 {
+			camel.push(c.to_ascii_lowercase());
+		} else if upper {
+			camel.push(c.to_ascii_uppercase());
+			upper = false;
+		} else {
+			camel.push(c);
+		}
+	}
+	camel
+}
@@ -0,0 +1,732 @@
+//! escrow-cli, for trying the escrow program out against a validator without the JS scripts. It handles
+//! plain token escrows: init creates and funds the temp token account and the escrow account, the
+//! associated token accounts that don't exist yet are created along the way
+
+mod bench;
+mod idl;
+mod pubsub;
+mod rpc;
+
+use std::{fs, path::Path, str::FromStr};
+
+use bpf_program_template::{
+	address_lookup_table,
+	client::{self, ClientError, ComputeBudget, LookupTable, Rpc},
+	instruction,
+	state::{Config, Escrow, EscrowStatus},
+	subscription::{EscrowSubscription, EscrowUpdate},
+	token,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use ed25519_dalek::{Keypair, Signer};
+use solana_program::{
+	instruction::Instruction,
+	message::VersionedMessage,
+	program_pack::Pack,
+	pubkey::Pubkey,
+	system_instruction,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+use pubsub::PubsubClient;
+use rpc::RpcClient;
+
+type CliResult<T> = Result<T, String>;
+
+struct Context {
+	rpc: RpcClient,
+	payer: Keypair,
+	program_id: Pubkey,
+	budget: ComputeBudget,
+	/// Sends v0 transactions using it if it's set
+	lookup_table: Option<LookupTable>,
+}
+
+fn main() {
+	let pubkey_arg = |name: &'static str, help: &'static str| {
+		Arg::with_name(name).long(name).value_name("PUBKEY").takes_value(true).required(true).help(help)
+	};
+	let amount_arg = |name: &'static str, help: &'static str| {
+		Arg::with_name(name).long(name).value_name("AMOUNT").takes_value(true).required(true).help(help)
+	};
+	let matches = App::new("escrow-cli")
+		.about("Talks to the escrow program")
+		.setting(AppSettings::SubcommandRequiredElseHelp)
+		.arg(
+			Arg::with_name("url")
+				.long("url")
+				.short("u")
+				.value_name("URL")
+				.takes_value(true)
+				.global(true)
+				.default_value("http://localhost:8899")
+				.help("JSON RPC URL of the cluster"),
+		)
+		.arg(
+			Arg::with_name("keypair")
+				.long("keypair")
+				.short("k")
+				.value_name("PATH")
+				.takes_value(true)
+				.global(true)
+				.help("Keypair file that pays and signs, ~/.config/solana/id.json by default"),
+		)
+		.arg(
+			Arg::with_name("program-id")
+				.long("program-id")
+				.value_name("PUBKEY")
+				.takes_value(true)
+				.global(true)
+				.help("Address the escrow program is deployed at, its declared id by default. Only the declared id takes instructions from init, exchange and cancel"),
+		)
+		.arg(
+			Arg::with_name("priority-fee")
+				.long("priority-fee")
+				.value_name("MICRO_LAMPORTS")
+				.takes_value(true)
+				.global(true)
+				.help("Priority fee per compute unit of the transactions sent"),
+		)
+		.arg(
+			Arg::with_name("measure-compute-units")
+				.long("measure-compute-units")
+				.global(true)
+				.help("Simulate transactions first and ask for the compute units they use instead of the default limit"),
+		)
+		.arg(
+			Arg::with_name("lookup-table")
+				.long("lookup-table")
+				.value_name("PUBKEY")
+				.takes_value(true)
+				.global(true)
+				.help("Address lookup table to send v0 transactions with, see create-lookup-table"),
+		)
+		.subcommand(
+			SubCommand::with_name("init")
+				.about("Deposits tokens into a new escrow")
+				.arg(pubkey_arg("deposit-mint", "Mint of the tokens to deposit"))
+				.arg(amount_arg("deposit-amount", "How many to deposit, in raw units"))
+				.arg(pubkey_arg("receive-mint", "Mint of the tokens to get for them"))
+				.arg(amount_arg("expected-amount", "How many to get, in raw units")),
+		)
+		.subcommand(
+			SubCommand::with_name("exchange")
+				.about("Takes an escrow")
+				.arg(pubkey_arg("escrow", "The escrow account"))
+				.arg(
+					Arg::with_name("v2")
+						.long("v2")
+						.help("Send ExchangeV2, which takes fewer accounts. Programs from before it don't know it"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("cancel")
+				.about("Cancels one of your escrows, the deposit goes back to its refund address")
+				.arg(pubkey_arg("escrow", "The escrow account")),
+		)
+		.subcommand(
+			SubCommand::with_name("init-config")
+				.about("Creates the program's config, signed by the keypair as the program's upgrade authority")
+				.arg(
+					Arg::with_name("admin")
+						.long("admin")
+						.value_name("PUBKEY")
+						.takes_value(true)
+						.help("The config's admin, the keypair's by default"),
+				)
+				.arg(
+					Arg::with_name("listing-fee")
+						.long("listing-fee")
+						.value_name("LAMPORTS")
+						.takes_value(true)
+						.default_value("0")
+						.help("What opening an escrow costs"),
+				)
+				.arg(
+					Arg::with_name("crank-bounty")
+						.long("crank-bounty")
+						.value_name("LAMPORTS")
+						.takes_value(true)
+						.default_value("0")
+						.help("What closing an expired escrow pays the crank"),
+				)
+				.arg(
+					Arg::with_name("heartbeat-period")
+						.long("heartbeat-period")
+						.value_name("SECONDS")
+						.takes_value(true)
+						.default_value("0")
+						.help("How long without a heartbeat before a standby config takes over, 0 means never"),
+				)
+				.arg(
+					Arg::with_name("dormancy-period")
+						.long("dormancy-period")
+						.value_name("SECONDS")
+						.takes_value(true)
+						.default_value("0")
+						.help("How long an escrow's initializer can be inactive before it's swept, 0 means never"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("bench")
+				.about("Measures the compute units of InitEscrow, Exchange, ExchangeV2 and Cancel against a baseline, on escrows of its own")
+				.arg(
+					Arg::with_name("baseline")
+						.long("baseline")
+						.value_name("PATH")
+						.takes_value(true)
+						.default_value(concat!(env!("CARGO_MANIFEST_DIR"), "/../cu-baseline.json"))
+						.help("The units each instruction took last time, as written by --save"),
+				)
+				.arg(
+					Arg::with_name("threshold")
+						.long("threshold")
+						.value_name("PERCENT")
+						.takes_value(true)
+						.default_value("5")
+						.help("How much more than its baseline an instruction can take before bench fails"),
+				)
+				.arg(
+					Arg::with_name("save")
+						.long("save")
+						.help("Write what was measured as the baseline instead of comparing"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("list")
+				.about("Lists open escrows")
+				.arg(
+					Arg::with_name("initializer")
+						.long("initializer")
+						.value_name("PUBKEY")
+						.takes_value(true)
+						.help("Only the escrows of this initializer"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("watch")
+				.about("Prints escrows as they're created, filled and cancelled")
+				.arg(
+					Arg::with_name("initializer")
+						.long("initializer")
+						.value_name("PUBKEY")
+						.takes_value(true)
+						.help("Only the escrows of this initializer"),
+				)
+				.arg(
+					Arg::with_name("ws-url")
+						.long("ws-url")
+						.value_name("URL")
+						.takes_value(true)
+						.help("Websocket URL of the cluster, the next port of --url's by default like the solana CLI's"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("show")
+				.about("Prints an escrow")
+				.arg(Arg::with_name("escrow").value_name("PUBKEY").required(true).help("The escrow account")),
+		)
+		.subcommand(
+			SubCommand::with_name("create-lookup-table")
+				.about("Creates an address lookup table with the program, the token programs, the sysvars and the config in it"),
+		)
+		.subcommand(
+			SubCommand::with_name("extend-lookup-table")
+				.about("Adds addresses to one of your lookup tables")
+				.arg(pubkey_arg("table", "The lookup table"))
+				.arg(
+					Arg::with_name("address")
+						.value_name("PUBKEY")
+						.required(true)
+						.multiple(true)
+						.help("The addresses to add"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("idl")
+				.about("Generates the program's IDL, in Shank's format")
+				.arg(
+					Arg::with_name("src")
+						.long("src")
+						.value_name("DIR")
+						.takes_value(true)
+						.default_value(concat!(env!("CARGO_MANIFEST_DIR"), "/../interface/src"))
+						.help("The interface crate's src directory"),
+				)
+				.arg(
+					Arg::with_name("out")
+						.long("out")
+						.value_name("PATH")
+						.takes_value(true)
+						.help("Where to write it, stdout by default"),
+				),
+		)
+		.get_matches();
+
+	if let Err(error) = run(&matches) {
+		eprintln!("error: {}", error);
+		std::process::exit(1);
+	}
+}
+
+fn run(matches: &ArgMatches) -> CliResult<()> {
+	let (command, command_matches) = matches.subcommand();
+	let command_matches = command_matches.ok_or("no command")?;
+	let program_id = match matches.value_of("program-id") {
+		Some(program_id) => parse_pubkey(program_id)?,
+		None => bpf_program_template::id(),
+	};
+	// idl doesn't talk to the cluster, the program id only goes in its metadata
+	if command == "idl" {
+		let idl = idl::generate(Path::new(command_matches.value_of("src").unwrap()), &program_id)?;
+		let idl = serde_json::to_string_pretty(&idl).map_err(|error| error.to_string())?;
+		return match command_matches.value_of("out") {
+			Some(out) => {
+				if let Some(dir) = Path::new(out).parent() {
+					fs::create_dir_all(dir).map_err(|error| format!("{}: {}", dir.display(), error))?;
+				}
+				fs::write(out, idl + "\n").map_err(|error| format!("{}: {}", out, error))
+			},
+			None => {
+				println!("{}", idl);
+				Ok(())
+			},
+		};
+	}
+	let rpc = RpcClient::new(matches.value_of("url").unwrap().to_string());
+
+	// list and show only read
+	match command {
+		"list" => {
+			let initializer = command_matches.value_of("initializer").map(parse_pubkey).transpose()?;
+			let escrows = client::list_open_escrows(&rpc, &program_id, initializer.as_ref()).map_err(client_error)?;
+			for (address, escrow) in escrows {
+				println!(
+					"{} {} of {} for {} of {}",
+					address, escrow.deposited_amount, escrow.deposit_mint_pubkey, escrow.expected_amount, escrow.receive_mint_pubkey
+				);
+			}
+			return Ok(());
+		},
+		"show" => {
+			let address = parse_pubkey(command_matches.value_of("escrow").unwrap())?;
+			print_escrow(&address, &client::fetch_escrow(&rpc, &address).map_err(client_error)?);
+			return Ok(());
+		},
+		"watch" => {
+			let ws_url = match command_matches.value_of("ws-url") {
+				Some(ws_url) => ws_url.to_string(),
+				None => websocket_url(matches.value_of("url").unwrap())?,
+			};
+			return watch(&rpc, &ws_url, &program_id, command_matches);
+		},
+		_ => {},
+	}
+
+	let keypair_path = match matches.value_of("keypair") {
+		Some(path) => path.to_string(),
+		None => format!("{}/.config/solana/id.json", std::env::var("HOME").map_err(|error| error.to_string())?),
+	};
+	let lookup_table = matches
+		.value_of("lookup-table")
+		.map(|table| client::fetch_lookup_table(&rpc, &parse_pubkey(table)?).map_err(client_error))
+		.transpose()?;
+	let context = Context {
+		rpc,
+		payer: read_keypair(&keypair_path)?,
+		program_id,
+		budget: ComputeBudget {
+			unit_price: matches.value_of("priority-fee").map(parse_amount).transpose()?.unwrap_or(0),
+			measure_units: matches.is_present("measure-compute-units"),
+		},
+		lookup_table,
+	};
+	match command {
+		"init" => init(&context, command_matches),
+		"exchange" => exchange(&context, command_matches),
+		"cancel" => cancel(&context, command_matches),
+		"init-config" => init_config(&context, command_matches),
+		"bench" => bench::run(&context, command_matches),
+		"create-lookup-table" => create_lookup_table(&context),
+		"extend-lookup-table" => extend_lookup_table(&context, command_matches),
+		_ => unreachable!(),
+	}
+}
+
+fn init(context: &Context, matches: &ArgMatches) -> CliResult<()> {
+	let deposit_mint = parse_pubkey(matches.value_of("deposit-mint").unwrap())?;
+	let deposit_amount = parse_amount(matches.value_of("deposit-amount").unwrap())?;
+	let receive_mint = parse_pubkey(matches.value_of("receive-mint").unwrap())?;
+	let expected_amount = parse_amount(matches.value_of("expected-amount").unwrap())?;
+
+	let (instructions, temp_token_account, escrow_account) =
+		init_instructions(context, &deposit_mint, deposit_amount, &receive_mint, expected_amount)?;
+	let signature = send(context, &instructions, &[&temp_token_account, &escrow_account])?;
+	println!("escrow {}", pubkey_of(&escrow_account));
+	println!("signature {}", signature);
+	Ok(())
+}
+
+fn init_config(context: &Context, matches: &ArgMatches) -> CliResult<()> {
+	let payer = pubkey_of(&context.payer);
+	let admin = matches.value_of("admin").map(parse_pubkey).transpose()?.unwrap_or(payer);
+	let instruction = instruction::initialize_config(
+		&context.program_id,
+		&payer,
+		&admin,
+		parse_amount(matches.value_of("listing-fee").unwrap())?,
+		parse_seconds(matches.value_of("heartbeat-period").unwrap())?,
+		parse_amount(matches.value_of("crank-bounty").unwrap())?,
+		parse_seconds(matches.value_of("dormancy-period").unwrap())?,
+	)
+	.map_err(|error| error.to_string())?;
+	let signature = send(context, &[instruction], &[])?;
+	println!("config {}", Config::find_address(&context.program_id).0);
+	println!("signature {}", signature);
+	Ok(())
+}
+
+/// The instructions init sends, with the keypairs of the temp token account and the escrow account
+/// that sign for them besides the payer
+fn init_instructions(
+	context: &Context,
+	deposit_mint: &Pubkey,
+	deposit_amount: u64,
+	receive_mint: &Pubkey,
+	expected_amount: u64,
+) -> CliResult<(Vec<Instruction>, Keypair, Keypair)> {
+	let initializer = pubkey_of(&context.payer);
+
+	let (deposit_token_program_id, deposit_decimals) = fetch_mint(context, deposit_mint)?;
+	let (receive_token_program_id, _) = fetch_mint(context, receive_mint)?;
+	let deposit_source = token::get_associated_token_address(&initializer, deposit_mint, &deposit_token_program_id);
+	let config = context
+		.rpc
+		.get_account(&Config::find_address(&context.program_id).0)?
+		.ok_or("the program has no config yet")?;
+	let config = Config::unpack(&config.data).map_err(|error| error.to_string())?;
+
+	let mut instructions = Vec::new();
+	let token_to_receive_account = create_associated_token_account_if_missing(
+		context,
+		&initializer,
+		receive_mint,
+		&receive_token_program_id,
+		&mut instructions,
+	)?;
+
+	let temp_token_account = Keypair::generate(&mut rand::rngs::OsRng);
+	instructions.push(system_instruction::create_account(
+		&initializer,
+		&pubkey_of(&temp_token_account),
+		context.rpc.get_minimum_balance_for_rent_exemption(TokenAccount::LEN)?,
+		TokenAccount::LEN as u64,
+		&deposit_token_program_id,
+	));
+	instructions.push(
+		token::initialize_account3(&deposit_token_program_id, &pubkey_of(&temp_token_account), deposit_mint, &initializer)
+			.map_err(|error| error.to_string())?,
+	);
+	instructions.push(
+		token::transfer_checked(
+			&deposit_token_program_id,
+			&deposit_source,
+			deposit_mint,
+			&pubkey_of(&temp_token_account),
+			&initializer,
+			deposit_amount,
+			deposit_decimals,
+			&[],
+		)
+		.map_err(|error| error.to_string())?,
+	);
+
+	let escrow_account = Keypair::generate(&mut rand::rngs::OsRng);
+	instructions.push(system_instruction::create_account(
+		&initializer,
+		&pubkey_of(&escrow_account),
+		context.rpc.get_minimum_balance_for_rent_exemption(Escrow::LEN)?,
+		Escrow::LEN as u64,
+		&context.program_id,
+	));
+	instructions.push(
+		instruction::init_escrow(
+			&context.program_id,
+			&initializer,
+			&pubkey_of(&temp_token_account),
+			&token_to_receive_account,
+			&pubkey_of(&escrow_account),
+			&deposit_token_program_id,
+			deposit_mint,
+			receive_mint,
+			config.standby_config_pubkey != Pubkey::default(),
+			expected_amount,
+		)
+		.map_err(|error| error.to_string())?,
+	);
+
+	Ok((instructions, temp_token_account, escrow_account))
+}
+
+fn exchange(context: &Context, matches: &ArgMatches) -> CliResult<()> {
+	let escrow_account = parse_pubkey(matches.value_of("escrow").unwrap())?;
+	let instructions = exchange_instructions(context, &escrow_account, &pubkey_of(&context.payer), matches.is_present("v2"))?;
+	println!("signature {}", send(context, &instructions, &[])?);
+	Ok(())
+}
+
+/// The instructions exchange sends, for `taker` to take the escrow. ExchangeV2 with `v2`
+fn exchange_instructions(context: &Context, escrow_account: &Pubkey, taker: &Pubkey, v2: bool) -> CliResult<Vec<Instruction>> {
+	let escrow = client::fetch_escrow(&context.rpc, escrow_account).map_err(client_error)?;
+
+	let (token_program_id, _) = fetch_mint(context, &escrow.deposit_mint_pubkey)?;
+	let (sending_token_program_id, _) = fetch_mint(context, &escrow.receive_mint_pubkey)?;
+	let takers_sending_account =
+		token::get_associated_token_address(taker, &escrow.receive_mint_pubkey, &sending_token_program_id);
+
+	let mut instructions = Vec::new();
+	let takers_token_to_receive_account = create_associated_token_account_if_missing(
+		context,
+		taker,
+		&escrow.deposit_mint_pubkey,
+		&token_program_id,
+		&mut instructions,
+	)?;
+	// Same arguments either way
+	let exchange = match v2 {
+		true => instruction::exchange_v2,
+		false => instruction::exchange,
+	};
+	instructions.push(
+		exchange(
+			&context.program_id,
+			escrow_account,
+			&escrow,
+			taker,
+			&takers_sending_account,
+			&takers_token_to_receive_account,
+			&token_program_id,
+			&sending_token_program_id,
+			None,
+		)
+		.map_err(|error| error.to_string())?,
+	);
+
+	Ok(instructions)
+}
+
+fn cancel(context: &Context, matches: &ArgMatches) -> CliResult<()> {
+	let escrow_account = parse_pubkey(matches.value_of("escrow").unwrap())?;
+	let instructions = cancel_instructions(context, &escrow_account)?;
+	println!("signature {}", send(context, &instructions, &[])?);
+	Ok(())
+}
+
+/// The instructions cancel sends, the payer has to be the escrow's initializer
+fn cancel_instructions(context: &Context, escrow_account: &Pubkey) -> CliResult<Vec<Instruction>> {
+	let escrow = client::fetch_escrow(&context.rpc, escrow_account).map_err(client_error)?;
+	if escrow.initializer_pubkey != pubkey_of(&context.payer) {
+		return Err(format!("only the initializer {} can cancel it", escrow.initializer_pubkey));
+	}
+
+	let (token_program_id, _) = fetch_mint(context, &escrow.deposit_mint_pubkey)?;
+	let mut instructions = Vec::new();
+	let refund_token_account = create_associated_token_account_if_missing(
+		context,
+		&escrow.refund_pubkey,
+		&escrow.deposit_mint_pubkey,
+		&token_program_id,
+		&mut instructions,
+	)?;
+	instructions.push(
+		instruction::cancel(&context.program_id, escrow_account, &escrow, &refund_token_account, &token_program_id)
+			.map_err(|error| error.to_string())?,
+	);
+
+	Ok(instructions)
+}
+
+fn watch(rpc: &RpcClient, ws_url: &str, program_id: &Pubkey, matches: &ArgMatches) -> CliResult<()> {
+	let initializer = matches.value_of("initializer").map(parse_pubkey).transpose()?;
+	let pubsub = PubsubClient::connect(ws_url)?;
+	let mut subscription = EscrowSubscription::new(pubsub, program_id, initializer.as_ref()).map_err(client_error)?;
+	// Listed after subscribing so the escrows opened in between come as Created
+	for (address, escrow) in client::list_open_escrows(rpc, program_id, initializer.as_ref()).map_err(client_error)? {
+		subscription.track(&address, escrow).map_err(client_error)?;
+	}
+	for update in subscription {
+		let update = update.map_err(client_error)?;
+		let event = match update {
+			EscrowUpdate::Created(..) => "created",
+			EscrowUpdate::Updated(..) => "updated",
+			EscrowUpdate::Filled(..) => "filled",
+			EscrowUpdate::Cancelled(..) => "cancelled",
+			EscrowUpdate::Expired(..) => "expired",
+			EscrowUpdate::Closed(..) => "closed",
+		};
+		let escrow = update.escrow();
+		println!(
+			"{} {} {} of {} for {} of {}",
+			event,
+			update.address(),
+			escrow.deposited_amount,
+			escrow.deposit_mint_pubkey,
+			escrow.expected_amount,
+			escrow.receive_mint_pubkey
+		);
+	}
+	Ok(())
+}
+
+fn create_lookup_table(context: &Context) -> CliResult<()> {
+	let payer = pubkey_of(&context.payer);
+	let (instructions, table) =
+		client::create_common_lookup_table(&context.program_id, &payer, &payer, context.rpc.get_slot()?);
+	let signature = send(context, &instructions, &[])?;
+	println!("lookup table {}", table);
+	println!("signature {}", signature);
+	Ok(())
+}
+
+fn extend_lookup_table(context: &Context, matches: &ArgMatches) -> CliResult<()> {
+	let table = parse_pubkey(matches.value_of("table").unwrap())?;
+	let addresses = matches.values_of("address").unwrap().map(parse_pubkey).collect::<CliResult<Vec<_>>>()?;
+	let payer = pubkey_of(&context.payer);
+	let instruction = address_lookup_table::extend_lookup_table(&table, &payer, &payer, &addresses);
+	println!("signature {}", send(context, &[instruction], &[])?);
+	Ok(())
+}
+
+fn print_escrow(address: &Pubkey, escrow: &Escrow) {
+	let status = match EscrowStatus::from_u8(escrow.status) {
+		Ok(EscrowStatus::Uninitialized) => "uninitialized",
+		Ok(EscrowStatus::Active) => "active",
+		Ok(EscrowStatus::Completed) => "completed",
+		Ok(EscrowStatus::Cancelled) => "cancelled",
+		Ok(EscrowStatus::Expired) => "expired",
+		Err(_) => "unknown",
+	};
+	println!("escrow            {}", address);
+	println!("status            {}{}", status, if escrow.is_frozen.get() { ", frozen" } else { "" });
+	println!("initializer       {}", escrow.initializer_pubkey);
+	println!("refund address    {}", escrow.refund_pubkey);
+	println!("temp account      {}", escrow.temp_token_account_pubkey);
+	println!("deposit           {} of {}", escrow.deposited_amount, escrow.deposit_mint_pubkey);
+	println!("expected          {} of {}", escrow.expected_amount, escrow.receive_mint_pubkey);
+	if escrow.expires_at != 0 {
+		println!("expires at        {}", escrow.expires_at);
+	}
+}
+
+/// The mint's token program (its owner) and decimals
+fn fetch_mint(context: &Context, mint: &Pubkey) -> CliResult<(Pubkey, u8)> {
+	let account = context.rpc.get_account(mint)?.ok_or_else(|| format!("no mint at {}", mint))?;
+	if !token::is_token_program(&account.owner) {
+		return Err(format!("{} isn't a token mint", mint));
+	}
+	// Token-2022 extensions go behind the base mint
+	let mint_info = account
+		.data
+		.get(..Mint::LEN)
+		.map(Mint::unpack_from_slice)
+		.ok_or_else(|| format!("{} isn't a token mint", mint))?
+		.map_err(|error| error.to_string())?;
+	Ok((account.owner, mint_info.decimals))
+}
+
+/// `wallet`'s associated token account for `mint`, with an instruction creating it pushed onto
+/// `instructions` if it doesn't exist yet
+fn create_associated_token_account_if_missing(
+	context: &Context,
+	wallet: &Pubkey,
+	mint: &Pubkey,
+	token_program_id: &Pubkey,
+	instructions: &mut Vec<Instruction>,
+) -> CliResult<Pubkey> {
+	let address = token::get_associated_token_address(wallet, mint, token_program_id);
+	if context.rpc.get_account(&address)?.is_none() {
+		instructions.push(token::create_associated_token_account(&pubkey_of(&context.payer), wallet, mint, token_program_id));
+	}
+	Ok(address)
+}
+
+/// Signs `instructions` with the payer and `signers` and sends them as one transaction, a v0 one if
+/// there's a lookup table
+fn send(context: &Context, instructions: &[Instruction], signers: &[&Keypair]) -> CliResult<String> {
+	let payer = pubkey_of(&context.payer);
+	let message = match &context.lookup_table {
+		Some(table) => client::v0_message(&context.rpc, instructions, &payer, std::slice::from_ref(table), &context.budget),
+		None => client::message_with_budget(&context.rpc, instructions, &payer, &context.budget).map(VersionedMessage::Legacy),
+	}
+	.map_err(client_error)?;
+	context.rpc.send(&sign(&message, &[&[&context.payer], signers].concat())?)
+}
+
+/// The wire format of a transaction: the signatures as a short vec, then the message they sign
+fn sign(message: &VersionedMessage, keypairs: &[&Keypair]) -> CliResult<Vec<u8>> {
+	let message_data = message.serialize();
+	let signers = &message.static_account_keys()[..message.header().num_required_signatures as usize];
+	// Fewer than 128 signatures fit in a transaction, so the short vec length is a single byte
+	let mut transaction = vec![signers.len() as u8];
+	for signer in signers {
+		let keypair = keypairs
+			.iter()
+			.find(|keypair| pubkey_of(keypair) == *signer)
+			.ok_or_else(|| format!("no keypair for signer {}", signer))?;
+		transaction.extend_from_slice(&keypair.sign(&message_data).to_bytes());
+	}
+	transaction.extend_from_slice(&message_data);
+	Ok(transaction)
+}
+
+/// A keypair file of solana-keygen, a JSON array of the 64 bytes
+fn read_keypair(path: &str) -> CliResult<Keypair> {
+	let file = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+	let bytes: Vec<u8> = serde_json::from_str(&file).map_err(|error| format!("{}: {}", path, error))?;
+	Keypair::from_bytes(&bytes).map_err(|error| format!("{}: {}", path, error))
+}
+
+fn pubkey_of(keypair: &Keypair) -> Pubkey {
+	Pubkey::new_from_array(keypair.public.to_bytes())
+}
+
+fn parse_pubkey(pubkey: &str) -> CliResult<Pubkey> {
+	Pubkey::from_str(pubkey).map_err(|error| format!("{}: {}", pubkey, error))
+}
+
+/// http(s)://host:port as ws(s)://host:port+1, where solana-test-validator and the solana CLI have it
+fn websocket_url(url: &str) -> CliResult<String> {
+	let (scheme, rest) = url.split_once("://").ok_or_else(|| format!("{}: not a URL", url))?;
+	let scheme = match scheme {
+		"https" => "wss",
+		_ => "ws",
+	};
+	let (host, path) = match rest.find('/') {
+		Some(slash) => rest.split_at(slash),
+		None => (rest, ""),
+	};
+	let host = match host.rsplit_once(':') {
+		Some((name, port)) => {
+			let port = port.parse::<u16>().map_err(|error| format!("{}: {}", url, error))?;
+			format!("{}:{}", name, port.wrapping_add(1))
+		},
+		None => host.to_string(),
+	};
+	Ok(format!("{}://{}{}", scheme, host, path))
+}
+
+fn parse_amount(amount: &str) -> CliResult<u64> {
+	amount.parse().map_err(|error| format!("{}: {}", amount, error))
+}
+
+fn parse_seconds(seconds: &str) -> CliResult<i64> {
+	seconds.parse().map_err(|error| format!("{}: {}", seconds, error))
+}
+
+fn client_error(error: ClientError<String>) -> String {
+	match error {
+		ClientError::Rpc(error) => error,
+		ClientError::AccountNotFound(pubkey) => format!("no account at {}", pubkey),
+		ClientError::Program(error) => error.to_string(),
+	}
+}
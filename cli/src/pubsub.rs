@@ -0,0 +1,144 @@
+//! The pubsub calls `watch` makes, over tungstenite
+
+use std::{
+	collections::{HashMap, VecDeque},
+	net::TcpStream,
+};
+
+use bpf_program_template::{
+	client::Memcmp,
+	subscription::{Notification, Pubsub},
+};
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+use crate::rpc::{decode_data, parse_pubkey};
+
+pub struct PubsubClient {
+	socket: WebSocket<MaybeTlsStream<TcpStream>>,
+	next_id: u64,
+	/// The addresses of the accountSubscribe subscriptions, by subscription id
+	accounts: HashMap<u64, Pubkey>,
+	/// Notifications that came in while waiting for a subscription's id
+	pending: VecDeque<Value>,
+}
+
+impl PubsubClient {
+	pub fn connect(url: &str) -> Result<Self, String> {
+		let (socket, _) = tungstenite::connect(url).map_err(|error| format!("{}: {}", url, error))?;
+		Ok(PubsubClient {
+			socket,
+			next_id: 1,
+			accounts: HashMap::new(),
+			pending: VecDeque::new(),
+		})
+	}
+
+	/// Sends a request and waits for its result, keeping the notifications that come first
+	fn call(&mut self, method: &str, params: Value) -> Result<Value, String> {
+		let id = self.next_id;
+		self.next_id += 1;
+		let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+		self.socket
+			.write_message(Message::Text(request.to_string()))
+			.map_err(|error| format!("{}: {}", method, error))?;
+		loop {
+			let message = self.read()?;
+			if message["id"] == id {
+				if let Some(error) = message.get("error") {
+					return Err(format!("{}: {}", method, error));
+				}
+				return Ok(message["result"].clone());
+			}
+			if message.get("method").is_some() {
+				self.pending.push_back(message);
+			}
+		}
+	}
+
+	fn read(&mut self) -> Result<Value, String> {
+		loop {
+			match self.socket.read_message().map_err(|error| error.to_string())? {
+				Message::Text(text) => return serde_json::from_str(&text).map_err(|error| error.to_string()),
+				Message::Close(_) => return Err("the node closed the connection".to_string()),
+				// Pings get their pongs from tungstenite
+				_ => {},
+			}
+		}
+	}
+}
+
+impl Pubsub for PubsubClient {
+	type Error = String;
+
+	fn program_subscribe(&mut self, program_id: &Pubkey, filters: &[Memcmp]) -> Result<(), String> {
+		let filters = filters
+			.iter()
+			.map(|filter| json!({"memcmp": {"offset": filter.offset, "bytes": bs58::encode(&filter.bytes).into_string()}}))
+			.collect::<Vec<_>>();
+		self.call(
+			"programSubscribe",
+			json!([program_id.to_string(), {"encoding": "base64", "commitment": "confirmed", "filters": filters}]),
+		)?;
+		Ok(())
+	}
+
+	fn account_subscribe(&mut self, pubkey: &Pubkey) -> Result<(), String> {
+		let subscription = self
+			.call("accountSubscribe", json!([pubkey.to_string(), {"encoding": "base64", "commitment": "confirmed"}]))?
+			.as_u64()
+			.ok_or("accountSubscribe: no subscription id")?;
+		self.accounts.insert(subscription, *pubkey);
+		Ok(())
+	}
+
+	fn account_unsubscribe(&mut self, pubkey: &Pubkey) -> Result<(), String> {
+		let subscription = match self.accounts.iter().find(|(_, account)| *account == pubkey) {
+			Some((subscription, _)) => *subscription,
+			None => return Ok(()),
+		};
+		self.accounts.remove(&subscription);
+		self.call("accountUnsubscribe", json!([subscription]))?;
+		Ok(())
+	}
+
+	fn logs_subscribe(&mut self, program_id: &Pubkey) -> Result<(), String> {
+		self.call("logsSubscribe", json!([{"mentions": [program_id.to_string()]}, {"commitment": "confirmed"}]))?;
+		Ok(())
+	}
+
+	fn next_notification(&mut self) -> Result<Notification, String> {
+		loop {
+			let message = match self.pending.pop_front() {
+				Some(message) => message,
+				None => self.read()?,
+			};
+			let params = &message["params"];
+			match message["method"].as_str() {
+				Some("programNotification") => {
+					let value = &params["result"]["value"];
+					return Ok(Notification::Account(parse_pubkey(&value["pubkey"])?, decode_data(&value["account"]["data"])?));
+				},
+				Some("accountNotification") => {
+					// Notifications of a subscription that was just dropped can still come in
+					let pubkey = match params["subscription"].as_u64().and_then(|id| self.accounts.get(&id)) {
+						Some(pubkey) => *pubkey,
+						None => continue,
+					};
+					return Ok(Notification::Account(pubkey, decode_data(&params["result"]["value"]["data"])?));
+				},
+				Some("logsNotification") => {
+					// Failed transactions' events didn't happen
+					let value = &params["result"]["value"];
+					if !value["err"].is_null() {
+						continue;
+					}
+					let logs = value["logs"].as_array().ok_or("logsNotification: no logs")?;
+					return Ok(Notification::Logs(logs.iter().filter_map(|line| line.as_str()).map(str::to_string).collect()));
+				},
+				_ => {},
+			}
+		}
+	}
+}
@@ -0,0 +1,158 @@
+//! Just the JSON-RPC calls the CLI makes, see client.rs for why this isn't solana-client
+
+use std::{str::FromStr, thread, time::Duration};
+
+use bpf_program_template::client::{Account, Memcmp, Rpc};
+use serde_json::{json, Value};
+use solana_program::{hash::Hash, message::VersionedMessage, pubkey::Pubkey};
+
+/// How long send waits for the transaction to be confirmed, in tries a second apart
+const CONFIRM_TRIES: usize = 30;
+
+pub struct RpcClient {
+	url: String,
+	http: reqwest::blocking::Client,
+}
+
+impl RpcClient {
+	pub fn new(url: String) -> Self {
+		RpcClient {
+			url,
+			http: reqwest::blocking::Client::new(),
+		}
+	}
+
+	fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+		let response: Value = self
+			.http
+			.post(&self.url)
+			.json(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+			.send()
+			.and_then(|response| response.json())
+			.map_err(|error| format!("{}: {}", method, error))?;
+		if let Some(error) = response.get("error") {
+			return Err(format!("{}: {}", method, error));
+		}
+		Ok(response["result"].clone())
+	}
+
+	pub fn get_minimum_balance_for_rent_exemption(&self, len: usize) -> Result<u64, String> {
+		self.call("getMinimumBalanceForRentExemption", json!([len]))?
+			.as_u64()
+			.ok_or_else(|| "getMinimumBalanceForRentExemption: not a number".to_string())
+	}
+
+	/// A finalized slot, recent enough for CreateLookupTable
+	pub fn get_slot(&self) -> Result<u64, String> {
+		self.call("getSlot", json!([{"commitment": "finalized"}]))?
+			.as_u64()
+			.ok_or_else(|| "getSlot: not a number".to_string())
+	}
+
+	/// Sends a signed transaction and waits until it's confirmed, returns its signature
+	pub fn send(&self, transaction: &[u8]) -> Result<String, String> {
+		let signature = self.call(
+			"sendTransaction",
+			json!([base64::encode(transaction), {"encoding": "base64", "preflightCommitment": "confirmed"}]),
+		)?;
+		let signature = signature.as_str().ok_or("sendTransaction: no signature")?.to_string();
+		for _ in 0..CONFIRM_TRIES {
+			let statuses = self.call("getSignatureStatuses", json!([[signature]]))?;
+			let status = &statuses["value"][0];
+			if !status.is_null() {
+				if !status["err"].is_null() {
+					return Err(format!("{} failed: {}", signature, status["err"]));
+				}
+				if matches!(status["confirmationStatus"].as_str(), Some("confirmed") | Some("finalized")) {
+					return Ok(signature);
+				}
+			}
+			thread::sleep(Duration::from_secs(1));
+		}
+		Err(format!("{} wasn't confirmed in time", signature))
+	}
+
+	/// The logs of the message when it's simulated, like simulate_units_consumed
+	pub fn simulate_logs(&self, message: &VersionedMessage) -> Result<Vec<String>, String> {
+		let result = self.simulate(message)?;
+		let logs = result["logs"].as_array().ok_or("simulateTransaction: no logs")?;
+		Ok(logs.iter().filter_map(|log| log.as_str().map(str::to_string)).collect())
+	}
+
+	/// simulateTransaction's value, an error if the transaction fails
+	fn simulate(&self, message: &VersionedMessage) -> Result<Value, String> {
+		// Zeroed signatures, sigVerify is off
+		let signatures = message.header().num_required_signatures as usize;
+		let mut transaction = vec![signatures as u8];
+		transaction.resize(1 + signatures * 64, 0);
+		transaction.extend_from_slice(&message.serialize());
+		let result = self.call(
+			"simulateTransaction",
+			json!([base64::encode(transaction), {"encoding": "base64", "sigVerify": false, "commitment": "confirmed"}]),
+		)?;
+		if !result["value"]["err"].is_null() {
+			return Err(format!("simulateTransaction: {} {}", result["value"]["err"], result["value"]["logs"]));
+		}
+		Ok(result["value"].clone())
+	}
+}
+
+impl Rpc for RpcClient {
+	type Error = String;
+
+	fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, String> {
+		let result = self.call(
+			"getAccountInfo",
+			json!([pubkey.to_string(), {"encoding": "base64", "commitment": "confirmed"}]),
+		)?;
+		match &result["value"] {
+			Value::Null => Ok(None),
+			account => Ok(Some(Account {
+				lamports: account["lamports"].as_u64().ok_or("getAccountInfo: no lamports")?,
+				owner: parse_pubkey(&account["owner"])?,
+				executable: account["executable"].as_bool().unwrap_or_default(),
+				data: decode_data(&account["data"])?,
+			})),
+		}
+	}
+
+	fn get_program_accounts(&self, program_id: &Pubkey, filters: &[Memcmp]) -> Result<Vec<(Pubkey, Vec<u8>)>, String> {
+		let filters = filters
+			.iter()
+			.map(|filter| json!({"memcmp": {"offset": filter.offset, "bytes": bs58::encode(&filter.bytes).into_string()}}))
+			.collect::<Vec<_>>();
+		let result = self.call(
+			"getProgramAccounts",
+			json!([program_id.to_string(), {"encoding": "base64", "commitment": "confirmed", "filters": filters}]),
+		)?;
+		result
+			.as_array()
+			.ok_or("getProgramAccounts: not a list")?
+			.iter()
+			.map(|account| Ok((parse_pubkey(&account["pubkey"])?, decode_data(&account["account"]["data"])?)))
+			.collect()
+	}
+
+	fn get_latest_blockhash(&self) -> Result<Hash, String> {
+		let result = self.call("getLatestBlockhash", json!([{"commitment": "confirmed"}]))?;
+		let blockhash = result["value"]["blockhash"].as_str().ok_or("getLatestBlockhash: no blockhash")?;
+		Hash::from_str(blockhash).map_err(|error| format!("getLatestBlockhash: {}", error))
+	}
+
+	fn simulate_units_consumed(&self, message: &VersionedMessage) -> Result<u64, String> {
+		self.simulate(message)?["unitsConsumed"]
+			.as_u64()
+			.ok_or_else(|| "simulateTransaction: no unitsConsumed, the node is too old".to_string())
+	}
+}
+
+pub fn parse_pubkey(value: &Value) -> Result<Pubkey, String> {
+	let pubkey = value.as_str().ok_or("not a pubkey")?;
+	Pubkey::from_str(pubkey).map_err(|error| format!("{}: {}", pubkey, error))
+}
+
+/// Account data comes as [base64, "base64"] with the encoding we ask for, empty for closed accounts
+pub fn decode_data(value: &Value) -> Result<Vec<u8>, String> {
+	let data = value[0].as_str().ok_or("no account data")?;
+	base64::decode(data).map_err(|error| error.to_string())
+}
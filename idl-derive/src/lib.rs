@@ -0,0 +1,25 @@
+//! Derives named after Shank's, so the program's instructions can carry Shank's `#[account(..)]`
+//! attributes. Like Shank's own they expand to nothing, the attributes are only read
+//! by the IDL generator (`escrow-cli idl`), which parses the source. Switching to the shank crate is a
+//! matter of changing the import once it can be pulled in
+
+use proc_macro::TokenStream;
+
+/// For the instruction enum, each variant's accounts go in `#[account(index, writable, signer,
+/// optional, name = "..", desc = "..")]` attributes
+#[proc_macro_derive(ShankInstruction, attributes(account))]
+pub fn shank_instruction(_input: TokenStream) -> TokenStream {
+	TokenStream::new()
+}
+
+/// For the state types accounts are made of
+#[proc_macro_derive(ShankAccount)]
+pub fn shank_account(_input: TokenStream) -> TokenStream {
+	TokenStream::new()
+}
+
+/// For the types instruction arguments and accounts are made of
+#[proc_macro_derive(ShankType)]
+pub fn shank_type(_input: TokenStream) -> TokenStream {
+	TokenStream::new()
+}
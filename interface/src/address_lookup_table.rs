@@ -0,0 +1,105 @@
+//! The address lookup table program's CreateLookupTable and ExtendLookupTable, and reading a table's
+//! addresses. solana-program 1.9 has v0 messages but not the program, so these are encoded by hand
+//! like the compute budget instructions (bincode: a u32 tag, then the fields)
+
+use solana_program::{
+	instruction::{AccountMeta, Instruction},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+	system_program,
+	sysvar,
+};
+
+use crate::{
+	state::Config,
+	token::{token_2022, token_program},
+	treasury::find_treasury_address,
+};
+
+solana_program::declare_id!("AddressLookupTab1e1111111111111111111111111");
+
+/// The table's metadata in front of its addresses
+pub const LOOKUP_TABLE_META_SIZE: usize = 56;
+
+/// Lookups index the table with a u8
+pub const LOOKUP_TABLE_MAX_ADDRESSES: usize = 256;
+
+/// The metadata's first u32 for an initialized table
+const LOOKUP_TABLE_STATE: u32 = 1;
+
+const CREATE_LOOKUP_TABLE: u32 = 0;
+const EXTEND_LOOKUP_TABLE: u32 = 2;
+
+/// The table `authority` creates at `recent_slot`
+pub fn find_lookup_table_address(authority: &Pubkey, recent_slot: u64) -> (Pubkey, u8) {
+	Pubkey::find_program_address(&[authority.as_ref(), &recent_slot.to_le_bytes()], &id())
+}
+
+/// Creates an empty table owned by `authority`, `payer` pays its rent. `recent_slot` has to be in the
+/// SlotHashes sysvar still, a finalized slot from getSlot is. Returns the table's address too
+pub fn create_lookup_table(authority: &Pubkey, payer: &Pubkey, recent_slot: u64) -> (Instruction, Pubkey) {
+	let (table, bump) = find_lookup_table_address(authority, recent_slot);
+	let mut data = CREATE_LOOKUP_TABLE.to_le_bytes().to_vec();
+	data.extend_from_slice(&recent_slot.to_le_bytes());
+	data.push(bump);
+	let instruction = Instruction::new_with_bytes(
+		id(),
+		&data,
+		vec![
+			AccountMeta::new(table, false),
+			AccountMeta::new_readonly(*authority, true),
+			AccountMeta::new(*payer, true),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	(instruction, table)
+}
+
+/// Appends `addresses` to `table`, `payer` tops up its rent. About 20 addresses fit in one transaction
+/// next to the signatures
+pub fn extend_lookup_table(table: &Pubkey, authority: &Pubkey, payer: &Pubkey, addresses: &[Pubkey]) -> Instruction {
+	let mut data = EXTEND_LOOKUP_TABLE.to_le_bytes().to_vec();
+	data.extend_from_slice(&(addresses.len() as u64).to_le_bytes());
+	for address in addresses {
+		data.extend_from_slice(address.as_ref());
+	}
+	Instruction::new_with_bytes(
+		id(),
+		&data,
+		vec![
+			AccountMeta::new(*table, false),
+			AccountMeta::new_readonly(*authority, true),
+			AccountMeta::new(*payer, true),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+/// The addresses in a table account's data
+pub fn unpack_addresses(data: &[u8]) -> Result<Vec<Pubkey>, ProgramError> {
+	if data.len() < LOOKUP_TABLE_META_SIZE
+		|| !data[LOOKUP_TABLE_META_SIZE..].chunks_exact(32).remainder().is_empty()
+		|| data[..4] != LOOKUP_TABLE_STATE.to_le_bytes()
+	{
+		return Err(ProgramError::InvalidAccountData);
+	}
+	Ok(data[LOOKUP_TABLE_META_SIZE..].chunks_exact(32).map(Pubkey::new).collect())
+}
+
+/// What every escrow's instructions pass besides its own accounts: the program, both token programs,
+/// the sysvars, the system program and the program's config and treasury. The program ids that get
+/// invoked by a transaction itself stay in its message, lookups only load the accounts
+pub fn common_addresses(program_id: &Pubkey) -> Vec<Pubkey> {
+	vec![
+		*program_id,
+		token_program::id(),
+		token_2022::id(),
+		sysvar::rent::id(),
+		sysvar::clock::id(),
+		sysvar::instructions::id(),
+		system_program::id(),
+		Config::find_address(program_id).0,
+		Config::find_standby_address(program_id).0,
+		find_treasury_address(program_id).0,
+	]
+}
@@ -0,0 +1,422 @@
+//! Off-chain helpers for reading escrows and building the transactions that use them, so clients don't
+//! have to know the account layouts.
+//!
+//! These don't bring an RPC client of their own. solana-client 1.9 can't be resolved next to the
+//! solana-program this crate pins (its chrono wants a newer js-sys), so the RPC calls go through the Rpc
+//! trait, which is a few lines to implement over whatever client the integrator already has. The
+//! transactions come back as Messages with a recent blockhash, signing them is the wallet's business.
+//!
+//! Transactions with more accounts than fit in a legacy one go out as v0 messages loading accounts from
+//! address lookup tables, see v0_message. address_lookup_table::common_addresses makes a table for them
+//!
+//! subscription.rs has escrow updates as they happen, for bots that would poll list_open_escrows
+
+use solana_program::{
+	hash::Hash,
+	instruction::{CompiledInstruction, Instruction},
+	message::{
+		v0::{self, MessageAddressTableLookup},
+		Message, MessageHeader, VersionedMessage,
+	},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+};
+
+use crate::{
+	address_lookup_table,
+	compute_budget::{self, MAX_COMPUTE_UNIT_LIMIT},
+	instruction,
+	state::{Discriminator, Escrow, EscrowStatus, ESCROW_VERSION},
+};
+
+/// A getProgramAccounts memcmp filter, `bytes` at `offset` of the account data
+#[derive(Clone, Debug, PartialEq)]
+pub struct Memcmp {
+	pub offset: usize,
+	pub bytes: Vec<u8>,
+}
+
+/// An account as getAccountInfo returns it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+	pub lamports: u64,
+	pub owner: Pubkey,
+	pub executable: bool,
+	pub data: Vec<u8>,
+}
+
+/// The RPC calls the helpers make, like RpcClient's get_account, get_program_accounts_with_config and
+/// get_latest_blockhash
+pub trait Rpc {
+	type Error;
+
+	/// None if there's no account at `pubkey`
+	fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, Self::Error>;
+
+	/// The account's data, None if there's no account at `pubkey`
+	fn get_account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>, Self::Error> {
+		Ok(self.get_account(pubkey)?.map(|account| account.data))
+	}
+
+	/// Addresses and data of the accounts of `program_id` matching all of `filters`
+	fn get_program_accounts(&self, program_id: &Pubkey, filters: &[Memcmp]) -> Result<Vec<(Pubkey, Vec<u8>)>, Self::Error>;
+
+	fn get_latest_blockhash(&self) -> Result<Hash, Self::Error>;
+
+	/// Compute units the message uses when it's simulated, unsigned (simulateTransaction without
+	/// sigVerify). Legacy and v0 messages both
+	fn simulate_units_consumed(&self, message: &VersionedMessage) -> Result<u64, Self::Error>;
+}
+
+/// How much more than the simulation used ComputeBudget::measure_units asks for, in percent. State
+/// changes between the simulation and the transaction landing, like a transfer hook doing more
+pub const MEASURED_UNITS_MARGIN_PERCENT: u64 = 20;
+
+/// The compute budget instructions message_with_budget puts in front. The default adds none
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ComputeBudget {
+	/// Priority fee in micro-lamports per compute unit, none if 0
+	pub unit_price: u64,
+	/// Simulate the transaction and set its compute unit limit to what it used plus
+	/// MEASURED_UNITS_MARGIN_PERCENT, instead of the 200k per instruction default. Exchange with its
+	/// CPIs (transfer hooks, royalties, Token Metadata) can need more than that
+	pub measure_units: bool,
+}
+
+#[derive(Debug)]
+pub enum ClientError<E> {
+	Rpc(E),
+	AccountNotFound(Pubkey),
+	/// The account is there but isn't what it should be, or an instruction couldn't be built
+	Program(ProgramError),
+}
+
+impl<E> From<ProgramError> for ClientError<E> {
+	fn from(error: ProgramError) -> Self {
+		ClientError::Program(error)
+	}
+}
+
+/// Fetches the escrow at `escrow_account` and decodes it, whatever its layout version
+pub fn fetch_escrow<R: Rpc>(rpc: &R, escrow_account: &Pubkey) -> Result<Escrow, ClientError<R::Error>> {
+	let data = rpc
+		.get_account_data(escrow_account)
+		.map_err(ClientError::Rpc)?
+		.ok_or(ClientError::AccountNotFound(*escrow_account))?;
+	Ok(Escrow::unpack_versioned(&data)?)
+}
+
+/// The filters for current layout escrows that are still open, narrowed down to those of `initializer`
+/// if it's set. Escrows MigrateEscrow hasn't upgraded yet don't match, their fields are elsewhere
+pub fn open_escrow_filters(initializer: Option<&Pubkey>) -> Vec<Memcmp> {
+	let mut filters = vec![
+		Memcmp {
+			offset: 0,
+			bytes: Escrow::DISCRIMINATOR.to_vec(),
+		},
+		Memcmp {
+			offset: Escrow::VERSION_OFFSET,
+			bytes: vec![ESCROW_VERSION, EscrowStatus::Active as u8],
+		},
+	];
+	if let Some(initializer) = initializer {
+		filters.push(Memcmp {
+			offset: Escrow::INITIALIZER_OFFSET,
+			bytes: initializer.to_bytes().to_vec(),
+		});
+	}
+	filters
+}
+
+/// Every open escrow of `program_id`, or only those of `initializer`, with their addresses
+pub fn list_open_escrows<R: Rpc>(
+	rpc: &R,
+	program_id: &Pubkey,
+	initializer: Option<&Pubkey>,
+) -> Result<Vec<(Pubkey, Escrow)>, ClientError<R::Error>> {
+	rpc.get_program_accounts(program_id, &open_escrow_filters(initializer))
+		.map_err(ClientError::Rpc)?
+		.into_iter()
+		.map(|(pubkey, data)| Ok((pubkey, Escrow::unpack_versioned(&data)?)))
+		.collect()
+}
+
+/// A message of `instructions` paid for by `payer`, with a recent blockhash so it's ready to be signed
+pub fn message<R: Rpc>(rpc: &R, instructions: &[Instruction], payer: &Pubkey) -> Result<Message, ClientError<R::Error>> {
+	let mut message = Message::new(instructions, Some(payer));
+	message.recent_blockhash = rpc.get_latest_blockhash().map_err(ClientError::Rpc)?;
+	Ok(message)
+}
+
+/// Like message, with `budget`'s compute budget instructions in front of `instructions`
+pub fn message_with_budget<R: Rpc>(
+	rpc: &R,
+	instructions: &[Instruction],
+	payer: &Pubkey,
+	budget: &ComputeBudget,
+) -> Result<Message, ClientError<R::Error>> {
+	let simulation = |instructions: &[Instruction]| Ok(VersionedMessage::Legacy(message(rpc, instructions, payer)?));
+	let budget_instructions = budget_instructions(rpc, budget, simulation, instructions)?;
+	message(rpc, &[budget_instructions.as_slice(), instructions].concat(), payer)
+}
+
+/// The compute budget instructions of `budget` for `instructions`, `simulation` puts them in the
+/// message that's simulated for ComputeBudget::measure_units
+fn budget_instructions<R: Rpc>(
+	rpc: &R,
+	budget: &ComputeBudget,
+	simulation: impl FnOnce(&[Instruction]) -> Result<VersionedMessage, ClientError<R::Error>>,
+	instructions: &[Instruction],
+) -> Result<Vec<Instruction>, ClientError<R::Error>> {
+	let mut budget_instructions = Vec::new();
+	if budget.measure_units {
+		// Simulated with the most units there are, so it doesn't run out of the default ones
+		let simulation =
+			simulation(&[&[compute_budget::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT)], instructions].concat())?;
+		let units = rpc.simulate_units_consumed(&simulation).map_err(ClientError::Rpc)?;
+		let units = units.saturating_mul(100 + MEASURED_UNITS_MARGIN_PERCENT) / 100;
+		budget_instructions.push(compute_budget::set_compute_unit_limit(units.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32));
+	}
+	if budget.unit_price > 0 {
+		budget_instructions.push(compute_budget::set_compute_unit_price(budget.unit_price));
+	}
+	Ok(budget_instructions)
+}
+
+/// An address lookup table and the addresses in it
+#[derive(Clone, Debug, PartialEq)]
+pub struct LookupTable {
+	pub address: Pubkey,
+	pub addresses: Vec<Pubkey>,
+}
+
+/// Fetches the lookup table at `address`
+pub fn fetch_lookup_table<R: Rpc>(rpc: &R, address: &Pubkey) -> Result<LookupTable, ClientError<R::Error>> {
+	let data = rpc
+		.get_account_data(address)
+		.map_err(ClientError::Rpc)?
+		.ok_or(ClientError::AccountNotFound(*address))?;
+	Ok(LookupTable {
+		address: *address,
+		addresses: address_lookup_table::unpack_addresses(&data)?,
+	})
+}
+
+/// Instructions creating a lookup table of `authority` with address_lookup_table::common_addresses of
+/// `program_id` in it, and its address. The table is usable from the slot after the transaction lands
+pub fn create_common_lookup_table(
+	program_id: &Pubkey,
+	authority: &Pubkey,
+	payer: &Pubkey,
+	recent_slot: u64,
+) -> (Vec<Instruction>, Pubkey) {
+	let (create, table) = address_lookup_table::create_lookup_table(authority, payer, recent_slot);
+	let extend = address_lookup_table::extend_lookup_table(
+		&table,
+		authority,
+		payer,
+		&address_lookup_table::common_addresses(program_id),
+	);
+	(vec![create, extend], table)
+}
+
+/// A v0 message of `instructions` paid for by `payer`, the accounts that are in `lookup_tables` are
+/// loaded from them instead of taking 32 bytes each in the message. Signers and invoked programs can't
+/// be loaded so they stay in it. InvalidArgument if it comes to more than 256 accounts
+pub fn compile_v0_message(
+	instructions: &[Instruction],
+	payer: &Pubkey,
+	lookup_tables: &[LookupTable],
+	recent_blockhash: Hash,
+) -> Result<v0::Message, ProgramError> {
+	// Every account with how the instructions use it, the payer first
+	let mut keys = vec![CompiledKey {
+		pubkey: *payer,
+		signer: true,
+		writable: true,
+		invoked: false,
+	}];
+	let mut add = |pubkey: &Pubkey, signer: bool, writable: bool, invoked: bool| {
+		match keys.iter_mut().find(|key| key.pubkey == *pubkey) {
+			Some(key) => {
+				key.signer |= signer;
+				key.writable |= writable;
+				key.invoked |= invoked;
+			},
+			None => keys.push(CompiledKey {
+				pubkey: *pubkey,
+				signer,
+				writable,
+				invoked,
+			}),
+		}
+	};
+	for instruction in instructions {
+		add(&instruction.program_id, false, false, true);
+		for account in &instruction.accounts {
+			add(&account.pubkey, account.is_signer, account.is_writable, false);
+		}
+	}
+
+	// The first table that has an account loads it
+	let mut lookups = Vec::new();
+	let (mut loaded_writable, mut loaded_readonly) = (Vec::new(), Vec::new());
+	for table in lookup_tables {
+		let mut lookup = MessageAddressTableLookup {
+			account_key: table.address,
+			..MessageAddressTableLookup::default()
+		};
+		keys.retain(|key| {
+			if key.signer || key.invoked {
+				return true;
+			}
+			let index = match table.addresses.iter().position(|address| *address == key.pubkey) {
+				Some(index) if index < address_lookup_table::LOOKUP_TABLE_MAX_ADDRESSES => index as u8,
+				_ => return true,
+			};
+			if key.writable {
+				lookup.writable_indexes.push(index);
+				loaded_writable.push(key.pubkey);
+			} else {
+				lookup.readonly_indexes.push(index);
+				loaded_readonly.push(key.pubkey);
+			}
+			false
+		});
+		if !lookup.writable_indexes.is_empty() || !lookup.readonly_indexes.is_empty() {
+			lookups.push(lookup);
+		}
+	}
+
+	// Writable signers, readonly signers, writable non-signers, readonly non-signers. The sort is stable
+	// so the payer stays first
+	keys.sort_by_key(|key| (!key.signer, !key.writable));
+	let header = MessageHeader {
+		num_required_signatures: keys.iter().filter(|key| key.signer).count() as u8,
+		num_readonly_signed_accounts: keys.iter().filter(|key| key.signer && !key.writable).count() as u8,
+		num_readonly_unsigned_accounts: keys.iter().filter(|key| !key.signer && !key.writable).count() as u8,
+	};
+	let account_keys = keys.into_iter().map(|key| key.pubkey).collect::<Vec<_>>();
+
+	// Instructions index the message's keys followed by the loaded writable and then readonly ones
+	let all_keys = [account_keys.as_slice(), &loaded_writable, &loaded_readonly].concat();
+	if all_keys.len() > 256 {
+		return Err(ProgramError::InvalidArgument);
+	}
+	let index_of = |pubkey: &Pubkey| all_keys.iter().position(|key| key == pubkey).unwrap_or_default() as u8;
+	let instructions = instructions
+		.iter()
+		.map(|instruction| CompiledInstruction {
+			program_id_index: index_of(&instruction.program_id),
+			accounts: instruction.accounts.iter().map(|account| index_of(&account.pubkey)).collect(),
+			data: instruction.data.clone(),
+		})
+		.collect();
+
+	Ok(v0::Message {
+		header,
+		account_keys,
+		recent_blockhash,
+		instructions,
+		address_table_lookups: lookups,
+	})
+}
+
+struct CompiledKey {
+	pubkey: Pubkey,
+	signer: bool,
+	writable: bool,
+	invoked: bool,
+}
+
+/// Like message_with_budget, as a v0 message loading what it can from `lookup_tables`
+pub fn v0_message<R: Rpc>(
+	rpc: &R,
+	instructions: &[Instruction],
+	payer: &Pubkey,
+	lookup_tables: &[LookupTable],
+	budget: &ComputeBudget,
+) -> Result<VersionedMessage, ClientError<R::Error>> {
+	let compile = |instructions: &[Instruction]| {
+		let recent_blockhash = rpc.get_latest_blockhash().map_err(ClientError::Rpc)?;
+		Ok(VersionedMessage::V0(compile_v0_message(instructions, payer, lookup_tables, recent_blockhash)?))
+	};
+	let budget_instructions = budget_instructions(rpc, budget, compile, instructions)?;
+	compile(&[budget_instructions.as_slice(), instructions].concat())
+}
+
+/// instruction::init_escrow in a message paid for by the initializer. The escrow account and the funded
+/// temp token account have to exist already. Pass ComputeBudget::default() for no compute budget
+/// instructions, here and in exchange_message and cancel_message
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_message<R: Rpc>(
+	rpc: &R,
+	program_id: &Pubkey,
+	initializer: &Pubkey,
+	temp_token_account: &Pubkey,
+	token_to_receive_account: &Pubkey,
+	escrow_account: &Pubkey,
+	token_program_id: &Pubkey,
+	deposit_mint: &Pubkey,
+	receive_mint: &Pubkey,
+	standby_config: bool,
+	amount: u64,
+	budget: &ComputeBudget,
+) -> Result<Message, ClientError<R::Error>> {
+	let instruction = instruction::init_escrow(
+		program_id,
+		initializer,
+		temp_token_account,
+		token_to_receive_account,
+		escrow_account,
+		token_program_id,
+		deposit_mint,
+		receive_mint,
+		standby_config,
+		amount,
+	)?;
+	message_with_budget(rpc, &[instruction], initializer, budget)
+}
+
+/// Fetches the escrow and puts instruction::exchange of it in a message paid for by the taker
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_message<R: Rpc>(
+	rpc: &R,
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	taker: &Pubkey,
+	takers_sending_account: &Pubkey,
+	takers_token_to_receive_account: &Pubkey,
+	token_program_id: &Pubkey,
+	sending_token_program_id: &Pubkey,
+	reference: Option<Pubkey>,
+	budget: &ComputeBudget,
+) -> Result<Message, ClientError<R::Error>> {
+	let escrow = fetch_escrow(rpc, escrow_account)?;
+	let instruction = instruction::exchange(
+		program_id,
+		escrow_account,
+		&escrow,
+		taker,
+		takers_sending_account,
+		takers_token_to_receive_account,
+		token_program_id,
+		sending_token_program_id,
+		reference,
+	)?;
+	message_with_budget(rpc, &[instruction], taker, budget)
+}
+
+/// Fetches the escrow and puts instruction::cancel of it in a message paid for by the initializer
+pub fn cancel_message<R: Rpc>(
+	rpc: &R,
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	refund_token_account: &Pubkey,
+	token_program_id: &Pubkey,
+	budget: &ComputeBudget,
+) -> Result<Message, ClientError<R::Error>> {
+	let escrow = fetch_escrow(rpc, escrow_account)?;
+	let instruction = instruction::cancel(program_id, escrow_account, &escrow, refund_token_account, token_program_id)?;
+	message_with_budget(rpc, &[instruction], &escrow.initializer_pubkey, budget)
+}
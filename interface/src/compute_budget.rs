@@ -0,0 +1,26 @@
+//! The compute budget program's SetComputeUnitLimit and SetComputeUnitPrice. solana-program 1.9 doesn't
+//! have them yet, they're encoded by hand like Bubblegum's transfer
+
+use solana_program::instruction::Instruction;
+
+solana_program::declare_id!("ComputeBudget111111111111111111111111111111");
+
+/// The most compute units a transaction can ask for
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+/// The transaction's compute unit limit instead of the 200k per instruction default
+pub fn set_compute_unit_limit(units: u32) -> Instruction {
+	let mut data = vec![SET_COMPUTE_UNIT_LIMIT];
+	data.extend_from_slice(&units.to_le_bytes());
+	Instruction::new_with_bytes(id(), &data, vec![])
+}
+
+/// The priority fee, in micro-lamports per requested compute unit
+pub fn set_compute_unit_price(micro_lamports: u64) -> Instruction {
+	let mut data = vec![SET_COMPUTE_UNIT_PRICE];
+	data.extend_from_slice(&micro_lamports.to_le_bytes());
+	Instruction::new_with_bytes(id(), &data, vec![])
+}
@@ -0,0 +1,208 @@
+//! CPIs that don't touch the heap. solana-program's invoke_signed takes an Instruction, whose account
+//! metas and data are Vecs, and the program's allocator only frees the last allocation, so most of what
+//! a CPI through it allocates stays on the heap. This goes through sol_invoke_signed_c instead, the
+//! syscall C programs make their CPIs with. It takes plain pointers, so the metas, the data and the
+//! account infos are arrays on the caller's stack.
+//!
+//! The syscall writes the callee's changes to lamports, data and owners back through those pointers,
+//! but a new data length only reaches its own copy of the account info, never the AccountInfo. CPIs that
+//! create or resize accounts still go through solana-program's invoke_signed
+
+use solana_program::{
+	account_info::AccountInfo,
+	entrypoint::ProgramResult,
+	instruction::{AccountMeta, Instruction},
+	program,
+	pubkey::Pubkey,
+};
+
+/// How many accounts a CPI here can have, metas and account infos each. That's how large the arrays
+/// handed to the syscall are, CPIs with more go through solana-program's invoke_signed
+pub const MAX_CPI_ACCOUNTS: usize = 16;
+
+/// solana-program's invoke_signed, with the instruction's accounts and data as slices.
+/// `remaining_accounts` go after both `accounts` and `account_infos`, as writable and signers if they
+/// are in this instruction, like the extra accounts of a transfer hook or a multisig's signers
+pub fn invoke_signed<'a>(
+	program_id: &Pubkey,
+	accounts: &[AccountMeta],
+	data: &[u8],
+	account_infos: &[AccountInfo<'a>],
+	remaining_accounts: &[AccountInfo<'a>],
+	signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+	if accounts.len() + remaining_accounts.len() > MAX_CPI_ACCOUNTS
+		|| account_infos.len() + remaining_accounts.len() > MAX_CPI_ACCOUNTS
+	{
+		return invoke_signed_on_heap(program_id, accounts, data, account_infos, remaining_accounts, signers_seeds);
+	}
+
+	// The borrows solana-program's invoke_signed checks for, the callee can't change accounts that are
+	// borrowed here
+	let metas = accounts
+		.iter()
+		.map(|meta| (&meta.pubkey, meta.is_writable))
+		.chain(remaining_accounts.iter().map(|info| (info.key, info.is_writable)));
+	for (pubkey, is_writable) in metas {
+		for account_info in account_infos.iter().chain(remaining_accounts).filter(|info| info.key == pubkey) {
+			if is_writable {
+				account_info.try_borrow_mut_lamports()?;
+				account_info.try_borrow_mut_data()?;
+			} else {
+				account_info.try_borrow_lamports()?;
+				account_info.try_borrow_data()?;
+			}
+		}
+	}
+	invoke_signed_c(program_id, accounts, data, account_infos, remaining_accounts, signers_seeds)
+}
+
+/// For CPIs with more accounts than fit the arrays, and off-chain where there's no syscall to make
+fn invoke_signed_on_heap<'a>(
+	program_id: &Pubkey,
+	accounts: &[AccountMeta],
+	data: &[u8],
+	account_infos: &[AccountInfo<'a>],
+	remaining_accounts: &[AccountInfo<'a>],
+	signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+	let instruction = Instruction {
+		program_id: *program_id,
+		accounts: accounts
+			.iter()
+			.cloned()
+			.chain(remaining_accounts.iter().map(|info| AccountMeta {
+				pubkey: *info.key,
+				is_signer: info.is_signer,
+				is_writable: info.is_writable,
+			}))
+			.collect(),
+		data: data.to_vec(),
+	};
+	program::invoke_signed(&instruction, &[account_infos, remaining_accounts].concat(), signers_seeds)
+}
+
+#[cfg(not(target_arch = "bpf"))]
+fn invoke_signed_c<'a>(
+	program_id: &Pubkey,
+	accounts: &[AccountMeta],
+	data: &[u8],
+	account_infos: &[AccountInfo<'a>],
+	remaining_accounts: &[AccountInfo<'a>],
+	signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+	invoke_signed_on_heap(program_id, accounts, data, account_infos, remaining_accounts, signers_seeds)
+}
+
+#[cfg(target_arch = "bpf")]
+fn invoke_signed_c<'a>(
+	program_id: &Pubkey,
+	accounts: &[AccountMeta],
+	data: &[u8],
+	account_infos: &[AccountInfo<'a>],
+	remaining_accounts: &[AccountInfo<'a>],
+	signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+	use std::ptr::{null, null_mut};
+
+	/// The syscall's structs, sol/cpi.h's SolInstruction, SolAccountMeta and sol/pubkey.h's SolAccountInfo
+	#[repr(C)]
+	struct SolInstruction {
+		program_id: *const Pubkey,
+		accounts: *const SolAccountMeta,
+		accounts_len: u64,
+		data: *const u8,
+		data_len: u64,
+	}
+
+	#[repr(C)]
+	#[derive(Clone, Copy)]
+	struct SolAccountMeta {
+		pubkey: *const Pubkey,
+		is_writable: bool,
+		is_signer: bool,
+	}
+
+	#[repr(C)]
+	#[derive(Clone, Copy)]
+	struct SolAccountInfo {
+		key: *const Pubkey,
+		lamports: *mut u64,
+		data_len: u64,
+		data: *mut u8,
+		owner: *const Pubkey,
+		rent_epoch: u64,
+		is_signer: bool,
+		is_writable: bool,
+		executable: bool,
+	}
+
+	extern "C" {
+		fn sol_invoke_signed_c(
+			instruction_addr: *const u8,
+			account_infos_addr: *const u8,
+			account_infos_len: u64,
+			signers_seeds_addr: *const u8,
+			signers_seeds_len: u64,
+		) -> u64;
+	}
+
+	let mut metas = [SolAccountMeta { pubkey: null(), is_writable: false, is_signer: false }; MAX_CPI_ACCOUNTS];
+	for (sol_meta, (pubkey, is_writable, is_signer)) in metas.iter_mut().zip(
+		accounts
+			.iter()
+			.map(|meta| (&meta.pubkey, meta.is_writable, meta.is_signer))
+			.chain(remaining_accounts.iter().map(|info| (info.key, info.is_writable, info.is_signer))),
+	) {
+		*sol_meta = SolAccountMeta { pubkey, is_writable, is_signer };
+	}
+
+	let mut infos = [SolAccountInfo {
+		key: null(),
+		lamports: null_mut(),
+		data_len: 0,
+		data: null_mut(),
+		owner: null(),
+		rent_epoch: 0,
+		is_signer: false,
+		is_writable: false,
+		executable: false,
+	}; MAX_CPI_ACCOUNTS];
+	for (sol_info, info) in infos.iter_mut().zip(account_infos.iter().chain(remaining_accounts)) {
+		// Straight at the input the RefCells hold, their borrows were checked already
+		let (lamports, data) = unsafe { (&mut **info.lamports.as_ptr() as *mut u64, &mut **info.data.as_ptr()) };
+		*sol_info = SolAccountInfo {
+			key: info.key,
+			lamports,
+			data_len: data.len() as u64,
+			data: data.as_mut_ptr(),
+			owner: info.owner,
+			rent_epoch: info.rent_epoch,
+			is_signer: info.is_signer,
+			is_writable: info.is_writable,
+			executable: info.executable,
+		};
+	}
+
+	let instruction = SolInstruction {
+		program_id,
+		accounts: metas.as_ptr(),
+		accounts_len: (accounts.len() + remaining_accounts.len()) as u64,
+		data: data.as_ptr(),
+		data_len: data.len() as u64,
+	};
+	// The signer seeds are slices of slices of bytes, laid out like the syscall's SolSignerSeeds
+	let result = unsafe {
+		sol_invoke_signed_c(
+			&instruction as *const _ as *const u8,
+			infos.as_ptr() as *const u8,
+			(account_infos.len() + remaining_accounts.len()) as u64,
+			signers_seeds.as_ptr() as *const u8,
+			signers_seeds.len() as u64,
+		)
+	};
+	match result {
+		solana_program::entrypoint::SUCCESS => Ok(()),
+		_ => Err(result.into()),
+	}
+}
@@ -0,0 +1,162 @@
+// num-derive 0.3's FromPrimitive puts its impl inside a const, which newer compilers warn about. The
+// derives use the deprecated variants like any other, which would warn too
+#![allow(non_local_definitions, deprecated)]
+
+// Using this library so we don't need to implement fmt::Display for our new Error types 
+// (https://doc.rust-lang.org/rust-by-example/error/multiple_error_types/define_error_type.html)
+use thiserror::Error; 
+
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive as _;
+use solana_program::{
+	decode_error::DecodeError,
+	program_error::{PrintProgramError, ProgramError},
+};
+
+#[derive(Error, Debug, Copy, Clone, FromPrimitive)]
+pub enum EscrowError {
+	// Invalid Instruction
+	#[error("Invalid Instruction")]
+	InvalidInstruction,
+	#[error("Not Rent Exempt")]
+	NotRentExempt,
+	#[error("Expected Amount Mismatch")]
+	ExpectedAmountMismatch,
+	#[error("Amount Overflow")]
+	AmountOverflow,
+	#[error("Predecessor Escrow Not Settled")]
+	PredecessorNotSettled,
+	#[error("Escrow Expired")]
+	EscrowExpired,
+	#[error("Escrow Not Expired")]
+	EscrowNotExpired,
+	#[error("Program Paused")]
+	ProgramPaused,
+	#[error("Escrow Frozen")]
+	EscrowFrozen,
+	#[error("Escrow Not Dormant")]
+	EscrowNotDormant,
+	#[error("Slippage Exceeded")]
+	SlippageExceeded,
+	#[error("Token Account Frozen")]
+	TokenAccountFrozen,
+	#[error("Non Transferable Mint")]
+	NonTransferableMint,
+	// The private price mode that returned it is gone. It stays so the codes after it don't move
+	#[deprecated]
+	#[error("Expected Amount Commitment Mismatch")]
+	CommitmentMismatch,
+	#[error("Not An NFT")]
+	NotAnNft,
+	#[error("NFT Not In Collection")]
+	NotInCollection,
+	#[error("Stake Account Not Deactivated")]
+	StakeNotDeactivated,
+	#[error("Token Extension Not Allowed")]
+	ExtensionNotAllowed,
+	#[error("Escrow Not Active")]
+	EscrowNotActive,
+	#[error("Feature Disabled")]
+	FeatureDisabled,
+	#[error("Registry Page Full")]
+	RegistryPageFull,
+	#[error("Initializer Index Full")]
+	IndexFull,
+	#[error("Memo Too Long")]
+	MemoTooLong,
+	#[error("Escrow Seed In Use")]
+	EscrowSeedInUse,
+	// Nothing returns it anymore, the Wrong* errors say which account it was. It stays so the codes
+	// after it don't move
+	#[deprecated]
+	#[error("Invalid Authority Account")]
+	InvalidAuthorityAccount,
+	#[error("Incorrect Account Owner")]
+	IncorrectAccountOwner,
+	#[error("Token Mint Mismatch")]
+	TokenMintMismatch,
+	#[error("Zero Expected Amount")]
+	ZeroExpectedAmount,
+	#[error("Empty Deposit")]
+	EmptyDeposit,
+	#[error("Temp Account Has Delegate")]
+	TempAccountHasDelegate,
+	#[error("Temp Account Has Close Authority")]
+	TempAccountHasCloseAuthority,
+	#[error("Self Trade")]
+	SelfTrade,
+	#[error("Duplicate Account")]
+	DuplicateAccount,
+	#[error("Not Top Level")]
+	NotTopLevel,
+	#[error("Invalid Escrow Length")]
+	InvalidEscrowLength,
+	#[error("Account Closed")]
+	AccountClosed,
+	#[error("Wrong PDA")]
+	WrongPda,
+	#[error("Wrong Vault Account")]
+	WrongVaultAccount,
+	#[error("Wrong Initializer")]
+	WrongInitializer,
+	#[error("Wrong Receive Account")]
+	WrongReceiveAccount,
+	#[error("Wrong Refund Account")]
+	WrongRefundAccount,
+	#[error("Wrong Surplus Account")]
+	WrongSurplusAccount,
+	#[error("Wrong Creator Account")]
+	WrongCreatorAccount,
+	#[error("Wrong Predecessor")]
+	WrongPredecessor,
+	#[error("Wrong Reference Account")]
+	WrongReferenceAccount,
+	#[error("Wrong Group")]
+	WrongGroup,
+	#[error("Wrong Tracker Account")]
+	WrongTrackerAccount,
+	#[error("Royalty Overflow")]
+	RoyaltyOverflow,
+	#[error("Fee Overflow")]
+	FeeOverflow,
+	#[error("Interest Overflow")]
+	InterestOverflow,
+	#[error("Account Not Writable")]
+	AccountNotWritable,
+	#[error("Unsupported Instruction Version")]
+	UnsupportedInstructionVersion,
+	#[error("Wrong Event Authority")]
+	WrongEventAuthority,
+	#[error("Escrow Not Migrated")]
+	EscrowNotMigrated,
+}
+
+impl EscrowError {
+	/// The EscrowError behind `error`, None if it's not a ProgramError::Custom of ours
+	pub fn from_program_error(error: &ProgramError) -> Option<Self> {
+		match error {
+			ProgramError::Custom(code) => EscrowError::from_u32(*code),
+			_ => None,
+		}
+	}
+}
+
+impl From<EscrowError> for ProgramError {
+	fn from(e: EscrowError) -> Self {
+		ProgramError::Custom(e as u32)
+	}
+}
+
+impl<T> DecodeError<T> for EscrowError {
+	fn type_of() -> &'static str {
+		"EscrowError"
+	}
+}
+
+/// Logs the error's message, so explorers show what went wrong instead of just the custom error number.
+/// On the stack, see log.rs
+impl PrintProgramError for EscrowError {
+	fn print<E>(&self) {
+		crate::stack_msg!("Error: {}", self);
+	}
+}
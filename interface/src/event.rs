@@ -0,0 +1,417 @@
+//! What the program logs with sol_log_data when escrows are created, filled and cancelled, so indexers
+//! can follow them from transaction logs instead of scraping msg! strings, which come and go with the
+//! debug-logs feature. Each event is a single "Program data: <base64>" line: an Anchor style event
+//! discriminator, sha256("event:<type name>")[..8], then the fields in declaration order like borsh
+//! has them, so Anchor's event parsers read them too. Like any log, only the events of transactions
+//! that succeeded happened.
+//!
+//! Busy RPC nodes truncate logs, and events logged after the cut are lost. Instructions that bring
+//! event_cpi_accounts at the end get their events as Anchor's emit_cpi! has them instead: the program
+//! invokes itself with the event as the instruction data, signed by its event authority PDA, and the
+//! event is in the transaction's inner instructions, which aren't truncated. The program does
+//! nothing with that instruction but check it came from itself
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+	account_info::AccountInfo,
+	entrypoint::ProgramResult,
+	instruction::AccountMeta,
+	log::sol_log_data,
+	pubkey::Pubkey,
+};
+
+use crate::{
+	cpi,
+	state::{EscrowStatus, DISCRIMINATOR_LEN},
+};
+
+/// Seed for the event authority PDA, the signer of the program's event CPIs. Anchor's
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+/// What the data of an event CPI starts with, in front of the event's discriminator. Anchor's
+/// EVENT_IX_TAG_LE, so its clients find this program's events where they find their own
+pub const EVENT_IX_TAG_LE: [u8; 8] = 0x1d9a_cb51_2ea5_45e4u64.to_le_bytes();
+
+pub fn find_event_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+	Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], program_id)
+}
+
+/// The accounts that go at the end of an instruction for its events to be CPIs, after its trackers and
+/// before Exchange's instructions sysvar
+pub fn event_cpi_accounts(program_id: &Pubkey) -> [AccountMeta; 2] {
+	[
+		AccountMeta::new_readonly(find_event_authority_address(program_id).0, false),
+		AccountMeta::new_readonly(*program_id, false),
+	]
+}
+
+/// Whether `instruction_data` is an event CPI's rather than an EscrowInstruction
+pub fn is_event_cpi(instruction_data: &[u8]) -> bool {
+	instruction_data.starts_with(&EVENT_IX_TAG_LE)
+}
+
+/// An event's fields are packed on the stack behind its discriminator, EscrowFilled's are the most
+const MAX_EVENT_LEN: usize = EscrowFilled::LEN;
+
+pub trait Event: Sized {
+	const DISCRIMINATOR: [u8; 8];
+	const LEN: usize;
+
+	fn pack_into(&self, dst: &mut [u8]);
+
+	fn unpack_from(src: &[u8]) -> Option<Self>;
+
+	/// The discriminator and the fields, what emit logs and EscrowEvent::decode reads. Returns how much
+	/// of `dst` that took
+	fn encode_into(&self, dst: &mut [u8]) -> usize {
+		dst[..DISCRIMINATOR_LEN].copy_from_slice(&Self::DISCRIMINATOR);
+		self.pack_into(&mut dst[DISCRIMINATOR_LEN..DISCRIMINATOR_LEN + Self::LEN]);
+		DISCRIMINATOR_LEN + Self::LEN
+	}
+
+	/// EVENT_IX_TAG_LE and then what encode_into writes, the data of emit_cpi's CPI and what
+	/// EscrowEvent::decode_cpi reads
+	fn encode_cpi_into(&self, dst: &mut [u8]) -> usize {
+		dst[..EVENT_IX_TAG_LE.len()].copy_from_slice(&EVENT_IX_TAG_LE);
+		EVENT_IX_TAG_LE.len() + self.encode_into(&mut dst[EVENT_IX_TAG_LE.len()..])
+	}
+
+	/// Logs the event with sol_log_data, without touching the heap
+	fn emit(&self) {
+		let mut data = [0; DISCRIMINATOR_LEN + MAX_EVENT_LEN];
+		let len = self.encode_into(&mut data);
+		sol_log_data(&[&data[..len]]);
+	}
+
+	/// Emits the event as a CPI of the program into itself, see the top of the file, without touching the
+	/// heap either. `program` is the program's own account, `event_authority` the PDA of
+	/// find_event_authority_address with its bump seed
+	fn emit_cpi<'a>(&self, event_authority: &AccountInfo<'a>, program: &AccountInfo<'a>, bump_seed: u8) -> ProgramResult {
+		let mut data = [0; EVENT_IX_TAG_LE.len() + DISCRIMINATOR_LEN + MAX_EVENT_LEN];
+		let len = self.encode_cpi_into(&mut data);
+		cpi::invoke_signed(
+			program.key,
+			&[AccountMeta::new_readonly(*event_authority.key, true)],
+			&data[..len],
+			&[event_authority.clone(), program.clone()],
+			&[],
+			&[&[EVENT_AUTHORITY_SEED, &[bump_seed]]],
+		)
+	}
+}
+
+/// An escrow was opened by InitEscrow or BatchInitEscrow
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscrowCreated {
+	pub escrow: Pubkey,
+	pub initializer: Pubkey,
+	pub deposit_mint: Pubkey,
+	/// The default pubkey for escrows that take any mint
+	pub receive_mint: Pubkey,
+	pub deposited_amount: u64,
+	pub expected_amount: u64,
+}
+
+impl Event for EscrowCreated {
+	const DISCRIMINATOR: [u8; 8] = [70, 127, 105, 102, 92, 97, 7, 173];
+	const LEN: usize = 4 * 32 + 2 * 8;
+
+	fn pack_into(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, EscrowCreated::LEN];
+		let (escrow, initializer, deposit_mint, receive_mint, deposited_amount, expected_amount) =
+			mut_array_refs![dst, 32, 32, 32, 32, 8, 8];
+		escrow.copy_from_slice(self.escrow.as_ref());
+		initializer.copy_from_slice(self.initializer.as_ref());
+		deposit_mint.copy_from_slice(self.deposit_mint.as_ref());
+		receive_mint.copy_from_slice(self.receive_mint.as_ref());
+		*deposited_amount = self.deposited_amount.to_le_bytes();
+		*expected_amount = self.expected_amount.to_le_bytes();
+	}
+
+	fn unpack_from(src: &[u8]) -> Option<Self> {
+		if src.len() != EscrowCreated::LEN {
+			return None;
+		}
+		let src = array_ref![src, 0, EscrowCreated::LEN];
+		let (escrow, initializer, deposit_mint, receive_mint, deposited_amount, expected_amount) =
+			array_refs![src, 32, 32, 32, 32, 8, 8];
+		Some(EscrowCreated {
+			escrow: Pubkey::new_from_array(*escrow),
+			initializer: Pubkey::new_from_array(*initializer),
+			deposit_mint: Pubkey::new_from_array(*deposit_mint),
+			receive_mint: Pubkey::new_from_array(*receive_mint),
+			deposited_amount: u64::from_le_bytes(*deposited_amount),
+			expected_amount: u64::from_le_bytes(*expected_amount),
+		})
+	}
+}
+
+/// An escrow was filled by Exchange or ExchangeV2. The amounts are what the two sides' token accounts
+/// got, after transfer fees and the program's fee
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscrowFilled {
+	pub escrow: Pubkey,
+	pub initializer: Pubkey,
+	pub taker: Pubkey,
+	pub deposit_mint: Pubkey,
+	pub receive_mint: Pubkey,
+	/// Of the deposit mint
+	pub received_by_taker: u64,
+	/// Of the receive mint
+	pub received_by_initializer: u64,
+}
+
+impl Event for EscrowFilled {
+	const DISCRIMINATOR: [u8; 8] = [89, 73, 149, 182, 70, 91, 46, 23];
+	const LEN: usize = 5 * 32 + 2 * 8;
+
+	fn pack_into(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, EscrowFilled::LEN];
+		let (escrow, initializer, taker, deposit_mint, receive_mint, received_by_taker, received_by_initializer) =
+			mut_array_refs![dst, 32, 32, 32, 32, 32, 8, 8];
+		escrow.copy_from_slice(self.escrow.as_ref());
+		initializer.copy_from_slice(self.initializer.as_ref());
+		taker.copy_from_slice(self.taker.as_ref());
+		deposit_mint.copy_from_slice(self.deposit_mint.as_ref());
+		receive_mint.copy_from_slice(self.receive_mint.as_ref());
+		*received_by_taker = self.received_by_taker.to_le_bytes();
+		*received_by_initializer = self.received_by_initializer.to_le_bytes();
+	}
+
+	fn unpack_from(src: &[u8]) -> Option<Self> {
+		if src.len() != EscrowFilled::LEN {
+			return None;
+		}
+		let src = array_ref![src, 0, EscrowFilled::LEN];
+		let (escrow, initializer, taker, deposit_mint, receive_mint, received_by_taker, received_by_initializer) =
+			array_refs![src, 32, 32, 32, 32, 32, 8, 8];
+		Some(EscrowFilled {
+			escrow: Pubkey::new_from_array(*escrow),
+			initializer: Pubkey::new_from_array(*initializer),
+			taker: Pubkey::new_from_array(*taker),
+			deposit_mint: Pubkey::new_from_array(*deposit_mint),
+			receive_mint: Pubkey::new_from_array(*receive_mint),
+			received_by_taker: u64::from_le_bytes(*received_by_taker),
+			received_by_initializer: u64::from_le_bytes(*received_by_initializer),
+		})
+	}
+}
+
+/// An escrow was closed with its deposit going back, by Cancel, CancelGroup or SweepDormant (Cancelled)
+/// or by CrankExpired (Expired)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscrowCancelled {
+	pub escrow: Pubkey,
+	pub initializer: Pubkey,
+	/// Where the deposit went
+	pub refund: Pubkey,
+	pub deposit_mint: Pubkey,
+	pub refunded_amount: u64,
+	pub status: EscrowStatus,
+}
+
+impl Event for EscrowCancelled {
+	const DISCRIMINATOR: [u8; 8] = [98, 241, 195, 122, 213, 0, 162, 161];
+	const LEN: usize = 4 * 32 + 8 + 1;
+
+	fn pack_into(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, EscrowCancelled::LEN];
+		let (escrow, initializer, refund, deposit_mint, refunded_amount, status) =
+			mut_array_refs![dst, 32, 32, 32, 32, 8, 1];
+		escrow.copy_from_slice(self.escrow.as_ref());
+		initializer.copy_from_slice(self.initializer.as_ref());
+		refund.copy_from_slice(self.refund.as_ref());
+		deposit_mint.copy_from_slice(self.deposit_mint.as_ref());
+		*refunded_amount = self.refunded_amount.to_le_bytes();
+		status[0] = self.status as u8;
+	}
+
+	fn unpack_from(src: &[u8]) -> Option<Self> {
+		if src.len() != EscrowCancelled::LEN {
+			return None;
+		}
+		let src = array_ref![src, 0, EscrowCancelled::LEN];
+		let (escrow, initializer, refund, deposit_mint, refunded_amount, status) =
+			array_refs![src, 32, 32, 32, 32, 8, 1];
+		Some(EscrowCancelled {
+			escrow: Pubkey::new_from_array(*escrow),
+			initializer: Pubkey::new_from_array(*initializer),
+			refund: Pubkey::new_from_array(*refund),
+			deposit_mint: Pubkey::new_from_array(*deposit_mint),
+			refunded_amount: u64::from_le_bytes(*refunded_amount),
+			status: EscrowStatus::from_u8(status[0]).ok()?,
+		})
+	}
+}
+
+/// Any of the program's events, for indexers going through logs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscrowEvent {
+	Created(EscrowCreated),
+	Filled(EscrowFilled),
+	Cancelled(EscrowCancelled),
+}
+
+impl EscrowEvent {
+	/// The event in a sol_log_data field, the base64 decoded part of a "Program data: " log line. None
+	/// if it's not one of this program's
+	pub fn decode(data: &[u8]) -> Option<Self> {
+		if data.len() < DISCRIMINATOR_LEN {
+			return None;
+		}
+		let (discriminator, fields) = data.split_at(DISCRIMINATOR_LEN);
+		match *array_ref![discriminator, 0, DISCRIMINATOR_LEN] {
+			EscrowCreated::DISCRIMINATOR => EscrowCreated::unpack_from(fields).map(EscrowEvent::Created),
+			EscrowFilled::DISCRIMINATOR => EscrowFilled::unpack_from(fields).map(EscrowEvent::Filled),
+			EscrowCancelled::DISCRIMINATOR => EscrowCancelled::unpack_from(fields).map(EscrowEvent::Cancelled),
+			_ => None,
+		}
+	}
+
+	/// The event of an event CPI, the data of one of the program's inner instructions. None if it's
+	/// not an event CPI or not one of this program's events
+	pub fn decode_cpi(instruction_data: &[u8]) -> Option<Self> {
+		match is_event_cpi(instruction_data) {
+			true => Self::decode(&instruction_data[EVENT_IX_TAG_LE.len()..]),
+			false => None,
+		}
+	}
+
+	/// The program's events in the log messages of a transaction, in the order they were logged. Only
+	/// the "Program data: " lines of `program_id` itself count, any program can log those. Events
+	/// behind a "Log truncated" and those of event CPIs aren't in the logs
+	#[cfg(all(feature = "client", not(target_arch = "bpf")))]
+	pub fn from_logs<S: AsRef<str>>(logs: &[S], program_id: &Pubkey) -> Vec<Self> {
+		let program_id = program_id.to_string();
+		// The programs being invoked, the last one is logging. Logged as "Program <id> invoke [<depth>]"
+		// and "Program <id> success" or "Program <id> failed: <error>"
+		let mut invoked: Vec<&str> = Vec::new();
+		let mut events = Vec::new();
+		for line in logs {
+			let line = line.as_ref();
+			if let Some(data) = line.strip_prefix("Program data: ") {
+				let decoded = match (invoked.last(), data.split_once(' ')) {
+					(Some(&program), None) if program == program_id => base64::decode(data).ok(),
+					_ => None,
+				};
+				events.extend(decoded.as_deref().and_then(EscrowEvent::decode));
+				continue;
+			}
+			let mut words = line.split(' ');
+			match (words.next(), words.next(), words.next()) {
+				(Some("Program"), Some(program), Some("invoke")) => invoked.push(program),
+				(Some("Program"), Some(program), Some("success" | "failed:")) if invoked.last() == Some(&program) => {
+					invoked.pop();
+				},
+				_ => {},
+			}
+		}
+		events
+	}
+
+	pub fn escrow(&self) -> &Pubkey {
+		match self {
+			EscrowEvent::Created(event) => &event.escrow,
+			EscrowEvent::Filled(event) => &event.escrow,
+			EscrowEvent::Cancelled(event) => &event.escrow,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use solana_program::hash::hash;
+
+	fn events() -> [EscrowEvent; 3] {
+		[
+			EscrowEvent::Created(EscrowCreated {
+				escrow: Pubkey::new_unique(),
+				initializer: Pubkey::new_unique(),
+				deposit_mint: Pubkey::new_unique(),
+				receive_mint: Pubkey::default(),
+				deposited_amount: 1_000,
+				expected_amount: 2_000,
+			}),
+			EscrowEvent::Filled(EscrowFilled {
+				escrow: Pubkey::new_unique(),
+				initializer: Pubkey::new_unique(),
+				taker: Pubkey::new_unique(),
+				deposit_mint: Pubkey::new_unique(),
+				receive_mint: Pubkey::new_unique(),
+				received_by_taker: 999,
+				received_by_initializer: 1_998,
+			}),
+			EscrowEvent::Cancelled(EscrowCancelled {
+				escrow: Pubkey::new_unique(),
+				initializer: Pubkey::new_unique(),
+				refund: Pubkey::new_unique(),
+				deposit_mint: Pubkey::new_unique(),
+				refunded_amount: 1_000,
+				status: EscrowStatus::Expired,
+			}),
+		]
+	}
+
+	/// What emit logs with sol_log_data
+	fn logged(event: &EscrowEvent) -> Vec<u8> {
+		let mut data = vec![0; DISCRIMINATOR_LEN + MAX_EVENT_LEN];
+		let len = match event {
+			EscrowEvent::Created(event) => event.encode_into(&mut data),
+			EscrowEvent::Filled(event) => event.encode_into(&mut data),
+			EscrowEvent::Cancelled(event) => event.encode_into(&mut data),
+		};
+		data.truncate(len);
+		data
+	}
+
+	/// The data of emit_cpi's CPI
+	fn invoked_with(event: &EscrowEvent) -> Vec<u8> {
+		let mut data = vec![0; EVENT_IX_TAG_LE.len() + DISCRIMINATOR_LEN + MAX_EVENT_LEN];
+		let len = match event {
+			EscrowEvent::Created(event) => event.encode_cpi_into(&mut data),
+			EscrowEvent::Filled(event) => event.encode_cpi_into(&mut data),
+			EscrowEvent::Cancelled(event) => event.encode_cpi_into(&mut data),
+		};
+		data.truncate(len);
+		data
+	}
+
+	#[test]
+	fn discriminators_are_anchors() {
+		let discriminator = |name: &str| hash(format!("event:{}", name).as_bytes()).to_bytes()[..8].to_vec();
+		assert_eq!(EscrowCreated::DISCRIMINATOR.to_vec(), discriminator("EscrowCreated"));
+		assert_eq!(EscrowFilled::DISCRIMINATOR.to_vec(), discriminator("EscrowFilled"));
+		assert_eq!(EscrowCancelled::DISCRIMINATOR.to_vec(), discriminator("EscrowCancelled"));
+	}
+
+	#[test]
+	fn decode_reads_what_emit_logs() {
+		for event in events() {
+			let data = logged(&event);
+			assert_eq!(EscrowEvent::decode(&data), Some(event));
+			// Cut short or with more than the fields, or behind another discriminator, it's not an event
+			assert_eq!(EscrowEvent::decode(&data[..data.len() - 1]), None);
+			assert_eq!(EscrowEvent::decode(&[&data[..], &[0]].concat()), None);
+			assert_eq!(EscrowEvent::decode(&[&[0; DISCRIMINATOR_LEN][..], &data[DISCRIMINATOR_LEN..]].concat()), None);
+		}
+		assert_eq!(EscrowEvent::decode(&[]), None);
+	}
+
+	#[test]
+	fn decode_cpi_reads_what_emit_cpi_invokes_with() {
+		for event in events() {
+			let data = invoked_with(&event);
+			assert!(data.starts_with(&EVENT_IX_TAG_LE));
+			assert_eq!(&data[EVENT_IX_TAG_LE.len()..], logged(&event).as_slice());
+			assert!(is_event_cpi(&data));
+			assert_eq!(EscrowEvent::decode_cpi(&data), Some(event));
+			// The logged event isn't a CPI's, and a CPI's doesn't decode as a logged one
+			assert_eq!(EscrowEvent::decode_cpi(&logged(&event)), None);
+			assert_eq!(EscrowEvent::decode(&data), None);
+			assert_eq!(EscrowEvent::decode_cpi(&data[..data.len() - 1]), None);
+		}
+		assert_eq!(EscrowEvent::decode_cpi(&EVENT_IX_TAG_LE), None);
+		assert_eq!(EscrowEvent::decode_cpi(&[]), None);
+	}
+}
@@ -0,0 +1,2792 @@
+use std::convert::TryInto;
+use escrow_idl_derive::{ShankInstruction, ShankType};
+use solana_program::{
+	bpf_loader_upgradeable,
+	instruction::{AccountMeta, Instruction},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+	system_program, sysvar,
+};
+
+use crate::{
+	check_program_account,
+	error::EscrowError::{InvalidInstruction, UnsupportedInstructionVersion},
+	state::{Config, Escrow, ExtensionPolicy},
+	token::{check_token_program_id, MAX_SIGNERS},
+	treasury::{find_treasury_address, REFUND_LISTING_FEE_ON_EXCHANGE},
+};
+
+#[cfg(feature = "borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, ShankInstruction)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EscrowInstruction {
+	/// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the
+    /// escrow's PDA [b"escrow", escrow]
+    /// Fails or logs a warning, depending on the config's extension policy, if either mint has a permanent
+    /// delegate or the temp token account has its CPI guard on
+    ///
+    /// The initializer's index, the registry page and the stats account go after all the other accounts
+    /// in that order, if they exist (see InitializeIndex, InitializeRegistryPage and InitializeStats)
+    /// The rent and the time come from Rent::get and Clock::get, older clients that still pass the rent
+    /// sysvar as account 4 (and everything after it one further) or the clock sysvar after the config
+    /// account keep working
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person initializing the escrow, pays the listing fee
+    /// 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer,
+    ///    without a delegate or close authority
+    /// 2. `[]` The initializer's token account for the token they will receive should the trade go through
+    /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade. Exactly Escrow::LEN long
+    ///    (see `memo` otherwise) and owned by this program.
+    ///    The uninitialized PDA [b"escrow-account", account 0, seed] if `escrow_seed` is set
+    /// 4. `[]` The token program owning the temp token account, spl-token or Token-2022
+    /// 5. `[writable]` The treasury PDA that collects the listing fee
+    /// 6. `[]` The system program
+    /// 7. `[writable]` The config account, only written to when there's a listing fee
+    /// 8. `[]` The standby config account, only if the config has one
+    /// 9. `[signer, writable]` The rent payer, only if `rent_payer` is set
+    /// 10. `[]` The native mint, only if `wrap_sol_deposit` is set. `[writable]` The initializer's token
+    ///     account the vault is funded from instead, only if `vault_deposit` is set
+    /// 11. `[]` The deposited NFT's mint, only if `nft` is set
+    /// 12. `[writable]` The NFT's Metaplex metadata account, the PDA [b"metadata", metadata program, mint], only if `nft` is set
+    /// 13. `[]` The mint of account 1's token, only for plain token deposits (no SOL, NFTs or stake accounts).
+    ///     With `vault_deposit` it's followed by its transfer hook accounts (see Exchange)
+    /// 14. `[]` The mint of account 2's token, not for collection offers. The escrow keeps both mints' decimals
+    /// 15. `[]` The predecessor escrow account, only if `predecessor` is set
+    /// 16. `[writable]` The spl-token multisig owning account 1 followed by `multisig_signers` `[signer]`
+    ///     accounts of its signers, only if `multisig_signers` isn't 0
+    /// 17. `[writable]` Optional, an EscrowGroup owned by the initializer to attach this escrow to
+    ///
+    /// A programmable NFT (pNFT) can't be deposited by handing over its token account, which stays frozen.
+    /// Account 1 is the escrow PDA's associated token account for the mint then, which Token Metadata
+    /// moves the NFT into, and these go right after the metadata account:
+    ///
+    /// * `[writable]` The initializer's token account holding the pNFT
+    /// * `[]` The PDA account
+    /// * `[]` The pNFT's master edition
+    /// * `[writable]` The token record of the initializer's token account
+    /// * `[writable]` The token record of account 1
+    /// * `[]` The instructions sysvar
+    /// * `[]` The associated token program
+    /// * `[]` The system program
+    /// * `[]` The Token Metadata program
+    /// * `[]` The rule set's program and `[]` the rule set, only if the pNFT has one
+	#[account(0, writable, signer, name = "initializer", desc = "Pays the listing fee")]
+	#[account(1, writable, name = "temp_token_account", desc = "The funded temp token account, or what holds the deposit for other kinds of deposits")]
+	#[account(2, name = "token_to_receive_account", desc = "The initializer's token account for the token they receive")]
+	#[account(3, writable, name = "escrow", desc = "The escrow account, owned by this program or the PDA of escrow_seed")]
+	#[account(4, name = "token_program", desc = "The token program owning the temp token account")]
+	#[account(5, writable, name = "treasury", desc = "The treasury PDA that collects the listing fee")]
+	#[account(6, name = "system_program", desc = "The system program")]
+	#[account(7, writable, name = "config", desc = "The config account")]
+	#[account(8, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	#[account(9, writable, signer, optional, name = "rent_payer", desc = "Pays for the accounts the program creates, with rent_payer")]
+	#[account(10, writable, optional, name = "native_mint_or_deposit_source", desc = "The native mint with wrap_sol_deposit, the account funding the vault with vault_deposit")]
+	#[account(11, optional, name = "nft_mint", desc = "The deposited NFT's mint, with nft")]
+	#[account(12, writable, optional, name = "nft_metadata", desc = "The NFT's Metaplex metadata account, with nft")]
+	#[account(13, optional, name = "deposit_mint", desc = "The mint of the temp token account's token, for plain token deposits")]
+	#[account(14, optional, name = "receive_mint", desc = "The mint of the token to receive, not for collection offers")]
+	#[account(15, optional, name = "predecessor", desc = "The predecessor escrow account, with predecessor")]
+	#[account(16, writable, optional, name = "multisig", desc = "The multisig owning the temp token account, followed by its signers, with multisig_signers")]
+	#[account(17, writable, optional, name = "group", desc = "An EscrowGroup of the initializer to attach the escrow to")]
+	InitEscrow {
+		/// The amount party A expects to receive of token Y, more than 0
+		amount: u64,
+		/// An open escrow with `retain_history` that has to be filled before this one can be exchanged.
+		/// Cancelling it, or closing its record before this one is filled, leaves this one unfillable
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey::option"))]
+		predecessor: Option<Pubkey>,
+		/// Where Cancel sends the deposit and the reclaimed rent and Exchange the rent and the SOL the escrow
+		/// asks for, the initializer if None. Escrows of a multisig need one that isn't the multisig
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey::option"))]
+		refund_address: Option<Pubkey>,
+		/// Unix timestamp the escrow expires at, after which it can't be exchanged and anyone can
+		/// close it with CrankExpired. Never expires if None
+		expires_at: Option<i64>,
+		/// Deposit this many lamports instead of tokens, so the other side gets raw SOL. Account 1 is the
+		/// escrow's SOL vault (the PDA [b"sol-vault", escrow]) then and account 4 the system program
+		native_sol_deposit: Option<u64>,
+		/// Wrap this many lamports into wSOL for the deposit, so the initializer doesn't have to. Account 1
+		/// is then the uninitialized PDA [b"wsol", escrow], which the program creates as a wSOL account
+		/// already owned by the escrow PDA, account 4 has to be spl-token and the native mint comes after
+		/// the standby config account. Can't be combined with `native_sol_deposit`
+		wrap_sol_deposit: Option<u64>,
+		/// List an NFT: the temp token account has to hold exactly 1 token of a 0 decimals mint with
+		/// Metaplex metadata, which gets checked against the mint. Can't be combined with SOL deposits
+		nft: bool,
+		/// Make this a collection offer, a standing bid any holder of an NFT in this verified Metaplex
+		/// collection can fill. `amount` has to be 1 and account 2 isn't used (pass the initializer),
+		/// the NFT goes to the initializer's associated token account for its mint. pNFTs can't fill these
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey::option"))]
+		collection: Option<Pubkey>,
+		/// Deposit a compressed NFT instead of tokens, moved to the escrow PDA in its Bubblegum tree. See
+		/// CompressedNft for the accounts. Can't be combined with the other kinds of deposits
+		compressed_nft: Option<CompressedNft>,
+		/// How many signers of a multisig owning the temp token account sign for it, 0 if account 0 owns it.
+		/// The multisig becomes the escrow's initializer then, account 0 only pays. Only for token
+		/// deposits (NFTs too, not pNFTs), the escrow can't be attached to a group and `refund_address` has to be set
+		multisig_signers: u8,
+		/// Lamports to put in on top of the deposit, e.g. an NFT plus 2 SOL. They're kept in the escrow
+		/// account and paid to the taker along with the deposit
+		lamports_deposit: Option<u64>,
+		/// Lamports the taker has to pay on top of `amount`, e.g. 100 USDC plus 2 SOL for an NFT
+		expected_lamports: Option<u64>,
+		/// Deposit a deactivated stake account, worth its lamports. Account 1 is the stake account then,
+		/// with account 0 as both its staker and withdrawer, and account 4 the stake program. After the
+		/// standby config account come `[]` the clock sysvar and `[signer]` the lockup's custodian if the
+		/// lockup is in force, the stake program wants its signature to change the withdrawer then.
+		/// Can't be combined with the other kinds of deposits
+		stake_account: bool,
+		/// Deposit this many tokens into a vault the program creates, so there's no temp token account to
+		/// create and fund first. Account 1 is then the uninitialized PDA [b"vault", escrow], which the
+		/// program creates as a token account of account 13's mint already owned by the escrow PDA and
+		/// funds from account 10. Only for plain token deposits and not with a multisig
+		vault_deposit: Option<u64>,
+		/// Have the program create the escrow account at the PDA [b"escrow-account", account 0, seed],
+		/// paid by account 0, instead of passing in one created beforehand. Clients can work out the
+		/// address from the seed alone (see Escrow::find_address). A seed that's still in use by an
+		/// escrow or its record fails with EscrowSeedInUse, so one initializer can open many escrows by
+		/// counting seeds up without mixing them up
+		escrow_seed: Option<u64>,
+		/// Keep the escrow account around once it's settled, marked Completed, Cancelled or Expired
+		/// (see EscrowStatus) instead of closing it, as an on-chain record of the trade. It keeps just its
+		/// rent, which comes back with CloseEscrowRecord. CrankExpired pays no bounty for these, there's
+		/// nothing above the rent to pay it from
+		retain_history: bool,
+		/// A label for the deal, like "Q3 treasury swap", at most MAX_MEMO_LEN bytes of UTF-8. It goes
+		/// behind the escrow (see Escrow::memo), so account 3 has to be created Escrow::len_with_memo long.
+		/// With `escrow_seed` the program sizes it. Encoded as a u8 length and the text, not borsh's u32 length
+		/// (unless the instruction is borsh, see BORSH_TAG_FLAG)
+		memo: Option<String>,
+		/// Someone else (account 9, like a dapp's sponsor wallet) pays for the accounts the program
+		/// creates: the escrow account with `escrow_seed`, the vaults, the wSOL account and a pNFT's token
+		/// account and record. The initializer still pays the deposit and the listing fee and stays the
+		/// escrow's authority. The rent comes back to the refund address like it always does
+		rent_payer: bool,
+		/// Exchange fails with SelfTrade if the taker is the initializer or the refund address, or pays
+		/// from the initializer's receive account (see Escrow::prevent_self_trade)
+		prevent_self_trade: bool,
+	},
+
+	/// Accepts a trade. Like InitEscrow the config's extension policy applies to both mints and the
+	/// taker's sending account
+	/// The initializer's index, the registry page, the pair stats account of the escrow's mints and the
+	/// stats account go after all the other accounts in that order, if they exist (see InitializeIndex,
+	/// InitializeRegistryPage, InitializePairStats and InitializeStats). The instructions sysvar goes
+	/// after those when the config only allows top level exchanges (see SetTopLevelExchange)
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The account of the person taking the trade, pays for receive accounts that don't exist yet
+	/// 1. `[writable]` The taker's token account for the token they send 
+	/// 2. `[writable]` The taker's token account for the token they will receive should the trade go through
+	/// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
+	/// 4. `[writable]` The escrow's refund address (the initializer unless set otherwise at init), gets the
+	///    escrow's rent back. If account 5 gets created it has to be the initializer
+	/// 5. `[writable]` The initializer's token account that will receive tokens
+	/// 6. `[writable]` The escrow account holding the escrow info
+	/// 7. `[]` The token program of the deposited token, spl-token or Token-2022
+	/// 8. `[]` The escrow's PDA account, [b"escrow", escrow account]
+	/// 9. `[writable]` The config account, to check the program isn't paused. Only written to when the listing fee is refunded
+	/// 10. `[]` The token program of the token the taker sends, spl-token or Token-2022
+	///
+	/// Followed by these, in order, only when they apply to the escrow:
+	///
+	/// * `[]` The mint of the token the taker sends, every token movement is a transfer_checked. For
+	///   Token-2022 mints with a transfer hook it's followed by the hook program, the hook's extra account
+	///   metas account ([b"extra-account-metas", mint] of the hook program) and the extra accounts listed there
+	/// * `[]` For collection offers, the metadata account of the NFT the taker sends. Account 5 is the
+	///   initializer's associated token account for that mint then
+	/// * `[writable]` The deposited token's mint, the same way. Not for native SOL or compressed NFT escrows
+	/// * `[]` The associated token account program and `[]` the system program, if account 2 or account 5
+	///   doesn't exist yet. It gets created as the taker's associated token account for the deposited
+	///   token or the initializer's for the token the taker sends. Not account 2 when the taker doesn't get
+	///   a token account (native SOL, unwrapped wSOL, compressed NFTs, stake accounts), pNFTs are Token
+	///   Metadata's business
+	/// * For pNFT escrows, `[writable]` the pNFT's metadata account and the accounts InitEscrow takes after
+	///   it from the master edition on, with the token records of account 3 and account 2. Account 2 is the
+	///   taker's associated token account for the pNFT, Token Metadata creates it if needed
+	/// * For compressed NFT escrows, the Bubblegum accounts (see CompressedNft). Account 3 is the tree then,
+	///   account 7 the Bubblegum program and account 2 the cNFT's new owner
+	/// * For stake account escrows, `[]` the clock sysvar and `[signer]` the lockup's custodian if the
+	///   lockup is in force. Account 3 is the stake account then, account 7 the stake program and
+	///   account 2 the stake account's new staker and withdrawer
+	/// * For other NFT escrows, `[]` the NFT's metadata account
+	/// * `[writable]` For NFT escrows, the associated token account for the token the taker sends of each
+	///   creator with a share, in metadata order. They get their royalty (seller_fee_basis_points of the
+	///   expected amount, split by share) out of what the taker pays and the initializer gets the rest
+	/// * `[]` The system program if the escrow asks for lamports on top of the tokens. The taker pays
+	///   them to account 4, and gets the lamports the initializer put in on top of the deposit
+	/// * `[writable]` The treasury PDA, refunds the initializer's listing fee
+	/// * `[]` The system program, for the listing fee refund
+	/// * `[]` The predecessor escrow account if the escrow is chained to one
+	/// * `[]` The `reference` account if one was given, per the Solana Pay spec it's only there so the
+	///   transaction can be found by looking up signatures for that key
+	/// * `[writable]` The escrow's group if it's in one (see CreateGroup), the escrow leaves it
+	/// * `[writable]` A token account of the initializer for the deposited token, if the temp token account
+	///   holds more than the recorded deposit. The surplus goes back there
+	/// * `[writable]` The receipt PDA [b"receipt", escrow], `[]` the system program and `[]` the rent
+	///   sysvar, only if `receipt` is set
+	///
+	/// For native SOL escrows account 3 is the escrow's SOL vault, account 7 the system program and
+	/// account 2 any account to pay the lamports to. The vault's rent goes to account 4 with the rest
+	///
+	/// Amounts of interest-bearing mints (`amount`, the escrow's expected amount and the slippage bounds
+	/// on what the taker gets) are UI amounts with the interest so far, in raw units. The time comes from
+	/// Clock::get, older clients that still pass the clock sysvar before the system program keep working
+	#[account(0, writable, signer, name = "taker", desc = "Pays for receive accounts that don't exist yet")]
+	#[account(1, writable, name = "takers_sending_account", desc = "The taker's token account for the token they send")]
+	#[account(2, writable, name = "takers_token_to_receive_account", desc = "The taker's token account for the deposited token")]
+	#[account(3, writable, name = "temp_token_account", desc = "The escrow's temp token account, or what holds the deposit")]
+	#[account(4, writable, name = "refund_address", desc = "The escrow's refund address, gets the escrow's rent back")]
+	#[account(5, writable, name = "initializers_token_to_receive_account", desc = "The initializer's token account that receives the taker's tokens")]
+	#[account(6, writable, name = "escrow", desc = "The escrow account")]
+	#[account(7, name = "token_program", desc = "The token program of the deposited token")]
+	#[account(8, name = "pda", desc = "The escrow's PDA")]
+	#[account(9, writable, name = "config", desc = "The config account")]
+	#[account(10, name = "sending_token_program", desc = "The token program of the token the taker sends")]
+	Exchange {
+		/// the amount the taker expects to be paid in the other token
+		amount: u64,
+		/// Solana Pay reference key, see the program's solana_pay
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey::option"))]
+		reference: Option<Pubkey>,
+		/// Most the taker is willing to send, checked against what the escrow asks for
+		max_amount_in: Option<u64>,
+		/// Least the taker is willing to receive, checked against what the escrow pays out
+		min_amount_out: Option<u64>,
+		/// Unwrap a wSOL deposit, so the taker gets lamports instead. Account 2 is then any account to
+		/// pay them to rather than a token account
+		unwrap_sol: bool,
+		/// For compressed NFT escrows, the tree's root the proof was made against and how many proof
+		/// accounts there are (see CompressedNft)
+		compressed_nft_proof: Option<CompressedNftProof>,
+		/// Write a TradeReceipt of the fill, paid for by the taker. There's one receipt per escrow address,
+		/// so it can't be set again for an escrow re-created at an address that already has one
+		receipt: bool,
+	},
+
+	/// Cancels an escrow, giving the deposited tokens back to the escrow's refund address and closing the escrow
+	/// The initializer's index, the registry page and the stats account go after all the other accounts
+	/// in that order, if they exist (see InitializeIndex, InitializeRegistryPage and InitializeStats)
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The initializer of the escrow. For escrows of a multisig it's the multisig, which
+	///    doesn't sign, and enough of its signers come `[signer]` after all the other accounts
+	/// 1. `[writable]` The PDA's temp token account to return tokens from and eventually close
+	/// 2. `[writable]` A token account owned by the refund address that gets the deposited tokens back
+	/// 3. `[writable]` The escrow account holding the escrow info
+	/// 4. `[]` The token program
+	/// 5. `[]` The PDA account
+	/// 6. `[writable]` The refund address set at init (the initializer by default), receives the reclaimed rent
+	/// 7. `[writable]` The escrow's EscrowGroup, only if it was attached to one
+	/// 8. `[writable]` The deposited token's mint and its transfer hook accounts (see Exchange), not for native SOL or compressed NFT escrows
+	/// 9. .. For pNFT escrows the pNFT accounts like in Exchange, with account 2 being the refund address's
+	///    associated token account. CrankExpired and SweepDormant take these too
+	///
+	/// For native SOL escrows account 1 is the SOL vault, account 2 the refund address again and account 4 the system program
+	///
+	/// For compressed NFT escrows account 1 is the merkle tree, account 2 the refund address again, account
+	/// 4 the Bubblegum program and the Bubblegum accounts (see CompressedNft) go last
+	///
+	/// For stake account escrows account 1 is the stake account, account 2 the refund address again,
+	/// account 4 the stake program and the stake accounts like in Exchange go last
+	///
+	/// Escrows that paid a listing fee take `[writable]` the primary config account after all of those and
+	/// before a multisig's signers, the treasury keeps the fee and stops holding it back for a refund
+	#[account(0, signer, name = "initializer", desc = "The initializer, or their multisig followed by its signers at the end")]
+	#[account(1, writable, name = "temp_token_account", desc = "The escrow's temp token account, or what holds the deposit")]
+	#[account(2, writable, name = "refund_token_account", desc = "The refund address's token account for the deposit")]
+	#[account(3, writable, name = "escrow", desc = "The escrow account")]
+	#[account(4, name = "token_program", desc = "The token program of the deposited token")]
+	#[account(5, name = "pda", desc = "The escrow's PDA")]
+	#[account(6, writable, name = "refund_address", desc = "The escrow's refund address, gets the rent back")]
+	#[account(7, writable, optional, name = "group", desc = "The escrow's EscrowGroup, if it's in one")]
+	#[account(8, writable, optional, name = "deposit_mint", desc = "The deposited token's mint, for token deposits")]
+	Cancel {
+		/// Same as in Exchange
+		compressed_nft_proof: Option<CompressedNftProof>,
+	},
+
+	/// Creates an EscrowGroup PDA that escrows can be attached to at init and cancelled through as one unit
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The maker, pays for the group account
+	/// 1. `[writable]` The group account, the PDA [b"group", maker, name]
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	#[account(0, writable, signer, name = "maker", desc = "Pays for the group account")]
+	#[account(1, writable, name = "group", desc = "The group PDA [b\"group\", maker, name]")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	CreateGroup {
+		/// Name of the group, zero padded
+		name: [u8; 32]
+	},
+
+	/// Cancels every escrow of a group passed in. The group account is closed once no escrows are left in it
+	/// The initializer's index, the registry page and the stats account go after all the other accounts
+	/// in that order, if they exist (see InitializeIndex, InitializeRegistryPage and InitializeStats)
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The maker of the group, receives the group account's rent
+	/// 1. `[writable]` The group account
+	/// 2. `[]` The token program, shared by the temp token accounts of all escrows passed in. The system
+	///    program for native SOL escrows, Bubblegum for compressed NFT escrows and the stake program for
+	///    stake account escrows, so each of those kinds is cancelled apart from the others
+	/// 3. .. Repeated for each escrow of the group being cancelled, like accounts 3, 1, 2, 6 and 5 of Cancel:
+	///    * `[writable]` The escrow account
+	///    * `[writable]` The escrow's temp token account, or what holds the deposit
+	///    * `[writable]` A token account owned by the escrow's refund address that gets the deposited tokens back
+	///    * `[writable]` The escrow's refund address, receives the reclaimed rent
+	///    * `[]` The escrow's PDA account
+	///    * `[writable]` The deposited token's mint and its transfer hook accounts (see Exchange), not for native SOL or compressed NFT escrows
+	///    * The pNFT, compressed NFT or stake accounts Cancel takes for the escrow, the maker pays for the pNFT's
+	///    * `[writable]` The primary config account, only if the escrow paid a listing fee (see Cancel)
+	#[account(0, writable, signer, name = "maker", desc = "The group's maker, gets the group account's rent back")]
+	#[account(1, writable, name = "group", desc = "The group account")]
+	#[account(2, name = "token_program", desc = "The token program of all the escrows passed in")]
+	CancelGroup {
+		/// Cancel's proofs of the compressed NFT escrows passed in, in the same order. Encoded as a u8 count
+		/// and the proofs, like BatchInitEscrow's amounts
+		compressed_nft_proofs: Vec<CompressedNftProof>,
+	},
+
+	/// Same as InitEscrow but for many escrows at once from one initializer, so market makers can
+	/// list a bunch of quotes in a single transaction. Batched escrows can't be attached to a group
+	/// The initializer's index, the registry page and the stats account go after all the other accounts
+	/// in that order, if they exist (see InitializeIndex, InitializeRegistryPage and InitializeStats)
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The account of the person initializing the escrows, pays the listing fees
+	/// 1. `[]` The token program, shared by all the temp token accounts
+	/// 2. `[writable]` The treasury PDA that collects the listing fees
+	/// 3. `[]` The system program
+	/// 4. `[writable]` The config account, only written to when there's a listing fee
+	/// 5. `[]` The standby config account, only if the config has one
+	/// 6. ..6+5N Repeated for each escrow, in the same order as `amounts`:
+	///    * `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer
+	///    * `[]` The initializer's token account for the token they will receive should the trade go through
+	///    * `[writable]` The escrow account
+	///    * `[]` The mint of the temp token account's token
+	///    * `[]` The mint of the token to receive
+	#[account(0, writable, signer, name = "initializer", desc = "Pays the listing fees")]
+	#[account(1, name = "token_program", desc = "The token program of all the temp token accounts")]
+	#[account(2, writable, name = "treasury", desc = "The treasury PDA that collects the listing fees")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	#[account(4, writable, name = "config", desc = "The config account")]
+	#[account(5, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	BatchInitEscrow {
+		/// The amount party A expects to receive of token Y, for each escrow. Encoded as a u8 count and the
+		/// amounts, not borsh's u32 count (unless the instruction is borsh, see BORSH_TAG_FLAG)
+		amounts: Vec<u64>
+	},
+
+	/// Creates the program's config account. Only the program's upgrade authority can do this
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The program's upgrade authority, pays for the config account
+	/// 1. `[writable]` The config account, the PDA [b"config"]
+	/// 2. `[]` The program's ProgramData account
+	/// 3. `[]` The rent sysvar
+	/// 4. `[]` The system program
+	#[account(0, writable, signer, name = "upgrade_authority", desc = "The program's upgrade authority, pays for the config account")]
+	#[account(1, writable, name = "config", desc = "The config PDA [b\"config\"]")]
+	#[account(2, name = "program_data", desc = "The program's ProgramData account")]
+	#[account(3, name = "rent", desc = "The rent sysvar")]
+	#[account(4, name = "system_program", desc = "The system program")]
+	InitializeConfig {
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+		admin: Pubkey,
+		listing_fee_lamports: u64,
+		/// Seconds without a heartbeat from the admin before a standby config takes over, 0 means never
+		heartbeat_period: i64,
+		crank_bounty_lamports: u64,
+		/// Seconds of initializer inactivity before SweepDormant can close an escrow, 0 means never
+		dormancy_period: i64,
+	},
+
+	/// Creates the standby config the primary config fails over to, managed by its own admin
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The admin of the primary config, pays for the standby config account
+	/// 1. `[writable]` The primary config account
+	/// 2. `[writable]` The standby config account, the PDA [b"config", b"standby"]
+	/// 3. `[]` The rent sysvar
+	/// 4. `[]` The system program
+	#[account(0, writable, signer, name = "admin", desc = "The primary config's admin, pays for the standby config account")]
+	#[account(1, writable, name = "config", desc = "The primary config account")]
+	#[account(2, writable, name = "standby_config", desc = "The standby config PDA [b\"config\", b\"standby\"]")]
+	#[account(3, name = "rent", desc = "The rent sysvar")]
+	#[account(4, name = "system_program", desc = "The system program")]
+	InitializeStandbyConfig {
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+		admin: Pubkey,
+		listing_fee_lamports: u64,
+		crank_bounty_lamports: u64,
+		dormancy_period: i64,
+	},
+
+	/// Changes the parameters of the primary or the standby config. On the primary this counts as a heartbeat
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config
+	/// 1. `[writable]` The config account
+	#[account(0, signer, name = "admin", desc = "The config's admin")]
+	#[account(1, writable, name = "config", desc = "The config account")]
+	UpdateConfig {
+		listing_fee_lamports: u64,
+		crank_bounty_lamports: u64,
+		dormancy_period: i64,
+	},
+
+	/// Shows the primary admin is still around, so the standby config doesn't take over
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the primary config
+	/// 1. `[writable]` The primary config account
+	#[account(0, signer, name = "admin", desc = "The primary config's admin")]
+	#[account(1, writable, name = "config", desc = "The primary config account")]
+	Heartbeat,
+
+	/// Sends any tokens in the temp token account above the recorded deposit (airdrops, mistaken
+	/// transfers) back to the initializer
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The initializer of the escrow
+	/// 1. `[writable]` The PDA's temp token account
+	/// 2. `[writable]` A token account of the initializer for the deposited token, receives the surplus
+	/// 3. `[]` The escrow account holding the escrow info
+	/// 4. `[]` The token program
+	/// 5. `[]` The PDA account
+	/// 6. `[writable]` The deposited token's mint and its transfer hook accounts (see Exchange), only if there's a surplus
+	#[account(0, signer, name = "initializer", desc = "The escrow's initializer")]
+	#[account(1, writable, name = "temp_token_account", desc = "The escrow's temp token account")]
+	#[account(2, writable, name = "surplus_token_account", desc = "The initializer's token account that gets the surplus")]
+	#[account(3, name = "escrow", desc = "The escrow account")]
+	#[account(4, name = "token_program", desc = "The token program of the deposited token")]
+	#[account(5, name = "pda", desc = "The escrow's PDA")]
+	#[account(6, writable, optional, name = "deposit_mint", desc = "The deposited token's mint, if there's a surplus")]
+	SweepSurplus,
+
+	/// Moves tokens that were sent straight to an escrow PDA's associated token account (instead of into
+	/// an escrow) to wherever the admin says. The escrow has to be closed or settled already, only pNFT
+	/// deposits sit in associated token accounts of the PDA, so this can't touch deposits
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config in effect
+	/// 1. `[writable]` The PDA's associated token account holding the stranded tokens
+	/// 2. `[writable]` The token account to move the tokens to
+	/// 3. `[]` The token program
+	/// 4. `[]` The PDA account
+	/// 5. `[]` The (closed or settled) escrow account the PDA belongs to
+	/// 6. `[]` The config account
+	/// 7. `[]` The standby config account, only if the config has one
+	/// 8. `[]` The stranded token's mint and its transfer hook accounts (see Exchange)
+	#[account(0, signer, name = "admin", desc = "The admin of the config in effect")]
+	#[account(1, writable, name = "stranded_token_account", desc = "The PDA's associated token account holding the stranded tokens")]
+	#[account(2, writable, name = "destination_token_account", desc = "Where the tokens go")]
+	#[account(3, name = "token_program", desc = "The token program")]
+	#[account(4, name = "pda", desc = "The escrow's PDA")]
+	#[account(5, name = "escrow", desc = "The closed or settled escrow account the PDA belongs to")]
+	#[account(6, name = "config", desc = "The config account")]
+	#[account(7, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	#[account(8, name = "mint", desc = "The stranded token's mint")]
+	RecoverStrandedAssets,
+
+	/// Closes an escrow past its expiry, returning everything to the refund address like Cancel does.
+	/// Anyone can call this and gets the config's crank bounty out of the escrow account's rent
+	/// The initializer's index, the registry page and the stats account go after all the other accounts
+	/// in that order, if they exist (see InitializeIndex, InitializeRegistryPage and InitializeStats)
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` Whoever is cranking, receives the bounty
+	/// 1. `[writable]` The PDA's temp token account to return tokens from and eventually close
+	/// 2. `[writable]` A token account owned by the refund address that gets the deposited tokens back
+	/// 3. `[writable]` The escrow account holding the escrow info
+	/// 4. `[]` The token program
+	/// 5. `[]` The PDA account
+	/// 6. `[writable]` The escrow's refund address, receives the rest of the reclaimed rent
+	/// 7. `[writable]` The config account, only written to when the escrow paid a listing fee
+	/// 8. `[]` The standby config account, only if the config has one
+	/// 9. `[writable]` The escrow's EscrowGroup, only if it was attached to one
+	/// 10. `[writable]` The deposited token's mint and its transfer hook accounts (see Exchange), not for native SOL escrows
+	/// 11. .. The accounts Cancel takes for pNFT, compressed NFT and stake account escrows, with the cranker
+	///     paying for what Token Metadata creates
+	#[account(0, writable, signer, name = "cranker", desc = "Whoever is cranking, gets the bounty")]
+	#[account(1, writable, name = "temp_token_account", desc = "The escrow's temp token account, or what holds the deposit")]
+	#[account(2, writable, name = "refund_token_account", desc = "The refund address's token account for the deposit")]
+	#[account(3, writable, name = "escrow", desc = "The escrow account")]
+	#[account(4, name = "token_program", desc = "The token program of the deposited token")]
+	#[account(5, name = "pda", desc = "The escrow's PDA")]
+	#[account(6, writable, name = "refund_address", desc = "The escrow's refund address, gets the rent back")]
+	#[account(7, writable, name = "config", desc = "The config account")]
+	#[account(8, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	#[account(9, writable, optional, name = "group", desc = "The escrow's EscrowGroup, if it's in one")]
+	#[account(10, writable, optional, name = "deposit_mint", desc = "The deposited token's mint, for token deposits")]
+	CrankExpired {
+		/// Same as in Cancel
+		compressed_nft_proof: Option<CompressedNftProof>,
+	},
+
+	/// Pauses or unpauses the program. While paused InitEscrow, BatchInitEscrow and Exchange fail,
+	/// everything that closes escrows keeps working. The flag lives on the primary config but the
+	/// admin of whichever config is in effect can flip it, so it still works after a failover
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config in effect
+	/// 1. `[writable]` The primary config account
+	/// 2. `[]` The standby config account, only if the config has one
+	#[account(0, signer, name = "admin", desc = "The admin of the config in effect")]
+	#[account(1, writable, name = "config", desc = "The primary config account")]
+	#[account(2, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	SetPaused {
+		paused: bool,
+	},
+
+	/// Temporarily delists an escrow, Exchange fails on it until it's thawed. Cancel still works
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The initializer of the escrow
+	/// 1. `[writable]` The escrow account
+	#[account(0, signer, name = "initializer", desc = "The escrow's initializer")]
+	#[account(1, writable, name = "escrow", desc = "The escrow account")]
+	FreezeEscrow,
+
+	/// Lists a frozen escrow again. On an escrow that isn't frozen this only records that the
+	/// initializer is still around, see SweepDormant
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The initializer of the escrow
+	/// 1. `[writable]` The escrow account
+	#[account(0, signer, name = "initializer", desc = "The escrow's initializer")]
+	#[account(1, writable, name = "escrow", desc = "The escrow account")]
+	ThawEscrow,
+
+	/// Closes an escrow whose initializer hasn't touched it for the config's dormancy period, so
+	/// abandoned escrows don't stay around forever. Anyone can call this, but everything still goes
+	/// back to the escrow's refund address exactly like Cancel
+	/// The initializer's index, the registry page and the stats account go after all the other accounts
+	/// in that order, if they exist (see InitializeIndex, InitializeRegistryPage and InitializeStats)
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` Whoever is sweeping, the admin or anyone else
+	/// 1. `[writable]` The PDA's temp token account to return tokens from and eventually close
+	/// 2. `[writable]` A token account owned by the refund address that gets the deposited tokens back
+	/// 3. `[writable]` The escrow account holding the escrow info
+	/// 4. `[]` The token program
+	/// 5. `[]` The PDA account
+	/// 6. `[writable]` The escrow's refund address, receives the reclaimed rent
+	/// 7. `[writable]` The config account, only written to when the escrow paid a listing fee
+	/// 8. `[]` The standby config account, only if the config has one
+	/// 9. `[writable]` The escrow's EscrowGroup, only if it was attached to one
+	/// 10. `[writable]` The deposited token's mint and its transfer hook accounts (see Exchange), not for native SOL escrows
+	/// 11. .. The accounts Cancel takes for pNFT, compressed NFT and stake account escrows. The sweeper pays
+	///     for what Token Metadata creates, so it has to be writable for pNFT escrows
+	#[account(0, signer, name = "sweeper", desc = "Whoever is sweeping")]
+	#[account(1, writable, name = "temp_token_account", desc = "The escrow's temp token account, or what holds the deposit")]
+	#[account(2, writable, name = "refund_token_account", desc = "The refund address's token account for the deposit")]
+	#[account(3, writable, name = "escrow", desc = "The escrow account")]
+	#[account(4, name = "token_program", desc = "The token program of the deposited token")]
+	#[account(5, name = "pda", desc = "The escrow's PDA")]
+	#[account(6, writable, name = "refund_address", desc = "The escrow's refund address, gets the rent back")]
+	#[account(7, writable, name = "config", desc = "The config account")]
+	#[account(8, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	#[account(9, writable, optional, name = "group", desc = "The escrow's EscrowGroup, if it's in one")]
+	#[account(10, writable, optional, name = "deposit_mint", desc = "The deposited token's mint, for token deposits")]
+	SweepDormant {
+		/// Same as in Cancel
+		compressed_nft_proof: Option<CompressedNftProof>,
+	},
+
+	/// Sets what InitEscrow, BatchInitEscrow and Exchange do about a permanent delegate on either mint or a
+	/// CPI guard on the token accounts the program moves tokens out of for their owner. Like the pause
+	/// flag it lives on the primary config and the admin of whichever config is in effect can set it
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config in effect
+	/// 1. `[writable]` The primary config account
+	/// 2. `[]` The standby config account, only if the config has one
+	#[account(0, signer, name = "admin", desc = "The admin of the config in effect")]
+	#[account(1, writable, name = "config", desc = "The primary config account")]
+	#[account(2, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	SetExtensionPolicy {
+		policy: ExtensionPolicy,
+	},
+
+	/// Upgrades a v0 escrow account (the first release's 105 bytes) to the current layout in place.
+	/// Anyone can do it, nothing about the trade changes. Does nothing for escrows on the current version
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` Pays the rent the bigger account needs
+	/// 1. `[writable]` The escrow account
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	///
+	/// v0 escrows didn't keep their mints and deposit, those are read from these after the others. Their
+	/// temp token account belonged to the program-wide PDA [b"escrow"], which hands it to the escrow's PDA:
+	///
+	/// 4. `[writable]` The temp token account
+	/// 5. `[]` The initializer's token account that receives tokens
+	/// 6. `[]` Account 4's mint
+	/// 7. `[]` Account 5's mint
+	/// 8. `[]` The token program
+	/// 9. `[]` The program-wide PDA, Escrow::find_legacy_authority_address
+	#[account(0, writable, signer, name = "payer", desc = "Pays the rent the bigger account needs")]
+	#[account(1, writable, name = "escrow", desc = "The escrow account")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	#[account(4, writable, optional, name = "temp_token_account", desc = "The temp token account, for v0 escrows")]
+	#[account(5, optional, name = "initializers_token_to_receive_account", desc = "The initializer's receive account, for v0 escrows")]
+	#[account(6, optional, name = "deposit_mint", desc = "The temp token account's mint, for v0 escrows")]
+	#[account(7, optional, name = "receive_mint", desc = "The receive account's mint, for v0 escrows")]
+	#[account(8, optional, name = "token_program", desc = "The token program, for v0 escrows")]
+	#[account(9, optional, name = "legacy_pda", desc = "The program-wide PDA [b\"escrow\"], for v0 escrows")]
+	MigrateEscrow,
+
+	/// Closes the record of a settled escrow that was kept with `retain_history`, its rent goes back to
+	/// the refund address
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The initializer of the escrow. For escrows of a multisig it's the multisig, which
+	///    doesn't sign, and enough of its signers come `[signer]` after the other accounts
+	/// 1. `[writable]` The escrow account, Completed, Cancelled or Expired
+	/// 2. `[writable]` The escrow's refund address
+	#[account(0, signer, name = "initializer", desc = "The initializer, or their multisig followed by its signers at the end")]
+	#[account(1, writable, name = "escrow", desc = "The settled escrow account")]
+	#[account(2, writable, name = "refund_address", desc = "The escrow's refund address")]
+	CloseEscrowRecord,
+
+	/// Creates the stats account, the PDA [b"stats"]. Anyone can pay for it. From then on the
+	/// instructions that open and close escrows take it as their last account and keep its counters,
+	/// escrows counted in it have to bring it along when they're closed
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` Pays the stats account's rent
+	/// 1. `[writable]` The uninitialized stats PDA
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	#[account(0, writable, signer, name = "payer", desc = "Pays the stats account's rent")]
+	#[account(1, writable, name = "stats", desc = "The stats PDA [b\"stats\"]")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	InitializeStats,
+
+	/// Creates the stats account of a mint pair, the PDA [b"pair-stats", deposit mint, receive mint].
+	/// Anyone can pay for it. Exchange takes it right before the stats account (or last without one) and
+	/// records fills of escrows depositing `deposit_mint` for `receive_mint` in it
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` Pays the pair stats account's rent
+	/// 1. `[writable]` The uninitialized pair stats PDA
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	#[account(0, writable, signer, name = "payer", desc = "Pays the pair stats account's rent")]
+	#[account(1, writable, name = "pair_stats", desc = "The pair stats PDA [b\"pair-stats\", deposit mint, receive mint]")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	InitializePairStats {
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+		deposit_mint: Pubkey,
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+		receive_mint: Pubkey,
+	},
+
+	/// Turns features of the program off or back on (see the FEATURE_* bits in state). InitEscrow,
+	/// BatchInitEscrow and Exchange fail when they'd use one that's off. Like the pause flag it lives on
+	/// the primary config and the admin of whichever config is in effect can set it
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config in effect
+	/// 1. `[writable]` The primary config account
+	/// 2. `[]` The standby config account, only if the config has one
+	#[account(0, signer, name = "admin", desc = "The admin of the config in effect")]
+	#[account(1, writable, name = "config", desc = "The primary config account")]
+	#[account(2, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	SetFeatures {
+		/// FEATURE_* bits of what to turn off, everything else is turned on
+		disabled_features: u16,
+	},
+
+	/// First step of handing a (primary or standby) config over to a new admin, who then has to take it
+	/// with AcceptAdmin. Until they do the current admin stays in charge and can nominate someone else,
+	/// or Pubkey::default() to call it off
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config
+	/// 1. `[writable]` The config account
+	#[account(0, signer, name = "admin", desc = "The config's admin")]
+	#[account(1, writable, name = "config", desc = "The config account")]
+	NominateAdmin {
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+		new_admin: Pubkey,
+	},
+
+	/// Makes the nominated admin (see NominateAdmin) the config's admin
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The nominated admin
+	/// 1. `[writable]` The config account
+	#[account(0, signer, name = "new_admin", desc = "The nominated admin")]
+	#[account(1, writable, name = "config", desc = "The config account")]
+	AcceptAdmin,
+
+	/// Sets just the listing fee of a (primary or standby) config. Unlike UpdateConfig it leaves the rest
+	/// alone, so proposals changing different parameters under a governance admin can't undo each other
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config
+	/// 1. `[writable]` The config account
+	#[account(0, signer, name = "admin", desc = "The config's admin")]
+	#[account(1, writable, name = "config", desc = "The config account")]
+	SetListingFee {
+		listing_fee_lamports: u64,
+	},
+
+	/// Moves collected listing fees out of the treasury. It always keeps its rent and the listing fees of
+	/// open escrows, which are refunded out of it when they're filled (see REFUND_LISTING_FEE_ON_EXCHANGE)
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config in effect
+	/// 1. `[writable]` The treasury PDA
+	/// 2. `[writable]` Where the lamports go, like a governance treasury
+	/// 3. `[]` The rent sysvar
+	/// 4. `[]` The system program
+	/// 5. `[]` The primary config account
+	/// 6. `[]` The standby config account, only if the config has one
+	#[account(0, signer, name = "admin", desc = "The admin of the config in effect")]
+	#[account(1, writable, name = "treasury", desc = "The treasury PDA")]
+	#[account(2, writable, name = "destination", desc = "Where the lamports go")]
+	#[account(3, name = "rent", desc = "The rent sysvar")]
+	#[account(4, name = "system_program", desc = "The system program")]
+	#[account(5, name = "config", desc = "The primary config account")]
+	#[account(6, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	WithdrawTreasury {
+		lamports: u64,
+	},
+
+	/// Creates page `index` of the registry of open escrows (see RegistryPage), anyone can pay for one.
+	/// InitEscrow and BatchInitEscrow add their escrows to a page passed right before the stats account
+	/// (or last without one), and whatever settles a registered escrow has to pass its page the same way
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` Pays the page's rent
+	/// 1. `[writable]` The uninitialized page, the PDA [b"registry", index as u32 LE]
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	#[account(0, writable, signer, name = "payer", desc = "Pays the page's rent")]
+	#[account(1, writable, name = "registry_page", desc = "The page PDA [b\"registry\", index]")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	InitializeRegistryPage {
+		index: u32,
+	},
+
+	/// Creates the initializer's index of their open escrows (see InitializerIndex). InitEscrow and
+	/// BatchInitEscrow add their escrows to it when it's passed right before the registry page (or where
+	/// the registry page would go), and whatever settles an indexed escrow has to pass it the same way
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The initializer, pays the index's rent
+	/// 1. `[writable]` The uninitialized index, the PDA [b"index", initializer]
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	#[account(0, writable, signer, name = "payer", desc = "The initializer, pays the index's rent")]
+	#[account(1, writable, name = "index", desc = "The index PDA [b\"index\", initializer]")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	InitializeIndex,
+
+	/// Grows a current escrow account by up to 10 KiB, for the fields later program versions add. The
+	/// new space is zeroed and whoever signs tops up the rent for it. The initializer or the admin can
+	/// do it, for active escrows and records alike. Older layouts need MigrateEscrow first
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The escrow's initializer or the admin of the config in effect, pays the rent
+	/// 1. `[writable]` The escrow account
+	/// 2. `[]` The rent sysvar
+	/// 3. `[]` The system program
+	/// 4. `[]` The primary config account, only if the admin signs
+	/// 5. `[]` The standby config account, only if the admin signs and the config has one
+	#[account(0, writable, signer, name = "authority", desc = "The escrow's initializer or the admin of the config in effect, pays the rent")]
+	#[account(1, writable, name = "escrow", desc = "The escrow account")]
+	#[account(2, name = "rent", desc = "The rent sysvar")]
+	#[account(3, name = "system_program", desc = "The system program")]
+	#[account(4, optional, name = "config", desc = "The primary config account, if the admin signs")]
+	#[account(5, optional, name = "standby_config", desc = "The standby config account, if the admin signs and the config has one")]
+	ReallocEscrow {
+		/// The account's new length, bigger than it is now
+		new_len: u32,
+	},
+
+	/// Makes Exchange fail unless it's an instruction of the transaction itself rather than a CPI from
+	/// another program, which could run whatever it likes around the fill. Exchange then takes the
+	/// instructions sysvar as its very last account. Like the pause flag it lives on the primary config
+	/// and the admin of whichever config is in effect can set it
+	/// Accounts expected:
+	///
+	/// 0. `[signer]` The admin of the config in effect
+	/// 1. `[writable]` The primary config account
+	/// 2. `[]` The standby config account, only if the config has one
+	#[account(0, signer, name = "admin", desc = "The admin of the config in effect")]
+	#[account(1, writable, name = "config", desc = "The primary config account")]
+	#[account(2, optional, name = "standby_config", desc = "The standby config account, if the config has one")]
+	SetTopLevelExchange {
+		top_level_only: bool,
+	},
+
+	/// Exchange with fewer accounts, for transactions that compose it with other instructions. The PDA
+	/// and the refund address stay, the transfers are signed for the PDA and the rents go to the refund
+	/// address. What goes is what the program can do without:
+	///
+	/// * the taker's token program, when it's the deposited token's too
+	/// * the system program more than once, one up front covers everything that calls it
+	/// * the receipt's rent sysvar, the rent comes from Rent::get
+	///
+	/// Takes the tag VERSIONED_TAGS_START (see EXCHANGE_V2_TAG), programs that don't know it fail with
+	/// UnsupportedInstructionVersion so clients can fall back to Exchange. Otherwise it's Exchange, the
+	/// trackers and the instructions sysvar go at the end the same way
+	/// Accounts expected:
+	///
+	/// 0. `[signer, writable]` The account of the person taking the trade, pays for receive accounts that don't exist yet
+	/// 1. `[writable]` The taker's token account for the token they send
+	/// 2. `[writable]` The taker's token account for the token they will receive should the trade go through
+	/// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
+	/// 4. `[writable]` The escrow's refund address (the initializer unless set otherwise at init), gets the
+	///    escrow's rent back. If account 5 gets created it has to be the initializer
+	/// 5. `[writable]` The initializer's token account that will receive tokens
+	/// 6. `[writable]` The escrow account holding the escrow info
+	/// 7. `[]` The token program of the deposited token, spl-token or Token-2022
+	/// 8. `[]` The escrow's PDA account, [b"escrow", escrow account]
+	/// 9. `[writable]` The config account, to check the program isn't paused. Only written to when the listing fee is refunded
+	/// 10. `[]` The token program of the token the taker sends, only if account 1 isn't account 7's
+	/// 11. `[]` The system program, only if a receive account gets created, the escrow asks for lamports
+	///     on top, the listing fee gets refunded or `receipt` is set. Never for native SOL escrows,
+	///     account 7 is the system program there
+	///
+	/// Followed by the accounts of Exchange's list, without the system program and the rent sysvar in it
+	#[account(0, writable, signer, name = "taker", desc = "Pays for receive accounts that don't exist yet")]
+	#[account(1, writable, name = "takers_sending_account", desc = "The taker's token account for the token they send")]
+	#[account(2, writable, name = "takers_token_to_receive_account", desc = "The taker's token account for the deposited token")]
+	#[account(3, writable, name = "temp_token_account", desc = "The escrow's temp token account, or what holds the deposit")]
+	#[account(4, writable, name = "refund_address", desc = "The escrow's refund address, gets the escrow's rent back")]
+	#[account(5, writable, name = "initializers_token_to_receive_account", desc = "The initializer's token account that receives the taker's tokens")]
+	#[account(6, writable, name = "escrow", desc = "The escrow account")]
+	#[account(7, name = "token_program", desc = "The token program of the deposited token")]
+	#[account(8, name = "pda", desc = "The escrow's PDA")]
+	#[account(9, writable, name = "config", desc = "The config account")]
+	#[account(10, optional, name = "sending_token_program", desc = "The token program of the token the taker sends, if it's another one")]
+	#[account(11, optional, name = "system_program", desc = "The system program, if anything calls it")]
+	ExchangeV2 {
+		/// the amount the taker expects to be paid in the other token
+		amount: u64,
+		/// Solana Pay reference key, see the program's solana_pay
+		#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey::option"))]
+		reference: Option<Pubkey>,
+		max_amount_in: Option<u64>,
+		min_amount_out: Option<u64>,
+		unwrap_sol: bool,
+		compressed_nft_proof: Option<CompressedNftProof>,
+		receipt: bool,
+	},
+}
+
+/// A compressed NFT for InitEscrow to deposit. Account 1 is the cNFT's merkle tree then, account 4 the
+/// Bubblegum program, and these come after the standby config account:
+///
+/// * `[]` The PDA account, the cNFT's new owner
+/// * `[]` The cNFT's delegate, the initializer if there isn't one
+/// * `[]` The tree authority, the PDA [merkle tree] of Bubblegum
+/// * `[]` The noop log wrapper program
+/// * `[]` The account compression program
+/// * `[]` The system program
+/// * `[]` `proof_len` proof accounts, the proof path's nodes the tree's canopy doesn't cover
+///
+/// Exchange and Cancel take the last five too, with a proof against the tree's root at the time
+#[derive(Debug, ShankType)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompressedNft {
+	/// The tree's root the proof was made against
+	pub root: [u8; 32],
+	pub data_hash: [u8; 32],
+	pub creator_hash: [u8; 32],
+	/// The leaf's nonce, which is also its index in the tree
+	pub nonce: u64,
+	pub proof_len: u8,
+}
+
+/// For compressed NFT escrows, the tree's root a proof was made against and how many proof accounts there are
+pub type CompressedNftProof = ([u8; 32], u8);
+
+/// Tags from this one up are kept for new versions of existing instructions, like an ExchangeV2 whose
+/// fields don't fit the old one's. New instructions take the tags below BORSH_TAG_FLAG. A program that doesn't
+/// know a tag up there fails with UnsupportedInstructionVersion instead of InvalidInstruction, so
+/// clients can tell they have to fall back to the older version
+pub const VERSIONED_TAGS_START: u8 = 128;
+
+/// ExchangeV2's tag
+pub const EXCHANGE_V2_TAG: u8 = VERSIONED_TAGS_START;
+
+/// The enum's variants below this index have it as their tag, the ones from here on are the versioned
+/// instructions in the order of VERSIONED_TAGS
+const VERSIONED_VARIANTS_START: u8 = 31;
+
+const VERSIONED_TAGS: [u8; 1] = [EXCHANGE_V2_TAG];
+
+/// A tag with this bit set is the instruction's tag followed by its fields in borsh, the way a borsh
+/// client serializes the enum, instead of the hand-rolled encoding. That's what clients generated from
+/// the IDL send. Fields can't be left off the end there, they all have to be in the data
+pub const BORSH_TAG_FLAG: u8 = 0x40;
+
+impl EscrowInstruction {
+	/// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
+	///
+	/// New parameters only ever go at the end of an instruction's data and are optional, so clients
+	/// keep working across versions: fields that are missing at the end read as None, false or 0, and
+	/// anything after the last field this version knows is ignored
+	pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+		let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
+		#[cfg(feature = "borsh")]
+		if tag & BORSH_TAG_FLAG != 0 {
+			return Self::unpack_borsh(*tag & !BORSH_TAG_FLAG, rest);
+		}
+
+		let instruction = match *tag {
+			0 => {
+				let amount = Self::unpack_amount(rest)?;
+				let (predecessor, rest) = Self::unpack_pubkey_option(&rest[8..])?;
+				let (refund_address, rest) = Self::unpack_pubkey_option(rest)?;
+				let (expires_at, rest) = Self::unpack_amount_option(rest)?;
+				let (native_sol_deposit, rest) = Self::unpack_amount_option(rest)?;
+				let (wrap_sol_deposit, rest) = Self::unpack_amount_option(rest)?;
+				let (nft, rest) = match rest.split_first() {
+					None => (false, rest),
+					Some((0, rest)) => (false, rest),
+					Some((1, rest)) => (true, rest),
+					_ => return Err(InvalidInstruction.into()),
+				};
+				let (collection, rest) = Self::unpack_pubkey_option(rest)?;
+				let (compressed_nft, rest) = match rest.split_first() {
+					None => (None, rest),
+					Some((0, rest)) => (None, rest),
+					Some((1, rest)) if rest.len() >= 105 => (
+						Some(CompressedNft {
+							root: rest[..32].try_into().unwrap(),
+							data_hash: rest[32..64].try_into().unwrap(),
+							creator_hash: rest[64..96].try_into().unwrap(),
+							nonce: Self::unpack_amount(&rest[96..])?,
+							proof_len: rest[104],
+						}),
+						&rest[105..],
+					),
+					_ => return Err(InvalidInstruction.into()),
+				};
+				let (multisig_signers, rest) = match rest.split_first() {
+					Some((multisig_signers, rest)) => (*multisig_signers, rest),
+					None => (0, rest),
+				};
+				let (lamports_deposit, rest) = Self::unpack_amount_option(rest)?;
+				let (expected_lamports, rest) = Self::unpack_amount_option(rest)?;
+				let (stake_account, rest) = match rest.split_first() {
+					None => (false, rest),
+					Some((0, rest)) => (false, rest),
+					Some((1, rest)) => (true, rest),
+					_ => return Err(InvalidInstruction.into()),
+				};
+				let (vault_deposit, rest) = Self::unpack_amount_option(rest)?;
+				let (escrow_seed, rest) = Self::unpack_amount_option(rest)?;
+				let (retain_history, rest) = match rest.split_first() {
+					None => (false, rest),
+					Some((0, rest)) => (false, rest),
+					Some((1, rest)) => (true, rest),
+					_ => return Err(InvalidInstruction.into()),
+				};
+				// A u8 length and the text, no bytes or a 0 length means no memo
+				let (memo, rest) = match rest.split_first() {
+					None => (None, rest),
+					Some((0, rest)) => (None, rest),
+					Some((len, rest)) => {
+						let text = rest.get(..*len as usize).ok_or(InvalidInstruction)?;
+						(Some(String::from_utf8(text.to_vec()).map_err(|_| InvalidInstruction)?), &rest[text.len()..])
+					},
+				};
+				let (rent_payer, rest) = match rest.split_first() {
+					None => (false, rest),
+					Some((0, rest)) => (false, rest),
+					Some((1, rest)) => (true, rest),
+					_ => return Err(InvalidInstruction.into()),
+				};
+				let prevent_self_trade = match rest.first() {
+					None | Some(0) => false,
+					Some(1) => true,
+					_ => return Err(InvalidInstruction.into()),
+				};
+				Self::InitEscrow {
+					amount,
+					predecessor,
+					refund_address,
+					expires_at: expires_at.map(|ts| ts as i64),
+					native_sol_deposit,
+					wrap_sol_deposit,
+					nft,
+					collection,
+					compressed_nft,
+					multisig_signers,
+					lamports_deposit,
+					expected_lamports,
+					stake_account,
+					vault_deposit,
+					escrow_seed,
+					retain_history,
+					memo,
+					rent_payer,
+					prevent_self_trade,
+				}
+			},
+			// ExchangeV2's fields are Exchange's
+			1 | EXCHANGE_V2_TAG => {
+				let amount = Self::unpack_amount(rest)?;
+				let (reference, rest) = Self::unpack_pubkey_option(&rest[8..])?;
+				let (max_amount_in, rest) = Self::unpack_amount_option(rest)?;
+				let (min_amount_out, rest) = Self::unpack_amount_option(rest)?;
+				// Missing means no, like the options before it
+				let (unwrap_sol, rest) = match rest.split_first() {
+					None => (false, rest),
+					Some((0, rest)) => (false, rest),
+					Some((1, rest)) => (true, rest),
+					_ => return Err(InvalidInstruction.into()),
+				};
+				let (compressed_nft_proof, rest) = Self::unpack_proof_option(rest)?;
+				let receipt = match rest.first() {
+					None | Some(0) => false,
+					Some(1) => true,
+					_ => return Err(InvalidInstruction.into()),
+				};
+				if *tag == EXCHANGE_V2_TAG {
+					Self::ExchangeV2 {
+						amount,
+						reference,
+						max_amount_in,
+						min_amount_out,
+						unwrap_sol,
+						compressed_nft_proof,
+						receipt,
+					}
+				} else {
+					Self::Exchange {
+						amount,
+						reference,
+						max_amount_in,
+						min_amount_out,
+						unwrap_sol,
+						compressed_nft_proof,
+						receipt,
+					}
+				}
+			},
+			2 => Self::Cancel {
+				compressed_nft_proof: Self::unpack_proof_option(rest)?.0,
+			},
+			3 => Self::CreateGroup {
+				name: rest
+					.get(..32)
+					.and_then(|slice| slice.try_into().ok())
+					.ok_or(InvalidInstruction)?,
+			},
+			4 => {
+				// A u8 count followed by that many proofs, nothing at all for none
+				let compressed_nft_proofs = match rest.split_first() {
+					None => Vec::new(),
+					Some((count, rest)) => (0..*count as usize)
+						.map(|i| {
+							let proof = rest.get(i * 33..i * 33 + 33).ok_or(InvalidInstruction)?;
+							Ok((proof[..32].try_into().unwrap(), proof[32]))
+						})
+						.collect::<Result<Vec<CompressedNftProof>, ProgramError>>()?,
+				};
+				Self::CancelGroup { compressed_nft_proofs }
+			},
+			5 => {
+				// A u8 count followed by that many amounts
+				let (count, rest) = rest.split_first().ok_or(InvalidInstruction)?;
+				let amounts = (0..*count as usize)
+					.map(|i| Self::unpack_amount(rest.get(i * 8..).ok_or(InvalidInstruction)?))
+					.collect::<Result<Vec<u64>, ProgramError>>()?;
+				Self::BatchInitEscrow { amounts }
+			},
+			6 => Self::InitializeConfig {
+				admin: Self::unpack_pubkey(rest)?,
+				listing_fee_lamports: Self::unpack_amount(rest.get(32..).ok_or(InvalidInstruction)?)?,
+				heartbeat_period: Self::unpack_amount(rest.get(40..).ok_or(InvalidInstruction)?)? as i64,
+				crank_bounty_lamports: Self::unpack_amount(rest.get(48..).ok_or(InvalidInstruction)?)?,
+				dormancy_period: Self::unpack_amount(rest.get(56..).ok_or(InvalidInstruction)?)? as i64,
+			},
+			7 => Self::InitializeStandbyConfig {
+				admin: Self::unpack_pubkey(rest)?,
+				listing_fee_lamports: Self::unpack_amount(rest.get(32..).ok_or(InvalidInstruction)?)?,
+				crank_bounty_lamports: Self::unpack_amount(rest.get(40..).ok_or(InvalidInstruction)?)?,
+				dormancy_period: Self::unpack_amount(rest.get(48..).ok_or(InvalidInstruction)?)? as i64,
+			},
+			8 => Self::UpdateConfig {
+				listing_fee_lamports: Self::unpack_amount(rest)?,
+				crank_bounty_lamports: Self::unpack_amount(rest.get(8..).ok_or(InvalidInstruction)?)?,
+				dormancy_period: Self::unpack_amount(rest.get(16..).ok_or(InvalidInstruction)?)? as i64,
+			},
+			9 => Self::Heartbeat,
+			10 => Self::SweepSurplus,
+			11 => Self::RecoverStrandedAssets,
+			12 => Self::CrankExpired {
+				compressed_nft_proof: Self::unpack_proof_option(rest)?.0,
+			},
+			13 => Self::SetPaused {
+				paused: match rest.first() {
+					Some(0) => false,
+					Some(1) => true,
+					_ => return Err(InvalidInstruction.into()),
+				},
+			},
+			14 => Self::FreezeEscrow,
+			15 => Self::ThawEscrow,
+			16 => Self::SweepDormant {
+				compressed_nft_proof: Self::unpack_proof_option(rest)?.0,
+			},
+			17 => Self::SetExtensionPolicy {
+				policy: ExtensionPolicy::from_u8(*rest.first().ok_or(InvalidInstruction)?)
+					.map_err(|_| InvalidInstruction)?,
+			},
+			18 => Self::MigrateEscrow,
+			19 => Self::CloseEscrowRecord,
+			20 => Self::InitializeStats,
+			21 => Self::InitializePairStats {
+				deposit_mint: Self::unpack_pubkey(rest)?,
+				receive_mint: Self::unpack_pubkey(rest.get(32..).ok_or(InvalidInstruction)?)?,
+			},
+			22 => Self::SetFeatures {
+				disabled_features: rest
+					.get(..2)
+					.and_then(|slice| slice.try_into().ok())
+					.map(u16::from_le_bytes)
+					.ok_or(InvalidInstruction)?,
+			},
+			23 => Self::NominateAdmin {
+				new_admin: Self::unpack_pubkey(rest)?,
+			},
+			24 => Self::AcceptAdmin,
+			25 => Self::SetListingFee {
+				listing_fee_lamports: Self::unpack_amount(rest)?,
+			},
+			26 => Self::WithdrawTreasury {
+				lamports: Self::unpack_amount(rest)?,
+			},
+			27 => Self::InitializeRegistryPage {
+				index: rest
+					.get(..4)
+					.and_then(|slice| slice.try_into().ok())
+					.map(u32::from_le_bytes)
+					.ok_or(InvalidInstruction)?,
+			},
+			28 => Self::InitializeIndex,
+			29 => Self::ReallocEscrow {
+				new_len: rest
+					.get(..4)
+					.and_then(|slice| slice.try_into().ok())
+					.map(u32::from_le_bytes)
+					.ok_or(InvalidInstruction)?,
+			},
+			30 => Self::SetTopLevelExchange {
+				top_level_only: match rest.first() {
+					Some(0) => false,
+					Some(1) => true,
+					_ => return Err(InvalidInstruction.into()),
+				},
+			},
+			tag if tag >= VERSIONED_TAGS_START => return Err(UnsupportedInstructionVersion.into()),
+			_ => return Err(InvalidInstruction.into()),
+		};
+		instruction.check()?;
+		Ok(instruction)
+	}
+
+	/// An instruction whose fields are borsh (see BORSH_TAG_FLAG), `tag` without the flag
+	#[cfg(feature = "borsh")]
+	fn unpack_borsh(tag: u8, fields: &[u8]) -> Result<Self, ProgramError> {
+		// The enum's borsh is the variant's index and then its fields
+		let index = match VERSIONED_TAGS.iter().position(|versioned_tag| *versioned_tag == tag) {
+			Some(position) => VERSIONED_VARIANTS_START + position as u8,
+			None if tag >= VERSIONED_TAGS_START => return Err(UnsupportedInstructionVersion.into()),
+			None if tag >= VERSIONED_VARIANTS_START => return Err(InvalidInstruction.into()),
+			None => tag,
+		};
+		let mut data = Vec::with_capacity(1 + fields.len());
+		data.push(index);
+		data.extend_from_slice(fields);
+		let instruction = <Self as BorshDeserialize>::deserialize(&mut data.as_slice()).map_err(|_| InvalidInstruction)?;
+		instruction.check()?;
+		Ok(instruction)
+	}
+
+	/// What unpack checks on top of the encoding
+	fn check(&self) -> Result<(), ProgramError> {
+		if let Self::InitEscrow {
+			amount,
+			native_sol_deposit,
+			wrap_sol_deposit,
+			nft,
+			collection,
+			compressed_nft,
+			multisig_signers,
+			stake_account,
+			vault_deposit,
+			..
+		} = self
+		{
+			// Only one kind of deposit at a time
+			let deposits = [
+				native_sol_deposit.is_some(),
+				wrap_sol_deposit.is_some(),
+				*nft,
+				compressed_nft.is_some(),
+				*stake_account,
+				vault_deposit.is_some(),
+			];
+			if deposits.iter().filter(|&&set| set).count() > 1 {
+				return Err(InvalidInstruction.into());
+			}
+			// The system program, Bubblegum and the stake program don't know multisigs, and the token
+			// program caps their signers
+			if *multisig_signers > 0
+				&& (native_sol_deposit.is_some() || wrap_sol_deposit.is_some() || compressed_nft.is_some() || *stake_account
+					|| vault_deposit.is_some() || *multisig_signers as usize > MAX_SIGNERS)
+			{
+				return Err(InvalidInstruction.into());
+			}
+			// A collection offer buys one NFT
+			if collection.is_some() && *amount != 1 {
+				return Err(InvalidInstruction.into());
+			}
+		}
+		Ok(())
+	}
+
+	/// Packs the instruction with its fields in borsh, for the tag with BORSH_TAG_FLAG set
+	#[cfg(feature = "borsh")]
+	pub fn pack_borsh(&self) -> Vec<u8> {
+		let mut buf = self.try_to_vec().unwrap();
+		buf[0] = Self::tag_of_variant(buf[0]) | BORSH_TAG_FLAG;
+		buf
+	}
+
+	/// The tag of the enum's variant at `index`, which is the index itself except for the versioned
+	/// instructions (see VERSIONED_TAGS_START)
+	pub fn tag_of_variant(index: u8) -> u8 {
+		match index.checked_sub(VERSIONED_VARIANTS_START) {
+			Some(position) => VERSIONED_TAGS[position as usize],
+			None => index,
+		}
+	}
+
+	/// Packs an [EscrowInstruction](enum.EscrowInstruction.html) into a byte buffer, the way unpack reads it
+	pub fn pack(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+		match self {
+			Self::InitEscrow {
+				amount,
+				predecessor,
+				refund_address,
+				expires_at,
+				native_sol_deposit,
+				wrap_sol_deposit,
+				nft,
+				collection,
+				compressed_nft,
+				multisig_signers,
+				lamports_deposit,
+				expected_lamports,
+				stake_account,
+				vault_deposit,
+				escrow_seed,
+				retain_history,
+				memo,
+				rent_payer,
+				prevent_self_trade,
+			} => {
+				buf.push(0);
+				buf.extend_from_slice(&amount.to_le_bytes());
+				Self::pack_pubkey_option(&mut buf, predecessor);
+				Self::pack_pubkey_option(&mut buf, refund_address);
+				Self::pack_amount_option(&mut buf, &expires_at.map(|ts| ts as u64));
+				Self::pack_amount_option(&mut buf, native_sol_deposit);
+				Self::pack_amount_option(&mut buf, wrap_sol_deposit);
+				buf.push(*nft as u8);
+				Self::pack_pubkey_option(&mut buf, collection);
+				match compressed_nft {
+					Some(compressed_nft) => {
+						buf.push(1);
+						buf.extend_from_slice(&compressed_nft.root);
+						buf.extend_from_slice(&compressed_nft.data_hash);
+						buf.extend_from_slice(&compressed_nft.creator_hash);
+						buf.extend_from_slice(&compressed_nft.nonce.to_le_bytes());
+						buf.push(compressed_nft.proof_len);
+					},
+					None => buf.push(0),
+				}
+				buf.push(*multisig_signers);
+				Self::pack_amount_option(&mut buf, lamports_deposit);
+				Self::pack_amount_option(&mut buf, expected_lamports);
+				buf.push(*stake_account as u8);
+				Self::pack_amount_option(&mut buf, vault_deposit);
+				Self::pack_amount_option(&mut buf, escrow_seed);
+				buf.push(*retain_history as u8);
+				match memo {
+					Some(memo) => {
+						buf.push(memo.len() as u8);
+						buf.extend_from_slice(memo.as_bytes());
+					},
+					None => buf.push(0),
+				}
+				buf.push(*rent_payer as u8);
+				buf.push(*prevent_self_trade as u8);
+			},
+			Self::Exchange {
+				amount,
+				reference,
+				max_amount_in,
+				min_amount_out,
+				unwrap_sol,
+				compressed_nft_proof,
+				receipt,
+			}
+			| Self::ExchangeV2 {
+				amount,
+				reference,
+				max_amount_in,
+				min_amount_out,
+				unwrap_sol,
+				compressed_nft_proof,
+				receipt,
+			} => {
+				buf.push(if matches!(self, Self::ExchangeV2 { .. }) { EXCHANGE_V2_TAG } else { 1 });
+				buf.extend_from_slice(&amount.to_le_bytes());
+				Self::pack_pubkey_option(&mut buf, reference);
+				Self::pack_amount_option(&mut buf, max_amount_in);
+				Self::pack_amount_option(&mut buf, min_amount_out);
+				buf.push(*unwrap_sol as u8);
+				Self::pack_proof_option(&mut buf, compressed_nft_proof);
+				buf.push(*receipt as u8);
+			},
+			Self::Cancel { compressed_nft_proof } => {
+				buf.push(2);
+				Self::pack_proof_option(&mut buf, compressed_nft_proof);
+			},
+			Self::CreateGroup { name } => {
+				buf.push(3);
+				buf.extend_from_slice(name);
+			},
+			Self::CancelGroup { compressed_nft_proofs } => {
+				buf.push(4);
+				buf.push(compressed_nft_proofs.len() as u8);
+				for (root, proof_len) in compressed_nft_proofs {
+					buf.extend_from_slice(root);
+					buf.push(*proof_len);
+				}
+			},
+			Self::BatchInitEscrow { amounts } => {
+				buf.push(5);
+				buf.push(amounts.len() as u8);
+				for amount in amounts {
+					buf.extend_from_slice(&amount.to_le_bytes());
+				}
+			},
+			Self::InitializeConfig {
+				admin,
+				listing_fee_lamports,
+				heartbeat_period,
+				crank_bounty_lamports,
+				dormancy_period,
+			} => {
+				buf.push(6);
+				buf.extend_from_slice(admin.as_ref());
+				buf.extend_from_slice(&listing_fee_lamports.to_le_bytes());
+				buf.extend_from_slice(&heartbeat_period.to_le_bytes());
+				buf.extend_from_slice(&crank_bounty_lamports.to_le_bytes());
+				buf.extend_from_slice(&dormancy_period.to_le_bytes());
+			},
+			Self::InitializeStandbyConfig {
+				admin,
+				listing_fee_lamports,
+				crank_bounty_lamports,
+				dormancy_period,
+			} => {
+				buf.push(7);
+				buf.extend_from_slice(admin.as_ref());
+				buf.extend_from_slice(&listing_fee_lamports.to_le_bytes());
+				buf.extend_from_slice(&crank_bounty_lamports.to_le_bytes());
+				buf.extend_from_slice(&dormancy_period.to_le_bytes());
+			},
+			Self::UpdateConfig {
+				listing_fee_lamports,
+				crank_bounty_lamports,
+				dormancy_period,
+			} => {
+				buf.push(8);
+				buf.extend_from_slice(&listing_fee_lamports.to_le_bytes());
+				buf.extend_from_slice(&crank_bounty_lamports.to_le_bytes());
+				buf.extend_from_slice(&dormancy_period.to_le_bytes());
+			},
+			Self::Heartbeat => buf.push(9),
+			Self::SweepSurplus => buf.push(10),
+			Self::RecoverStrandedAssets => buf.push(11),
+			Self::CrankExpired { compressed_nft_proof } => {
+				buf.push(12);
+				Self::pack_proof_option(&mut buf, compressed_nft_proof);
+			},
+			Self::SetPaused { paused } => {
+				buf.push(13);
+				buf.push(*paused as u8);
+			},
+			Self::FreezeEscrow => buf.push(14),
+			Self::ThawEscrow => buf.push(15),
+			Self::SweepDormant { compressed_nft_proof } => {
+				buf.push(16);
+				Self::pack_proof_option(&mut buf, compressed_nft_proof);
+			},
+			Self::SetExtensionPolicy { policy } => {
+				buf.push(17);
+				buf.push(*policy as u8);
+			},
+			Self::MigrateEscrow => buf.push(18),
+			Self::CloseEscrowRecord => buf.push(19),
+			Self::InitializeStats => buf.push(20),
+			Self::InitializePairStats { deposit_mint, receive_mint } => {
+				buf.push(21);
+				buf.extend_from_slice(deposit_mint.as_ref());
+				buf.extend_from_slice(receive_mint.as_ref());
+			},
+			Self::SetFeatures { disabled_features } => {
+				buf.push(22);
+				buf.extend_from_slice(&disabled_features.to_le_bytes());
+			},
+			Self::NominateAdmin { new_admin } => {
+				buf.push(23);
+				buf.extend_from_slice(new_admin.as_ref());
+			},
+			Self::AcceptAdmin => buf.push(24),
+			Self::SetListingFee { listing_fee_lamports } => {
+				buf.push(25);
+				buf.extend_from_slice(&listing_fee_lamports.to_le_bytes());
+			},
+			Self::WithdrawTreasury { lamports } => {
+				buf.push(26);
+				buf.extend_from_slice(&lamports.to_le_bytes());
+			},
+			Self::InitializeRegistryPage { index } => {
+				buf.push(27);
+				buf.extend_from_slice(&index.to_le_bytes());
+			},
+			Self::InitializeIndex => buf.push(28),
+			Self::ReallocEscrow { new_len } => {
+				buf.push(29);
+				buf.extend_from_slice(&new_len.to_le_bytes());
+			},
+			Self::SetTopLevelExchange { top_level_only } => {
+				buf.push(30);
+				buf.push(*top_level_only as u8);
+			},
+		}
+		buf
+	}
+
+	fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
+		let amount = input
+			.get(..8)
+			.and_then(|slice| slice.try_into().ok())
+			.map(u64::from_le_bytes)
+			.ok_or(InvalidInstruction)?;
+		Ok(amount)
+	}
+
+	fn unpack_pubkey(input: &[u8]) -> Result<Pubkey, ProgramError> {
+		let key = input.get(..32).map(Pubkey::new).ok_or(InvalidInstruction)?;
+		Ok(key)
+	}
+
+	/// Optional pubkey at the end of the data, like spl-token's COption: no bytes or a 0 tag means None,
+	/// a 1 tag is followed by the 32 byte key
+	fn unpack_pubkey_option(input: &[u8]) -> Result<(Option<Pubkey>, &[u8]), ProgramError> {
+		match input.split_first() {
+			None => Ok((None, input)),
+			Some((&0, rest)) => Ok((None, rest)),
+			Some((&1, rest)) if rest.len() >= 32 => {
+				let (key, rest) = rest.split_at(32);
+				Ok((Some(Pubkey::new(key)), rest))
+			},
+			_ => Err(InvalidInstruction.into()),
+		}
+	}
+
+	/// Same as unpack_pubkey_option but for a merkle root followed by a u8 proof length
+	fn unpack_proof_option(input: &[u8]) -> Result<(Option<CompressedNftProof>, &[u8]), ProgramError> {
+		match input.split_first() {
+			None => Ok((None, input)),
+			Some((&0, rest)) => Ok((None, rest)),
+			Some((&1, rest)) if rest.len() >= 33 => Ok((Some((rest[..32].try_into().unwrap(), rest[32])), &rest[33..])),
+			_ => Err(InvalidInstruction.into()),
+		}
+	}
+
+	/// Same as unpack_pubkey_option but for a u64
+	fn unpack_amount_option(input: &[u8]) -> Result<(Option<u64>, &[u8]), ProgramError> {
+		match input.split_first() {
+			None => Ok((None, input)),
+			Some((&0, rest)) => Ok((None, rest)),
+			Some((&1, rest)) if rest.len() >= 8 => Ok((Some(Self::unpack_amount(rest)?), &rest[8..])),
+			_ => Err(InvalidInstruction.into()),
+		}
+	}
+
+	fn pack_pubkey_option(buf: &mut Vec<u8>, key: &Option<Pubkey>) {
+		match key {
+			Some(key) => {
+				buf.push(1);
+				buf.extend_from_slice(key.as_ref());
+			},
+			None => buf.push(0),
+		}
+	}
+
+	fn pack_proof_option(buf: &mut Vec<u8>, proof: &Option<CompressedNftProof>) {
+		match proof {
+			Some((root, proof_len)) => {
+				buf.push(1);
+				buf.extend_from_slice(root);
+				buf.push(*proof_len);
+			},
+			None => buf.push(0),
+		}
+	}
+
+	fn pack_amount_option(buf: &mut Vec<u8>, amount: &Option<u64>) {
+		match amount {
+			Some(amount) => {
+				buf.push(1);
+				buf.extend_from_slice(&amount.to_le_bytes());
+			},
+			None => buf.push(0),
+		}
+	}
+}
+
+/// InitEscrow's accounts for a plain token deposit, in the order the program takes them (the numbers are
+/// the ones in InitEscrow's docs). The system program fills its slot by itself, the rent and clock
+/// sysvars aren't passed anymore. The accounts SOL, NFT, stake account and multisig deposits take aren't in here
+pub struct InitEscrowAccounts {
+	/// 0
+	pub initializer: Pubkey,
+	/// 1
+	pub temp_token_account: Pubkey,
+	/// 2
+	pub token_to_receive_account: Pubkey,
+	/// 3
+	pub escrow: Pubkey,
+	/// 4
+	pub token_program: Pubkey,
+	/// 5, find_treasury_address
+	pub treasury: Pubkey,
+	/// 7, Config::find_address
+	pub config: Pubkey,
+	/// 8, Config::find_standby_address if the config has a standby config
+	pub standby_config: Option<Pubkey>,
+	/// 9, with `rent_payer`
+	pub rent_payer: Option<Pubkey>,
+	/// 13
+	pub deposit_mint: Pubkey,
+	/// 14
+	pub receive_mint: Pubkey,
+	/// 15, with `predecessor`
+	pub predecessor: Option<Pubkey>,
+	/// 17
+	pub group: Option<Pubkey>,
+}
+
+impl InitEscrowAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new(self.initializer, true),
+			AccountMeta::new(self.temp_token_account, false),
+			AccountMeta::new_readonly(self.token_to_receive_account, false),
+			AccountMeta::new(self.escrow, false),
+			AccountMeta::new_readonly(self.token_program, false),
+			AccountMeta::new(self.treasury, false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new(self.config, false),
+		];
+		if let Some(standby_config) = self.standby_config {
+			accounts.push(AccountMeta::new_readonly(standby_config, false));
+		}
+		if let Some(rent_payer) = self.rent_payer {
+			accounts.push(AccountMeta::new(rent_payer, true));
+		}
+		accounts.push(AccountMeta::new_readonly(self.deposit_mint, false));
+		accounts.push(AccountMeta::new_readonly(self.receive_mint, false));
+		if let Some(predecessor) = self.predecessor {
+			accounts.push(AccountMeta::new_readonly(predecessor, false));
+		}
+		if let Some(group) = self.group {
+			accounts.push(AccountMeta::new(group, false));
+		}
+		accounts
+	}
+}
+
+/// Exchange's accounts for an escrow of a plain token deposit whose receive accounts exist, in the order
+/// the program takes them. The sysvars and the system program are put in by themselves where the flags
+/// say so. Transfer hook, fee and interest accounts, NFT accounts, the surplus account and the receipt
+/// accounts aren't in here
+pub struct ExchangeAccounts {
+	/// 0
+	pub taker: Pubkey,
+	/// 1
+	pub takers_sending_account: Pubkey,
+	/// 2
+	pub takers_token_to_receive_account: Pubkey,
+	/// 3
+	pub temp_token_account: Pubkey,
+	/// 4, the escrow's refund address
+	pub refund_address: Pubkey,
+	/// 5
+	pub initializers_token_to_receive_account: Pubkey,
+	/// 6
+	pub escrow: Pubkey,
+	/// 7
+	pub token_program: Pubkey,
+	/// 8, Escrow::authority_address
+	pub pda: Pubkey,
+	/// 9
+	pub config: Pubkey,
+	/// 10
+	pub sending_token_program: Pubkey,
+	pub receive_mint: Pubkey,
+	pub deposit_mint: Pubkey,
+	/// The system program, for escrows asking for lamports on top
+	pub lamports: bool,
+	/// find_treasury_address followed by the system program, for escrows that paid a listing fee
+	pub treasury: Option<Pubkey>,
+	pub predecessor: Option<Pubkey>,
+	pub reference: Option<Pubkey>,
+	/// The escrow's group, if it's in one
+	pub group: Option<Pubkey>,
+}
+
+impl ExchangeAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new(self.taker, true),
+			AccountMeta::new(self.takers_sending_account, false),
+			AccountMeta::new(self.takers_token_to_receive_account, false),
+			AccountMeta::new(self.temp_token_account, false),
+			AccountMeta::new(self.refund_address, false),
+			AccountMeta::new(self.initializers_token_to_receive_account, false),
+			AccountMeta::new(self.escrow, false),
+			AccountMeta::new_readonly(self.token_program, false),
+			AccountMeta::new_readonly(self.pda, false),
+			AccountMeta::new(self.config, false),
+			AccountMeta::new_readonly(self.sending_token_program, false),
+			AccountMeta::new_readonly(self.receive_mint, false),
+			AccountMeta::new(self.deposit_mint, false),
+		];
+		if self.lamports {
+			accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+		}
+		if let Some(treasury) = self.treasury {
+			accounts.push(AccountMeta::new(treasury, false));
+			accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+		}
+		if let Some(predecessor) = self.predecessor {
+			accounts.push(AccountMeta::new_readonly(predecessor, false));
+		}
+		if let Some(reference) = self.reference {
+			accounts.push(AccountMeta::new_readonly(reference, false));
+		}
+		if let Some(group) = self.group {
+			accounts.push(AccountMeta::new(group, false));
+		}
+		accounts
+	}
+
+	/// The same accounts in ExchangeV2's order, `sending_token_program` only if it isn't `token_program`
+	/// and the system program once
+	pub fn to_v2_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new(self.taker, true),
+			AccountMeta::new(self.takers_sending_account, false),
+			AccountMeta::new(self.takers_token_to_receive_account, false),
+			AccountMeta::new(self.temp_token_account, false),
+			AccountMeta::new(self.refund_address, false),
+			AccountMeta::new(self.initializers_token_to_receive_account, false),
+			AccountMeta::new(self.escrow, false),
+			AccountMeta::new_readonly(self.token_program, false),
+			AccountMeta::new_readonly(self.pda, false),
+			AccountMeta::new(self.config, false),
+		];
+		if self.sending_token_program != self.token_program {
+			accounts.push(AccountMeta::new_readonly(self.sending_token_program, false));
+		}
+		if self.lamports || self.treasury.is_some() {
+			accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+		}
+		accounts.push(AccountMeta::new_readonly(self.receive_mint, false));
+		accounts.push(AccountMeta::new(self.deposit_mint, false));
+		if let Some(treasury) = self.treasury {
+			accounts.push(AccountMeta::new(treasury, false));
+		}
+		if let Some(predecessor) = self.predecessor {
+			accounts.push(AccountMeta::new_readonly(predecessor, false));
+		}
+		if let Some(reference) = self.reference {
+			accounts.push(AccountMeta::new_readonly(reference, false));
+		}
+		if let Some(group) = self.group {
+			accounts.push(AccountMeta::new(group, false));
+		}
+		accounts
+	}
+}
+
+/// Cancel's accounts for an escrow of a token deposit, in the order the program takes them. The pNFT,
+/// compressed NFT and multisig accounts aren't in here
+pub struct CancelAccounts {
+	/// 0, signs
+	pub initializer: Pubkey,
+	/// 1
+	pub temp_token_account: Pubkey,
+	/// 2
+	pub refund_token_account: Pubkey,
+	/// 3
+	pub escrow: Pubkey,
+	/// 4
+	pub token_program: Pubkey,
+	/// 5, Escrow::authority_address
+	pub pda: Pubkey,
+	/// 6
+	pub refund_address: Pubkey,
+	/// 7, if the escrow is in one
+	pub group: Option<Pubkey>,
+	/// 8
+	pub deposit_mint: Pubkey,
+	/// Config::find_address if the escrow paid a listing fee
+	pub config: Option<Pubkey>,
+}
+
+impl CancelAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new_readonly(self.initializer, true),
+			AccountMeta::new(self.temp_token_account, false),
+			AccountMeta::new(self.refund_token_account, false),
+			AccountMeta::new(self.escrow, false),
+			AccountMeta::new_readonly(self.token_program, false),
+			AccountMeta::new_readonly(self.pda, false),
+			AccountMeta::new(self.refund_address, false),
+		];
+		if let Some(group) = self.group {
+			accounts.push(AccountMeta::new(group, false));
+		}
+		accounts.push(AccountMeta::new(self.deposit_mint, false));
+		if let Some(config) = self.config {
+			accounts.push(AccountMeta::new(config, false));
+		}
+		accounts
+	}
+}
+
+/// InitEscrow for a plain token deposit of `temp_token_account`'s tokens, with every option left off. The
+/// temp token account has to be funded and owned by `initializer` already. `standby_config` says whether
+/// the config has a standby config account, the trackers (see InitEscrow) can be pushed onto the accounts
+/// after
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow(
+	program_id: &Pubkey,
+	initializer: &Pubkey,
+	temp_token_account: &Pubkey,
+	token_to_receive_account: &Pubkey,
+	escrow_account: &Pubkey,
+	token_program_id: &Pubkey,
+	deposit_mint: &Pubkey,
+	receive_mint: &Pubkey,
+	standby_config: bool,
+	amount: u64,
+) -> Result<Instruction, ProgramError> {
+	check_program_account(program_id)?;
+	check_token_program_id(token_program_id)?;
+	let data = EscrowInstruction::InitEscrow {
+		amount,
+		predecessor: None,
+		refund_address: None,
+		expires_at: None,
+		native_sol_deposit: None,
+		wrap_sol_deposit: None,
+		nft: false,
+		collection: None,
+		compressed_nft: None,
+		multisig_signers: 0,
+		lamports_deposit: None,
+		expected_lamports: None,
+		stake_account: false,
+		vault_deposit: None,
+		escrow_seed: None,
+		retain_history: false,
+		memo: None,
+		rent_payer: false,
+		prevent_self_trade: false,
+	}
+	.pack();
+
+	let accounts = InitEscrowAccounts {
+		initializer: *initializer,
+		temp_token_account: *temp_token_account,
+		token_to_receive_account: *token_to_receive_account,
+		escrow: *escrow_account,
+		token_program: *token_program_id,
+		treasury: find_treasury_address(program_id).0,
+		config: Config::find_address(program_id).0,
+		standby_config: standby_config.then(|| Config::find_standby_address(program_id).0),
+		rent_payer: None,
+		deposit_mint: *deposit_mint,
+		receive_mint: *receive_mint,
+		predecessor: None,
+		group: None,
+	};
+
+	Ok(Instruction {
+		program_id: *program_id,
+		accounts: accounts.to_account_metas(),
+		data,
+	})
+}
+
+/// Exchange of `escrow`, the escrow account at `escrow_account`, for escrows of plain token deposits. Both
+/// of the taker's token accounts have to exist already. Token-2022 mints with a transfer hook, a transfer
+/// fee or interest take more accounts than these, as do NFT escrows and a temp token account holding a
+/// surplus (see Exchange). Like with init_escrow the trackers can be pushed onto the accounts after
+#[allow(clippy::too_many_arguments)]
+pub fn exchange(
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	escrow: &Escrow,
+	taker: &Pubkey,
+	takers_sending_account: &Pubkey,
+	takers_token_to_receive_account: &Pubkey,
+	token_program_id: &Pubkey,
+	sending_token_program_id: &Pubkey,
+	reference: Option<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+	let accounts = exchange_accounts(
+		program_id,
+		escrow_account,
+		escrow,
+		taker,
+		takers_sending_account,
+		takers_token_to_receive_account,
+		token_program_id,
+		sending_token_program_id,
+		reference,
+	)?;
+	let data = EscrowInstruction::Exchange {
+		amount: escrow.deposited_amount,
+		reference,
+		max_amount_in: None,
+		min_amount_out: None,
+		unwrap_sol: false,
+		compressed_nft_proof: None,
+		receipt: false,
+	}
+	.pack();
+
+	Ok(Instruction {
+		program_id: *program_id,
+		accounts: accounts.to_account_metas(),
+		data,
+	})
+}
+
+/// exchange with ExchangeV2's fewer accounts. Programs from before ExchangeV2 fail it with
+/// UnsupportedInstructionVersion, exchange is what to send them
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_v2(
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	escrow: &Escrow,
+	taker: &Pubkey,
+	takers_sending_account: &Pubkey,
+	takers_token_to_receive_account: &Pubkey,
+	token_program_id: &Pubkey,
+	sending_token_program_id: &Pubkey,
+	reference: Option<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+	let accounts = exchange_accounts(
+		program_id,
+		escrow_account,
+		escrow,
+		taker,
+		takers_sending_account,
+		takers_token_to_receive_account,
+		token_program_id,
+		sending_token_program_id,
+		reference,
+	)?;
+	let data = EscrowInstruction::ExchangeV2 {
+		amount: escrow.deposited_amount,
+		reference,
+		max_amount_in: None,
+		min_amount_out: None,
+		unwrap_sol: false,
+		compressed_nft_proof: None,
+		receipt: false,
+	}
+	.pack();
+
+	Ok(Instruction {
+		program_id: *program_id,
+		accounts: accounts.to_v2_account_metas(),
+		data,
+	})
+}
+
+#[allow(clippy::too_many_arguments)]
+fn exchange_accounts(
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	escrow: &Escrow,
+	taker: &Pubkey,
+	takers_sending_account: &Pubkey,
+	takers_token_to_receive_account: &Pubkey,
+	token_program_id: &Pubkey,
+	sending_token_program_id: &Pubkey,
+	reference: Option<Pubkey>,
+) -> Result<ExchangeAccounts, ProgramError> {
+	check_program_account(program_id)?;
+	check_token_program_id(token_program_id)?;
+	check_token_program_id(sending_token_program_id)?;
+	Ok(ExchangeAccounts {
+		taker: *taker,
+		takers_sending_account: *takers_sending_account,
+		takers_token_to_receive_account: *takers_token_to_receive_account,
+		temp_token_account: escrow.temp_token_account_pubkey,
+		refund_address: escrow.refund_pubkey,
+		initializers_token_to_receive_account: escrow.initializer_token_to_receive_account_pubkey,
+		escrow: *escrow_account,
+		token_program: *token_program_id,
+		pda: escrow.authority_address(escrow_account, program_id)?,
+		config: Config::find_address(program_id).0,
+		sending_token_program: *sending_token_program_id,
+		receive_mint: escrow.receive_mint_pubkey,
+		deposit_mint: escrow.deposit_mint_pubkey,
+		lamports: escrow.expected_lamports > 0,
+		treasury: (escrow.listing_fee > 0).then(|| find_treasury_address(program_id).0),
+		predecessor: Some(escrow.predecessor_pubkey).filter(|predecessor| *predecessor != Pubkey::default()),
+		reference,
+		group: Some(escrow.group_pubkey).filter(|group| *group != Pubkey::default()),
+	})
+}
+
+/// Cancel of `escrow`, the escrow account at `escrow_account`, for escrows of plain token deposits signed
+/// by their initializer. `refund_token_account` is the refund address's token account for the deposit
+pub fn cancel(
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	escrow: &Escrow,
+	refund_token_account: &Pubkey,
+	token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+	check_program_account(program_id)?;
+	check_token_program_id(token_program_id)?;
+	let data = EscrowInstruction::Cancel { compressed_nft_proof: None }.pack();
+
+	let accounts = CancelAccounts {
+		initializer: escrow.initializer_pubkey,
+		temp_token_account: escrow.temp_token_account_pubkey,
+		refund_token_account: *refund_token_account,
+		escrow: *escrow_account,
+		token_program: *token_program_id,
+		pda: escrow.authority_address(escrow_account, program_id)?,
+		refund_address: escrow.refund_pubkey,
+		group: Some(escrow.group_pubkey).filter(|group| *group != Pubkey::default()),
+		deposit_mint: escrow.deposit_mint_pubkey,
+		config: listing_fee_config(program_id, escrow),
+	};
+
+	Ok(Instruction {
+		program_id: *program_id,
+		accounts: accounts.to_account_metas(),
+		data,
+	})
+}
+
+/// CancelGroup's accounts, in the order the program takes them. The native SOL escrows of a group take
+/// the system program instead of a token program and no mint, so they aren't in here
+pub struct CancelGroupAccounts {
+	/// 0, signs
+	pub maker: Pubkey,
+	/// 1
+	pub group: Pubkey,
+	/// 2
+	pub token_program: Pubkey,
+	/// 3..
+	pub escrows: Vec<CancelGroupEscrowAccounts>,
+}
+
+/// The accounts CancelGroup takes for each escrow of a token deposit
+pub struct CancelGroupEscrowAccounts {
+	pub escrow: Pubkey,
+	pub temp_token_account: Pubkey,
+	pub refund_token_account: Pubkey,
+	pub refund_address: Pubkey,
+	/// Escrow::authority_address
+	pub pda: Pubkey,
+	pub deposit_mint: Pubkey,
+	/// Config::find_address if the escrow paid a listing fee
+	pub config: Option<Pubkey>,
+}
+
+impl CancelGroupAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new(self.maker, true),
+			AccountMeta::new(self.group, false),
+			AccountMeta::new_readonly(self.token_program, false),
+		];
+		for escrow in &self.escrows {
+			accounts.push(AccountMeta::new(escrow.escrow, false));
+			accounts.push(AccountMeta::new(escrow.temp_token_account, false));
+			accounts.push(AccountMeta::new(escrow.refund_token_account, false));
+			accounts.push(AccountMeta::new(escrow.refund_address, false));
+			accounts.push(AccountMeta::new_readonly(escrow.pda, false));
+			accounts.push(AccountMeta::new(escrow.deposit_mint, false));
+			if let Some(config) = escrow.config {
+				accounts.push(AccountMeta::new(config, false));
+			}
+		}
+		accounts
+	}
+}
+
+/// MigrateEscrow's accounts, in the order the program takes them
+pub struct MigrateEscrowAccounts {
+	/// 0, signs
+	pub payer: Pubkey,
+	/// 1
+	pub escrow: Pubkey,
+	/// 4 to 9, only for v0 escrows
+	pub v0: Option<MigrateV0EscrowAccounts>,
+}
+
+/// The accounts MigrateEscrow reads a v0 escrow's mints and deposit from
+pub struct MigrateV0EscrowAccounts {
+	/// 4
+	pub temp_token_account: Pubkey,
+	/// 5
+	pub token_to_receive_account: Pubkey,
+	/// 6
+	pub deposit_mint: Pubkey,
+	/// 7
+	pub receive_mint: Pubkey,
+	/// 8
+	pub token_program: Pubkey,
+	/// 9, Escrow::find_legacy_authority_address
+	pub legacy_pda: Pubkey,
+}
+
+impl MigrateEscrowAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new(self.payer, true),
+			AccountMeta::new(self.escrow, false),
+			AccountMeta::new_readonly(sysvar::rent::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		];
+		if let Some(v0) = &self.v0 {
+			accounts.push(AccountMeta::new(v0.temp_token_account, false));
+			accounts.push(AccountMeta::new_readonly(v0.token_to_receive_account, false));
+			accounts.push(AccountMeta::new_readonly(v0.deposit_mint, false));
+			accounts.push(AccountMeta::new_readonly(v0.receive_mint, false));
+			accounts.push(AccountMeta::new_readonly(v0.token_program, false));
+			accounts.push(AccountMeta::new_readonly(v0.legacy_pda, false));
+		}
+		accounts
+	}
+}
+
+/// The accounts of the instructions an escrow's initializer sends on their own, FreezeEscrow, ThawEscrow
+/// and CloseEscrowRecord, in the order the program takes them
+pub struct InitializerAccounts {
+	/// 0, signs
+	pub initializer: Pubkey,
+	/// 1
+	pub escrow: Pubkey,
+	/// 2, CloseEscrowRecord's only
+	pub refund_address: Option<Pubkey>,
+}
+
+impl InitializerAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new_readonly(self.initializer, true),
+			AccountMeta::new(self.escrow, false),
+		];
+		if let Some(refund_address) = self.refund_address {
+			accounts.push(AccountMeta::new(refund_address, false));
+		}
+		accounts
+	}
+}
+
+/// SweepSurplus's accounts for an escrow of a plain token deposit, in the order the program takes them
+pub struct SweepSurplusAccounts {
+	/// 0, signs
+	pub initializer: Pubkey,
+	/// 1
+	pub temp_token_account: Pubkey,
+	/// 2
+	pub surplus_token_account: Pubkey,
+	/// 3
+	pub escrow: Pubkey,
+	/// 4
+	pub token_program: Pubkey,
+	/// 5, Escrow::authority_address
+	pub pda: Pubkey,
+	/// 6, the program doesn't read it when there's no surplus
+	pub deposit_mint: Pubkey,
+}
+
+impl SweepSurplusAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		vec![
+			AccountMeta::new_readonly(self.initializer, true),
+			AccountMeta::new(self.temp_token_account, false),
+			AccountMeta::new(self.surplus_token_account, false),
+			AccountMeta::new_readonly(self.escrow, false),
+			AccountMeta::new_readonly(self.token_program, false),
+			AccountMeta::new_readonly(self.pda, false),
+			AccountMeta::new(self.deposit_mint, false),
+		]
+	}
+}
+
+/// The accounts of the instructions a config's own admin sends, UpdateConfig, Heartbeat, NominateAdmin,
+/// AcceptAdmin (signed by the nominated admin) and SetListingFee
+pub struct ConfigAccounts {
+	/// 0, signs
+	pub admin: Pubkey,
+	/// 1, Config::find_address or Config::find_standby_address
+	pub config: Pubkey,
+}
+
+impl ConfigAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		vec![
+			AccountMeta::new_readonly(self.admin, true),
+			AccountMeta::new(self.config, false),
+		]
+	}
+}
+
+/// The accounts of the instructions setting the flags on the primary config, SetPaused,
+/// SetExtensionPolicy, SetFeatures and SetTopLevelExchange, which the admin of the config in effect sends
+pub struct ConfigInEffectAccounts {
+	/// 0, signs
+	pub admin: Pubkey,
+	/// 1, Config::find_address
+	pub config: Pubkey,
+	/// 2, Config::find_standby_address if the config has a standby config
+	pub standby_config: Option<Pubkey>,
+}
+
+impl ConfigInEffectAccounts {
+	fn new(program_id: &Pubkey, admin: &Pubkey, standby_config: bool) -> Self {
+		ConfigInEffectAccounts {
+			admin: *admin,
+			config: Config::find_address(program_id).0,
+			standby_config: standby_config.then(|| Config::find_standby_address(program_id).0),
+		}
+	}
+
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new_readonly(self.admin, true),
+			AccountMeta::new(self.config, false),
+		];
+		if let Some(standby_config) = self.standby_config {
+			accounts.push(AccountMeta::new_readonly(standby_config, false));
+		}
+		accounts
+	}
+}
+
+/// WithdrawTreasury's accounts, in the order the program takes them
+pub struct WithdrawTreasuryAccounts {
+	/// 0, signs
+	pub admin: Pubkey,
+	/// 1, find_treasury_address
+	pub treasury: Pubkey,
+	/// 2
+	pub destination: Pubkey,
+	/// 5, Config::find_address
+	pub config: Pubkey,
+	/// 6, Config::find_standby_address if the config has a standby config
+	pub standby_config: Option<Pubkey>,
+}
+
+impl WithdrawTreasuryAccounts {
+	pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+		let mut accounts = vec![
+			AccountMeta::new_readonly(self.admin, true),
+			AccountMeta::new(self.treasury, false),
+			AccountMeta::new(self.destination, false),
+			AccountMeta::new_readonly(sysvar::rent::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new_readonly(self.config, false),
+		];
+		if let Some(standby_config) = self.standby_config {
+			accounts.push(AccountMeta::new_readonly(standby_config, false));
+		}
+		accounts
+	}
+}
+
+/// The primary config, for the instructions closing escrows that paid a listing fee the treasury holds back
+fn listing_fee_config(program_id: &Pubkey, escrow: &Escrow) -> Option<Pubkey> {
+	(REFUND_LISTING_FEE_ON_EXCHANGE && escrow.listing_fee > 0).then(|| Config::find_address(program_id).0)
+}
+
+fn instruction(program_id: &Pubkey, accounts: Vec<AccountMeta>, instruction: EscrowInstruction) -> Result<Instruction, ProgramError> {
+	check_program_account(program_id)?;
+	Ok(Instruction {
+		program_id: *program_id,
+		accounts,
+		data: instruction.pack(),
+	})
+}
+
+/// CancelGroup of the group at `group_account` made by `maker`, for groups of plain token deposits of one
+/// token program. `escrows` are the escrow accounts with their escrows and the refund addresses' token
+/// accounts for the deposits, like cancel's. The trackers can be pushed onto the accounts after
+pub fn cancel_group(
+	program_id: &Pubkey,
+	maker: &Pubkey,
+	group_account: &Pubkey,
+	escrows: &[(Pubkey, &Escrow, Pubkey)],
+	token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+	check_token_program_id(token_program_id)?;
+	let accounts = CancelGroupAccounts {
+		maker: *maker,
+		group: *group_account,
+		token_program: *token_program_id,
+		escrows: escrows
+			.iter()
+			.map(|(escrow_account, escrow, refund_token_account)| {
+				Ok(CancelGroupEscrowAccounts {
+					escrow: *escrow_account,
+					temp_token_account: escrow.temp_token_account_pubkey,
+					refund_token_account: *refund_token_account,
+					refund_address: escrow.refund_pubkey,
+					pda: escrow.authority_address(escrow_account, program_id)?,
+					deposit_mint: escrow.deposit_mint_pubkey,
+					config: listing_fee_config(program_id, escrow),
+				})
+			})
+			.collect::<Result<_, ProgramError>>()?,
+	};
+	let data = EscrowInstruction::CancelGroup { compressed_nft_proofs: Vec::new() };
+	instruction(program_id, accounts.to_account_metas(), data)
+}
+
+/// MigrateEscrow of a v0 escrow, `escrow` is what Escrow::unpack_versioned_unchecked reads from it. The
+/// mints are the ones of its temp token account and its receive account, `token_program_id` the token
+/// program of both
+pub fn migrate_v0_escrow(
+	program_id: &Pubkey,
+	payer: &Pubkey,
+	escrow_account: &Pubkey,
+	escrow: &Escrow,
+	deposit_mint: &Pubkey,
+	receive_mint: &Pubkey,
+	token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+	check_token_program_id(token_program_id)?;
+	let accounts = MigrateEscrowAccounts {
+		payer: *payer,
+		escrow: *escrow_account,
+		v0: Some(MigrateV0EscrowAccounts {
+			temp_token_account: escrow.temp_token_account_pubkey,
+			token_to_receive_account: escrow.initializer_token_to_receive_account_pubkey,
+			deposit_mint: *deposit_mint,
+			receive_mint: *receive_mint,
+			token_program: *token_program_id,
+			legacy_pda: Escrow::find_legacy_authority_address(program_id).0,
+		}),
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::MigrateEscrow)
+}
+
+/// CloseEscrowRecord of `escrow`, the settled escrow account at `escrow_account`
+pub fn close_escrow_record(program_id: &Pubkey, escrow_account: &Pubkey, escrow: &Escrow) -> Result<Instruction, ProgramError> {
+	let accounts = InitializerAccounts {
+		initializer: escrow.initializer_pubkey,
+		escrow: *escrow_account,
+		refund_address: Some(escrow.refund_pubkey),
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::CloseEscrowRecord)
+}
+
+/// FreezeEscrow of the escrow account at `escrow_account`, signed by its initializer
+pub fn freeze_escrow(program_id: &Pubkey, initializer: &Pubkey, escrow_account: &Pubkey) -> Result<Instruction, ProgramError> {
+	let accounts = InitializerAccounts {
+		initializer: *initializer,
+		escrow: *escrow_account,
+		refund_address: None,
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::FreezeEscrow)
+}
+
+/// ThawEscrow of the escrow account at `escrow_account`, signed by its initializer
+pub fn thaw_escrow(program_id: &Pubkey, initializer: &Pubkey, escrow_account: &Pubkey) -> Result<Instruction, ProgramError> {
+	let accounts = InitializerAccounts {
+		initializer: *initializer,
+		escrow: *escrow_account,
+		refund_address: None,
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::ThawEscrow)
+}
+
+/// SweepSurplus of `escrow`, the escrow account at `escrow_account`, for escrows of plain token deposits.
+/// `surplus_token_account` is the initializer's token account that gets the surplus
+pub fn sweep_surplus(
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	escrow: &Escrow,
+	surplus_token_account: &Pubkey,
+	token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+	check_token_program_id(token_program_id)?;
+	let accounts = SweepSurplusAccounts {
+		initializer: escrow.initializer_pubkey,
+		temp_token_account: escrow.temp_token_account_pubkey,
+		surplus_token_account: *surplus_token_account,
+		escrow: *escrow_account,
+		token_program: *token_program_id,
+		pda: escrow.authority_address(escrow_account, program_id)?,
+		deposit_mint: escrow.deposit_mint_pubkey,
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::SweepSurplus)
+}
+
+/// InitializeConfig signed and paid for by the program's upgrade authority
+pub fn initialize_config(
+	program_id: &Pubkey,
+	upgrade_authority: &Pubkey,
+	admin: &Pubkey,
+	listing_fee_lamports: u64,
+	heartbeat_period: i64,
+	crank_bounty_lamports: u64,
+	dormancy_period: i64,
+) -> Result<Instruction, ProgramError> {
+	let accounts = vec![
+		AccountMeta::new(*upgrade_authority, true),
+		AccountMeta::new(Config::find_address(program_id).0, false),
+		AccountMeta::new_readonly(Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0, false),
+		AccountMeta::new_readonly(sysvar::rent::id(), false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	let data = EscrowInstruction::InitializeConfig {
+		admin: *admin,
+		listing_fee_lamports,
+		heartbeat_period,
+		crank_bounty_lamports,
+		dormancy_period,
+	};
+	instruction(program_id, accounts, data)
+}
+
+/// InitializeStandbyConfig signed and paid for by the primary config's admin `primary_admin`, `admin` is
+/// the standby config's own admin
+pub fn initialize_standby_config(
+	program_id: &Pubkey,
+	primary_admin: &Pubkey,
+	admin: &Pubkey,
+	listing_fee_lamports: u64,
+	crank_bounty_lamports: u64,
+	dormancy_period: i64,
+) -> Result<Instruction, ProgramError> {
+	let accounts = vec![
+		AccountMeta::new(*primary_admin, true),
+		AccountMeta::new(Config::find_address(program_id).0, false),
+		AccountMeta::new(Config::find_standby_address(program_id).0, false),
+		AccountMeta::new_readonly(sysvar::rent::id(), false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	let data = EscrowInstruction::InitializeStandbyConfig {
+		admin: *admin,
+		listing_fee_lamports,
+		crank_bounty_lamports,
+		dormancy_period,
+	};
+	instruction(program_id, accounts, data)
+}
+
+/// UpdateConfig of the (primary or standby) config account at `config`
+pub fn update_config(
+	program_id: &Pubkey,
+	admin: &Pubkey,
+	config: &Pubkey,
+	listing_fee_lamports: u64,
+	crank_bounty_lamports: u64,
+	dormancy_period: i64,
+) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigAccounts { admin: *admin, config: *config };
+	let data = EscrowInstruction::UpdateConfig {
+		listing_fee_lamports,
+		crank_bounty_lamports,
+		dormancy_period,
+	};
+	instruction(program_id, accounts.to_account_metas(), data)
+}
+
+/// Heartbeat of the primary config's admin
+pub fn heartbeat(program_id: &Pubkey, admin: &Pubkey) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigAccounts {
+		admin: *admin,
+		config: Config::find_address(program_id).0,
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::Heartbeat)
+}
+
+/// NominateAdmin of `new_admin` for the (primary or standby) config account at `config`
+pub fn nominate_admin(program_id: &Pubkey, admin: &Pubkey, config: &Pubkey, new_admin: &Pubkey) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigAccounts { admin: *admin, config: *config };
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::NominateAdmin { new_admin: *new_admin })
+}
+
+/// AcceptAdmin of the config account at `config`, signed by the admin it nominated
+pub fn accept_admin(program_id: &Pubkey, new_admin: &Pubkey, config: &Pubkey) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigAccounts { admin: *new_admin, config: *config };
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::AcceptAdmin)
+}
+
+/// SetListingFee of the (primary or standby) config account at `config`
+pub fn set_listing_fee(program_id: &Pubkey, admin: &Pubkey, config: &Pubkey, listing_fee_lamports: u64) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigAccounts { admin: *admin, config: *config };
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::SetListingFee { listing_fee_lamports })
+}
+
+/// SetPaused signed by the admin of the config in effect. `standby_config` says whether the config has a
+/// standby config account, like init_escrow's
+pub fn set_paused(program_id: &Pubkey, admin: &Pubkey, standby_config: bool, paused: bool) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigInEffectAccounts::new(program_id, admin, standby_config);
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::SetPaused { paused })
+}
+
+/// SetExtensionPolicy signed by the admin of the config in effect, see set_paused
+pub fn set_extension_policy(
+	program_id: &Pubkey,
+	admin: &Pubkey,
+	standby_config: bool,
+	policy: ExtensionPolicy,
+) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigInEffectAccounts::new(program_id, admin, standby_config);
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::SetExtensionPolicy { policy })
+}
+
+/// SetFeatures signed by the admin of the config in effect, see set_paused
+pub fn set_features(program_id: &Pubkey, admin: &Pubkey, standby_config: bool, disabled_features: u16) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigInEffectAccounts::new(program_id, admin, standby_config);
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::SetFeatures { disabled_features })
+}
+
+/// SetTopLevelExchange signed by the admin of the config in effect, see set_paused
+pub fn set_top_level_exchange(
+	program_id: &Pubkey,
+	admin: &Pubkey,
+	standby_config: bool,
+	top_level_only: bool,
+) -> Result<Instruction, ProgramError> {
+	let accounts = ConfigInEffectAccounts::new(program_id, admin, standby_config);
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::SetTopLevelExchange { top_level_only })
+}
+
+/// WithdrawTreasury of `lamports` to `destination`, signed by the admin of the config in effect, see set_paused
+pub fn withdraw_treasury(
+	program_id: &Pubkey,
+	admin: &Pubkey,
+	destination: &Pubkey,
+	standby_config: bool,
+	lamports: u64,
+) -> Result<Instruction, ProgramError> {
+	let accounts = WithdrawTreasuryAccounts {
+		admin: *admin,
+		treasury: find_treasury_address(program_id).0,
+		destination: *destination,
+		config: Config::find_address(program_id).0,
+		standby_config: standby_config.then(|| Config::find_standby_address(program_id).0),
+	};
+	instruction(program_id, accounts.to_account_metas(), EscrowInstruction::WithdrawTreasury { lamports })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// One of each variant with its optional fields set, InitEscrow twice for the fields only some
+	/// deposits take
+	fn every_variant() -> Vec<EscrowInstruction> {
+		let key = |byte| Pubkey::new_from_array([byte; 32]);
+		vec![
+			EscrowInstruction::InitEscrow {
+				amount: 1_000,
+				predecessor: Some(key(1)),
+				refund_address: Some(key(2)),
+				expires_at: Some(1_700_000_000),
+				native_sol_deposit: Some(5_000),
+				wrap_sol_deposit: None,
+				nft: false,
+				collection: None,
+				compressed_nft: None,
+				multisig_signers: 0,
+				lamports_deposit: Some(6_000),
+				expected_lamports: Some(7_000),
+				stake_account: false,
+				vault_deposit: None,
+				escrow_seed: Some(8),
+				retain_history: true,
+				memo: Some("memo".to_string()),
+				rent_payer: true,
+				prevent_self_trade: true,
+			},
+			EscrowInstruction::InitEscrow {
+				amount: 1,
+				predecessor: None,
+				refund_address: None,
+				expires_at: None,
+				native_sol_deposit: None,
+				wrap_sol_deposit: None,
+				nft: false,
+				collection: Some(key(3)),
+				compressed_nft: Some(CompressedNft {
+					root: [4; 32],
+					data_hash: [5; 32],
+					creator_hash: [6; 32],
+					nonce: 7,
+					proof_len: 14,
+				}),
+				multisig_signers: 0,
+				lamports_deposit: None,
+				expected_lamports: None,
+				stake_account: false,
+				vault_deposit: None,
+				escrow_seed: None,
+				retain_history: false,
+				memo: None,
+				rent_payer: false,
+				prevent_self_trade: false,
+			},
+			EscrowInstruction::Exchange {
+				amount: 2_000,
+				reference: Some(key(8)),
+				max_amount_in: Some(2_100),
+				min_amount_out: Some(900),
+				unwrap_sol: true,
+				compressed_nft_proof: Some(([9; 32], 14)),
+				receipt: true,
+			},
+			EscrowInstruction::Cancel { compressed_nft_proof: Some(([10; 32], 14)) },
+			EscrowInstruction::CreateGroup { name: [11; 32] },
+			EscrowInstruction::CancelGroup { compressed_nft_proofs: vec![([12; 32], 14), ([13; 32], 3)] },
+			EscrowInstruction::BatchInitEscrow { amounts: vec![1, 2, 3] },
+			EscrowInstruction::InitializeConfig {
+				admin: key(14),
+				listing_fee_lamports: 1,
+				heartbeat_period: 2,
+				crank_bounty_lamports: 3,
+				dormancy_period: 4,
+			},
+			EscrowInstruction::InitializeStandbyConfig {
+				admin: key(15),
+				listing_fee_lamports: 1,
+				crank_bounty_lamports: 2,
+				dormancy_period: 3,
+			},
+			EscrowInstruction::UpdateConfig {
+				listing_fee_lamports: 1,
+				crank_bounty_lamports: 2,
+				dormancy_period: 3,
+			},
+			EscrowInstruction::Heartbeat,
+			EscrowInstruction::SweepSurplus,
+			EscrowInstruction::RecoverStrandedAssets,
+			EscrowInstruction::CrankExpired { compressed_nft_proof: Some(([16; 32], 14)) },
+			EscrowInstruction::SetPaused { paused: true },
+			EscrowInstruction::FreezeEscrow,
+			EscrowInstruction::ThawEscrow,
+			EscrowInstruction::SweepDormant { compressed_nft_proof: Some(([17; 32], 14)) },
+			EscrowInstruction::SetExtensionPolicy { policy: ExtensionPolicy::Deny },
+			EscrowInstruction::MigrateEscrow,
+			EscrowInstruction::CloseEscrowRecord,
+			EscrowInstruction::InitializeStats,
+			EscrowInstruction::InitializePairStats { deposit_mint: key(18), receive_mint: key(19) },
+			EscrowInstruction::SetFeatures { disabled_features: 0b101 },
+			EscrowInstruction::NominateAdmin { new_admin: key(20) },
+			EscrowInstruction::AcceptAdmin,
+			EscrowInstruction::SetListingFee { listing_fee_lamports: 1 },
+			EscrowInstruction::WithdrawTreasury { lamports: 2 },
+			EscrowInstruction::InitializeRegistryPage { index: 3 },
+			EscrowInstruction::InitializeIndex,
+			EscrowInstruction::ReallocEscrow { new_len: 4 },
+			EscrowInstruction::SetTopLevelExchange { top_level_only: true },
+			EscrowInstruction::ExchangeV2 {
+				amount: 3_000,
+				reference: Some(key(21)),
+				max_amount_in: Some(3_100),
+				min_amount_out: Some(800),
+				unwrap_sol: true,
+				compressed_nft_proof: Some(([22; 32], 14)),
+				receipt: true,
+			},
+		]
+	}
+
+	/// The instructions aren't PartialEq, their Debug has every field
+	fn assert_same(unpacked: Result<EscrowInstruction, ProgramError>, instruction: &EscrowInstruction) {
+		assert_eq!(format!("{:?}", unpacked.unwrap()), format!("{:?}", instruction));
+	}
+
+	#[test]
+	fn pack_then_unpack_is_the_same_instruction() {
+		for instruction in every_variant() {
+			assert_same(EscrowInstruction::unpack(&instruction.pack()), &instruction);
+		}
+	}
+
+	#[test]
+	fn versioned_instructions_pack_with_their_tag() {
+		let instructions = every_variant();
+		let exchange_v2 = instructions.last().unwrap();
+		assert!(matches!(exchange_v2, EscrowInstruction::ExchangeV2 { .. }));
+		assert_eq!(exchange_v2.pack()[0], EXCHANGE_V2_TAG);
+	}
+
+	#[test]
+	fn trailing_bytes_are_ignored() {
+		for instruction in every_variant() {
+			let mut data = instruction.pack();
+			data.extend_from_slice(&[0xff; 40]);
+			assert_same(EscrowInstruction::unpack(&data), &instruction);
+		}
+	}
+
+	#[cfg(feature = "borsh")]
+	#[test]
+	fn pack_borsh_then_unpack_is_the_same_instruction() {
+		for instruction in every_variant() {
+			let data = instruction.pack_borsh();
+			assert_ne!(data[0] & BORSH_TAG_FLAG, 0);
+			assert_eq!(data[0] & !BORSH_TAG_FLAG, instruction.pack()[0]);
+			assert_same(EscrowInstruction::unpack(&data), &instruction);
+		}
+		let exchange_v2 = every_variant().pop().unwrap();
+		assert_eq!(exchange_v2.pack_borsh()[0], EXCHANGE_V2_TAG | BORSH_TAG_FLAG);
+	}
+
+	#[cfg(feature = "borsh")]
+	#[test]
+	fn borsh_trailing_bytes_are_ignored_and_missing_fields_are_not() {
+		for instruction in every_variant() {
+			let mut data = instruction.pack_borsh();
+			data.extend_from_slice(&[0xff; 40]);
+			assert_same(EscrowInstruction::unpack(&data), &instruction);
+		}
+		let exchange = EscrowInstruction::Exchange {
+			amount: 42,
+			reference: None,
+			max_amount_in: None,
+			min_amount_out: None,
+			unwrap_sol: false,
+			compressed_nft_proof: None,
+			receipt: false,
+		};
+		let data = exchange.pack_borsh();
+		assert!(EscrowInstruction::unpack(&data[..data.len() - 1]).is_err());
+	}
+
+	#[test]
+	fn missing_optional_fields_are_none() {
+		let amount = 42u64.to_le_bytes();
+		match EscrowInstruction::unpack(&[&[0][..], &amount].concat()).unwrap() {
+			EscrowInstruction::InitEscrow {
+				amount: 42,
+				predecessor: None,
+				refund_address: None,
+				expires_at: None,
+				native_sol_deposit: None,
+				wrap_sol_deposit: None,
+				nft: false,
+				collection: None,
+				compressed_nft: None,
+				multisig_signers: 0,
+				lamports_deposit: None,
+				expected_lamports: None,
+				stake_account: false,
+				vault_deposit: None,
+				escrow_seed: None,
+				retain_history: false,
+				memo: None,
+				rent_payer: false,
+				prevent_self_trade: false,
+			} => {},
+			instruction => panic!("{:?}", instruction),
+		}
+		for tag in [1, EXCHANGE_V2_TAG] {
+			// Cut off after the reference too
+			for data in [[&[tag][..], &amount].concat(), [&[tag][..], &amount, &[1], &[8; 32]].concat()] {
+				match EscrowInstruction::unpack(&data).unwrap() {
+					EscrowInstruction::Exchange {
+						amount: 42,
+						max_amount_in: None,
+						min_amount_out: None,
+						unwrap_sol: false,
+						compressed_nft_proof: None,
+						receipt: false,
+						..
+					}
+					| EscrowInstruction::ExchangeV2 {
+						amount: 42,
+						max_amount_in: None,
+						min_amount_out: None,
+						unwrap_sol: false,
+						compressed_nft_proof: None,
+						receipt: false,
+						..
+					} => {},
+					instruction => panic!("{:?}", instruction),
+				}
+			}
+		}
+		for tag in [2, 12, 16] {
+			match EscrowInstruction::unpack(&[tag]).unwrap() {
+				EscrowInstruction::Cancel { compressed_nft_proof: None }
+				| EscrowInstruction::CrankExpired { compressed_nft_proof: None }
+				| EscrowInstruction::SweepDormant { compressed_nft_proof: None } => {},
+				instruction => panic!("{:?}", instruction),
+			}
+		}
+		match EscrowInstruction::unpack(&[4]).unwrap() {
+			EscrowInstruction::CancelGroup { compressed_nft_proofs } => assert!(compressed_nft_proofs.is_empty()),
+			instruction => panic!("{:?}", instruction),
+		}
+	}
+}
@@ -0,0 +1,33 @@
+use solana_program::{entrypoint::ProgramResult, program_error::ProgramError, pubkey::Pubkey};
+
+pub mod address_lookup_table;
+#[cfg(all(feature = "client", not(target_arch = "bpf")))]
+pub mod client;
+pub mod compute_budget;
+pub mod cpi;
+pub mod error;
+pub mod event;
+pub mod instruction;
+pub mod log;
+pub mod parse;
+#[cfg(feature = "serde")]
+mod serde_pubkey;
+pub mod state;
+#[cfg(all(feature = "client", not(target_arch = "bpf")))]
+pub mod subscription;
+pub mod token;
+pub mod treasury;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+// Where the program is deployed. Deployments from target/deploy's own keypair change this to its address
+solana_program::declare_id!("DLLCEFQXAFAqATHaGzR9FV99QnvdntNN1zhUcHdc3Sx5");
+
+/// Makes sure `program_id` is the escrow program's, like spl_token::check_program_account. For
+/// instruction builders and for programs checking the escrow program account they CPI into
+pub fn check_program_account(program_id: &Pubkey) -> ProgramResult {
+	if program_id != &id() {
+		return Err(ProgramError::IncorrectProgramId);
+	}
+	Ok(())
+}
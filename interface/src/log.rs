@@ -0,0 +1,64 @@
+//! Log lines formatted on the stack. msg! with arguments format!s the line into a String first, which
+//! is heap the program never gets back and pulls alloc's formatting into the binary. stack_msg! takes
+//! the same arguments. Pubkeys don't belong in them, their Display encodes to a String too, Pubkey::log
+//! logs one without that
+
+use std::fmt;
+
+use solana_program::log::sol_log;
+
+/// msg! without the heap. Lines longer than LogLine::CAPACITY are cut off
+#[macro_export]
+macro_rules! stack_msg {
+	($($arg:tt)+) => {{
+		use ::std::fmt::Write as _;
+		let mut line = $crate::log::LogLine::new();
+		let _ = ::std::write!(line, $($arg)+);
+		line.log();
+	}};
+}
+
+/// A log line of up to CAPACITY bytes, what doesn't fit is cut off
+pub struct LogLine {
+	buffer: [u8; LogLine::CAPACITY],
+	len: usize,
+}
+
+impl LogLine {
+	/// Enough for the program's messages and a few numbers
+	pub const CAPACITY: usize = 128;
+
+	pub fn new() -> Self {
+		LogLine {
+			buffer: [0; LogLine::CAPACITY],
+			len: 0,
+		}
+	}
+
+	pub fn as_str(&self) -> &str {
+		std::str::from_utf8(&self.buffer[..self.len]).unwrap_or_default()
+	}
+
+	pub fn log(&self) {
+		sol_log(self.as_str());
+	}
+}
+
+impl Default for LogLine {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl fmt::Write for LogLine {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		// Cut at a char boundary so the line stays valid UTF-8
+		let mut end = s.len().min(Self::CAPACITY - self.len);
+		while !s.is_char_boundary(end) {
+			end -= 1;
+		}
+		self.buffer[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+		self.len += end;
+		Ok(())
+	}
+}
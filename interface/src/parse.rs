@@ -0,0 +1,151 @@
+//! Instructions decoded for people, for explorer plugins and wallets' transaction previews. Accounts
+//! get the names the instruction docs give them (the `#[account(..)]` attributes). Only the leading
+//! accounts every call passes are named, the rest depend on the escrow and the flags and stay unnamed
+
+use std::fmt;
+
+use solana_program::{program_error::ProgramError, pubkey::Pubkey, sysvar};
+
+use crate::instruction::EscrowInstruction;
+
+#[derive(Debug)]
+pub struct ParsedEscrowInstruction {
+	/// The variant's name, like "InitEscrow"
+	pub name: &'static str,
+	pub instruction: EscrowInstruction,
+	pub accounts: Vec<ParsedAccount>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParsedAccount {
+	/// Position in the instruction's accounts
+	pub index: usize,
+	/// None for the accounts after the ones every call passes
+	pub name: Option<&'static str>,
+	pub pubkey: Pubkey,
+}
+
+/// Decodes `data` and puts names on `accounts`, the instruction's account keys in order
+pub fn parse_instruction(data: &[u8], accounts: &[Pubkey]) -> Result<ParsedEscrowInstruction, ProgramError> {
+	let instruction = EscrowInstruction::unpack(data)?;
+	let (name, account_names) = match instruction {
+		// The old InitEscrow layout, with the rent sysvar
+		EscrowInstruction::InitEscrow { .. } if matches!(accounts.get(4), Some(account) if sysvar::rent::check_id(account)) => ("InitEscrow", &[
+			"initializer", "temp_token_account", "token_to_receive_account", "escrow", "rent", "token_program",
+			"treasury", "system_program", "config", "clock",
+		][..]),
+		_ => describe(&instruction),
+	};
+	let accounts = accounts
+		.iter()
+		.enumerate()
+		.map(|(index, pubkey)| ParsedAccount {
+			index,
+			name: account_names.get(index).copied(),
+			pubkey: *pubkey,
+		})
+		.collect();
+	Ok(ParsedEscrowInstruction {
+		name,
+		instruction,
+		accounts,
+	})
+}
+
+/// The instruction's name and the names of the accounts up to its first optional one, which are the
+/// `#[account(..)]` attributes' in instruction.rs
+fn describe(instruction: &EscrowInstruction) -> (&'static str, &'static [&'static str]) {
+	match instruction {
+		EscrowInstruction::InitEscrow { .. } => ("InitEscrow", &[
+			"initializer", "temp_token_account", "token_to_receive_account", "escrow", "token_program", "treasury",
+			"system_program", "config",
+		]),
+		EscrowInstruction::Exchange { .. } => ("Exchange", &[
+			"taker", "takers_sending_account", "takers_token_to_receive_account", "temp_token_account",
+			"initializer", "initializers_token_to_receive_account", "escrow", "token_program", "pda", "config",
+			"sending_token_program",
+		]),
+		EscrowInstruction::Cancel { .. } => ("Cancel", &[
+			"initializer", "temp_token_account", "refund_token_account", "escrow", "token_program", "pda",
+			"refund_address",
+		]),
+		EscrowInstruction::CreateGroup { .. } => ("CreateGroup", &["maker", "group", "rent", "system_program"]),
+		EscrowInstruction::CancelGroup { .. } => ("CancelGroup", &["maker", "group", "token_program"]),
+		EscrowInstruction::BatchInitEscrow { .. } => ("BatchInitEscrow", &[
+			"initializer", "token_program", "treasury", "system_program", "config",
+		]),
+		EscrowInstruction::InitializeConfig { .. } => ("InitializeConfig", &[
+			"upgrade_authority", "config", "program_data", "rent", "system_program",
+		]),
+		EscrowInstruction::InitializeStandbyConfig { .. } => ("InitializeStandbyConfig", &[
+			"admin", "config", "standby_config", "rent", "system_program",
+		]),
+		EscrowInstruction::UpdateConfig { .. } => ("UpdateConfig", &["admin", "config"]),
+		EscrowInstruction::Heartbeat => ("Heartbeat", &["admin", "config"]),
+		EscrowInstruction::SweepSurplus => ("SweepSurplus", &[
+			"initializer", "temp_token_account", "surplus_token_account", "escrow", "token_program", "pda",
+		]),
+		EscrowInstruction::RecoverStrandedAssets => ("RecoverStrandedAssets", &[
+			"admin", "stranded_token_account", "destination_token_account", "token_program", "pda", "escrow",
+			"config",
+		]),
+		EscrowInstruction::CrankExpired { .. } => ("CrankExpired", &[
+			"cranker", "temp_token_account", "refund_token_account", "escrow", "token_program", "pda",
+			"refund_address", "config",
+		]),
+		EscrowInstruction::SetPaused { .. } => ("SetPaused", &["admin", "config"]),
+		EscrowInstruction::FreezeEscrow => ("FreezeEscrow", &["initializer", "escrow"]),
+		EscrowInstruction::ThawEscrow => ("ThawEscrow", &["initializer", "escrow"]),
+		EscrowInstruction::SweepDormant { .. } => ("SweepDormant", &[
+			"sweeper", "temp_token_account", "refund_token_account", "escrow", "token_program", "pda",
+			"refund_address", "config",
+		]),
+		EscrowInstruction::SetExtensionPolicy { .. } => ("SetExtensionPolicy", &["admin", "config"]),
+		EscrowInstruction::MigrateEscrow => ("MigrateEscrow", &["payer", "escrow", "rent", "system_program"]),
+		EscrowInstruction::CloseEscrowRecord => ("CloseEscrowRecord", &[
+			"initializer", "escrow", "refund_address",
+		]),
+		EscrowInstruction::InitializeStats => ("InitializeStats", &["payer", "stats", "rent", "system_program"]),
+		EscrowInstruction::InitializePairStats { .. } => ("InitializePairStats", &[
+			"payer", "pair_stats", "rent", "system_program",
+		]),
+		EscrowInstruction::SetFeatures { .. } => ("SetFeatures", &["admin", "config"]),
+		EscrowInstruction::NominateAdmin { .. } => ("NominateAdmin", &["admin", "config"]),
+		EscrowInstruction::AcceptAdmin => ("AcceptAdmin", &["new_admin", "config"]),
+		EscrowInstruction::SetListingFee { .. } => ("SetListingFee", &["admin", "config"]),
+		EscrowInstruction::WithdrawTreasury { .. } => ("WithdrawTreasury", &[
+			"admin", "treasury", "destination", "rent", "system_program", "config",
+		]),
+		EscrowInstruction::InitializeRegistryPage { .. } => ("InitializeRegistryPage", &[
+			"payer", "registry_page", "rent", "system_program",
+		]),
+		EscrowInstruction::InitializeIndex => ("InitializeIndex", &["payer", "index", "rent", "system_program"]),
+		EscrowInstruction::ReallocEscrow { .. } => ("ReallocEscrow", &[
+			"authority", "escrow", "rent", "system_program",
+		]),
+		EscrowInstruction::SetTopLevelExchange { .. } => ("SetTopLevelExchange", &["admin", "config"]),
+		EscrowInstruction::ExchangeV2 { .. } => ("ExchangeV2", &[
+			"taker", "takers_sending_account", "takers_token_to_receive_account", "temp_token_account",
+			"initializer", "initializers_token_to_receive_account", "escrow", "token_program", "pda", "config",
+		]),
+	}
+}
+
+impl fmt::Display for ParsedEscrowInstruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{:?}", self.instruction)?;
+		for account in &self.accounts {
+			writeln!(f, "  {}", account)?;
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for ParsedAccount {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.name {
+			Some(name) => write!(f, "#{} {}: {}", self.index, name, self.pubkey),
+			None => write!(f, "#{}: {}", self.index, self.pubkey),
+		}
+	}
+}
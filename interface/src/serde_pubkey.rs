@@ -0,0 +1,34 @@
+//! Pubkeys as base58 strings for serde, like explorers and the JSON RPC show them, instead of the
+//! byte arrays Pubkey serializes to by itself. For `#[serde(with = "crate::serde_pubkey")]`
+
+use std::str::FromStr;
+
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+use solana_program::pubkey::Pubkey;
+
+pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.collect_str(pubkey)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+	let pubkey = String::deserialize(deserializer)?;
+	Pubkey::from_str(&pubkey).map_err(|error| D::Error::custom(format!("{}: {}", pubkey, error)))
+}
+
+/// The same for Option<Pubkey>, None is null
+pub mod option {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(pubkey: &Option<Pubkey>, serializer: S) -> Result<S::Ok, S::Error> {
+		match pubkey {
+			Some(pubkey) => serializer.collect_str(pubkey),
+			None => serializer.serialize_none(),
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Pubkey>, D::Error> {
+		Option::<String>::deserialize(deserializer)?
+			.map(|pubkey| Pubkey::from_str(&pubkey).map_err(|error| D::Error::custom(format!("{}: {}", pubkey, error))))
+			.transpose()
+	}
+}
@@ -0,0 +1,1301 @@
+use std::mem::size_of;
+
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+	program_pack::{IsInitialized, Pack, Sealed},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+};
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use escrow_idl_derive::{ShankAccount, ShankType};
+
+use crate::error::EscrowError;
+
+#[cfg(feature = "borsh")]
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Anchor's account discriminator, sha256("account:<type name>")[..8]. Every account this program owns
+/// starts with its type's, so Anchor clients and explorers can tell them apart and decode them
+pub trait Discriminator {
+	const DISCRIMINATOR: [u8; 8];
+}
+
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// Whether `data` is an account of type T, going by its discriminator
+pub fn has_discriminator<T: Discriminator>(data: &[u8]) -> bool {
+	data.get(..DISCRIMINATOR_LEN) == Some(&T::DISCRIMINATOR[..])
+}
+
+/// What close_program_account leaves in front of closed accounts, Anchor uses the same
+pub const CLOSED_ACCOUNT_DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [255; DISCRIMINATOR_LEN];
+
+pub fn has_closed_discriminator(data: &[u8]) -> bool {
+	data.get(..DISCRIMINATOR_LEN) == Some(&CLOSED_ACCOUNT_DISCRIMINATOR[..])
+}
+
+/// Checks the discriminator in front of a T account. A fresh account is all zeroes, so no discriminator
+/// is fine while it's still uninitialized
+fn check_discriminator<T: Discriminator>(discriminator: &[u8; DISCRIMINATOR_LEN], is_initialized: bool) -> Result<(), ProgramError> {
+	if *discriminator == T::DISCRIMINATOR || (*discriminator == [0; DISCRIMINATOR_LEN] && !is_initialized) {
+		Ok(())
+	} else {
+		Err(ProgramError::InvalidAccountData)
+	}
+}
+
+/// Seed for an escrow's own PDA, [b"escrow", escrow], the authority over its deposit. Every escrow
+/// gets its own so one signing for the wrong escrow can't reach any other escrow's deposit
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Seed for escrow accounts InitEscrow creates itself, [b"escrow-account", initializer, seed as u64 le]
+pub const ESCROW_ACCOUNT_SEED: &[u8] = b"escrow-account";
+
+/// Seed for the system account PDA holding the lamports of a native SOL escrow, [b"sol-vault", escrow]
+pub const SOL_VAULT_SEED: &[u8] = b"sol-vault";
+
+/// Seed for the wSOL token account InitEscrow creates when it wraps the deposit itself, [b"wsol", escrow]
+pub const WSOL_SEED: &[u8] = b"wsol";
+
+/// Seed for the token account InitEscrow creates and moves the deposit into with `vault_deposit`, [b"vault", escrow]
+pub const VAULT_SEED: &[u8] = b"vault";
+
+/// Layout version of escrow accounts, the first byte after the discriminator. v0 accounts (see V0_LEN)
+/// don't have one yet, MigrateEscrow turns them into the current one
+pub const ESCROW_VERSION: u8 = 1;
+
+/// Most bytes an escrow's memo can have (see Escrow::memo)
+pub const MAX_MEMO_LEN: usize = 64;
+
+/// A bool for Pod types, which can't hold a bool since only 0 and 1 are valid ones
+#[repr(transparent)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Pod, Zeroable)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(from = "bool", into = "bool"))]
+pub struct PodBool(u8);
+
+impl PodBool {
+	pub fn get(self) -> bool {
+		self.0 != 0
+	}
+}
+
+impl From<bool> for PodBool {
+	fn from(value: bool) -> Self {
+		PodBool(value as u8)
+	}
+}
+
+impl From<PodBool> for bool {
+	fn from(value: PodBool) -> Self {
+		value.get()
+	}
+}
+
+/// Where an escrow is in its life. Without `retain_history` a settled escrow's account is closed, so
+/// only those with it ever get to Completed, Cancelled or Expired
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, ShankType)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EscrowStatus {
+	Uninitialized,
+	Active,
+	/// Filled by Exchange
+	Completed,
+	/// Closed by Cancel, CancelGroup or SweepDormant
+	Cancelled,
+	/// Closed by CrankExpired
+	Expired,
+}
+
+impl EscrowStatus {
+	pub fn from_u8(status: u8) -> Result<Self, ProgramError> {
+		match status {
+			0 => Ok(EscrowStatus::Uninitialized),
+			1 => Ok(EscrowStatus::Active),
+			2 => Ok(EscrowStatus::Completed),
+			3 => Ok(EscrowStatus::Cancelled),
+			4 => Ok(EscrowStatus::Expired),
+			_ => Err(ProgramError::InvalidAccountData),
+		}
+	}
+}
+
+/// Escrow::normalized_price is scaled by 10^PRICE_DECIMALS so it stays an integer
+pub const PRICE_DECIMALS: u32 = 9;
+
+// It's the program's responsibility to check that received accounts == expected accounts
+//
+// Escrow accounts are the discriminator and then this struct, which they're cast to and from in place
+// (see load) like Anchor's zero copy accounts. Its fields are ordered by size to leave no padding but
+// the explicit one. The other state types are packed by hand.
+//
+// With the borsh feature the state types get borsh and BorshSchema too, for tooling that speaks borsh.
+// Their borsh encoding is exactly the account layout after the discriminator, like Anchor's accounts.
+//
+// With the serde feature Escrow gets Serialize and Deserialize, for services that put escrows in JSON.
+// Pubkeys are base58 strings there and the PodBools bools
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Escrow {
+	/// ESCROW_VERSION, see unpack_versioned for the older layouts
+	pub version: u8,
+	/// An EscrowStatus. Uninitialized and Active are the 0 and 1 of the old is_initialized bool
+	pub status: u8,
+	/// Set by FreezeEscrow. A frozen escrow can't be exchanged until it's thawed, it can still be cancelled
+	pub is_frozen: PodBool,
+	/// The deposit is lamports in the escrow's SOL vault (see find_sol_vault_address) instead of tokens.
+	/// `temp_token_account_pubkey` is the vault then
+	pub is_native_sol: PodBool,
+	/// The deposit is an NFT that was checked at init: a single token of a 0 decimals mint with Metaplex metadata
+	pub is_nft: PodBool,
+	/// The NFT is a Metaplex programmable NFT. Its token account stays frozen, so it only moves through
+	/// Token Metadata's Transfer and `temp_token_account_pubkey` is the escrow PDA's associated token account for it
+	pub is_programmable_nft: PodBool,
+	/// The deposit is a compressed NFT the escrow PDA owns in a Bubblegum tree, `temp_token_account_pubkey`
+	/// is the tree then. The leaf's hashes and nonce below are what Bubblegum needs to find it again
+	pub is_compressed_nft: PodBool,
+	/// The deposit is a deactivated stake account whose staker and withdrawer are the escrow PDA,
+	/// `temp_token_account_pubkey` is the stake account then and the deposited amount its lamports
+	pub is_stake_account: PodBool,
+	/// Decimals of the deposit's mint, read from the mint at init. 9 for SOL and stake accounts, 0 for NFTs
+	pub deposit_decimals: u8,
+	/// Decimals of the mint the initializer receives, 0 for collection offers
+	pub expected_decimals: u8,
+	/// Canonical bump of the escrow's PDA (see find_authority_address). It's found once at init, after
+	/// that the PDA signs with it straight away instead of searching for it again
+	pub authority_bump_seed: u8,
+	/// Set at init. Once the escrow is settled its account stays around as a record with its final status
+	/// instead of being closed, until the initializer closes it with CloseEscrowRecord
+	pub retain_history: PodBool,
+	/// The stats account was there at init and counted this escrow as open, so whatever closes it has to
+	/// bring the stats account too
+	pub is_counted: PodBool,
+	/// The escrow was added to a RegistryPage at init, whatever closes it has to bring that page too
+	pub is_registered: PodBool,
+	/// The escrow was added to its initializer's InitializerIndex at init, whatever closes it has to bring the index too
+	pub is_indexed: PodBool,
+	/// Set at init. Exchange refuses takers that are the initializer or pay from the initializer's
+	/// receive account, so market makers don't fill their own quotes. It took the padding byte that put
+	/// the u64s on their 8 byte alignment, older escrows have a 0 there
+	pub prevent_self_trade: PodBool,
+	pub expected_amount: u64,
+	/// Lamports paid into the treasury at init, kept here so the refund matches what was actually paid
+	pub listing_fee: u64,
+	/// What was in the temp token account at init. Exchange pays out exactly this, anything above it is surplus
+	pub deposited_amount: u64,
+	/// Unix timestamp after which the escrow can't be exchanged anymore and anyone can crank it closed, 0 means never
+	pub expires_at: i64,
+	/// Unix timestamp the initializer last did something with the escrow (InitEscrow, FreezeEscrow,
+	/// ThawEscrow). Once that's longer ago than the config's dormancy period anyone can close it
+	pub last_active_at: i64,
+	/// Unix timestamp of InitEscrow, 0 for escrows MigrateEscrow brought over from v0
+	pub created_at: i64,
+	/// Slot of InitEscrow, 0 for escrows MigrateEscrow brought over from v0
+	pub created_at_slot: u64,
+	pub compressed_nft_nonce: u64,
+	/// Lamports the initializer put in on top of the deposit. They sit in the escrow account itself,
+	/// so they go to the taker on Exchange and come back with the rent when the escrow is closed otherwise
+	pub deposited_lamports: u64,
+	/// Lamports the taker pays the initializer on top of `expected_amount`
+	pub expected_lamports: u64,
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub initializer_pubkey: Pubkey,
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub temp_token_account_pubkey: Pubkey,
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub initializer_token_to_receive_account_pubkey: Pubkey,
+	/// The EscrowGroup this escrow was attached to at init, Pubkey::default() if it isn't in one
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub group_pubkey: Pubkey,
+	/// Escrow that has to be filled before this one can be exchanged, Pubkey::default() if none
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub predecessor_pubkey: Pubkey,
+	/// Owner of the accounts Cancel returns the deposit and rent to, the initializer unless set otherwise at init
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub refund_pubkey: Pubkey,
+	/// For collection offers, the verified Metaplex collection any NFT of which fills the escrow.
+	/// Pubkey::default() for regular escrows
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub collection_pubkey: Pubkey,
+	pub compressed_nft_data_hash: [u8; 32],
+	pub compressed_nft_creator_hash: [u8; 32],
+	/// Mint of the deposit, so indexers can filter escrows by pair with a memcmp instead of reading every
+	/// temp token account. The native mint for SOL, Pubkey::default() for compressed NFTs and stake accounts
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub deposit_mint_pubkey: Pubkey,
+	/// Mint of the token the initializer receives, Pubkey::default() for collection offers
+	#[cfg_attr(feature = "serde", serde(with = "crate::serde_pubkey"))]
+	pub receive_mint_pubkey: Pubkey,
+}
+
+// Escrow's field offsets (VERSION_OFFSET and the ones after it) are counted for this size. The array
+// only compiles with a length of 448, and const assert! is newer than the BPF toolchain too
+const _: [(); 448] = [(); size_of::<Escrow>()];
+
+impl Escrow {
+	/// Whether the deposit sits in a token account, which it doesn't for native SOL, compressed NFT and
+	/// stake account escrows
+	pub fn holds_tokens(&self) -> bool {
+		!self.is_native_sol.get() && !self.is_compressed_nft.get() && !self.is_stake_account.get()
+	}
+
+	/// What one whole deposited token costs in whole tokens of the other side, times 10^PRICE_DECIMALS.
+	/// Raw amounts of mints with different decimals (6 for USDC, 9 for SOL) don't compare, this does.
+	/// Lamports on top of either side aren't in it
+	pub fn normalized_price(&self) -> Option<u128> {
+		if self.deposited_amount == 0 {
+			return None;
+		}
+		let numerator = (self.expected_amount as u128)
+			.checked_mul(10u128.checked_pow(self.deposit_decimals as u32 + PRICE_DECIMALS)?)?;
+		let denominator = (self.deposited_amount as u128)
+			.checked_mul(10u128.checked_pow(self.expected_decimals as u32)?)?;
+		Some(numerator / denominator)
+	}
+
+	pub fn is_expired(&self, unix_timestamp: i64) -> bool {
+		self.expires_at != 0 && unix_timestamp >= self.expires_at
+	}
+
+	/// Where InitEscrow puts the escrow account for `seed`, so clients know it before the escrow exists
+	pub fn find_address(initializer: &Pubkey, seed: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[ESCROW_ACCOUNT_SEED, initializer.as_ref(), &seed.to_le_bytes()], program_id)
+	}
+
+	pub fn find_authority_address(escrow: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[ESCROW_SEED, escrow.as_ref()], program_id)
+	}
+
+	/// The program-wide PDA [b"escrow"] the first release handed every temp token account to. It's only
+	/// used by MigrateEscrow now, which has it hand a v0 escrow's temp token account on to the escrow's PDA
+	pub fn find_legacy_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[ESCROW_SEED], program_id)
+	}
+
+	/// The escrow's PDA from the stored bump, create_program_address costs a fraction of what
+	/// find_program_address does
+	pub fn authority_address(&self, escrow: &Pubkey, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+		Pubkey::create_program_address(&[ESCROW_SEED, escrow.as_ref(), &[self.authority_bump_seed]], program_id)
+			.map_err(|_| ProgramError::InvalidSeeds)
+	}
+
+	/// A copy of the escrow in `src` whatever its layout version. Current accounts (which can be longer,
+	/// see ReallocEscrow) are cast (see load) and v0 ones still get unpacked field by field until
+	/// MigrateEscrow upgrades them
+	pub fn unpack_versioned_unchecked(src: &[u8]) -> Result<Self, ProgramError> {
+		match src.len() {
+			len if len >= Escrow::LEN => Ok(*Escrow::load(src)?),
+			Escrow::V0_LEN => Escrow::unpack_v0(array_ref![src, 0, Escrow::V0_LEN]),
+			_ => Err(ProgramError::InvalidAccountData),
+		}
+	}
+
+	/// Like unpack_versioned_unchecked, for active escrows only. Settled ones kept as records can only be
+	/// read with unpack_versioned_unchecked. v0 ones don't know their mints, deposit and PDA bump, nothing
+	/// can be done with them until MigrateEscrow fills those in
+	pub fn unpack_versioned(src: &[u8]) -> Result<Self, ProgramError> {
+		if src.len() == Escrow::V0_LEN {
+			return Err(EscrowError::EscrowNotMigrated.into());
+		}
+		let escrow = Escrow::unpack_versioned_unchecked(src)?;
+		match escrow.status()? {
+			EscrowStatus::Active => Ok(escrow),
+			EscrowStatus::Uninitialized => Err(ProgramError::UninitializedAccount),
+			_ => Err(EscrowError::EscrowNotActive.into()),
+		}
+	}
+
+	pub fn status(&self) -> Result<EscrowStatus, ProgramError> {
+		EscrowStatus::from_u8(self.status)
+	}
+
+	/// Whether the escrow is open for business, not yet settled or kept as a record of a settled one
+	pub fn is_active(&self) -> bool {
+		self.status == EscrowStatus::Active as u8
+	}
+
+	/// Writes the escrow into `dst` in the current layout, discriminator included. v0 accounts are too
+	/// short for it until MigrateEscrow has grown them
+	pub fn pack_versioned(self, dst: &mut [u8]) -> Result<(), ProgramError> {
+		if dst.len() < Escrow::LEN {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let (discriminator_dst, body_dst) = dst[..Escrow::LEN].split_at_mut(DISCRIMINATOR_LEN);
+		discriminator_dst.copy_from_slice(&Escrow::DISCRIMINATOR);
+		body_dst.copy_from_slice(bytemuck::bytes_of(&self));
+		Ok(())
+	}
+
+	pub fn find_sol_vault_address(escrow: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[SOL_VAULT_SEED, escrow.as_ref()], program_id)
+	}
+
+	pub fn find_wsol_address(escrow: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[WSOL_SEED, escrow.as_ref()], program_id)
+	}
+
+	pub fn find_vault_address(escrow: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[VAULT_SEED, escrow.as_ref()], program_id)
+	}
+
+	/// A standing bid for any NFT of `collection_pubkey`. Instead of a fixed token account the NFT goes to
+	/// the initializer's associated token account for it
+	pub fn is_collection_offer(&self) -> bool {
+		self.collection_pubkey != Pubkey::default()
+	}
+
+	/// The escrowed cNFT's leaf, for a proof against `root`
+	pub fn compressed_nft_leaf(&self, root: [u8; 32]) -> Leaf {
+		Leaf {
+			root,
+			data_hash: self.compressed_nft_data_hash,
+			creator_hash: self.compressed_nft_creator_hash,
+			nonce: self.compressed_nft_nonce,
+		}
+	}
+
+	pub fn is_dormant(&self, dormancy_period: i64, unix_timestamp: i64) -> bool {
+		dormancy_period > 0 && unix_timestamp.saturating_sub(self.last_active_at) >= dormancy_period
+	}
+}
+
+/// What Bubblegum needs to find a leaf in its tree besides the owner and delegate. `root` is the tree's
+/// root the proof was made against, the rest doesn't change when the cNFT changes owners
+pub struct Leaf {
+	pub root: [u8; 32],
+	pub data_hash: [u8; 32],
+	pub creator_hash: [u8; 32],
+	pub nonce: u64,
+}
+
+impl Discriminator for Escrow {
+	const DISCRIMINATOR: [u8; 8] = [31, 213, 123, 187, 186, 22, 218, 155];
+}
+
+impl IsInitialized for Escrow {
+	fn is_initialized(&self) -> bool {
+		self.status != EscrowStatus::Uninitialized as u8
+	}
+}
+
+impl Escrow {
+	/// Length of current accounts. They can be longer after ReallocEscrow, the memo goes behind the struct
+	pub const LEN: usize = DISCRIMINATOR_LEN + size_of::<Escrow>(); // 8 (discriminator) + 448 (struct) = 456
+
+	// Byte offsets of the fields in current escrow accounts, discriminator included, for getProgramAccounts
+	// memcmp filters and dataSlice. Older layouts (see unpack_versioned) have them elsewhere, their
+	// version byte at VERSION_OFFSET tells them apart. Counted by hand, offset_of! is newer than the BPF
+	// toolchain: 16 u8/bool fields, 10 u64/i64s, then the pubkeys and hashes. The size check under the
+	// struct breaks the build when a field goes in anywhere but at the end
+	pub const VERSION_OFFSET: usize = DISCRIMINATOR_LEN;
+	/// An EscrowStatus, right after the version
+	pub const STATUS_OFFSET: usize = DISCRIMINATOR_LEN + 1;
+	pub const EXPECTED_AMOUNT_OFFSET: usize = DISCRIMINATOR_LEN + 16;
+	pub const DEPOSITED_AMOUNT_OFFSET: usize = DISCRIMINATOR_LEN + 16 + 2 * 8;
+	pub const EXPIRES_AT_OFFSET: usize = DISCRIMINATOR_LEN + 16 + 3 * 8;
+	pub const INITIALIZER_OFFSET: usize = DISCRIMINATOR_LEN + 16 + 10 * 8;
+	pub const TEMP_TOKEN_ACCOUNT_OFFSET: usize = Escrow::INITIALIZER_OFFSET + 32;
+	pub const REFUND_OFFSET: usize = Escrow::INITIALIZER_OFFSET + 5 * 32;
+	pub const DEPOSIT_MINT_OFFSET: usize = Escrow::INITIALIZER_OFFSET + 9 * 32;
+	pub const RECEIVE_MINT_OFFSET: usize = Escrow::INITIALIZER_OFFSET + 10 * 32;
+
+	/// Where an escrow account's memo goes, right behind the struct: a u8 length, then the UTF-8 text
+	pub const MEMO_OFFSET: usize = Escrow::LEN;
+
+	/// Length of an escrow account with room for a `memo_len` byte memo
+	pub fn len_with_memo(memo_len: usize) -> usize {
+		Escrow::MEMO_OFFSET + 1 + memo_len
+	}
+
+	/// The memo in an escrow account's data, None if it has none or no room for one
+	pub fn memo(src: &[u8]) -> Option<&str> {
+		let (len, text) = src.get(Escrow::MEMO_OFFSET..)?.split_first()?;
+		match text.get(..*len as usize)? {
+			[] => None,
+			text => std::str::from_utf8(text).ok(),
+		}
+	}
+
+	/// Puts `memo` behind the escrow in `dst`, which needs to be at least len_with_memo long
+	pub fn write_memo(dst: &mut [u8], memo: &str) -> Result<(), ProgramError> {
+		if memo.len() > MAX_MEMO_LEN {
+			return Err(EscrowError::MemoTooLong.into());
+		}
+		let (len_dst, text_dst) = dst
+			.get_mut(Escrow::MEMO_OFFSET..Escrow::len_with_memo(memo.len()))
+			.ok_or(ProgramError::AccountDataTooSmall)?
+			.split_first_mut()
+			.unwrap();
+		*len_dst = memo.len() as u8;
+		text_dst.copy_from_slice(memo.as_bytes());
+		Ok(())
+	}
+
+	/// Length of v0 accounts, the escrows of the program's first release: is_initialized, the initializer,
+	/// the temp token account, the initializer's receive account and the expected amount, packed in that
+	/// order. Their temp token accounts belong to the program-wide PDA (see find_legacy_authority_address)
+	pub const V0_LEN: usize = 105; // 1 (bool) + 3 * 32 (Pubkey) + 8 (u64) = 105
+
+	/// The escrow of an account's data, cast in place without copying. All zeroes passes as an
+	/// uninitialized escrow, so InitEscrow can fill in fresh accounts. Whatever comes after LEN isn't read
+	pub fn load(src: &[u8]) -> Result<&Escrow, ProgramError> {
+		if src.len() < Escrow::LEN {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let (discriminator, body) = src[..Escrow::LEN].split_at(DISCRIMINATOR_LEN);
+		let escrow: &Escrow = bytemuck::try_from_bytes(body).map_err(|_| ProgramError::InvalidAccountData)?;
+		escrow.check_header(array_ref![discriminator, 0, DISCRIMINATOR_LEN])?;
+		Ok(escrow)
+	}
+
+	/// Like load, for changing the escrow in place. The discriminator is left as it is, fresh accounts
+	/// get theirs from pack_versioned
+	pub fn load_mut(src: &mut [u8]) -> Result<&mut Escrow, ProgramError> {
+		if src.len() < Escrow::LEN {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let (discriminator, body) = src[..Escrow::LEN].split_at_mut(DISCRIMINATOR_LEN);
+		let escrow: &mut Escrow = bytemuck::try_from_bytes_mut(body).map_err(|_| ProgramError::InvalidAccountData)?;
+		escrow.check_header(array_ref![discriminator, 0, DISCRIMINATOR_LEN])?;
+		Ok(escrow)
+	}
+
+	fn check_header(&self, discriminator: &[u8; DISCRIMINATOR_LEN]) -> Result<(), ProgramError> {
+		check_discriminator::<Escrow>(discriminator, self.is_initialized())?;
+		match self.version {
+			ESCROW_VERSION => Ok(()),
+			0 if !self.is_initialized() => Ok(()),
+			_ => Err(ProgramError::InvalidAccountData),
+		}
+	}
+
+	/// The fields v0 didn't have are zeroes and the version stays 0, the refund address is the initializer
+	fn unpack_v0(src: &[u8; Escrow::V0_LEN]) -> Result<Self, ProgramError> {
+		let (is_initialized, initializer_pubkey, temp_token_account_pubkey, initializer_token_to_receive_account_pubkey, expected_amount) =
+			array_refs![src, 1, 32, 32, 32, 8];
+
+		let mut escrow = Escrow::zeroed();
+		escrow.status = match is_initialized {
+			[0] => EscrowStatus::Uninitialized as u8,
+			[1] => EscrowStatus::Active as u8,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		escrow.initializer_pubkey = Pubkey::new_from_array(*initializer_pubkey);
+		escrow.temp_token_account_pubkey = Pubkey::new_from_array(*temp_token_account_pubkey);
+		escrow.initializer_token_to_receive_account_pubkey = Pubkey::new_from_array(*initializer_token_to_receive_account_pubkey);
+		escrow.expected_amount = u64::from_le_bytes(*expected_amount);
+		escrow.refund_pubkey = escrow.initializer_pubkey;
+		Ok(escrow)
+	}
+
+}
+
+
+pub const GROUP_SEED: &[u8] = b"group";
+
+/// A named set of escrows from one maker (e.g. a grid of price quotes) that can be cancelled together.
+/// Lives at the PDA [b"group", maker, name]
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct EscrowGroup {
+	pub is_initialized: bool,
+	pub maker_pubkey: Pubkey,
+	pub name: [u8; 32],
+	/// How many escrows are still attached, the group account gets closed once this hits 0 in CancelGroup
+	pub escrow_count: u32,
+}
+
+impl EscrowGroup {
+	pub fn find_address(maker_pubkey: &Pubkey, name: &[u8; 32], program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[GROUP_SEED, maker_pubkey.as_ref(), name], program_id)
+	}
+}
+
+impl Sealed for EscrowGroup {}
+
+impl Discriminator for EscrowGroup {
+	const DISCRIMINATOR: [u8; 8] = [69, 43, 63, 189, 106, 255, 237, 57];
+}
+
+impl IsInitialized for EscrowGroup {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for EscrowGroup {
+	const LEN: usize = 77; // 8 (discriminator) + 1 (bool) + 32 (Pubkey) + 32 (name) + 4 (u32) = 77
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, EscrowGroup::LEN];
+		let (discriminator, is_initialized, maker_pubkey, name, escrow_count) = array_refs![src, DISCRIMINATOR_LEN, 1, 32, 32, 4];
+		let is_initialized = match is_initialized {
+			[0] => false,
+			[1] => true,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		check_discriminator::<EscrowGroup>(discriminator, is_initialized)?;
+
+		Ok(EscrowGroup {
+			is_initialized,
+			maker_pubkey: Pubkey::new_from_array(*maker_pubkey),
+			name: *name,
+			escrow_count: u32::from_le_bytes(*escrow_count),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, EscrowGroup::LEN];
+		let (discriminator_dst, is_initialized_dst, maker_pubkey_dst, name_dst, escrow_count_dst) =
+			mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 32, 32, 4];
+
+		*discriminator_dst = EscrowGroup::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		maker_pubkey_dst.copy_from_slice(self.maker_pubkey.as_ref());
+		*name_dst = self.name;
+		*escrow_count_dst = self.escrow_count.to_le_bytes();
+	}
+}
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const STANDBY_CONFIG_SEED: &[u8] = b"standby";
+
+/// Bits of Config::disabled_features, one per kind of escrow or extra the admin can turn off with
+/// SetFeatures. Turning one off only stops new escrows (or fills) from using it, what's open stays open
+/// 1 << 0 was the private price mode's, it stays unused so the bits after it don't move
+/// NFT deposits, collection offers and compressed NFTs
+pub const FEATURE_NFT: u16 = 1 << 1;
+/// Native SOL and wSOL deposits, lamports on either side and unwrapping on Exchange
+pub const FEATURE_NATIVE_SOL: u16 = 1 << 2;
+pub const FEATURE_STAKE: u16 = 1 << 3;
+pub const FEATURE_BATCH_INIT: u16 = 1 << 4;
+pub const FEATURE_RETAIN_HISTORY: u16 = 1 << 5;
+pub const FEATURE_RECEIPTS: u16 = 1 << 6;
+
+/// What the program does about Token-2022 extensions that undermine an escrow: a permanent delegate on a
+/// mint can take the tokens back out of any account, after settlement too, and a CPI guard on a token
+/// account stops the program from moving its tokens for the owner
+#[derive(Clone, Copy, Debug, PartialEq, ShankType)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExtensionPolicy {
+	Allow,
+	/// Goes through, but says so in the logs
+	Warn,
+	Deny,
+}
+
+impl ExtensionPolicy {
+	pub fn from_u8(policy: u8) -> Result<Self, ProgramError> {
+		match policy {
+			0 => Ok(ExtensionPolicy::Allow),
+			1 => Ok(ExtensionPolicy::Warn),
+			2 => Ok(ExtensionPolicy::Deny),
+			_ => Err(ProgramError::InvalidAccountData),
+		}
+	}
+}
+
+/// Protocol parameters. The primary config lives at the PDA [b"config"], its optional standby at
+/// [b"config", b"standby"]. The standby's parameters take over whenever the primary admin hasn't sent
+/// a heartbeat for `heartbeat_period` seconds, so governance survives losing the primary admin key
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct Config {
+	pub is_initialized: bool,
+	pub is_standby: bool,
+	/// Can be an SPL Governance PDA. Everything the admin does is one instruction with a fixed set of
+	/// accounts, so it can be a proposal's instruction as is
+	pub admin: Pubkey,
+	/// Lamports an initializer pays into the treasury on InitEscrow, so spamming escrows (which bloat
+	/// getProgramAccounts results for indexers) isn't free. 0 turns the fee off
+	pub listing_fee_lamports: u64,
+	/// Unix timestamp of the admin's last Heartbeat (or UpdateConfig), only used on the primary
+	pub last_heartbeat: i64,
+	/// Seconds without a heartbeat before the standby takes over, 0 means never
+	pub heartbeat_period: i64,
+	/// The standby config's address, Pubkey::default() if there is none. Only set on the primary
+	pub standby_config_pubkey: Pubkey,
+	/// Lamports CrankExpired pays whoever closes an expired escrow, taken from the escrow account's rent
+	pub crank_bounty_lamports: u64,
+	/// Set with SetPaused, only on the primary. While paused no new escrows can be made or filled,
+	/// but Cancel keeps working so users can always get out
+	pub is_paused: bool,
+	/// Seconds an escrow's initializer has to be inactive before SweepDormant can close it, 0 turns it off
+	pub dormancy_period: i64,
+	/// Set with SetExtensionPolicy, only on the primary like the pause flag. Allow until it's set
+	pub extension_policy: ExtensionPolicy,
+	/// FEATURE_* bits of what's turned off, set with SetFeatures. Only on the primary, everything's on until it's set
+	pub disabled_features: u16,
+	/// Who NominateAdmin named to take over as admin, until they AcceptAdmin. Pubkey::default() if nobody is
+	pub pending_admin: Pubkey,
+	/// Set with SetTopLevelExchange, only on the primary. Exchange then has to be an instruction of the
+	/// transaction itself and not a CPI, so no other program can wrap code around a maker's fill
+	pub top_level_exchange_only: bool,
+	/// Listing fees of open escrows the treasury still owes back (see REFUND_LISTING_FEE_ON_EXCHANGE),
+	/// WithdrawTreasury leaves them in. Only on the primary
+	pub refundable_listing_fees: u64,
+}
+
+impl Config {
+	pub fn find_address(program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+	}
+
+	pub fn find_standby_address(program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[CONFIG_SEED, STANDBY_CONFIG_SEED], program_id)
+	}
+
+	/// Whether the primary admin has gone quiet for longer than the heartbeat period
+	pub fn is_heartbeat_expired(&self, unix_timestamp: i64) -> bool {
+		self.heartbeat_period > 0 && unix_timestamp.saturating_sub(self.last_heartbeat) > self.heartbeat_period
+	}
+
+	/// Whether none of the FEATURE_* bits in `features` are turned off
+	pub fn has_features(&self, features: u16) -> bool {
+		self.disabled_features & features == 0
+	}
+}
+
+impl Sealed for Config {}
+
+impl Discriminator for Config {
+	const DISCRIMINATOR: [u8; 8] = [155, 12, 170, 224, 30, 250, 204, 130];
+}
+
+impl IsInitialized for Config {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for Config {
+	const LEN: usize = 159; // 8 (discriminator) + 4 (bool) + 1 (u8) + 2 (u16) + 3 * 32 (Pubkey) + 6 * 8 (u64/i64) = 159
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, Config::LEN];
+		let (
+			discriminator,
+			is_initialized,
+			is_standby,
+			admin,
+			listing_fee_lamports,
+			last_heartbeat,
+			heartbeat_period,
+			standby_config_pubkey,
+			crank_bounty_lamports,
+			is_paused,
+			dormancy_period,
+			extension_policy,
+			disabled_features,
+			pending_admin,
+			top_level_exchange_only,
+			refundable_listing_fees,
+		) = array_refs![src, DISCRIMINATOR_LEN, 1, 1, 32, 8, 8, 8, 32, 8, 1, 8, 1, 2, 32, 1, 8];
+		let unpack_bool = |byte: &[u8; 1]| match byte {
+			[0] => Ok(false),
+			[1] => Ok(true),
+			_ => Err(ProgramError::InvalidAccountData),
+		};
+		let is_initialized = unpack_bool(is_initialized)?;
+		check_discriminator::<Config>(discriminator, is_initialized)?;
+
+		Ok(Config {
+			is_initialized,
+			is_standby: unpack_bool(is_standby)?,
+			admin: Pubkey::new_from_array(*admin),
+			listing_fee_lamports: u64::from_le_bytes(*listing_fee_lamports),
+			last_heartbeat: i64::from_le_bytes(*last_heartbeat),
+			heartbeat_period: i64::from_le_bytes(*heartbeat_period),
+			standby_config_pubkey: Pubkey::new_from_array(*standby_config_pubkey),
+			crank_bounty_lamports: u64::from_le_bytes(*crank_bounty_lamports),
+			is_paused: unpack_bool(is_paused)?,
+			dormancy_period: i64::from_le_bytes(*dormancy_period),
+			extension_policy: ExtensionPolicy::from_u8(extension_policy[0])?,
+			disabled_features: u16::from_le_bytes(*disabled_features),
+			pending_admin: Pubkey::new_from_array(*pending_admin),
+			top_level_exchange_only: unpack_bool(top_level_exchange_only)?,
+			refundable_listing_fees: u64::from_le_bytes(*refundable_listing_fees),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, Config::LEN];
+		let (
+			discriminator_dst,
+			is_initialized_dst,
+			is_standby_dst,
+			admin_dst,
+			listing_fee_lamports_dst,
+			last_heartbeat_dst,
+			heartbeat_period_dst,
+			standby_config_pubkey_dst,
+			crank_bounty_lamports_dst,
+			is_paused_dst,
+			dormancy_period_dst,
+			extension_policy_dst,
+			disabled_features_dst,
+			pending_admin_dst,
+			top_level_exchange_only_dst,
+			refundable_listing_fees_dst,
+		) = mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 1, 32, 8, 8, 8, 32, 8, 1, 8, 1, 2, 32, 1, 8];
+
+		*discriminator_dst = Config::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		is_standby_dst[0] = self.is_standby as u8;
+		admin_dst.copy_from_slice(self.admin.as_ref());
+		*listing_fee_lamports_dst = self.listing_fee_lamports.to_le_bytes();
+		*last_heartbeat_dst = self.last_heartbeat.to_le_bytes();
+		*heartbeat_period_dst = self.heartbeat_period.to_le_bytes();
+		standby_config_pubkey_dst.copy_from_slice(self.standby_config_pubkey.as_ref());
+		*crank_bounty_lamports_dst = self.crank_bounty_lamports.to_le_bytes();
+		is_paused_dst[0] = self.is_paused as u8;
+		*dormancy_period_dst = self.dormancy_period.to_le_bytes();
+		extension_policy_dst[0] = self.extension_policy as u8;
+		*disabled_features_dst = self.disabled_features.to_le_bytes();
+		pending_admin_dst.copy_from_slice(self.pending_admin.as_ref());
+		top_level_exchange_only_dst[0] = self.top_level_exchange_only as u8;
+		*refundable_listing_fees_dst = self.refundable_listing_fees.to_le_bytes();
+	}
+}
+
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+/// What an Exchange with `receipt` set records about the fill, at the PDA [b"receipt", escrow]. The
+/// taker pays its rent. Amounts are raw units of what actually arrived, after transfer fees
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct TradeReceipt {
+	pub is_initialized: bool,
+	pub escrow_pubkey: Pubkey,
+	/// The escrow's initializer
+	pub maker_pubkey: Pubkey,
+	pub taker_pubkey: Pubkey,
+	/// Mint of what the taker got, the escrow's deposit mint
+	pub deposit_mint_pubkey: Pubkey,
+	/// Mint of what the taker paid with
+	pub receive_mint_pubkey: Pubkey,
+	/// What the taker got, the deposit
+	pub deposit_amount: u64,
+	/// What the maker got, without the creators' royalties
+	pub receive_amount: u64,
+	pub slot: u64,
+	pub unix_timestamp: i64,
+}
+
+impl TradeReceipt {
+	pub fn find_address(escrow: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[RECEIPT_SEED, escrow.as_ref()], program_id)
+	}
+}
+
+impl Sealed for TradeReceipt {}
+
+impl Discriminator for TradeReceipt {
+	const DISCRIMINATOR: [u8; 8] = [81, 155, 106, 207, 56, 213, 102, 174];
+}
+
+impl IsInitialized for TradeReceipt {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for TradeReceipt {
+	const LEN: usize = 201; // 8 (discriminator) + 1 (bool) + 5 * 32 (Pubkey) + 4 * 8 (u64/i64) = 201
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, TradeReceipt::LEN];
+		let (
+			discriminator,
+			is_initialized,
+			escrow_pubkey,
+			maker_pubkey,
+			taker_pubkey,
+			deposit_mint_pubkey,
+			receive_mint_pubkey,
+			deposit_amount,
+			receive_amount,
+			slot,
+			unix_timestamp,
+		) = array_refs![src, DISCRIMINATOR_LEN, 1, 32, 32, 32, 32, 32, 8, 8, 8, 8];
+		let is_initialized = match is_initialized {
+			[0] => false,
+			[1] => true,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		check_discriminator::<TradeReceipt>(discriminator, is_initialized)?;
+
+		Ok(TradeReceipt {
+			is_initialized,
+			escrow_pubkey: Pubkey::new_from_array(*escrow_pubkey),
+			maker_pubkey: Pubkey::new_from_array(*maker_pubkey),
+			taker_pubkey: Pubkey::new_from_array(*taker_pubkey),
+			deposit_mint_pubkey: Pubkey::new_from_array(*deposit_mint_pubkey),
+			receive_mint_pubkey: Pubkey::new_from_array(*receive_mint_pubkey),
+			deposit_amount: u64::from_le_bytes(*deposit_amount),
+			receive_amount: u64::from_le_bytes(*receive_amount),
+			slot: u64::from_le_bytes(*slot),
+			unix_timestamp: i64::from_le_bytes(*unix_timestamp),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, TradeReceipt::LEN];
+		let (
+			discriminator_dst,
+			is_initialized_dst,
+			escrow_pubkey_dst,
+			maker_pubkey_dst,
+			taker_pubkey_dst,
+			deposit_mint_pubkey_dst,
+			receive_mint_pubkey_dst,
+			deposit_amount_dst,
+			receive_amount_dst,
+			slot_dst,
+			unix_timestamp_dst,
+		) = mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 32, 32, 32, 32, 32, 8, 8, 8, 8];
+
+		*discriminator_dst = TradeReceipt::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		escrow_pubkey_dst.copy_from_slice(self.escrow_pubkey.as_ref());
+		maker_pubkey_dst.copy_from_slice(self.maker_pubkey.as_ref());
+		taker_pubkey_dst.copy_from_slice(self.taker_pubkey.as_ref());
+		deposit_mint_pubkey_dst.copy_from_slice(self.deposit_mint_pubkey.as_ref());
+		receive_mint_pubkey_dst.copy_from_slice(self.receive_mint_pubkey.as_ref());
+		*deposit_amount_dst = self.deposit_amount.to_le_bytes();
+		*receive_amount_dst = self.receive_amount.to_le_bytes();
+		*slot_dst = self.slot.to_le_bytes();
+		*unix_timestamp_dst = self.unix_timestamp.to_le_bytes();
+	}
+}
+
+pub const STATS_SEED: &[u8] = b"stats";
+
+/// Protocol wide counters at the PDA [b"stats"], so dashboards get the activity from one account. Only
+/// escrows made once InitializeStats created it are counted, see Escrow::is_counted
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct Stats {
+	pub is_initialized: bool,
+	pub escrows_created: u64,
+	/// Escrows counted as created that haven't been filled or closed yet
+	pub open_escrows: u64,
+	pub fills: u64,
+	/// Closed without a fill, by Cancel, CancelGroup, CrankExpired or SweepDormant
+	pub cancellations: u64,
+	/// Lamports that changed hands in fills: SOL and wSOL legs plus lamports paid on top of either side.
+	/// Token amounts of different mints don't add up to anything
+	pub sol_volume: u64,
+}
+
+impl Stats {
+	pub fn find_address(program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[STATS_SEED], program_id)
+	}
+}
+
+impl Sealed for Stats {}
+
+impl Discriminator for Stats {
+	const DISCRIMINATOR: [u8; 8] = [190, 125, 51, 63, 169, 197, 36, 238];
+}
+
+impl IsInitialized for Stats {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for Stats {
+	const LEN: usize = 49; // 8 (discriminator) + 1 (bool) + 5 * 8 (u64) = 49
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, Stats::LEN];
+		let (discriminator, is_initialized, escrows_created, open_escrows, fills, cancellations, sol_volume) =
+			array_refs![src, DISCRIMINATOR_LEN, 1, 8, 8, 8, 8, 8];
+		let is_initialized = match is_initialized {
+			[0] => false,
+			[1] => true,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		check_discriminator::<Stats>(discriminator, is_initialized)?;
+
+		Ok(Stats {
+			is_initialized,
+			escrows_created: u64::from_le_bytes(*escrows_created),
+			open_escrows: u64::from_le_bytes(*open_escrows),
+			fills: u64::from_le_bytes(*fills),
+			cancellations: u64::from_le_bytes(*cancellations),
+			sol_volume: u64::from_le_bytes(*sol_volume),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, Stats::LEN];
+		let (discriminator_dst, is_initialized_dst, escrows_created_dst, open_escrows_dst, fills_dst, cancellations_dst, sol_volume_dst) =
+			mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 8, 8, 8, 8, 8];
+
+		*discriminator_dst = Stats::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		*escrows_created_dst = self.escrows_created.to_le_bytes();
+		*open_escrows_dst = self.open_escrows.to_le_bytes();
+		*fills_dst = self.fills.to_le_bytes();
+		*cancellations_dst = self.cancellations.to_le_bytes();
+		*sol_volume_dst = self.sol_volume.to_le_bytes();
+	}
+}
+
+pub const REGISTRY_SEED: &[u8] = b"registry";
+
+/// Escrows one RegistryPage holds
+pub const REGISTRY_PAGE_CAPACITY: usize = 64;
+
+/// One page of the registry of open escrows, at the PDA [b"registry", index as u32 LE]. Pages are made
+/// with InitializeRegistryPage, InitEscrow adds its escrow to the page it's passed and settling it takes
+/// it out again. Clients enumerate open escrows by reading pages 0, 1, 2... until one doesn't exist.
+/// Only escrows opened with a page are in it, and their order isn't kept
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct RegistryPage {
+	pub is_initialized: bool,
+	pub index: u32,
+	/// At most REGISTRY_PAGE_CAPACITY
+	pub escrows: Vec<Pubkey>,
+}
+
+impl RegistryPage {
+	pub fn find_address(index: u32, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[REGISTRY_SEED, &index.to_le_bytes()], program_id)
+	}
+
+	pub fn add(&mut self, escrow: Pubkey) -> Result<(), ProgramError> {
+		if self.escrows.len() >= REGISTRY_PAGE_CAPACITY {
+			return Err(EscrowError::RegistryPageFull.into());
+		}
+		self.escrows.push(escrow);
+		Ok(())
+	}
+
+	/// Takes `escrow` out, swapping the last one into its place. False if it isn't on this page
+	pub fn remove(&mut self, escrow: &Pubkey) -> bool {
+		match self.escrows.iter().position(|registered| registered == escrow) {
+			Some(position) => {
+				self.escrows.swap_remove(position);
+				true
+			},
+			None => false,
+		}
+	}
+}
+
+impl Sealed for RegistryPage {}
+
+impl Discriminator for RegistryPage {
+	const DISCRIMINATOR: [u8; 8] = [190, 151, 207, 163, 226, 253, 16, 250];
+}
+
+impl IsInitialized for RegistryPage {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for RegistryPage {
+	const LEN: usize = 2062; // 8 (discriminator) + 1 (bool) + 4 (u32) + 1 (u8 count) + 64 * 32 (Pubkey) = 2062
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, RegistryPage::LEN];
+		let (discriminator, is_initialized, index, count, escrows) =
+			array_refs![src, DISCRIMINATOR_LEN, 1, 4, 1, REGISTRY_PAGE_CAPACITY * 32];
+		let is_initialized = match is_initialized {
+			[0] => false,
+			[1] => true,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		check_discriminator::<RegistryPage>(discriminator, is_initialized)?;
+		let count = count[0] as usize;
+		if count > REGISTRY_PAGE_CAPACITY {
+			return Err(ProgramError::InvalidAccountData);
+		}
+
+		Ok(RegistryPage {
+			is_initialized,
+			index: u32::from_le_bytes(*index),
+			escrows: escrows.chunks_exact(32).take(count).map(Pubkey::new).collect(),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, RegistryPage::LEN];
+		let (discriminator_dst, is_initialized_dst, index_dst, count_dst, escrows_dst) =
+			mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 4, 1, REGISTRY_PAGE_CAPACITY * 32];
+
+		*discriminator_dst = RegistryPage::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		*index_dst = self.index.to_le_bytes();
+		count_dst[0] = self.escrows.len() as u8;
+		escrows_dst.fill(0);
+		for (escrow_dst, escrow) in escrows_dst.chunks_exact_mut(32).zip(&self.escrows) {
+			escrow_dst.copy_from_slice(escrow.as_ref());
+		}
+	}
+}
+
+pub const INDEX_SEED: &[u8] = b"index";
+
+/// Open escrows an InitializerIndex holds
+pub const INDEX_CAPACITY: usize = 32;
+
+/// The open escrows of one initializer, at the PDA [b"index", initializer], so wallets get "my open
+/// orders" from one account. The initializer makes it with InitializeIndex, after that InitEscrow adds
+/// escrows to it when it's passed and settling them takes them out again. Their order isn't kept
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct InitializerIndex {
+	pub is_initialized: bool,
+	pub initializer_pubkey: Pubkey,
+	/// At most INDEX_CAPACITY
+	pub escrows: Vec<Pubkey>,
+}
+
+impl InitializerIndex {
+	pub fn find_address(initializer: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[INDEX_SEED, initializer.as_ref()], program_id)
+	}
+
+	pub fn add(&mut self, escrow: Pubkey) -> Result<(), ProgramError> {
+		if self.escrows.len() >= INDEX_CAPACITY {
+			return Err(EscrowError::IndexFull.into());
+		}
+		self.escrows.push(escrow);
+		Ok(())
+	}
+
+	/// Takes `escrow` out, swapping the last one into its place. False if it isn't in the index
+	pub fn remove(&mut self, escrow: &Pubkey) -> bool {
+		match self.escrows.iter().position(|indexed| indexed == escrow) {
+			Some(position) => {
+				self.escrows.swap_remove(position);
+				true
+			},
+			None => false,
+		}
+	}
+}
+
+impl Sealed for InitializerIndex {}
+
+impl Discriminator for InitializerIndex {
+	const DISCRIMINATOR: [u8; 8] = [11, 160, 136, 152, 147, 85, 76, 138];
+}
+
+impl IsInitialized for InitializerIndex {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for InitializerIndex {
+	const LEN: usize = 1066; // 8 (discriminator) + 1 (bool) + 32 (Pubkey) + 1 (u8 count) + 32 * 32 (Pubkey) = 1066
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, InitializerIndex::LEN];
+		let (discriminator, is_initialized, initializer_pubkey, count, escrows) =
+			array_refs![src, DISCRIMINATOR_LEN, 1, 32, 1, INDEX_CAPACITY * 32];
+		let is_initialized = match is_initialized {
+			[0] => false,
+			[1] => true,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		check_discriminator::<InitializerIndex>(discriminator, is_initialized)?;
+		let count = count[0] as usize;
+		if count > INDEX_CAPACITY {
+			return Err(ProgramError::InvalidAccountData);
+		}
+
+		Ok(InitializerIndex {
+			is_initialized,
+			initializer_pubkey: Pubkey::new_from_array(*initializer_pubkey),
+			escrows: escrows.chunks_exact(32).take(count).map(Pubkey::new).collect(),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, InitializerIndex::LEN];
+		let (discriminator_dst, is_initialized_dst, initializer_pubkey_dst, count_dst, escrows_dst) =
+			mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 32, 1, INDEX_CAPACITY * 32];
+
+		*discriminator_dst = InitializerIndex::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		initializer_pubkey_dst.copy_from_slice(self.initializer_pubkey.as_ref());
+		count_dst[0] = self.escrows.len() as u8;
+		escrows_dst.fill(0);
+		for (escrow_dst, escrow) in escrows_dst.chunks_exact_mut(32).zip(&self.escrows) {
+			escrow_dst.copy_from_slice(escrow.as_ref());
+		}
+	}
+}
+
+pub const PAIR_STATS_SEED: &[u8] = b"pair-stats";
+
+/// How long PairStats' window runs before it starts over, a day
+pub const PAIR_STATS_WINDOW: i64 = 24 * 60 * 60;
+
+/// Fills of one (deposit mint, receive mint) pair, at the PDA [b"pair-stats", deposit mint, receive mint],
+/// for market pages. Exchange keeps it up to date when it's passed in. Volumes are raw amounts of
+/// what actually arrived
+#[derive(ShankAccount)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize, BorshSchema))]
+pub struct PairStats {
+	pub is_initialized: bool,
+	pub deposit_mint_pubkey: Pubkey,
+	pub receive_mint_pubkey: Pubkey,
+	pub fills: u64,
+	pub deposit_volume: u64,
+	pub receive_volume: u64,
+	/// Escrow::normalized_price of the last fill, 0 before the first one
+	pub last_price: u128,
+	pub last_fill_at: i64,
+	/// When the current window started. The first fill more than PAIR_STATS_WINDOW after it starts a
+	/// new one, with the window counters back at 0
+	pub window_start: i64,
+	pub window_fills: u64,
+	pub window_deposit_volume: u64,
+	pub window_receive_volume: u64,
+}
+
+impl PairStats {
+	pub fn find_address(deposit_mint: &Pubkey, receive_mint: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[PAIR_STATS_SEED, deposit_mint.as_ref(), receive_mint.as_ref()], program_id)
+	}
+
+	/// Counts a fill at `unix_timestamp`
+	pub fn record_fill(&mut self, deposit_amount: u64, receive_amount: u64, price: Option<u128>, unix_timestamp: i64) {
+		if unix_timestamp.saturating_sub(self.window_start) >= PAIR_STATS_WINDOW {
+			self.window_start = unix_timestamp;
+			self.window_fills = 0;
+			self.window_deposit_volume = 0;
+			self.window_receive_volume = 0;
+		}
+		self.fills = self.fills.saturating_add(1);
+		self.deposit_volume = self.deposit_volume.saturating_add(deposit_amount);
+		self.receive_volume = self.receive_volume.saturating_add(receive_amount);
+		self.window_fills = self.window_fills.saturating_add(1);
+		self.window_deposit_volume = self.window_deposit_volume.saturating_add(deposit_amount);
+		self.window_receive_volume = self.window_receive_volume.saturating_add(receive_amount);
+		if let Some(price) = price {
+			self.last_price = price;
+		}
+		self.last_fill_at = unix_timestamp;
+	}
+}
+
+impl Sealed for PairStats {}
+
+impl Discriminator for PairStats {
+	const DISCRIMINATOR: [u8; 8] = [14, 35, 141, 74, 246, 178, 213, 125];
+}
+
+impl IsInitialized for PairStats {
+	fn is_initialized(&self) -> bool {
+		self.is_initialized
+	}
+}
+
+impl Pack for PairStats {
+	const LEN: usize = 153; // 8 (discriminator) + 1 (bool) + 2 * 32 (Pubkey) + 8 * 8 (u64/i64) + 16 (u128) = 153
+	fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+		let src = array_ref![src, 0, PairStats::LEN];
+		let (
+			discriminator,
+			is_initialized,
+			deposit_mint_pubkey,
+			receive_mint_pubkey,
+			fills,
+			deposit_volume,
+			receive_volume,
+			last_price,
+			last_fill_at,
+			window_start,
+			window_fills,
+			window_deposit_volume,
+			window_receive_volume,
+		) = array_refs![src, DISCRIMINATOR_LEN, 1, 32, 32, 8, 8, 8, 16, 8, 8, 8, 8, 8];
+		let is_initialized = match is_initialized {
+			[0] => false,
+			[1] => true,
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		check_discriminator::<PairStats>(discriminator, is_initialized)?;
+
+		Ok(PairStats {
+			is_initialized,
+			deposit_mint_pubkey: Pubkey::new_from_array(*deposit_mint_pubkey),
+			receive_mint_pubkey: Pubkey::new_from_array(*receive_mint_pubkey),
+			fills: u64::from_le_bytes(*fills),
+			deposit_volume: u64::from_le_bytes(*deposit_volume),
+			receive_volume: u64::from_le_bytes(*receive_volume),
+			last_price: u128::from_le_bytes(*last_price),
+			last_fill_at: i64::from_le_bytes(*last_fill_at),
+			window_start: i64::from_le_bytes(*window_start),
+			window_fills: u64::from_le_bytes(*window_fills),
+			window_deposit_volume: u64::from_le_bytes(*window_deposit_volume),
+			window_receive_volume: u64::from_le_bytes(*window_receive_volume),
+		})
+	}
+
+	fn pack_into_slice(&self, dst: &mut [u8]) {
+		let dst = array_mut_ref![dst, 0, PairStats::LEN];
+		let (
+			discriminator_dst,
+			is_initialized_dst,
+			deposit_mint_pubkey_dst,
+			receive_mint_pubkey_dst,
+			fills_dst,
+			deposit_volume_dst,
+			receive_volume_dst,
+			last_price_dst,
+			last_fill_at_dst,
+			window_start_dst,
+			window_fills_dst,
+			window_deposit_volume_dst,
+			window_receive_volume_dst,
+		) = mut_array_refs![dst, DISCRIMINATOR_LEN, 1, 32, 32, 8, 8, 8, 16, 8, 8, 8, 8, 8];
+
+		*discriminator_dst = PairStats::DISCRIMINATOR;
+		is_initialized_dst[0] = self.is_initialized as u8;
+		deposit_mint_pubkey_dst.copy_from_slice(self.deposit_mint_pubkey.as_ref());
+		receive_mint_pubkey_dst.copy_from_slice(self.receive_mint_pubkey.as_ref());
+		*fills_dst = self.fills.to_le_bytes();
+		*deposit_volume_dst = self.deposit_volume.to_le_bytes();
+		*receive_volume_dst = self.receive_volume.to_le_bytes();
+		*last_price_dst = self.last_price.to_le_bytes();
+		*last_fill_at_dst = self.last_fill_at.to_le_bytes();
+		*window_start_dst = self.window_start.to_le_bytes();
+		*window_fills_dst = self.window_fills.to_le_bytes();
+		*window_deposit_volume_dst = self.window_deposit_volume.to_le_bytes();
+		*window_receive_volume_dst = self.window_receive_volume.to_le_bytes();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn offsets_match_the_struct() {
+		let escrow = Escrow::zeroed();
+		let offset = |field: *const u8| DISCRIMINATOR_LEN + field as usize - &escrow as *const Escrow as usize;
+		assert_eq!(Escrow::VERSION_OFFSET, offset(&escrow.version));
+		assert_eq!(Escrow::STATUS_OFFSET, offset(&escrow.status));
+		assert_eq!(Escrow::EXPECTED_AMOUNT_OFFSET, offset(&escrow.expected_amount as *const u64 as *const u8));
+		assert_eq!(Escrow::DEPOSITED_AMOUNT_OFFSET, offset(&escrow.deposited_amount as *const u64 as *const u8));
+		assert_eq!(Escrow::EXPIRES_AT_OFFSET, offset(&escrow.expires_at as *const i64 as *const u8));
+		assert_eq!(Escrow::INITIALIZER_OFFSET, offset(escrow.initializer_pubkey.as_ref().as_ptr()));
+		assert_eq!(Escrow::TEMP_TOKEN_ACCOUNT_OFFSET, offset(escrow.temp_token_account_pubkey.as_ref().as_ptr()));
+		assert_eq!(Escrow::REFUND_OFFSET, offset(escrow.refund_pubkey.as_ref().as_ptr()));
+		assert_eq!(Escrow::DEPOSIT_MINT_OFFSET, offset(escrow.deposit_mint_pubkey.as_ref().as_ptr()));
+		assert_eq!(Escrow::RECEIVE_MINT_OFFSET, offset(escrow.receive_mint_pubkey.as_ref().as_ptr()));
+	}
+}
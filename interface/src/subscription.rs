@@ -0,0 +1,218 @@
+//! Escrow updates as they happen, from programSubscribe and accountSubscribe notifications instead of
+//! polling getProgramAccounts. Like client.rs this doesn't bring a websocket client, the Pubsub trait is
+//! what the integrator's has to do.
+//!
+//! programSubscribe sees escrows being created and changed, but not closed: a closed account belongs to
+//! the system program once the transaction is done. So every open escrow gets an accountSubscribe too.
+//! An account that's gone can't say whether it was filled or cancelled, escrows that don't keep their
+//! history (see Escrow::retain_history) are told apart by the events in the logs of the transaction
+//! that closed them (see event.rs), from a logsSubscribe
+
+use std::collections::{HashMap, VecDeque};
+
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+	client::{ClientError, Memcmp},
+	event::EscrowEvent,
+	state::{has_closed_discriminator, Discriminator, Escrow, EscrowStatus},
+};
+
+pub enum Notification {
+	/// An account's address and data. Closed accounts have none
+	Account(Pubkey, Vec<u8>),
+	/// The log messages of a transaction that succeeded
+	Logs(Vec<String>),
+}
+
+/// The subscriptions EscrowSubscription makes, over one websocket to a node's pubsub endpoint
+pub trait Pubsub {
+	type Error;
+
+	/// programSubscribe to the accounts of `program_id` matching all of `filters`
+	fn program_subscribe(&mut self, program_id: &Pubkey, filters: &[Memcmp]) -> Result<(), Self::Error>;
+
+	fn account_subscribe(&mut self, pubkey: &Pubkey) -> Result<(), Self::Error>;
+
+	fn account_unsubscribe(&mut self, pubkey: &Pubkey) -> Result<(), Self::Error>;
+
+	/// logsSubscribe to the transactions that mention `program_id`
+	fn logs_subscribe(&mut self, program_id: &Pubkey) -> Result<(), Self::Error>;
+
+	/// Blocks until the next notification of any subscription. Logs of transactions that failed are
+	/// left out, their events didn't happen
+	fn next_notification(&mut self) -> Result<Notification, Self::Error>;
+}
+
+#[derive(Clone, Copy)]
+pub enum EscrowUpdate {
+	/// A new escrow, or one EscrowSubscription::track was told about
+	Created(Pubkey, Escrow),
+	/// An open escrow changed, like a partial fill or a freeze
+	Updated(Pubkey, Escrow),
+	Filled(Pubkey, Escrow),
+	/// Cancelled by its initializer or the program's admin
+	Cancelled(Pubkey, Escrow),
+	Expired(Pubkey, Escrow),
+	/// The account was closed with no event seen for it first, with the escrow as it was last seen.
+	/// The logs notification can come after the account's, the logs of busy nodes get truncated and
+	/// event CPIs aren't in the logs at all
+	Closed(Pubkey, Escrow),
+}
+
+impl EscrowUpdate {
+	pub fn address(&self) -> &Pubkey {
+		match self {
+			EscrowUpdate::Created(address, _)
+			| EscrowUpdate::Updated(address, _)
+			| EscrowUpdate::Filled(address, _)
+			| EscrowUpdate::Cancelled(address, _)
+			| EscrowUpdate::Expired(address, _)
+			| EscrowUpdate::Closed(address, _) => address,
+		}
+	}
+
+	pub fn escrow(&self) -> &Escrow {
+		match self {
+			EscrowUpdate::Created(_, escrow)
+			| EscrowUpdate::Updated(_, escrow)
+			| EscrowUpdate::Filled(_, escrow)
+			| EscrowUpdate::Cancelled(_, escrow)
+			| EscrowUpdate::Expired(_, escrow)
+			| EscrowUpdate::Closed(_, escrow) => escrow,
+		}
+	}
+}
+
+/// The updates of the escrows of a program, or of one initializer's, as an iterator. Only escrows it saw
+/// open get updates: subscribe first, then track the escrows client::list_open_escrows finds, so the
+/// ones opened in between aren't missed
+pub struct EscrowSubscription<P> {
+	pubsub: P,
+	program_id: Pubkey,
+	/// The open escrows as last seen
+	open: HashMap<Pubkey, Escrow>,
+	/// Updates of a transaction that settled several escrows, still to be returned
+	pending: VecDeque<EscrowUpdate>,
+}
+
+impl<P: Pubsub> EscrowSubscription<P> {
+	pub fn new(mut pubsub: P, program_id: &Pubkey, initializer: Option<&Pubkey>) -> Result<Self, ClientError<P::Error>> {
+		// Settled escrows that keep their history still match, their statuses are what says they're settled
+		let mut filters = vec![Memcmp {
+			offset: 0,
+			bytes: Escrow::DISCRIMINATOR.to_vec(),
+		}];
+		if let Some(initializer) = initializer {
+			filters.push(Memcmp {
+				offset: Escrow::INITIALIZER_OFFSET,
+				bytes: initializer.to_bytes().to_vec(),
+			});
+		}
+		pubsub.program_subscribe(program_id, &filters).map_err(ClientError::Rpc)?;
+		pubsub.logs_subscribe(program_id).map_err(ClientError::Rpc)?;
+		Ok(EscrowSubscription {
+			pubsub,
+			program_id: *program_id,
+			open: HashMap::new(),
+			pending: VecDeque::new(),
+		})
+	}
+
+	/// Follows an escrow that was open before the subscription, without a Created update for it
+	pub fn track(&mut self, address: &Pubkey, escrow: Escrow) -> Result<(), ClientError<P::Error>> {
+		if self.open.insert(*address, escrow).is_none() {
+			self.pubsub.account_subscribe(address).map_err(ClientError::Rpc)?;
+		}
+		Ok(())
+	}
+
+	/// Blocks until an escrow changes. Notifications that don't change anything, like the second one of
+	/// a change both subscriptions see, are skipped
+	pub fn next_update(&mut self) -> Result<EscrowUpdate, ClientError<P::Error>> {
+		loop {
+			if let Some(update) = self.pending.pop_front() {
+				return Ok(update);
+			}
+			match self.pubsub.next_notification().map_err(ClientError::Rpc)? {
+				Notification::Account(address, data) => {
+					if let Some(update) = self.update(address, &data)? {
+						return Ok(update);
+					}
+				},
+				Notification::Logs(logs) => {
+					for event in EscrowEvent::from_logs(&logs, &self.program_id) {
+						self.settle(event)?;
+					}
+				},
+			}
+		}
+	}
+
+	/// Settles the open escrow the event is about, with the escrow as it was last seen open. Its
+	/// account's notifications after that are skipped, the escrow isn't open anymore
+	fn settle(&mut self, event: EscrowEvent) -> Result<(), ClientError<P::Error>> {
+		let address = *event.escrow();
+		let update = match event {
+			EscrowEvent::Created(_) => return Ok(()),
+			EscrowEvent::Filled(_) => EscrowUpdate::Filled,
+			EscrowEvent::Cancelled(cancelled) if cancelled.status == EscrowStatus::Expired => EscrowUpdate::Expired,
+			EscrowEvent::Cancelled(_) => EscrowUpdate::Cancelled,
+		};
+		if let Some(escrow) = self.open.remove(&address) {
+			self.pubsub.account_unsubscribe(&address).map_err(ClientError::Rpc)?;
+			self.pending.push_back(update(address, escrow));
+		}
+		Ok(())
+	}
+
+	fn update(&mut self, address: Pubkey, data: &[u8]) -> Result<Option<EscrowUpdate>, ClientError<P::Error>> {
+		if data.is_empty() || has_closed_discriminator(data) {
+			return match self.open.remove(&address) {
+				Some(escrow) => {
+					self.pubsub.account_unsubscribe(&address).map_err(ClientError::Rpc)?;
+					Ok(Some(EscrowUpdate::Closed(address, escrow)))
+				},
+				None => Ok(None),
+			};
+		}
+		// Not an escrow. programSubscribe's filters don't let anything else through
+		let escrow = match Escrow::unpack_versioned_unchecked(data) {
+			Ok(escrow) => escrow,
+			Err(_) => return Ok(None),
+		};
+
+		let settled = match escrow.status() {
+			Ok(EscrowStatus::Active) => {
+				let update = match self.open.insert(address, escrow) {
+					None => {
+						self.pubsub.account_subscribe(&address).map_err(ClientError::Rpc)?;
+						EscrowUpdate::Created(address, escrow)
+					},
+					Some(last) if bytemuck::bytes_of(&last) == bytemuck::bytes_of(&escrow) => return Ok(None),
+					Some(_) => EscrowUpdate::Updated(address, escrow),
+				};
+				return Ok(Some(update));
+			},
+			Ok(EscrowStatus::Completed) => EscrowUpdate::Filled(address, escrow),
+			Ok(EscrowStatus::Cancelled) => EscrowUpdate::Cancelled(address, escrow),
+			Ok(EscrowStatus::Expired) => EscrowUpdate::Expired(address, escrow),
+			Ok(EscrowStatus::Uninitialized) | Err(_) => return Ok(None),
+		};
+		// Settled escrows that were never seen open were settled before the subscription
+		if self.open.remove(&address).is_none() {
+			return Ok(None);
+		}
+		self.pubsub.account_unsubscribe(&address).map_err(ClientError::Rpc)?;
+		Ok(Some(settled))
+	}
+}
+
+impl<P: Pubsub> Iterator for EscrowSubscription<P> {
+	type Item = Result<EscrowUpdate, ClientError<P::Error>>;
+
+	/// Never ends, it's next_update
+	fn next(&mut self) -> Option<Self::Item> {
+		Some(self.next_update())
+	}
+}
@@ -0,0 +1,30 @@
+//! The token programs instructions are checked against. Their ids are declared here instead of coming
+//! from spl-token so the interface doesn't pull the token program in
+
+use solana_program::{entrypoint::ProgramResult, program_error::ProgramError, pubkey::Pubkey};
+
+/// SPL Token, spl_token::id()
+pub mod token_program {
+	solana_program::declare_id!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+}
+
+/// SPL Token-2022. Its accounts and mints start out with the exact spl-token layout (extensions go after
+/// it) and the instructions we use are encoded the same way, so we get by without the crate
+pub mod token_2022 {
+	solana_program::declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+}
+
+/// Most signers a token multisig can have, spl_token::instruction::MAX_SIGNERS
+pub const MAX_SIGNERS: usize = 11;
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+	*program_id == token_program::id() || *program_id == token_2022::id()
+}
+
+/// Makes sure instructions built for `token_program_id` go to one of the token programs
+pub fn check_token_program_id(token_program_id: &Pubkey) -> ProgramResult {
+	if !is_token_program(token_program_id) {
+		return Err(ProgramError::IncorrectProgramId);
+	}
+	Ok(())
+}
@@ -0,0 +1,13 @@
+use solana_program::pubkey::Pubkey;
+
+/// Seed for the treasury PDA. The treasury is a plain system account (no data), which is why the
+/// program can sign lamports back out of it with invoke_signed. The fee itself is set in the Config
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// If true the listing fee goes back to the initializer once their escrow gets filled, so only
+/// escrows that never trade end up paying it
+pub const REFUND_LISTING_FEE_ON_EXCHANGE: bool = true;
+
+pub fn find_treasury_address(program_id: &Pubkey) -> (Pubkey, u8) {
+	Pubkey::find_program_address(&[TREASURY_SEED], program_id)
+}
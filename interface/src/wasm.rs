@@ -0,0 +1,31 @@
+//! Decoding for browser dapps, built with `wasm-pack build interface --features wasm`. Escrows and
+//! instructions come back as the JSON the serde feature gives them (pubkeys in base58), for JSON.parse.
+//! Amounts are plain JSON numbers, so ones above 2^53 lose precision unless they're parsed with
+//! something like json-bigint
+
+use solana_program::program_error::ProgramError;
+use wasm_bindgen::prelude::*;
+
+use crate::{instruction::EscrowInstruction, state::Escrow};
+
+/// The escrow in an escrow account's data, whatever its layout version and status
+#[wasm_bindgen(js_name = decodeEscrow)]
+pub fn decode_escrow(data: &[u8]) -> Result<String, JsValue> {
+	let mut words = vec![0u64; data.len().div_ceil(8)];
+	// Escrow::load casts the data in place, which needs it 8 byte aligned like account data is on chain
+	let aligned = &mut bytemuck::cast_slice_mut::<u64, u8>(&mut words)[..data.len()];
+	aligned.copy_from_slice(data);
+	let escrow = Escrow::unpack_versioned_unchecked(aligned).map_err(to_js)?;
+	serde_json::to_string(&escrow).map_err(|error| JsValue::from_str(&error.to_string()))
+}
+
+/// The instruction in a transaction's instruction data for the escrow program
+#[wasm_bindgen(js_name = decodeInstruction)]
+pub fn decode_instruction(data: &[u8]) -> Result<String, JsValue> {
+	let instruction = EscrowInstruction::unpack(data).map_err(to_js)?;
+	serde_json::to_string(&instruction).map_err(|error| JsValue::from_str(&error.to_string()))
+}
+
+fn to_js(error: ProgramError) -> JsValue {
+	JsValue::from_str(&error.to_string())
+}
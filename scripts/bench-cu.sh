@@ -0,0 +1,92 @@
+#!/usr/bin/env bash
+#
+# Fails if this commit makes InitEscrow, Exchange, ExchangeV2 or Cancel take more compute units than
+# they do at <base> (origin/main by default) by more than bench's threshold. Builds the program at both
+# commits, deploys each to a fresh solana-test-validator at the declared program id and runs `bench` on
+# it, the base's numbers being the baseline. They're measured here rather than kept in cu-baseline.json
+# so both go through the same validator version. bench is always this commit's, so the base program has
+# to take the instructions it sends. Extra arguments go to bench, like --threshold
+#
+#   $ ./scripts/bench-cu.sh [<base>] [-- <bench args>]
+#
+
+here="$(dirname "$0")"
+
+set -e
+
+cd "$here"/..
+root=$(pwd)
+
+base=origin/main
+if [[ -n $1 && $1 != -- ]]; then
+  base=$1
+  shift
+fi
+if [[ $1 == -- ]]; then
+  shift
+fi
+
+work=$(mktemp -d)
+validator_pid=
+cleanup() {
+  if [[ -n $validator_pid ]]; then
+    kill "$validator_pid" 2>/dev/null || true
+    wait "$validator_pid" 2>/dev/null || true
+  fi
+  git worktree remove --force "$work"/base 2>/dev/null || true
+  rm -rf "$work"
+}
+trap cleanup EXIT
+
+program_id=$(sed -n 's/^solana_program::declare_id!("\(.*\)");$/\1/p' interface/src/lib.rs)
+if [[ -z $program_id ]]; then
+  echo "Error: no declare_id! in interface/src/lib.rs"
+  exit 1
+fi
+
+solana-keygen new --no-bip39-passphrase --silent --force -o "$work"/payer.json
+payer=$(solana-keygen pubkey "$work"/payer.json)
+
+echo "Building the program at $base"
+git worktree add --detach "$work"/base "$(git merge-base "$base" HEAD)" >/dev/null
+(cd "$work"/base && cargo build-bpf --bpf-out-dir "$work"/base-deploy)
+echo "Building the program at HEAD"
+cargo build-bpf --bpf-out-dir "$work"/head-deploy
+cargo build -q -p escrow-cli
+cli="$root"/target/debug/escrow-cli
+
+# Deploys $1 at the program id with the payer as its upgrade authority, so it can create the config
+start_validator() {
+  solana-test-validator --reset --quiet --ledger "$work"/ledger --mint "$payer" \
+    --upgradeable-program "$program_id" "$1" "$payer" >"$work"/validator.log 2>&1 &
+  validator_pid=$!
+  for _ in $(seq 60); do
+    if solana -u localhost cluster-version >/dev/null 2>&1; then
+      "$cli" -k "$work"/payer.json init-config >/dev/null
+      return
+    fi
+    sleep 1
+  done
+  echo "Error: solana-test-validator didn't start"
+  cat "$work"/validator.log
+  exit 1
+}
+
+stop_validator() {
+  kill "$validator_pid"
+  wait "$validator_pid" 2>/dev/null || true
+  validator_pid=
+}
+
+echo "Measuring $base"
+start_validator "$work"/base-deploy/bpf_program_template.so
+if ! "$cli" -k "$work"/payer.json bench --save --baseline "$work"/cu-baseline.json; then
+  echo "Error: bench fails on $base, if its program can't take this commit's instructions measure a"
+  echo "baseline of your own with bench --save"
+  exit 1
+fi
+stop_validator
+
+echo "Measuring HEAD"
+start_validator "$work"/head-deploy/bpf_program_template.so
+"$cli" -k "$work"/payer.json bench --baseline "$work"/cu-baseline.json "$@"
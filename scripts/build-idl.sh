@@ -0,0 +1,14 @@
+#!/usr/bin/env bash
+#
+# Generates target/idl/escrow.json, the program's IDL in Shank's format, for TypeScript client
+# generators (solita, Kinobi) and explorers' instruction decoding. The address in its metadata is the
+# declared program id unless --program-id says otherwise
+#
+
+here="$(dirname "$0")"
+
+set -e
+
+cd "$here"/..
+cargo run -q -p escrow-cli -- idl --out target/idl/escrow.json "$@"
+echo "Wrote target/idl/escrow.json"
@@ -0,0 +1,358 @@
+//! Typed wrappers for the accounts instructions take, so what an account has to be is checked right
+//! where it's read off the account list. The per-instruction structs read an instruction's fixed
+//! accounts in order and check everything about them, each other and the escrow, what's optional stays
+//! with the processor.
+//!
+//! Only Exchange, Cancel and SweepSurplus have their structs so far. The other handlers still read
+//! their accounts with next_account_info and check them where they're used, with the same check_*
+//! functions these call
+
+use std::ops::Deref;
+
+use solana_program::{
+	account_info::{next_account_info, AccountInfo},
+	entrypoint::ProgramResult,
+	program_error::ProgramError,
+	program_pack::Pack,
+	pubkey::Pubkey,
+};
+use spl_token::state::Account as TokenAccount;
+
+use crate::{
+	error::EscrowError,
+	state::{Config, Escrow, EscrowGroup},
+	token::{self, check_token_program},
+};
+
+type AccountIter<'r, 'a> = std::slice::Iter<'r, AccountInfo<'a>>;
+
+/// An account that signed the transaction
+#[derive(Clone, Copy)]
+pub struct Signer<'r, 'a>(&'r AccountInfo<'a>);
+
+impl<'r, 'a> Signer<'r, 'a> {
+	pub fn new(account: &'r AccountInfo<'a>) -> Result<Self, ProgramError> {
+		if !account.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		Ok(Signer(account))
+	}
+
+	pub fn info(&self) -> &'r AccountInfo<'a> {
+		self.0
+	}
+}
+
+impl<'r, 'a> Deref for Signer<'r, 'a> {
+	type Target = AccountInfo<'a>;
+
+	fn deref(&self) -> &AccountInfo<'a> {
+		self.0
+	}
+}
+
+/// An account the transaction lets us write to
+#[derive(Clone, Copy)]
+pub struct Writable<'r, 'a>(&'r AccountInfo<'a>);
+
+impl<'r, 'a> Writable<'r, 'a> {
+	pub fn new(account: &'r AccountInfo<'a>) -> Result<Self, ProgramError> {
+		if !account.is_writable {
+			return Err(EscrowError::AccountNotWritable.into());
+		}
+		Ok(Writable(account))
+	}
+
+	pub fn info(&self) -> &'r AccountInfo<'a> {
+		self.0
+	}
+}
+
+impl<'r, 'a> Deref for Writable<'r, 'a> {
+	type Target = AccountInfo<'a>;
+
+	fn deref(&self) -> &AccountInfo<'a> {
+		self.0
+	}
+}
+
+/// State types of this program that ProgramOwned can read
+pub trait ProgramAccount: Sized {
+	fn unpack_account(data: &[u8]) -> Result<Self, ProgramError>;
+}
+
+impl ProgramAccount for Escrow {
+	/// Any layout version, it comes back as the current one
+	fn unpack_account(data: &[u8]) -> Result<Self, ProgramError> {
+		Escrow::unpack_versioned(data)
+	}
+}
+
+impl ProgramAccount for Config {
+	fn unpack_account(data: &[u8]) -> Result<Self, ProgramError> {
+		Config::unpack(data)
+	}
+}
+
+impl ProgramAccount for EscrowGroup {
+	fn unpack_account(data: &[u8]) -> Result<Self, ProgramError> {
+		EscrowGroup::unpack(data)
+	}
+}
+
+/// An account of this program and what's in it. The copy in `info` doesn't follow later writes to the account
+pub struct ProgramOwned<'r, 'a, T> {
+	pub account: &'r AccountInfo<'a>,
+	pub info: T,
+}
+
+impl<'r, 'a, T: ProgramAccount> ProgramOwned<'r, 'a, T> {
+	pub fn new(account: &'r AccountInfo<'a>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		check_owner(account, program_id)?;
+		let info = T::unpack_account(&account.try_borrow_data()?)?;
+		Ok(ProgramOwned { account, info })
+	}
+
+	/// For accounts the instruction writes to
+	pub fn new_writable(account: &'r AccountInfo<'a>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		Self::new(Writable::new(account)?.info(), program_id)
+	}
+}
+
+impl<'r, 'a, T> Deref for ProgramOwned<'r, 'a, T> {
+	type Target = AccountInfo<'a>;
+
+	fn deref(&self) -> &AccountInfo<'a> {
+		self.account
+	}
+}
+
+/// A token account of `token_program` holding `mint`
+pub struct TokenAccountOf<'r, 'a> {
+	pub account: &'r AccountInfo<'a>,
+	pub info: TokenAccount,
+}
+
+impl<'r, 'a> TokenAccountOf<'r, 'a> {
+	pub fn new(account: &'r AccountInfo<'a>, token_program: &AccountInfo, mint: &Pubkey) -> Result<Self, ProgramError> {
+		check_token_program(account, token_program)?;
+		let info = token::unpack_account(account)?;
+		if info.mint != *mint {
+			return Err(EscrowError::TokenMintMismatch.into());
+		}
+		Ok(TokenAccountOf { account, info })
+	}
+}
+
+impl<'r, 'a> Deref for TokenAccountOf<'r, 'a> {
+	type Target = AccountInfo<'a>;
+
+	fn deref(&self) -> &AccountInfo<'a> {
+		self.account
+	}
+}
+
+/// Accounts 0 to 10 of Exchange (see EscrowInstruction::Exchange). The programs are checked where
+/// they're called, which one account 7 has to be depends on the deposit
+pub struct ExchangeAccounts<'r, 'a> {
+	pub taker: Signer<'r, 'a>,
+	pub takers_sending_account: Writable<'r, 'a>,
+	pub takers_token_to_receive_account: Writable<'r, 'a>,
+	/// Whatever holds the deposit, the escrow's temp token account for token deposits
+	pub pda_temp_token_account: Writable<'r, 'a>,
+	/// The escrow's refund address, gets the escrow's rent back
+	pub refund_account: Writable<'r, 'a>,
+	pub initializers_token_to_receive_account: Writable<'r, 'a>,
+	/// Writable too
+	pub escrow: ProgramOwned<'r, 'a, Escrow>,
+	pub token_program: &'r AccountInfo<'a>,
+	pub pda_account: &'r AccountInfo<'a>,
+	/// Already checked against the pause flag in process
+	pub config_account: Writable<'r, 'a>,
+	pub sending_token_program: &'r AccountInfo<'a>,
+}
+
+impl<'r, 'a> ExchangeAccounts<'r, 'a> {
+	/// Reads the accounts off `account_info_iter` and checks they're the ones the escrow was made with
+	pub fn next(account_info_iter: &mut AccountIter<'r, 'a>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		Self::next_with(account_info_iter, program_id, false)
+	}
+
+	/// ExchangeV2's accounts 0 to 10 (see EscrowInstruction::ExchangeV2). Without account 10 the taker
+	/// sends a token of account 7's program, which is `sending_token_program` then
+	pub fn next_v2(account_info_iter: &mut AccountIter<'r, 'a>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		Self::next_with(account_info_iter, program_id, true)
+	}
+
+	fn next_with(account_info_iter: &mut AccountIter<'r, 'a>, program_id: &Pubkey, v2: bool) -> Result<Self, ProgramError> {
+		let taker = Signer::new(next_account_info(account_info_iter)?)?;
+		let takers_sending_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let takers_token_to_receive_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let pda_temp_token_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let refund_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let initializers_token_to_receive_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let escrow = ProgramOwned::<Escrow>::new_writable(next_account_info(account_info_iter)?, program_id)?;
+		let token_program = next_account_info(account_info_iter)?;
+		let pda_account = next_account_info(account_info_iter)?;
+		let config_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let sending_token_program = match v2 {
+			true if takers_sending_account.owner == token_program.key => token_program,
+			_ => next_account_info(account_info_iter)?,
+		};
+
+		if escrow.info.temp_token_account_pubkey != *pda_temp_token_account.key {
+			return Err(EscrowError::WrongVaultAccount.into());
+		}
+		// Not the initializer, a multisig initializer is a token account that can't spend lamports
+		if escrow.info.refund_pubkey != *refund_account.key {
+			return Err(EscrowError::WrongRefundAccount.into());
+		}
+		if !escrow.info.is_collection_offer()
+			&& escrow.info.initializer_token_to_receive_account_pubkey != *initializers_token_to_receive_account.key
+		{
+			return Err(EscrowError::WrongReceiveAccount.into());
+		}
+		check_authority(&escrow.info, escrow.account, pda_account, program_id)?;
+		// Token amounts are read before and after the transfers, the same account in two places would be
+		// counted twice
+		check_distinct(&[
+			takers_sending_account.info(),
+			takers_token_to_receive_account.info(),
+			pda_temp_token_account.info(),
+			initializers_token_to_receive_account.info(),
+			escrow.account,
+		])?;
+
+		Ok(ExchangeAccounts {
+			taker,
+			takers_sending_account,
+			takers_token_to_receive_account,
+			pda_temp_token_account,
+			refund_account,
+			initializers_token_to_receive_account,
+			escrow,
+			token_program,
+			pda_account,
+			config_account,
+			sending_token_program,
+		})
+	}
+}
+
+/// Accounts 0 to 6 of Cancel (see EscrowInstruction::Cancel). The initializer doesn't have to sign
+/// here, a multisig initializer's signers come at the end
+pub struct CancelAccounts<'r, 'a> {
+	pub initializer: &'r AccountInfo<'a>,
+	/// Whatever holds the deposit, the escrow's temp token account for token deposits
+	pub pda_temp_token_account: Writable<'r, 'a>,
+	pub refund_token_account: Writable<'r, 'a>,
+	/// Writable too
+	pub escrow: ProgramOwned<'r, 'a, Escrow>,
+	pub token_program: &'r AccountInfo<'a>,
+	pub pda_account: &'r AccountInfo<'a>,
+	/// Gets the escrow's rent back
+	pub refund_account: Writable<'r, 'a>,
+}
+
+impl<'r, 'a> CancelAccounts<'r, 'a> {
+	pub fn next(account_info_iter: &mut AccountIter<'r, 'a>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		let initializer = next_account_info(account_info_iter)?;
+		let pda_temp_token_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let refund_token_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let escrow = ProgramOwned::<Escrow>::new_writable(next_account_info(account_info_iter)?, program_id)?;
+		let token_program = next_account_info(account_info_iter)?;
+		let pda_account = next_account_info(account_info_iter)?;
+		let refund_account = Writable::new(next_account_info(account_info_iter)?)?;
+
+		if escrow.info.initializer_pubkey != *initializer.key {
+			return Err(EscrowError::WrongInitializer.into());
+		}
+		if escrow.info.temp_token_account_pubkey != *pda_temp_token_account.key {
+			return Err(EscrowError::WrongVaultAccount.into());
+		}
+		if escrow.info.refund_pubkey != *refund_account.key {
+			return Err(EscrowError::WrongRefundAccount.into());
+		}
+		check_authority(&escrow.info, escrow.account, pda_account, program_id)?;
+
+		Ok(CancelAccounts {
+			initializer,
+			pda_temp_token_account,
+			refund_token_account,
+			escrow,
+			token_program,
+			pda_account,
+			refund_account,
+		})
+	}
+}
+
+/// Accounts 0 to 5 of SweepSurplus (see EscrowInstruction::SweepSurplus)
+pub struct SweepSurplusAccounts<'r, 'a> {
+	pub initializer: Signer<'r, 'a>,
+	/// Writable too
+	pub pda_temp_token_account: TokenAccountOf<'r, 'a>,
+	pub initializers_surplus_token_account: Writable<'r, 'a>,
+	pub escrow: ProgramOwned<'r, 'a, Escrow>,
+	pub token_program: &'r AccountInfo<'a>,
+	pub pda_account: &'r AccountInfo<'a>,
+}
+
+impl<'r, 'a> SweepSurplusAccounts<'r, 'a> {
+	pub fn next(account_info_iter: &mut AccountIter<'r, 'a>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		let initializer = Signer::new(next_account_info(account_info_iter)?)?;
+		let pda_temp_token_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let initializers_surplus_token_account = Writable::new(next_account_info(account_info_iter)?)?;
+		let escrow = ProgramOwned::<Escrow>::new(next_account_info(account_info_iter)?, program_id)?;
+		let token_program = next_account_info(account_info_iter)?;
+		let pda_account = next_account_info(account_info_iter)?;
+
+		if escrow.info.initializer_pubkey != *initializer.key {
+			return Err(EscrowError::WrongInitializer.into());
+		}
+		if escrow.info.temp_token_account_pubkey != *pda_temp_token_account.key {
+			return Err(EscrowError::WrongVaultAccount.into());
+		}
+		let pda_temp_token_account = TokenAccountOf::new(pda_temp_token_account.info(), token_program, &escrow.info.deposit_mint_pubkey)?;
+		check_authority(&escrow.info, escrow.account, pda_account, program_id)?;
+
+		Ok(SweepSurplusAccounts {
+			initializer,
+			pda_temp_token_account,
+			initializers_surplus_token_account,
+			escrow,
+			token_program,
+			pda_account,
+		})
+	}
+}
+
+/// Makes sure `account` is owned by this program before its data is trusted, anyone can make an
+/// account that looks like one of ours
+pub fn check_owner(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+	if account.owner != program_id {
+		return Err(EscrowError::IncorrectAccountOwner.into());
+	}
+	Ok(())
+}
+
+/// Makes sure `pda_account` is the escrow's PDA. invoke_signed signs for the PDA whatever account was
+/// passed in its place, so the CPIs would just fail further down with a less telling error. v0 escrows'
+/// deposits are with the program-wide PDA instead until MigrateEscrow moves them, unpack_versioned
+/// doesn't let those through
+pub fn check_authority(escrow_info: &Escrow, escrow_account: &AccountInfo, pda_account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+	if *pda_account.key != escrow_info.authority_address(escrow_account.key, program_id)? {
+		return Err(EscrowError::WrongPda.into());
+	}
+	Ok(())
+}
+
+/// Makes sure none of `accounts` was passed twice, aliased accounts would have their balances counted twice
+pub fn check_distinct(accounts: &[&AccountInfo]) -> ProgramResult {
+	for (i, account) in accounts.iter().enumerate() {
+		if accounts[i + 1..].iter().any(|other| other.key == account.key) {
+			return Err(EscrowError::DuplicateAccount.into());
+		}
+	}
+	Ok(())
+}
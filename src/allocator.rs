@@ -0,0 +1,213 @@
+//! The program's heap allocator. solana-program's BumpAllocator never frees, so every CPI's Instruction
+//! and every Vec that grows stays on the 32KB heap until the instruction is done, and an Exchange with
+//! royalties, hooks and program accounts to create can run out of it. This is the same bump allocator
+//! except that the last allocation can be freed and resized where it is: the instruction built for a
+//! CPI goes away after it, and a Vec being pushed to grows in place instead of leaving its old buffer
+//! behind. Anything else freed stays used, like with BumpAllocator
+
+use std::{
+	alloc::{GlobalAlloc, Layout},
+	mem::size_of,
+	ptr::{self, null_mut},
+};
+
+/// The heap grows down from `start + len`. Its first word is where the lowest allocation starts, 0
+/// while nothing was allocated
+pub struct StackAllocator {
+	pub start: usize,
+	pub len: usize,
+}
+
+impl StackAllocator {
+	/// Where the lowest allocation starts
+	unsafe fn position(&self) -> usize {
+		match *(self.start as *const usize) {
+			0 => self.start + self.len,
+			position => position,
+		}
+	}
+
+	/// Where an allocation of `layout` ends up if the lowest one starts at `top`, None if it doesn't fit
+	fn below(&self, top: usize, layout: Layout) -> Option<usize> {
+		let position = top.saturating_sub(layout.size()) & !(layout.align().wrapping_sub(1));
+		if position < self.start + size_of::<usize>() {
+			return None;
+		}
+		Some(position)
+	}
+
+	unsafe fn set_position(&self, position: usize) {
+		*(self.start as *mut usize) = position;
+	}
+}
+
+unsafe impl GlobalAlloc for StackAllocator {
+	#[inline]
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		match self.below(self.position(), layout) {
+			Some(position) => {
+				self.set_position(position);
+				position as *mut u8
+			},
+			None => null_mut(),
+		}
+	}
+
+	#[inline]
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		if ptr as usize == self.position() {
+			self.set_position(ptr as usize + layout.size());
+		}
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		if ptr as usize != self.position() {
+			// Not the last one, a new allocation and a copy like GlobalAlloc's own realloc
+			let new_ptr = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+			if !new_ptr.is_null() {
+				ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+			}
+			return new_ptr;
+		}
+		// The last one, moved so it still ends where it does. That can overlap where it is, copy is memmove
+		let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+		match self.below(ptr as usize + layout.size(), new_layout) {
+			Some(position) => {
+				ptr::copy(ptr, position as *mut u8, layout.size().min(new_size));
+				self.set_position(position);
+				position as *mut u8
+			},
+			None => null_mut(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const HEAP_LEN: usize = 1024;
+
+	/// A heap over a zeroed buffer, which has to outlive the allocator
+	fn heap(buffer: &mut Vec<u64>) -> StackAllocator {
+		*buffer = vec![0; HEAP_LEN / size_of::<u64>()];
+		StackAllocator {
+			start: buffer.as_mut_ptr() as usize,
+			len: HEAP_LEN,
+		}
+	}
+
+	fn layout(size: usize, align: usize) -> Layout {
+		Layout::from_size_align(size, align).unwrap()
+	}
+
+	unsafe fn fill(ptr: *mut u8, len: usize, byte: u8) {
+		ptr::write_bytes(ptr, byte, len);
+	}
+
+	unsafe fn bytes<'a>(ptr: *mut u8, len: usize) -> &'a [u8] {
+		std::slice::from_raw_parts(ptr, len)
+	}
+
+	#[test]
+	fn resizes_the_last_allocation_where_it_is() {
+		let mut buffer = Vec::new();
+		let allocator = heap(&mut buffer);
+		let top = allocator.start + allocator.len;
+		unsafe {
+			let first = allocator.alloc(layout(16, 8));
+			let ptr = allocator.alloc(layout(16, 8));
+			for i in 0..16 {
+				*ptr.add(i) = i as u8;
+			}
+
+			// It keeps ending where it did and keeps what it had
+			let grown = allocator.realloc(ptr, layout(16, 8), 64);
+			assert_eq!(grown as usize, top - 16 - 64);
+			assert_eq!(bytes(grown, 16), (0..16).collect::<Vec<u8>>().as_slice());
+			assert_eq!(allocator.position(), grown as usize);
+
+			let shrunk = allocator.realloc(grown, layout(64, 8), 8);
+			assert_eq!(shrunk as usize, top - 16 - 8);
+			assert_eq!(bytes(shrunk, 8), (0..8).collect::<Vec<u8>>().as_slice());
+			assert_eq!(allocator.position(), shrunk as usize);
+			assert_eq!(first as usize, top - 16);
+		}
+	}
+
+	#[test]
+	fn copies_allocations_that_arent_the_last() {
+		let mut buffer = Vec::new();
+		let allocator = heap(&mut buffer);
+		unsafe {
+			let first = allocator.alloc(layout(16, 8));
+			fill(first, 16, 1);
+			let last = allocator.alloc(layout(16, 8));
+			fill(last, 16, 2);
+
+			let moved = allocator.realloc(first, layout(16, 8), 32);
+			assert_eq!(moved as usize, last as usize - 32);
+			assert_eq!(bytes(moved, 16), &[1; 16]);
+			// What it was copied from stays used, the one after it isn't touched
+			assert_eq!(bytes(first, 16), &[1; 16]);
+			assert_eq!(bytes(last, 16), &[2; 16]);
+			assert_eq!(allocator.position(), moved as usize);
+		}
+	}
+
+	#[test]
+	fn freeing_the_last_allocation_restores_the_position() {
+		let mut buffer = Vec::new();
+		let allocator = heap(&mut buffer);
+		let top = allocator.start + allocator.len;
+		unsafe {
+			let first = allocator.alloc(layout(32, 8));
+			let last = allocator.alloc(layout(16, 8));
+
+			// Only the last one is freed
+			allocator.dealloc(first, layout(32, 8));
+			assert_eq!(allocator.position(), last as usize);
+
+			allocator.dealloc(last, layout(16, 8));
+			assert_eq!(allocator.position(), first as usize);
+			assert_eq!(allocator.alloc(layout(16, 8)), last);
+			allocator.dealloc(last, layout(16, 8));
+			allocator.dealloc(first, layout(32, 8));
+			assert_eq!(allocator.position(), top);
+		}
+	}
+
+	#[test]
+	fn aligns_allocations() {
+		let mut buffer = Vec::new();
+		let allocator = heap(&mut buffer);
+		let top = allocator.start + allocator.len;
+		unsafe {
+			let three = allocator.alloc(layout(3, 1));
+			assert_eq!(three as usize, top - 3);
+			// Padded down to its alignment, below the 3 bytes
+			let word = allocator.alloc(layout(8, 8));
+			assert_eq!(word as usize % 8, 0);
+			assert_eq!(word as usize, top - 16);
+			let block = allocator.alloc(layout(4, 32));
+			assert_eq!(block as usize % 32, 0);
+			assert!(block as usize + 4 <= word as usize);
+		}
+	}
+
+	#[test]
+	fn out_of_heap_is_null() {
+		let mut buffer = Vec::new();
+		let allocator = heap(&mut buffer);
+		unsafe {
+			// The first word is the allocator's
+			assert!(allocator.alloc(layout(HEAP_LEN, 8)).is_null());
+			let ptr = allocator.alloc(layout(HEAP_LEN / 2, 8));
+			assert!(!ptr.is_null());
+			assert!(allocator.realloc(ptr, layout(HEAP_LEN / 2, 8), HEAP_LEN).is_null());
+			let rest = allocator.alloc(layout(HEAP_LEN / 2 - size_of::<usize>(), 8));
+			assert_eq!(rest as usize, allocator.start + size_of::<usize>());
+			assert!(allocator.alloc(layout(1, 1)).is_null());
+		}
+	}
+}
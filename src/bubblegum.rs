@@ -0,0 +1,72 @@
+//! Bubblegum's Transfer for compressed NFTs. Like Token Metadata it's encoded by hand, the mpl crates
+//! want a newer solana-program than this one
+
+use solana_program::{
+	instruction::{AccountMeta, Instruction},
+	pubkey::Pubkey,
+	system_program,
+};
+
+pub mod mpl_bubblegum {
+	solana_program::declare_id!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+}
+
+/// The log wrapper Bubblegum's trees write their changes to
+pub mod spl_noop {
+	solana_program::declare_id!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+}
+
+pub mod spl_account_compression {
+	solana_program::declare_id!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+}
+
+/// Anchor discriminator of Bubblegum's transfer, sha256("global:transfer")[..8]
+const TRANSFER_DISCRIMINATOR: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+
+pub use crate::state::Leaf;
+
+/// The tree's config PDA, [merkle_tree] of Bubblegum
+pub fn find_tree_authority(merkle_tree: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[merkle_tree.as_ref()], &mpl_bubblegum::id()).0
+}
+
+/// Bubblegum's transfer, signed by the leaf owner. `proof` are the proof path's nodes the tree's canopy
+/// doesn't cover. Leaves are never moved around in Bubblegum trees, so the index is the nonce
+pub fn transfer(
+	merkle_tree: &Pubkey,
+	leaf_owner: &Pubkey,
+	leaf_delegate: &Pubkey,
+	new_leaf_owner: &Pubkey,
+	leaf: &Leaf,
+	proof: &[Pubkey],
+) -> Instruction {
+	let mut accounts = vec![
+		AccountMeta::new_readonly(find_tree_authority(merkle_tree), false),
+		AccountMeta::new_readonly(*leaf_owner, true),
+		AccountMeta::new_readonly(*leaf_delegate, false),
+		AccountMeta::new_readonly(*new_leaf_owner, false),
+		AccountMeta::new(*merkle_tree, false),
+		AccountMeta::new_readonly(spl_noop::id(), false),
+		AccountMeta::new_readonly(spl_account_compression::id(), false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	accounts.extend(proof.iter().map(|node| AccountMeta::new_readonly(*node, false)));
+
+	Instruction {
+		program_id: mpl_bubblegum::id(),
+		accounts,
+		data: transfer_data(leaf).to_vec(),
+	}
+}
+
+/// Transfer's data, for the CPI without an Instruction on the heap
+pub fn transfer_data(leaf: &Leaf) -> [u8; 8 + 3 * 32 + 8 + 4] {
+	let mut data = [0; 8 + 3 * 32 + 8 + 4];
+	data[..8].copy_from_slice(&TRANSFER_DISCRIMINATOR);
+	data[8..40].copy_from_slice(&leaf.root);
+	data[40..72].copy_from_slice(&leaf.data_hash);
+	data[72..104].copy_from_slice(&leaf.creator_hash);
+	data[104..112].copy_from_slice(&leaf.nonce.to_le_bytes());
+	data[112..].copy_from_slice(&(leaf.nonce as u32).to_le_bytes());
+	data
+}
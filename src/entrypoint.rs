@@ -1,21 +1,237 @@
+//! The program's entrypoint, solana-program's `entrypoint!` with its own deserialization. The input has
+//! the instruction data after all the accounts, so the default builds every AccountInfo (two Rc
+//! allocations each) before anything looks at the instruction. This one skims over the accounts to the
+//! instruction data first and unpacks it: bad data fails before a single account is built, and the
+//! processor gets the instruction already unpacked
+//!
+//! The AccountInfos are still built all at once, like the default does. Parsing only the accounts an
+//! instruction needs is not done, see "Entrypoint" in the README
+
+use std::{cell::RefCell, mem::size_of, rc::Rc, slice};
+
 use solana_program::{
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg, pubkey::Pubkey,
+	account_info::AccountInfo,
+	entrypoint::{BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE, SUCCESS},
+	program_error::PrintProgramError,
+	pubkey::Pubkey,
+};
+
+use crate::{error::EscrowError, event, instruction::EscrowInstruction, processor::Processor};
+
+/// An account that isn't a duplicate of an earlier one, the others are its index
+const NON_DUP_MARKER: u8 = u8::MAX;
+
+/// # Safety
+/// `input` is what the runtime serializes for the BPF loader
+#[no_mangle]
+pub unsafe extern "C" fn entrypoint(input: *mut u8) -> u64 {
+	let (program_id, instruction_data) = instruction_of(input);
+	// The program's own event CPIs aren't EscrowInstructions, see event.rs
+	if event::is_event_cpi(instruction_data) {
+		let accounts = deserialize_accounts(input);
+		if let Err(error) = Processor::process(program_id, &accounts, instruction_data) {
+			error.print::<EscrowError>();
+			return error.into();
+		}
+		return SUCCESS;
+	}
+	let instruction = match EscrowInstruction::unpack(instruction_data) {
+		Ok(instruction) => instruction,
+		Err(error) => {
+			error.print::<EscrowError>();
+			return error.into();
+		},
+	};
+	let accounts = deserialize_accounts(input);
+	debug_msg!("process_instruction: {}: {} accounts, data={:?}", program_id, accounts.len(), instruction_data);
+	if let Err(error) = Processor::process_instruction(program_id, &accounts, instruction) {
+		error.print::<EscrowError>();
+		return error.into();
+	}
+	SUCCESS
+}
+
+// solana-program's custom_heap_default! with the allocator that frees, see allocator.rs
+#[cfg(target_arch = "bpf")]
+#[global_allocator]
+static ALLOCATOR: crate::allocator::StackAllocator = crate::allocator::StackAllocator {
+	start: solana_program::entrypoint::HEAP_START_ADDRESS as usize,
+	len: solana_program::entrypoint::HEAP_LENGTH,
 };
 
-use crate::processor::Processor;
-
-entrypoint!(process_instruction);
-fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    msg!(
-        "process_instruction: {}: {} accounts, data={:?}",
-        program_id,
-        accounts.len(),
-        instruction_data
-    );
-    Processor::process(program_id, accounts, instruction_data)
+/// Panics only log where they happened. custom_panic_default! logs the PanicInfo with msg!, which
+/// format!s the message and brings core's formatting of it into the binary. The file and line say
+/// which unwrap it was
+#[cfg(target_arch = "bpf")]
+#[no_mangle]
+fn custom_panic(info: &core::panic::PanicInfo<'_>) {
+	solana_program::log::sol_log("Panicked at");
+	if let Some(location) = info.location() {
+		solana_program::log::sol_log(location.file());
+		solana_program::log::sol_log_64(location.line() as u64, location.column() as u64, 0, 0, 0);
+	}
+}
+
+/// The program id and the instruction data, past the accounts
+unsafe fn instruction_of<'a>(input: *mut u8) -> (&'a Pubkey, &'a [u8]) {
+	let num_accounts = read::<u64>(input, 0) as usize;
+	let mut offset = size_of::<u64>();
+	for _ in 0..num_accounts {
+		offset = skip_account(input, offset);
+	}
+	let instruction_data_len = read::<u64>(input, offset) as usize;
+	offset += size_of::<u64>();
+	let instruction_data = slice::from_raw_parts(input.add(offset), instruction_data_len);
+	let program_id = &*(input.add(offset + instruction_data_len) as *const Pubkey);
+	(program_id, instruction_data)
+}
+
+/// Where the next account starts. Only the account's data length is read
+unsafe fn skip_account(input: *mut u8, offset: usize) -> usize {
+	if read::<u8>(input, offset) != NON_DUP_MARKER {
+		// The index and padding to 8 bytes
+		return offset + size_of::<u64>();
+	}
+	// Marker, signer, writable, executable and padding, then the key, the owner and the lamports
+	let data_len_offset = offset + size_of::<u64>() + 2 * size_of::<Pubkey>() + size_of::<u64>();
+	let data_len = read::<u64>(input, data_len_offset) as usize;
+	let mut offset = data_len_offset + size_of::<u64>() + data_len + MAX_PERMITTED_DATA_INCREASE;
+	offset += (input.add(offset) as *const u8).align_offset(BPF_ALIGN_OF_U128);
+	// The rent epoch
+	offset + size_of::<u64>()
 }
 
+/// The AccountInfos, in the layout solana-program's `entrypoint::deserialize` reads. All of them at
+/// once, "Entrypoint" in the README says why not on demand
+unsafe fn deserialize_accounts<'a>(input: *mut u8) -> Vec<AccountInfo<'a>> {
+	let num_accounts = read::<u64>(input, 0) as usize;
+	let mut offset = size_of::<u64>();
+	let mut accounts: Vec<AccountInfo> = Vec::with_capacity(num_accounts);
+	for _ in 0..num_accounts {
+		let dup_info = read::<u8>(input, offset);
+		if dup_info != NON_DUP_MARKER {
+			accounts.push(accounts[dup_info as usize].clone());
+			offset += size_of::<u64>();
+			continue;
+		}
+		let is_signer = read::<u8>(input, offset + 1) != 0;
+		let is_writable = read::<u8>(input, offset + 2) != 0;
+		let executable = read::<u8>(input, offset + 3) != 0;
+		offset += size_of::<u64>();
+
+		let key = &*(input.add(offset) as *const Pubkey);
+		offset += size_of::<Pubkey>();
+		let owner = &*(input.add(offset) as *const Pubkey);
+		offset += size_of::<Pubkey>();
+		#[allow(clippy::cast_ptr_alignment)]
+		let lamports = Rc::new(RefCell::new(&mut *(input.add(offset) as *mut u64)));
+		offset += size_of::<u64>();
+		let data_len = read::<u64>(input, offset) as usize;
+		offset += size_of::<u64>();
+		let data = Rc::new(RefCell::new(slice::from_raw_parts_mut(input.add(offset), data_len)));
+		offset += data_len + MAX_PERMITTED_DATA_INCREASE;
+		offset += (input.add(offset) as *const u8).align_offset(BPF_ALIGN_OF_U128);
+		let rent_epoch = read::<u64>(input, offset);
+		offset += size_of::<u64>();
+
+		accounts.push(AccountInfo {
+			key,
+			is_signer,
+			is_writable,
+			lamports,
+			data,
+			owner,
+			executable,
+			rent_epoch,
+		});
+	}
+	accounts
+}
+
+/// The loader keeps everything past the marker bytes 8-byte aligned
+#[allow(clippy::cast_ptr_alignment)]
+unsafe fn read<T: Copy>(input: *mut u8, offset: usize) -> T {
+	*(input.add(offset) as *const T)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use solana_program::entrypoint::deserialize;
+
+	/// (is_signer, is_writable, executable, key, owner, lamports, data, rent_epoch), or the index of
+	/// the account it duplicates
+	enum Account {
+		Unique(bool, bool, bool, Pubkey, Pubkey, u64, Vec<u8>, u64),
+		Duplicate(u8),
+	}
+
+	/// The input the BPF loader serializes, in u64s so it's aligned like the loader's
+	fn serialize(accounts: &[Account], instruction_data: &[u8], program_id: &Pubkey) -> Vec<u64> {
+		let mut input = Vec::new();
+		input.extend_from_slice(&(accounts.len() as u64).to_le_bytes());
+		for account in accounts {
+			match account {
+				Account::Unique(is_signer, is_writable, executable, key, owner, lamports, data, rent_epoch) => {
+					input.extend_from_slice(&[NON_DUP_MARKER, *is_signer as u8, *is_writable as u8, *executable as u8]);
+					input.extend_from_slice(&[0; 4]);
+					input.extend_from_slice(key.as_ref());
+					input.extend_from_slice(owner.as_ref());
+					input.extend_from_slice(&lamports.to_le_bytes());
+					input.extend_from_slice(&(data.len() as u64).to_le_bytes());
+					input.extend_from_slice(data);
+					input.resize(input.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+					input.resize(input.len() + (BPF_ALIGN_OF_U128 - input.len() % BPF_ALIGN_OF_U128) % BPF_ALIGN_OF_U128, 0);
+					input.extend_from_slice(&rent_epoch.to_le_bytes());
+				},
+				Account::Duplicate(index) => input.extend_from_slice(&[*index, 0, 0, 0, 0, 0, 0, 0]),
+			}
+		}
+		input.extend_from_slice(&(instruction_data.len() as u64).to_le_bytes());
+		input.extend_from_slice(instruction_data);
+		input.extend_from_slice(program_id.as_ref());
+		input.resize(input.len() + (8 - input.len() % 8) % 8, 0);
+		input.chunks(8).map(|word| u64::from_le_bytes(word.try_into().unwrap())).collect()
+	}
+
+	#[test]
+	fn deserializes_like_solana_program() {
+		let accounts = [
+			Account::Unique(true, true, false, Pubkey::new_unique(), Pubkey::new_unique(), 1_000, vec![1, 2, 3], 7),
+			Account::Unique(false, true, false, Pubkey::new_unique(), Pubkey::new_unique(), 2_000, vec![4; 16], 8),
+			Account::Unique(false, false, true, Pubkey::new_unique(), Pubkey::new_unique(), 3_000, Vec::new(), 9),
+			Account::Duplicate(0),
+			Account::Duplicate(1),
+			Account::Unique(false, false, false, Pubkey::new_unique(), Pubkey::new_unique(), 4_000, vec![5; 13], 10),
+		];
+		let program_id = Pubkey::new_unique();
+		let mut input = serialize(&accounts, &[1, 42, 0, 0, 0, 0, 0, 0, 0, 1], &program_id);
+		let input = input.as_mut_ptr() as *mut u8;
+
+		let (expected_program_id, expected_accounts, expected_instruction_data) = unsafe { deserialize(input) };
+		let (program_id_of, instruction_data) = unsafe { instruction_of(input) };
+		assert_eq!(program_id_of, expected_program_id);
+		assert_eq!(*program_id_of, program_id);
+		assert_eq!(instruction_data.as_ptr(), expected_instruction_data.as_ptr());
+		assert_eq!(instruction_data, expected_instruction_data);
+
+		let accounts = unsafe { deserialize_accounts(input) };
+		assert_eq!(accounts.len(), expected_accounts.len());
+		for (account, expected) in accounts.iter().zip(&expected_accounts) {
+			assert_eq!(account.key as *const Pubkey, expected.key as *const Pubkey);
+			assert_eq!(account.owner as *const Pubkey, expected.owner as *const Pubkey);
+			assert_eq!(account.is_signer, expected.is_signer);
+			assert_eq!(account.is_writable, expected.is_writable);
+			assert_eq!(account.executable, expected.executable);
+			assert_eq!(account.rent_epoch, expected.rent_epoch);
+			assert_eq!(&**account.lamports.borrow() as *const u64, &**expected.lamports.borrow() as *const u64);
+			assert_eq!(account.data.borrow().as_ptr(), expected.data.borrow().as_ptr());
+			assert_eq!(account.data_len(), expected.data_len());
+		}
+		// Duplicates share the account they duplicate
+		assert!(Rc::ptr_eq(&accounts[3].data, &accounts[0].data));
+		assert!(Rc::ptr_eq(&accounts[4].lamports, &accounts[1].lamports));
+		assert_eq!(accounts[5].lamports(), 4_000);
+		assert_eq!(*accounts[5].data.borrow(), &[5; 13][..]);
+	}
+}
@@ -1,5 +1,33 @@
+// The instructions, state and errors live in escrow-interface, so clients can have them without the
+// processor. They're re-exported where they used to be
+#[cfg(all(feature = "client", not(target_arch = "bpf")))]
+pub use escrow_interface::{client, subscription};
+pub use escrow_interface::{
+	address_lookup_table, check_id, check_program_account, error, event, id, instruction, stack_msg, state, treasury,
+	ID,
+};
+
+/// msg! for the logs that only say what the program is doing, like "Calling the token program...".
+/// They're only kept with the debug-logs feature, string logs cost compute units on every call
+macro_rules! debug_msg {
+	($($arg:tt)+) => {
+		if cfg!(feature = "debug-logs") {
+			solana_program::msg!($($arg)+);
+		}
+	};
+}
+
+pub mod accounts;
+pub mod allocator;
+pub mod bubblegum;
+// Its tests too, the workspace's tests build this with the cli's no-entrypoint
+#[cfg(any(test, not(feature = "no-entrypoint")))]
 pub mod entrypoint;
-pub mod instruction;
-pub mod error;
+pub mod metadata;
+#[cfg(all(feature = "client", not(target_arch = "bpf")))]
+pub mod preflight;
 pub mod processor;
-pub mod state;
\ No newline at end of file
+pub mod stake;
+pub mod token;
+#[cfg(not(target_arch = "bpf"))]
+pub mod solana_pay;
\ No newline at end of file
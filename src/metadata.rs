@@ -0,0 +1,317 @@
+//! The bits of Metaplex Token Metadata we read. Like Token-2022 it's read by hand, the mpl crates want
+//! a newer solana-program than this one
+
+use std::{
+	convert::{TryFrom, TryInto},
+	ops::Deref,
+};
+
+use solana_program::{
+	account_info::AccountInfo,
+	entrypoint::ProgramResult,
+	instruction::{AccountMeta, Instruction},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+	system_program,
+	sysvar,
+};
+
+use crate::{error::EscrowError, token::associated_token_program};
+
+pub mod mpl_token_metadata {
+	solana_program::declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+}
+
+pub const METADATA_SEED: &[u8] = b"metadata";
+
+/// Metadata accounts start with a Key byte, then the update authority and the mint
+const KEY_METADATA_V1: u8 = 4;
+const MINT_OFFSET: usize = 1 + 32;
+
+/// TokenStandard values of programmable NFTs, which live in frozen token accounts and can only be moved
+/// with Token Metadata's Transfer
+const TOKEN_STANDARD_PROGRAMMABLE_NON_FUNGIBLE: u8 = 4;
+const TOKEN_STANDARD_PROGRAMMABLE_NON_FUNGIBLE_EDITION: u8 = 5;
+
+/// Token Metadata's MAX_CREATOR_LIMIT, metadata can't list more creators
+pub const MAX_CREATORS: usize = 5;
+
+/// Token Metadata's Transfer instruction and its V1 args
+const INSTRUCTION_TRANSFER: u8 = 49;
+const TRANSFER_ARGS_V1: u8 = 0;
+
+/// The fields of a metadata account we use
+pub struct Metadata {
+	/// The creators' cut of a sale, split between them by share
+	pub seller_fee_basis_points: u16,
+	pub creators: Vec<Creator>,
+	pub token_standard: Option<u8>,
+	pub collection: Option<Collection>,
+	/// The authorization rule set a pNFT's transfers are checked against, if it has one
+	pub rule_set: Option<Pubkey>,
+}
+
+/// What each creator gets out of a sale, see Metadata::royalties. It's on the stack, there are no more
+/// than MAX_CREATORS of them
+pub struct Royalties {
+	royalties: [(Pubkey, u64); MAX_CREATORS],
+	len: usize,
+}
+
+impl Deref for Royalties {
+	type Target = [(Pubkey, u64)];
+
+	fn deref(&self) -> &Self::Target {
+		&self.royalties[..self.len]
+	}
+}
+
+#[derive(Clone, Copy)]
+pub struct Creator {
+	pub address: Pubkey,
+	pub verified: bool,
+	/// Percent of the creators' cut this one gets
+	pub share: u8,
+}
+
+/// The collection an NFT says it's in. Only `verified` ones were signed off by the collection's authority
+#[derive(Clone, Copy)]
+pub struct Collection {
+	pub verified: bool,
+	pub key: Pubkey,
+}
+
+impl Metadata {
+	/// What each creator with a share gets out of a sale for `price`, in metadata order. What's lost to
+	/// rounding stays with the seller. Token Metadata keeps the shares at 100 in total, metadata that
+	/// doesn't would pay out more than the price and fails with RoyaltyOverflow
+	pub fn royalties(&self, price: u64) -> Result<Royalties, ProgramError> {
+		let total = price as u128 * self.seller_fee_basis_points.min(10_000) as u128 / 10_000;
+		let mut royalties = Royalties { royalties: [(Pubkey::default(), 0); MAX_CREATORS], len: 0 };
+		for creator in self.creators.iter().filter(|creator| creator.share > 0) {
+			let royalty = u64::try_from(total * creator.share as u128 / 100).map_err(|_| EscrowError::RoyaltyOverflow)?;
+			*royalties.royalties.get_mut(royalties.len).ok_or(ProgramError::InvalidAccountData)? = (creator.address, royalty);
+			royalties.len += 1;
+		}
+		if royalties.iter().map(|(_, royalty)| *royalty as u128).sum::<u128>() > price as u128 {
+			return Err(EscrowError::RoyaltyOverflow.into());
+		}
+		Ok(royalties)
+	}
+
+	pub fn is_programmable(&self) -> bool {
+		matches!(
+			self.token_standard,
+			Some(TOKEN_STANDARD_PROGRAMMABLE_NON_FUNGIBLE) | Some(TOKEN_STANDARD_PROGRAMMABLE_NON_FUNGIBLE_EDITION)
+		)
+	}
+}
+
+/// The metadata PDA of `mint`, [b"metadata", metadata program, mint]
+pub fn find_metadata_address(mint: &Pubkey) -> (Pubkey, u8) {
+	Pubkey::find_program_address(
+		&[METADATA_SEED, mpl_token_metadata::id().as_ref(), mint.as_ref()],
+		&mpl_token_metadata::id(),
+	)
+}
+
+/// Makes sure `metadata` is the Token Metadata program's metadata account of `mint`
+pub fn check_metadata(metadata: &AccountInfo, mint: &Pubkey) -> ProgramResult {
+	if *metadata.owner != mpl_token_metadata::id() {
+		return Err(ProgramError::IncorrectProgramId);
+	}
+	if *metadata.key != find_metadata_address(mint).0 {
+		return Err(ProgramError::InvalidAccountData);
+	}
+	let data = metadata.try_borrow_data()?;
+	if data.first() != Some(&KEY_METADATA_V1) || data.get(MINT_OFFSET..MINT_OFFSET + 32) != Some(mint.as_ref()) {
+		return Err(ProgramError::InvalidAccountData);
+	}
+	Ok(())
+}
+
+/// Checks `metadata` like check_metadata and reads it. The account is borsh, so everything before the
+/// fields we want gets skipped over. Older accounts can end before the newer optional fields, those are None then
+pub fn unpack_metadata(metadata: &AccountInfo, mint: &Pubkey) -> Result<Metadata, ProgramError> {
+	check_metadata(metadata, mint)?;
+	let data = metadata.try_borrow_data()?;
+	let mut reader = Reader(&data[MINT_OFFSET + 32..]);
+
+	// name, symbol, uri
+	for _ in 0..3 {
+		let len = reader.u32()?;
+		reader.take(len as usize)?;
+	}
+	let seller_fee_basis_points = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+	let mut creators = Vec::new();
+	if reader.option()? {
+		for _ in 0..reader.u32()? {
+			let address = reader.pubkey()?;
+			let verified = reader.take(1)?[0] == 1;
+			let share = reader.take(1)?[0];
+			creators.push(Creator { address, verified, share });
+		}
+	}
+	// primary_sale_happened, is_mutable
+	reader.take(2)?;
+	// edition_nonce
+	if reader.option()? {
+		reader.take(1)?;
+	}
+	let token_standard = if reader.option()? { Some(reader.take(1)?[0]) } else { None };
+	let collection = if reader.option()? {
+		let verified = reader.take(1)?[0] == 1;
+		Some(Collection { verified, key: reader.pubkey()? })
+	} else {
+		None
+	};
+	// uses (use_method, remaining, total)
+	if reader.option()? {
+		reader.take(17)?;
+	}
+	// collection_details, both variants are a tag and 8 bytes
+	if reader.option()? {
+		reader.take(9)?;
+	}
+	// programmable_config, its only variant V1 holds the rule set
+	let rule_set = if reader.option()? {
+		reader.take(1)?;
+		if reader.option()? { Some(reader.pubkey()?) } else { None }
+	} else {
+		None
+	};
+
+	Ok(Metadata {
+		seller_fee_basis_points,
+		creators,
+		token_standard,
+		collection,
+		rule_set,
+	})
+}
+
+struct Reader<'d>(&'d [u8]);
+
+impl<'d> Reader<'d> {
+	fn take(&mut self, len: usize) -> Result<&'d [u8], ProgramError> {
+		if self.0.len() < len {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let (taken, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(taken)
+	}
+
+	fn u32(&mut self) -> Result<u32, ProgramError> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	fn pubkey(&mut self) -> Result<Pubkey, ProgramError> {
+		Ok(Pubkey::new_from_array(self.take(32)?.try_into().unwrap()))
+	}
+
+	/// Reads an Option tag, running out of data counts as None
+	fn option(&mut self) -> Result<bool, ProgramError> {
+		match self.0.first() {
+			None => Ok(false),
+			Some(0) | Some(1) => Ok(self.take(1)?[0] == 1),
+			_ => Err(ProgramError::InvalidAccountData),
+		}
+	}
+}
+
+/// Accounts of a Token Metadata Transfer. The token records are the pNFT ones of `token` and `destination`
+pub struct TransferAccounts<'k> {
+	pub token: &'k Pubkey,
+	pub token_owner: &'k Pubkey,
+	pub destination: &'k Pubkey,
+	pub destination_owner: &'k Pubkey,
+	pub mint: &'k Pubkey,
+	pub metadata: &'k Pubkey,
+	pub edition: &'k Pubkey,
+	pub owner_token_record: &'k Pubkey,
+	pub destination_token_record: &'k Pubkey,
+	pub authority: &'k Pubkey,
+	pub payer: &'k Pubkey,
+	pub token_program: &'k Pubkey,
+	/// The rule set's program and the rule set, if the NFT has one
+	pub authorization_rules: Option<(&'k Pubkey, &'k Pubkey)>,
+}
+
+/// Token Metadata's Transfer (V1 without authorization data). It creates the destination token account
+/// and token record if they don't exist yet, paid for by `payer`
+pub fn transfer(accounts: &TransferAccounts, amount: u64) -> Instruction {
+	// Left out optional accounts are passed as the Token Metadata program
+	let (authorization_rules_program, authorization_rules) = accounts
+		.authorization_rules
+		.unwrap_or((&mpl_token_metadata::ID, &mpl_token_metadata::ID));
+
+	let mut data = vec![INSTRUCTION_TRANSFER, TRANSFER_ARGS_V1];
+	data.extend_from_slice(&amount.to_le_bytes());
+	// No authorization data
+	data.push(0);
+
+	Instruction {
+		program_id: mpl_token_metadata::id(),
+		accounts: vec![
+			AccountMeta::new(*accounts.token, false),
+			AccountMeta::new_readonly(*accounts.token_owner, false),
+			AccountMeta::new(*accounts.destination, false),
+			AccountMeta::new_readonly(*accounts.destination_owner, false),
+			AccountMeta::new_readonly(*accounts.mint, false),
+			AccountMeta::new(*accounts.metadata, false),
+			AccountMeta::new_readonly(*accounts.edition, false),
+			AccountMeta::new(*accounts.owner_token_record, false),
+			AccountMeta::new(*accounts.destination_token_record, false),
+			AccountMeta::new_readonly(*accounts.authority, true),
+			AccountMeta::new(*accounts.payer, true),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new_readonly(sysvar::instructions::id(), false),
+			AccountMeta::new_readonly(*accounts.token_program, false),
+			AccountMeta::new_readonly(associated_token_program::id(), false),
+			AccountMeta::new_readonly(*authorization_rules_program, false),
+			AccountMeta::new_readonly(*authorization_rules, false),
+		],
+		data,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn metadata(seller_fee_basis_points: u16, shares: &[u8]) -> Metadata {
+		Metadata {
+			seller_fee_basis_points,
+			creators: shares
+				.iter()
+				.map(|share| Creator { address: Pubkey::new_unique(), verified: true, share: *share })
+				.collect(),
+			token_standard: None,
+			collection: None,
+			rule_set: None,
+		}
+	}
+
+	fn amounts(royalties: Royalties) -> Vec<u64> {
+		royalties.iter().map(|(_, royalty)| *royalty).collect()
+	}
+
+	#[test]
+	fn royalties_split_by_share() {
+		assert_eq!(amounts(metadata(500, &[70, 0, 30]).royalties(10_000).unwrap()), vec![350, 150]);
+		// More than 100% is taken as 100%
+		assert_eq!(amounts(metadata(20_000, &[100]).royalties(10_000).unwrap()), vec![10_000]);
+	}
+
+	#[test]
+	fn royalties_near_u64_max() {
+		assert_eq!(amounts(metadata(10_000, &[100]).royalties(u64::MAX).unwrap()), vec![u64::MAX]);
+		assert_eq!(amounts(metadata(10_000, &[50, 50]).royalties(u64::MAX).unwrap()), vec![u64::MAX / 2, u64::MAX / 2]);
+		assert_eq!(amounts(metadata(10_000, &[100]).royalties(u64::MAX - 1).unwrap()), vec![u64::MAX - 1]);
+		// Shares over 100 in total, a single creator's royalty doesn't fit a u64 or the royalties add up to more than the price
+		assert_eq!(metadata(10_000, &[200]).royalties(u64::MAX).err(), Some(EscrowError::RoyaltyOverflow.into()));
+		assert_eq!(metadata(10_000, &[60, 50]).royalties(u64::MAX).err(), Some(EscrowError::RoyaltyOverflow.into()));
+		assert_eq!(metadata(10_000, &[60, 50]).royalties(100).err(), Some(EscrowError::RoyaltyOverflow.into()));
+	}
+}
@@ -0,0 +1,208 @@
+//! Dry runs of escrow instructions before sending them. validate_instruction fetches the accounts an
+//! instruction takes and runs it through the processor natively, so what comes back is the error the
+//! program would return, EscrowError::from_program_error tells which one. Nothing is written back.
+//!
+//! It's the program's checks only: CPIs don't happen (they succeed without doing anything), so checks
+//! that need a CPI's effects, like the escrow account an escrow seed creates, don't hold. The runtime's
+//! own checks (signatures, rent of the accounts after the transaction, ..) aren't done either. Accounts
+//! that don't exist are empty system accounts, the ones the same transaction creates have to exist
+//! already, like for client::init_escrow_message
+
+use std::sync::Mutex;
+
+use solana_program::{
+	account_info::AccountInfo,
+	clock::Clock,
+	entrypoint::SUCCESS,
+	instruction::Instruction,
+	message::{Message, SanitizedMessage},
+	program_error::ProgramError,
+	program_stubs::{self, SyscallStubs},
+	pubkey::Pubkey,
+	rent::Rent,
+	system_program,
+	sysvar::{self, Sysvar},
+};
+
+use crate::{
+	client::{self, Account, ClientError, Rpc},
+	instruction,
+	processor::Processor,
+};
+
+/// The syscall stubs are global, one dry run at a time
+static STUBS_LOCK: Mutex<()> = Mutex::new(());
+
+/// What the program does with `instruction` as the only one of a transaction, Ok(Err(..)) with its
+/// error if it would fail
+pub fn validate_instruction<R: Rpc>(rpc: &R, instruction: &Instruction) -> Result<Result<(), ProgramError>, ClientError<R::Error>> {
+	// One account per address however often the instruction has it, signing and writable if any of its
+	// metas is, like in a message
+	let mut keys: Vec<(Pubkey, bool, bool)> = Vec::new();
+	for meta in &instruction.accounts {
+		match keys.iter_mut().find(|(pubkey, ..)| *pubkey == meta.pubkey) {
+			Some((_, is_signer, is_writable)) => {
+				*is_signer |= meta.is_signer;
+				*is_writable |= meta.is_writable;
+			},
+			None => keys.push((meta.pubkey, meta.is_signer, meta.is_writable)),
+		}
+	}
+	let mut fetched = keys
+		.iter()
+		.map(|(pubkey, ..)| fetch(rpc, pubkey, instruction))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	// The sysvars the program gets through syscalls, from the cluster too
+	let clock = fetch(rpc, &sysvar::clock::id(), instruction)?;
+	let rent = fetch(rpc, &sysvar::rent::id(), instruction)?;
+	let stubs = PreflightStubs {
+		clock: sysvar_of(&sysvar::clock::id(), clock)?,
+		rent: sysvar_of(&sysvar::rent::id(), rent)?,
+	};
+
+	let account_infos = keys
+		.iter()
+		.zip(fetched.iter_mut())
+		.map(|((pubkey, is_signer, is_writable), account)| {
+			AccountInfo::new(
+				pubkey,
+				*is_signer,
+				*is_writable,
+				&mut account.lamports,
+				&mut account.data,
+				&account.owner,
+				account.executable,
+				0,
+			)
+		})
+		.collect::<Vec<_>>();
+	let accounts = instruction
+		.accounts
+		.iter()
+		.map(|meta| account_infos.iter().find(|account| *account.key == meta.pubkey).unwrap().clone())
+		.collect::<Vec<_>>();
+
+	let _lock = STUBS_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+	let previous = program_stubs::set_syscall_stubs(Box::new(stubs));
+	let result = Processor::process(&instruction.program_id, &accounts, &instruction.data);
+	program_stubs::set_syscall_stubs(previous);
+	Ok(result)
+}
+
+/// validate_instruction of instruction::init_escrow, with the arguments of client::init_escrow_message
+#[allow(clippy::too_many_arguments)]
+pub fn validate_init<R: Rpc>(
+	rpc: &R,
+	program_id: &Pubkey,
+	initializer: &Pubkey,
+	temp_token_account: &Pubkey,
+	token_to_receive_account: &Pubkey,
+	escrow_account: &Pubkey,
+	token_program_id: &Pubkey,
+	deposit_mint: &Pubkey,
+	receive_mint: &Pubkey,
+	standby_config: bool,
+	amount: u64,
+) -> Result<Result<(), ProgramError>, ClientError<R::Error>> {
+	let instruction = instruction::init_escrow(
+		program_id,
+		initializer,
+		temp_token_account,
+		token_to_receive_account,
+		escrow_account,
+		token_program_id,
+		deposit_mint,
+		receive_mint,
+		standby_config,
+		amount,
+	)?;
+	validate_instruction(rpc, &instruction)
+}
+
+/// validate_instruction of instruction::exchange, with the arguments of client::exchange_message
+#[allow(clippy::too_many_arguments)]
+pub fn validate_exchange<R: Rpc>(
+	rpc: &R,
+	program_id: &Pubkey,
+	escrow_account: &Pubkey,
+	taker: &Pubkey,
+	takers_sending_account: &Pubkey,
+	takers_token_to_receive_account: &Pubkey,
+	token_program_id: &Pubkey,
+	sending_token_program_id: &Pubkey,
+	reference: Option<Pubkey>,
+) -> Result<Result<(), ProgramError>, ClientError<R::Error>> {
+	let escrow = client::fetch_escrow(rpc, escrow_account)?;
+	let instruction = instruction::exchange(
+		program_id,
+		escrow_account,
+		&escrow,
+		taker,
+		takers_sending_account,
+		takers_token_to_receive_account,
+		token_program_id,
+		sending_token_program_id,
+		reference,
+	)?;
+	validate_instruction(rpc, &instruction)
+}
+
+/// The account at `pubkey`, an empty system account if there's none. The instructions sysvar only
+/// exists in transactions, it's made up with `instruction` as the transaction's only one
+fn fetch<R: Rpc>(rpc: &R, pubkey: &Pubkey, instruction: &Instruction) -> Result<Account, ClientError<R::Error>> {
+	if sysvar::instructions::check_id(pubkey) {
+		let payer = instruction.accounts.iter().find(|meta| meta.is_signer).map(|meta| meta.pubkey);
+		let message = SanitizedMessage::try_from(Message::new(std::slice::from_ref(instruction), payer.as_ref()))
+			.map_err(|_| ProgramError::InvalidArgument)?;
+		return Ok(Account {
+			lamports: 0,
+			owner: sysvar::id(),
+			executable: false,
+			data: sysvar::instructions::construct_instructions_data(&message),
+		});
+	}
+	Ok(rpc.get_account(pubkey).map_err(ClientError::Rpc)?.unwrap_or(Account {
+		lamports: 0,
+		owner: system_program::id(),
+		executable: false,
+		data: Vec::new(),
+	}))
+}
+
+fn sysvar_of<S: Sysvar, E>(pubkey: &Pubkey, mut account: Account) -> Result<S, ClientError<E>> {
+	let account_info = AccountInfo::new(
+		pubkey,
+		false,
+		false,
+		&mut account.lamports,
+		&mut account.data,
+		&account.owner,
+		false,
+		0,
+	);
+	Ok(S::from_account_info(&account_info)?)
+}
+
+/// Quiet logs, and the clock and rent of the cluster for Clock::get and Rent::get
+struct PreflightStubs {
+	clock: Clock,
+	rent: Rent,
+}
+
+impl SyscallStubs for PreflightStubs {
+	fn sol_log(&self, _message: &str) {}
+
+	fn sol_log_data(&self, _fields: &[&[u8]]) {}
+
+	fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+		// Sysvar::get passes a Clock to write into
+		unsafe { *(var_addr as *mut Clock) = self.clock.clone() };
+		SUCCESS
+	}
+
+	fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+		unsafe { *(var_addr as *mut Rent) = self.rent };
+		SUCCESS
+	}
+}
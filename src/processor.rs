@@ -1,211 +1,4168 @@
 use solana_program::{
 	account_info::{next_account_info, AccountInfo},
-	entrypoint::ProgramResult,
+	entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+	instruction::AccountMeta,
 	program_error::ProgramError,
 	msg,
 	pubkey::Pubkey,
 	program_pack::{Pack, IsInitialized},
-	sysvar::{rent::Rent, Sysvar},
-	program::{invoke, invoke_signed},
+	sysvar::{self, clock::Clock, rent::Rent, Sysvar},
+	bpf_loader_upgradeable,
+	program::{get_return_data, invoke, invoke_signed},
+	system_instruction,
+	system_program,
 };
 
+use escrow_interface::cpi;
 use spl_token::state::Account as TokenAccount;
 
-use crate::{instruction::EscrowInstruction, error::EscrowError, state::Escrow};
+use crate::{
+	accounts::{
+		check_authority, check_distinct, check_owner, CancelAccounts, ExchangeAccounts, ProgramOwned, SweepSurplusAccounts,
+		TokenAccountOf,
+	},
+	bubblegum::{self, Leaf},
+	instruction::{CompressedNft, CompressedNftProof, EscrowInstruction},
+	error::EscrowError,
+	event::{self, EscrowCancelled, EscrowCreated, EscrowFilled, Event},
+	metadata,
+	stack_msg,
+	stake,
+	state::{
+		has_closed_discriminator, has_discriminator, Config, CLOSED_ACCOUNT_DISCRIMINATOR, DISCRIMINATOR_LEN,
+		Discriminator, Escrow, EscrowGroup, EscrowStatus, ExtensionPolicy, InitializerIndex, PairStats,
+		RegistryPage, Stats, TradeReceipt, CONFIG_SEED, ESCROW_ACCOUNT_SEED, ESCROW_SEED, ESCROW_VERSION,
+		FEATURE_BATCH_INIT, MAX_MEMO_LEN, FEATURE_NATIVE_SOL, FEATURE_NFT,
+		FEATURE_RECEIPTS, FEATURE_RETAIN_HISTORY, FEATURE_STAKE, GROUP_SEED, INDEX_SEED, PAIR_STATS_SEED,
+		RECEIPT_SEED, REGISTRY_SEED, SOL_VAULT_SEED, STANDBY_CONFIG_SEED, STATS_SEED, VAULT_SEED, WSOL_SEED,
+	},
+	token::{self, associated_token_program, check_token_program, get_associated_token_address},
+	treasury::{find_treasury_address, REFUND_LISTING_FEE_ON_EXCHANGE, TREASURY_SEED},
+};
+
+/// The mint of a token account, which transfer_checked checks the transfer against, and the accounts of
+/// the mint's transfer hook (its program, its extra account metas account and the extra accounts) if it has one.
+/// The mint is read once for its decimals and whether it takes a transfer fee, for every transfer of it
+#[derive(Clone, Copy)]
+struct MintAccounts<'r, 'a> {
+	mint: &'r AccountInfo<'a>,
+	hook_accounts: &'r [AccountInfo<'a>],
+	decimals: u8,
+	has_transfer_fee: bool,
+}
+
+/// What Token Metadata's Transfer needs for a programmable NFT besides the token accounts and their owners.
+/// The token records are the ones of the source and destination token accounts, `payer` pays for the
+/// destination token account and token record if they don't exist yet
+#[derive(Clone, Copy)]
+struct PnftAccounts<'r, 'a> {
+	mint: &'r AccountInfo<'a>,
+	metadata: &'r AccountInfo<'a>,
+	edition: &'r AccountInfo<'a>,
+	owner_token_record: &'r AccountInfo<'a>,
+	destination_token_record: &'r AccountInfo<'a>,
+	payer: &'r AccountInfo<'a>,
+	sysvar_instructions: &'r AccountInfo<'a>,
+	associated_token_program: &'r AccountInfo<'a>,
+	system_program: &'r AccountInfo<'a>,
+	token_metadata_program: &'r AccountInfo<'a>,
+	/// The rule set's program and the rule set, for pNFTs that have one
+	authorization_rules: Option<(&'r AccountInfo<'a>, &'r AccountInfo<'a>)>,
+}
+
+/// What Bubblegum's Transfer of a compressed NFT needs besides the tree and the leaf's owners. `proof`
+/// are the proof path's nodes the tree's canopy doesn't cover
+#[derive(Clone, Copy)]
+struct CnftAccounts<'r, 'a> {
+	bubblegum_program: &'r AccountInfo<'a>,
+	tree_authority: &'r AccountInfo<'a>,
+	log_wrapper: &'r AccountInfo<'a>,
+	compression_program: &'r AccountInfo<'a>,
+	system_program: &'r AccountInfo<'a>,
+	proof: &'r [AccountInfo<'a>],
+}
+
+/// What the stake program's Authorize needs besides the stake account and its authority. The custodian
+/// is only there if the stake account's lockup is in force
+#[derive(Clone, Copy)]
+struct StakeAccounts<'r, 'a> {
+	stake_program: &'r AccountInfo<'a>,
+	clock_sysvar: &'r AccountInfo<'a>,
+	custodian: Option<&'r AccountInfo<'a>>,
+}
+
+/// The optional accounts at the end that keep track of escrows, split off in process. Whatever an escrow
+/// was recorded in when it was opened has to be there again when it's settled
+#[derive(Clone, Copy)]
+struct Trackers<'r, 'a> {
+	stats: Option<&'r AccountInfo<'a>>,
+	registry_page: Option<&'r AccountInfo<'a>>,
+	/// The escrow's initializer's InitializerIndex
+	index: Option<&'r AccountInfo<'a>>,
+	/// Where the events go if they're CPIs, see event.rs. Unlike the others any instruction can bring these or not
+	event_cpi: Option<EventCpiAccounts<'r, 'a>>,
+}
+
+#[derive(Clone, Copy)]
+struct EventCpiAccounts<'r, 'a> {
+	event_authority: &'r AccountInfo<'a>,
+	program: &'r AccountInfo<'a>,
+	bump_seed: u8,
+}
+
+/// The accounts InitEscrow's NFT mode gets. A pNFT comes from a token account of the initializer and
+/// gets moved to the escrow PDA (`pda_account`) with Token Metadata
+enum NftDeposit<'r, 'a> {
+	Plain {
+		mint: &'r AccountInfo<'a>,
+		metadata: &'r AccountInfo<'a>,
+	},
+	Programmable {
+		source: &'r AccountInfo<'a>,
+		pda_account: &'r AccountInfo<'a>,
+		pnft: PnftAccounts<'r, 'a>,
+	},
+	/// A cNFT of the initializer's, moved to the escrow PDA in its tree
+	Compressed {
+		leaf: Leaf,
+		pda_account: &'r AccountInfo<'a>,
+		leaf_delegate: &'r AccountInfo<'a>,
+		cnft: CnftAccounts<'r, 'a>,
+	},
+}
 
 pub struct Processor;
 impl Processor {
 	pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
-		let instruction = EscrowInstruction::unpack(instruction_data)?;
+		if event::is_event_cpi(instruction_data) {
+			return Self::process_event_cpi(accounts, program_id);
+		}
+		Self::process_instruction(program_id, accounts, EscrowInstruction::unpack(instruction_data)?)
+	}
+
+	/// process of an instruction that's unpacked already, like the entrypoint's
+	pub fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], instruction: EscrowInstruction) -> ProgramResult {
+		// Everything that opens or fills escrows stops while the program is paused, or when it uses a
+		// feature that's turned off. Closing them never does, so users can always get their tokens out.
+		// When the config only allows top level exchanges Exchange's instructions sysvar goes after everything else
+		let features = Self::required_features(&instruction);
+		let accounts = match instruction {
+			EscrowInstruction::InitEscrow { .. } => {
+				Self::check_primary_config(accounts.get(Self::init_escrow_config_index(accounts)), features, program_id)?;
+				accounts
+			},
+			EscrowInstruction::BatchInitEscrow { .. } => {
+				Self::check_primary_config(accounts.get(Self::batch_init_escrow_config_index(accounts)), features, program_id)?;
+				accounts
+			},
+			EscrowInstruction::Exchange { .. } | EscrowInstruction::ExchangeV2 { .. } => {
+				let config = Self::check_primary_config(accounts.get(9), features, program_id)?;
+				if config.top_level_exchange_only {
+					let (instructions_sysvar, accounts) = accounts.split_last().ok_or(ProgramError::NotEnoughAccountKeys)?;
+					Self::check_top_level(instructions_sysvar, program_id)?;
+					accounts
+				} else {
+					accounts
+				}
+			},
+			_ => accounts,
+		};
+
+		// The event authority and the program itself go after everything but Exchange's instructions sysvar
+		let (accounts, event_cpi) = Self::split_event_cpi_accounts(accounts, program_id)?;
+
+		// The stats account is optional and always goes last, with Exchange's pair stats account, the
+		// registry page and the initializer's index right before it. They're told apart by their discriminators
+		let (accounts, stats_account) = Self::split_trailing_account::<Stats>(accounts, program_id);
+		let (accounts, pair_stats_account) = Self::split_trailing_account::<PairStats>(accounts, program_id);
+		let (accounts, registry_page) = Self::split_trailing_account::<RegistryPage>(accounts, program_id);
+		let (accounts, index) = Self::split_trailing_account::<InitializerIndex>(accounts, program_id);
+		let trackers = Trackers {
+			stats: stats_account,
+			registry_page,
+			index,
+			event_cpi,
+		};
 
 		match instruction {
-			EscrowInstruction::InitEscrow { amount } => {
+			EscrowInstruction::InitEscrow {
+				amount,
+				predecessor,
+				refund_address,
+				expires_at,
+				native_sol_deposit,
+				wrap_sol_deposit,
+				nft,
+				collection,
+				compressed_nft,
+				multisig_signers,
+				lamports_deposit,
+				expected_lamports,
+				stake_account,
+				vault_deposit,
+				escrow_seed,
+				retain_history,
+				memo,
+				rent_payer,
+				prevent_self_trade,
+			} => {
 				msg!("Instruction: InitEscrow");
-				Self::process_init_escrow(accounts, amount, program_id)
+				Self::process_init_escrow(
+					accounts,
+					amount,
+					predecessor,
+					refund_address,
+					expires_at,
+					native_sol_deposit,
+					wrap_sol_deposit,
+					nft,
+					collection,
+					compressed_nft,
+					multisig_signers,
+					lamports_deposit,
+					expected_lamports,
+					stake_account,
+					vault_deposit,
+					escrow_seed,
+					retain_history,
+					memo.as_deref(),
+					rent_payer,
+					prevent_self_trade,
+					trackers,
+					program_id,
+				)
 			},
-			EscrowInstruction::Exchange { amount } => {
+			EscrowInstruction::Exchange {
+				amount,
+				reference,
+				max_amount_in,
+				min_amount_out,
+				unwrap_sol,
+				compressed_nft_proof,
+				receipt,
+			} => {
 				msg!("Instruction: Exchange");
-				Self::process_exchange(accounts, amount, program_id)
+				Self::process_exchange(
+					accounts,
+					false,
+					amount,
+					reference,
+					max_amount_in,
+					min_amount_out,
+					unwrap_sol,
+					compressed_nft_proof,
+					receipt,
+					trackers,
+					pair_stats_account,
+					program_id,
+				)
+			},
+			EscrowInstruction::ExchangeV2 {
+				amount,
+				reference,
+				max_amount_in,
+				min_amount_out,
+				unwrap_sol,
+				compressed_nft_proof,
+				receipt,
+			} => {
+				msg!("Instruction: ExchangeV2");
+				Self::process_exchange(
+					accounts,
+					true,
+					amount,
+					reference,
+					max_amount_in,
+					min_amount_out,
+					unwrap_sol,
+					compressed_nft_proof,
+					receipt,
+					trackers,
+					pair_stats_account,
+					program_id,
+				)
+			},
+			EscrowInstruction::Cancel { compressed_nft_proof } => {
+				msg!("Instruction: Cancel");
+				Self::process_cancel(accounts, compressed_nft_proof, trackers, program_id)
+			},
+			EscrowInstruction::CreateGroup { name } => {
+				msg!("Instruction: CreateGroup");
+				Self::process_create_group(accounts, name, program_id)
+			},
+			EscrowInstruction::CancelGroup { compressed_nft_proofs } => {
+				msg!("Instruction: CancelGroup");
+				Self::process_cancel_group(accounts, &compressed_nft_proofs, trackers, program_id)
+			},
+			EscrowInstruction::BatchInitEscrow { amounts } => {
+				msg!("Instruction: BatchInitEscrow");
+				Self::process_batch_init_escrow(accounts, &amounts, trackers, program_id)
+			},
+			EscrowInstruction::InitializeConfig { admin, listing_fee_lamports, heartbeat_period, crank_bounty_lamports, dormancy_period } => {
+				msg!("Instruction: InitializeConfig");
+				Self::process_initialize_config(accounts, admin, listing_fee_lamports, heartbeat_period, crank_bounty_lamports, dormancy_period, program_id)
+			},
+			EscrowInstruction::InitializeStandbyConfig { admin, listing_fee_lamports, crank_bounty_lamports, dormancy_period } => {
+				msg!("Instruction: InitializeStandbyConfig");
+				Self::process_initialize_standby_config(accounts, admin, listing_fee_lamports, crank_bounty_lamports, dormancy_period, program_id)
+			},
+			EscrowInstruction::UpdateConfig { listing_fee_lamports, crank_bounty_lamports, dormancy_period } => {
+				msg!("Instruction: UpdateConfig");
+				Self::process_update_config(accounts, listing_fee_lamports, crank_bounty_lamports, dormancy_period, program_id)
+			},
+			EscrowInstruction::Heartbeat => {
+				msg!("Instruction: Heartbeat");
+				Self::process_heartbeat(accounts, program_id)
+			},
+			EscrowInstruction::SweepSurplus => {
+				msg!("Instruction: SweepSurplus");
+				Self::process_sweep_surplus(accounts, program_id)
+			},
+			EscrowInstruction::RecoverStrandedAssets => {
+				msg!("Instruction: RecoverStrandedAssets");
+				Self::process_recover_stranded_assets(accounts, program_id)
+			},
+			EscrowInstruction::CrankExpired { compressed_nft_proof } => {
+				msg!("Instruction: CrankExpired");
+				Self::process_crank_expired(accounts, compressed_nft_proof, trackers, program_id)
+			},
+			EscrowInstruction::SetPaused { paused } => {
+				msg!("Instruction: SetPaused");
+				Self::process_set_paused(accounts, paused, program_id)
+			},
+			EscrowInstruction::FreezeEscrow => {
+				msg!("Instruction: FreezeEscrow");
+				Self::process_set_frozen(accounts, true, program_id)
+			},
+			EscrowInstruction::ThawEscrow => {
+				msg!("Instruction: ThawEscrow");
+				Self::process_set_frozen(accounts, false, program_id)
+			},
+			EscrowInstruction::SweepDormant { compressed_nft_proof } => {
+				msg!("Instruction: SweepDormant");
+				Self::process_sweep_dormant(accounts, compressed_nft_proof, trackers, program_id)
+			},
+			EscrowInstruction::SetExtensionPolicy { policy } => {
+				msg!("Instruction: SetExtensionPolicy");
+				Self::process_set_extension_policy(accounts, policy, program_id)
+			},
+			EscrowInstruction::MigrateEscrow => {
+				msg!("Instruction: MigrateEscrow");
+				Self::process_migrate_escrow(accounts, program_id)
+			},
+			EscrowInstruction::ReallocEscrow { new_len } => {
+				msg!("Instruction: ReallocEscrow");
+				Self::process_realloc_escrow(accounts, new_len, program_id)
+			},
+			EscrowInstruction::CloseEscrowRecord => {
+				msg!("Instruction: CloseEscrowRecord");
+				Self::process_close_escrow_record(accounts, program_id)
+			},
+			EscrowInstruction::InitializeStats => {
+				msg!("Instruction: InitializeStats");
+				Self::process_initialize_stats(accounts, program_id)
+			},
+			EscrowInstruction::InitializePairStats { deposit_mint, receive_mint } => {
+				msg!("Instruction: InitializePairStats");
+				Self::process_initialize_pair_stats(accounts, deposit_mint, receive_mint, program_id)
+			},
+			EscrowInstruction::SetFeatures { disabled_features } => {
+				msg!("Instruction: SetFeatures");
+				Self::process_set_features(accounts, disabled_features, program_id)
+			},
+			EscrowInstruction::NominateAdmin { new_admin } => {
+				msg!("Instruction: NominateAdmin");
+				Self::process_nominate_admin(accounts, new_admin, program_id)
+			},
+			EscrowInstruction::AcceptAdmin => {
+				msg!("Instruction: AcceptAdmin");
+				Self::process_accept_admin(accounts, program_id)
+			},
+			EscrowInstruction::SetListingFee { listing_fee_lamports } => {
+				msg!("Instruction: SetListingFee");
+				Self::process_set_listing_fee(accounts, listing_fee_lamports, program_id)
+			},
+			EscrowInstruction::WithdrawTreasury { lamports } => {
+				msg!("Instruction: WithdrawTreasury");
+				Self::process_withdraw_treasury(accounts, lamports, program_id)
+			},
+			EscrowInstruction::InitializeRegistryPage { index } => {
+				msg!("Instruction: InitializeRegistryPage");
+				Self::process_initialize_registry_page(accounts, index, program_id)
+			},
+			EscrowInstruction::InitializeIndex => {
+				msg!("Instruction: InitializeIndex");
+				Self::process_initialize_index(accounts, program_id)
+			},
+			EscrowInstruction::SetTopLevelExchange { top_level_only } => {
+				msg!("Instruction: SetTopLevelExchange");
+				Self::process_set_top_level_exchange(accounts, top_level_only, program_id)
 			}
 		}
 	}
 
-	fn process_init_escrow(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -> ProgramResult {
+	#[allow(clippy::too_many_arguments)]
+	fn process_init_escrow(
+		accounts: &[AccountInfo],
+		amount: u64,
+		predecessor: Option<Pubkey>,
+		refund_address: Option<Pubkey>,
+		expires_at: Option<i64>,
+		native_sol_deposit: Option<u64>,
+		wrap_sol_deposit: Option<u64>,
+		nft: bool,
+		collection: Option<Pubkey>,
+		compressed_nft: Option<CompressedNft>,
+		multisig_signers: u8,
+		lamports_deposit: Option<u64>,
+		expected_lamports: Option<u64>,
+		stake_account: bool,
+		vault_deposit: Option<u64>,
+		escrow_seed: Option<u64>,
+		retain_history: bool,
+		memo: Option<&str>,
+		rent_payer: bool,
+		prevent_self_trade: bool,
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
 		let account_info_iter = &mut accounts.iter();
 		let initializer = next_account_info(account_info_iter)?;
 
 		if !initializer.is_signer {
 			return Err(ProgramError::MissingRequiredSignature);
 		}
+		if matches!(memo, Some(memo) if memo.len() > MAX_MEMO_LEN) {
+			return Err(EscrowError::MemoTooLong.into());
+		}
 
 		// This program must be owned by the Solana Token Program
 		let temp_token_account = next_account_info(account_info_iter)?;
 
 		// This one too, but we actually check it here. Why don't we check previously?
 		let token_to_receive_account = next_account_info(account_info_iter)?;
-		if *token_to_receive_account.owner != spl_token::id() {
-			return Err(ProgramError::IncorrectProgramId);
+		let escrow_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::get()?;
+		if Self::init_escrow_has_rent_sysvar(accounts) {
+			next_account_info(account_info_iter)?;
 		}
+		let token_program = next_account_info(account_info_iter)?;
+		let treasury = next_account_info(account_info_iter)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+		let (config_account, config, clock) = Self::load_config_accounts(account_info_iter, program_id)?;
+		let payer = if rent_payer {
+			let payer = next_account_info(account_info_iter)?;
+			if !payer.is_signer {
+				return Err(ProgramError::MissingRequiredSignature);
+			}
+			payer
+		} else {
+			initializer
+		};
+		let wrap_sol = match wrap_sol_deposit {
+			Some(lamports) => Some((lamports, next_account_info(account_info_iter)?)),
+			None => None,
+		};
+		let vault_source = match vault_deposit {
+			Some(amount) => Some((amount, next_account_info(account_info_iter)?)),
+			None => None,
+		};
+		let nft_deposit = if nft {
+			let mint = next_account_info(account_info_iter)?;
+			let metadata = next_account_info(account_info_iter)?;
+			let metadata_info = metadata::unpack_metadata(metadata, mint.key)?;
+			if metadata_info.is_programmable() {
+				let source = next_account_info(account_info_iter)?;
+				let pda_account = next_account_info(account_info_iter)?;
+				let pnft = Self::next_pnft_accounts(mint, metadata, &metadata_info, payer, account_info_iter)?;
+				Some(NftDeposit::Programmable { source, pda_account, pnft })
+			} else {
+				Some(NftDeposit::Plain { mint, metadata })
+			}
+		} else if let Some(compressed_nft) = compressed_nft {
+			let pda_account = next_account_info(account_info_iter)?;
+			let leaf_delegate = next_account_info(account_info_iter)?;
+			let cnft = Self::next_cnft_accounts(token_program, compressed_nft.proof_len, account_info_iter)?;
+			Some(NftDeposit::Compressed {
+				leaf: Leaf {
+					root: compressed_nft.root,
+					data_hash: compressed_nft.data_hash,
+					creator_hash: compressed_nft.creator_hash,
+					nonce: compressed_nft.nonce,
+				},
+				pda_account,
+				leaf_delegate,
+				cnft,
+			})
+		} else {
+			None
+		};
+		let stake_deposit = if stake_account {
+			Some(Self::next_stake_accounts(token_program, temp_token_account, account_info_iter)?)
+		} else {
+			None
+		};
+		let is_token_deposit = native_sol_deposit.is_none() && wrap_sol.is_none() && nft_deposit.is_none() && stake_deposit.is_none();
+		// A vault deposit needs the mint's transfer hook accounts, to move the tokens in with transfer_checked
+		let (deposit_mint, vault) = match vault_source {
+			Some((amount, source)) if is_token_deposit => {
+				let vault_mint = Self::next_mint(&token::unpack_account(source)?, account_info_iter)?;
+				(Some(vault_mint.mint), Some((amount, source, vault_mint)))
+			},
+			Some(_) => return Err(ProgramError::InvalidInstructionData),
+			None if is_token_deposit => (Some(next_account_info(account_info_iter)?), None),
+			None => (None, None),
+		};
+		let receive_mint = if collection.is_none() {
+			Some(next_account_info(account_info_iter)?)
+		} else {
+			None
+		};
 
-		let escrow_account = next_account_info(account_info_iter)?;
-		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		// Only an escrow that is still open makes sense to chain behind, and only one that retains its
+		// history can show it was filled rather than cancelled once it's settled
+		if let Some(predecessor) = predecessor {
+			let predecessor_account = next_account_info(account_info_iter)?;
+			if *predecessor_account.key != predecessor
+				|| !Self::is_open_escrow(predecessor_account, program_id)?
+				|| !Escrow::unpack_versioned(&predecessor_account.try_borrow_data()?)?.retain_history.get()
+			{
+				return Err(ProgramError::InvalidAccountData);
+			}
+		}
+
+		let multisig = if multisig_signers > 0 {
+			let multisig_account = next_account_info(account_info_iter)?;
+			let signers = account_info_iter
+				.as_slice()
+				.get(..multisig_signers as usize)
+				.ok_or(ProgramError::NotEnoughAccountKeys)?;
+			account_info_iter.nth(signers.len() - 1);
+			Some((multisig_account, signers))
+		} else {
+			None
+		};
+
+		// Attaching to a group is optional, it's just one more account at the end
+		let group_account = next_account_info(account_info_iter).ok();
+
+		Self::check_extensions(
+			Self::load_extension_policy(accounts.get(Self::init_escrow_config_index(accounts)), program_id)?,
+			&[Some(temp_token_account), vault_source.map(|(_, source)| source)],
+			&[deposit_mint, receive_mint],
+		)?;
+
+		// Creating it fails if there's an account at the address already, so no two escrows end up there.
+		// A seed that's still taken, by an open escrow or a kept record, gets its own error so wallets
+		// know to pick the next one
+		if let Some(escrow_seed) = escrow_seed {
+			if escrow_account.owner == program_id || escrow_account.data_len() > 0 {
+				return Err(EscrowError::EscrowSeedInUse.into());
+			}
+			let (escrow_pubkey, escrow_bump_seed) = Escrow::find_address(initializer.key, escrow_seed, program_id);
+			Self::create_pda_account(
+				payer,
+				escrow_account,
+				&escrow_pubkey,
+				memo.map_or(Escrow::LEN, |memo| Escrow::len_with_memo(memo.len())),
+				rent,
+				system_program_account,
+				&[ESCROW_ACCOUNT_SEED, initializer.key.as_ref(), &escrow_seed.to_le_bytes(), &[escrow_bump_seed]],
+				program_id,
+			)?;
+		}
+		Self::check_escrow_len(escrow_account, memo.map_or(Escrow::LEN, |memo| Escrow::len_with_memo(memo.len())))?;
+
+		Self::init_escrow(
+			initializer,
+			payer,
+			temp_token_account,
+			token_to_receive_account,
+			escrow_account,
+			rent,
+			token_program,
+			treasury,
+			system_program_account,
+			group_account,
+			amount,
+			config.listing_fee_lamports,
+			predecessor,
+			refund_address,
+			expires_at,
+			native_sol_deposit,
+			wrap_sol,
+			vault,
+			nft_deposit,
+			collection,
+			multisig,
+			lamports_deposit,
+			expected_lamports,
+			stake_deposit,
+			deposit_mint,
+			receive_mint,
+			retain_history,
+			trackers,
+			&clock,
+			program_id,
+		)?;
+		Self::reserve_listing_fee(config_account, config.listing_fee_lamports)?;
+
+		if let Some(memo) = memo {
+			Escrow::write_memo(&mut escrow_account.try_borrow_mut_data()?, memo)?;
+		}
+		if prevent_self_trade {
+			Escrow::load_mut(&mut escrow_account.try_borrow_mut_data()?)?.prevent_self_trade = true.into();
+		}
+		Ok(())
+	}
+
+	/// Whether InitEscrow's accounts are in the old layout, with the rent sysvar as account 4
+	fn init_escrow_has_rent_sysvar(accounts: &[AccountInfo]) -> bool {
+		matches!(accounts.get(4), Some(account) if sysvar::rent::check_id(account.key))
+	}
+
+	fn init_escrow_config_index(accounts: &[AccountInfo]) -> usize {
+		if Self::init_escrow_has_rent_sysvar(accounts) {
+			8
+		} else {
+			7
+		}
+	}
+
+	/// Whether BatchInitEscrow's accounts are in the old layout, with the rent sysvar as account 1
+	fn batch_init_escrow_has_rent_sysvar(accounts: &[AccountInfo]) -> bool {
+		matches!(accounts.get(1), Some(account) if sysvar::rent::check_id(account.key))
+	}
+
+	fn batch_init_escrow_config_index(accounts: &[AccountInfo]) -> usize {
+		if Self::batch_init_escrow_has_rent_sysvar(accounts) {
+			5
+		} else {
+			4
+		}
+	}
+
+	fn process_batch_init_escrow(
+		accounts: &[AccountInfo],
+		amounts: &[u64],
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let initializer = next_account_info(account_info_iter)?;
+
+		if !initializer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let rent = &Rent::get()?;
+		if Self::batch_init_escrow_has_rent_sysvar(accounts) {
+			next_account_info(account_info_iter)?;
+		}
+		let token_program = next_account_info(account_info_iter)?;
+		let treasury = next_account_info(account_info_iter)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+		let (config_account, config, clock) = Self::load_config_accounts(account_info_iter, program_id)?;
+
+		// One (temp token account, token to receive account, escrow account, their two mints) group per amount
+		let escrows = account_info_iter.as_slice().chunks_exact(5);
+		if !escrows.remainder().is_empty() || escrows.len() != amounts.len() {
+			return Err(ProgramError::NotEnoughAccountKeys);
+		}
+		let extension_policy =
+			Self::load_extension_policy(accounts.get(Self::batch_init_escrow_config_index(accounts)), program_id)?;
+		for (escrow, amount) in escrows.zip(amounts) {
+			Self::check_extensions(extension_policy, &[Some(&escrow[0])], &[Some(&escrow[3]), Some(&escrow[4])])?;
+			Self::check_escrow_len(&escrow[2], Escrow::LEN)?;
+			Self::init_escrow(
+				initializer,
+				initializer,
+				&escrow[0],
+				&escrow[1],
+				&escrow[2],
+				rent,
+				token_program,
+				treasury,
+				system_program_account,
+				None,
+				*amount,
+				config.listing_fee_lamports,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				Some(&escrow[3]),
+				Some(&escrow[4]),
+				false,
+				trackers,
+				&clock,
+				program_id,
+			)?;
+		}
+		let listing_fees = config.listing_fee_lamports
+			.checked_mul(amounts.len() as u64)
+			.ok_or(EscrowError::AmountOverflow)?;
+		Self::reserve_listing_fee(config_account, listing_fees)?;
+
+		Ok(())
+	}
+
+	/// Everything InitEscrow does once the accounts are parsed, shared with BatchInitEscrow.
+	/// The initializer needs to be checked as a signer by the caller. With `multisig` (the multisig
+	/// owning the temp token account and its signers) the multisig is the escrow's initializer instead
+	#[allow(clippy::too_many_arguments)]
+	fn init_escrow<'a>(
+		initializer: &AccountInfo<'a>,
+		payer: &AccountInfo<'a>,
+		temp_token_account: &AccountInfo<'a>,
+		token_to_receive_account: &AccountInfo<'a>,
+		escrow_account: &AccountInfo<'a>,
+		rent: &Rent,
+		token_program: &AccountInfo<'a>,
+		treasury: &AccountInfo<'a>,
+		system_program_account: &AccountInfo<'a>,
+		group_account: Option<&AccountInfo<'a>>,
+		amount: u64,
+		listing_fee: u64,
+		predecessor: Option<Pubkey>,
+		refund_address: Option<Pubkey>,
+		expires_at: Option<i64>,
+		native_sol_deposit: Option<u64>,
+		wrap_sol: Option<(u64, &AccountInfo<'a>)>,
+		vault: Option<(u64, &AccountInfo<'a>, MintAccounts<'_, 'a>)>,
+		nft_deposit: Option<NftDeposit<'_, 'a>>,
+		collection: Option<Pubkey>,
+		multisig: Option<(&AccountInfo<'a>, &[AccountInfo<'a>])>,
+		lamports_deposit: Option<u64>,
+		expected_lamports: Option<u64>,
+		stake_deposit: Option<StakeAccounts<'_, 'a>>,
+		deposit_mint: Option<&AccountInfo<'a>>,
+		receive_mint: Option<&AccountInfo<'a>>,
+		retain_history: bool,
+		trackers: Trackers,
+		clock: &Clock,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		// Nobody can fill an escrow asking for nothing in a way that means anything
+		if amount == 0 {
+			return Err(EscrowError::ZeroExpectedAmount.into());
+		}
+		if collection.is_none() {
+			check_distinct(&[temp_token_account, token_to_receive_account, escrow_account])?;
+		} else {
+			check_distinct(&[temp_token_account, escrow_account])?;
+		}
+
+		// The two legs can each be spl-token or Token-2022. Collection offers don't know the token account
+		// they receive into until they're filled
+		if collection.is_none() {
+			if !token::is_token_program(token_to_receive_account.owner) {
+				return Err(ProgramError::IncorrectProgramId);
+			}
+			Self::check_transferable(token_to_receive_account, &token::unpack_account(token_to_receive_account)?)?;
+		}
+		// The SOL vault, a wSOL account the program creates itself and the escrow PDA's token account for a
+		// pNFT aren't token accounts yet, and a cNFT's tree or a stake account never is
+		let has_temp_token_account = stake_deposit.is_none() && !matches!(
+			nft_deposit,
+			Some(NftDeposit::Programmable { .. }) | Some(NftDeposit::Compressed { .. })
+		);
+		if native_sol_deposit.is_none() && wrap_sol.is_none() && has_temp_token_account {
+			check_token_program(temp_token_account, token_program)?;
+			Self::check_transferable(temp_token_account, &token::unpack_account(temp_token_account)?)?;
+		}
+
+		// Token Metadata's Transfer takes a single signer, and groups belong to whoever signed for them
+		if multisig.is_some() && (matches!(nft_deposit, Some(NftDeposit::Programmable { .. })) || group_account.is_some()) {
+			return Err(EscrowError::InvalidInstruction.into());
+		}
+		let owner = multisig.map_or(initializer, |(multisig_account, _)| multisig_account);
+		// A Multisig is a token program account, lamports sent to it are stuck there. The escrow's rent and
+		// whatever SOL it pays out go to the refund address, which has to be somewhere else then
+		if multisig.is_some() && refund_address.filter(|refund_address| refund_address != owner.key).is_none() {
+			return Err(EscrowError::WrongRefundAccount.into());
+		}
 
 		if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
 			return Err(EscrowError::NotRentExempt.into());
 		}
 
-		// unpack_unchecked comes from default functions from trait in program_pack 
-		// https://docs.rs/solana-program/latest/src/solana_program/program_pack.rs.html#29-39
-		let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+		check_owner(escrow_account, program_id)?;
+		// An account closed earlier in the same transaction and topped up again is still ours, but it's
+		// marked so it can't come back as a new escrow
+		if has_closed_discriminator(&escrow_account.try_borrow_data()?) {
+			return Err(EscrowError::AccountClosed.into());
+		}
+		// A copy of the struct the account's data is cast to, it's written back with the discriminator at the end
+		let mut escrow_info = *Escrow::load(&escrow_account.try_borrow_data()?)?;
 		if escrow_info.is_initialized() {
 			return Err(ProgramError::AccountAlreadyInitialized);
 		}
 
 		// Now that we know escrow struct is uninitialized, let's initialize 
-		escrow_info.is_initialized = true;
-		escrow_info.initializer_pubkey = *initializer.key;
+		escrow_info.version = ESCROW_VERSION;
+		escrow_info.status = EscrowStatus::Active as u8;
+		escrow_info.retain_history = retain_history.into();
+		// Program Derived Address of this escrow, the only one that can move the deposit from here on.
+		// Only the canonical bump is ever stored
+		let (pda, authority_bump_seed) = Escrow::find_authority_address(escrow_account.key, program_id);
+		escrow_info.authority_bump_seed = authority_bump_seed;
+		escrow_info.initializer_pubkey = *owner.key;
 		escrow_info.temp_token_account_pubkey = *temp_token_account.key;
-		escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
+		if collection.is_none() {
+			escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
+		}
+		escrow_info.collection_pubkey = collection.unwrap_or_default();
 		escrow_info.expected_amount = amount;
+		escrow_info.listing_fee = listing_fee;
+		escrow_info.is_native_sol = native_sol_deposit.is_some().into();
+		escrow_info.predecessor_pubkey = predecessor.unwrap_or_default();
+		escrow_info.refund_pubkey = refund_address.unwrap_or(*owner.key);
+		escrow_info.expires_at = expires_at.unwrap_or(0);
+		escrow_info.last_active_at = clock.unix_timestamp;
+		escrow_info.created_at = clock.unix_timestamp;
+		escrow_info.created_at_slot = clock.slot;
+		escrow_info.expected_lamports = expected_lamports.unwrap_or(0);
+		// The decimals go in with the escrow so its price can be compared to others (see normalized_price)
+		escrow_info.expected_decimals = match receive_mint {
+			Some(receive_mint) => Self::unpack_mint_of(token_to_receive_account, receive_mint)?,
+			None => 0,
+		};
+		escrow_info.deposit_decimals = if native_sol_deposit.is_some() || wrap_sol.is_some() || stake_deposit.is_some() {
+			spl_token::native_mint::DECIMALS
+		} else if let (Some((_, source, _)), Some(deposit_mint)) = (vault, deposit_mint) {
+			// The vault doesn't exist yet, the account funding it has the same mint
+			Self::unpack_mint_of(source, deposit_mint)?
+		} else if let Some(deposit_mint) = deposit_mint {
+			Self::unpack_mint_of(temp_token_account, deposit_mint)?
+		} else {
+			// NFTs, which check_nft makes sure are 0 decimals
+			0
+		};
+		escrow_info.deposit_mint_pubkey = match (&nft_deposit, deposit_mint) {
+			(Some(NftDeposit::Plain { mint, .. }), _) => *mint.key,
+			(Some(NftDeposit::Programmable { pnft, .. }), _) => *pnft.mint.key,
+			(_, Some(deposit_mint)) => *deposit_mint.key,
+			_ if native_sol_deposit.is_some() || wrap_sol.is_some() => spl_token::native_mint::id(),
+			_ => Pubkey::default(),
+		};
+		escrow_info.receive_mint_pubkey = receive_mint.map_or_else(Pubkey::default, |receive_mint| *receive_mint.key);
+
+		// Lamports on top of the deposit go into the escrow account itself, next to its rent
+		if let Some(lamports) = lamports_deposit {
+			debug_msg!("Calling the system program to deposit SOL into the escrow account...");
+			invoke(
+				&system_instruction::transfer(initializer.key, escrow_account.key, lamports),
+				&[
+					initializer.clone(),
+					escrow_account.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+			escrow_info.deposited_lamports = lamports;
+		}
 
-		Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+		if let Some(lamports) = native_sol_deposit {
+			// The vault is a plain system account, topped up to rent exemption so it survives holding
+			// however little is deposited. The top up goes back with whatever else the vault holds when it's closed
+			if *temp_token_account.key != Escrow::find_sol_vault_address(escrow_account.key, program_id).0 {
+				return Err(ProgramError::InvalidAccountData);
+			}
+			let top_up = rent.minimum_balance(0).saturating_sub(temp_token_account.lamports());
+			escrow_info.deposited_amount = lamports;
 
-		// Program Derived Address
-		// TODO: why do we seed with address of byte array "escrow"? 
-		// Does creating a struct Escrow always start byte represenation with "escrow"?
-		// So if I named the struct "Foo" then i would use &[b"foo]?
-		let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+			// The top up is rent, so a separate rent payer pays it on its own
+			let (deposit, payer_top_up) = if payer.key == initializer.key {
+				(lamports.checked_add(top_up).ok_or(EscrowError::AmountOverflow)?, 0)
+			} else {
+				(lamports, top_up)
+			};
+			if payer_top_up > 0 {
+				debug_msg!("Calling the system program to top up the vault's rent...");
+				invoke(
+					&system_instruction::transfer(payer.key, temp_token_account.key, payer_top_up),
+					&[
+						payer.clone(),
+						temp_token_account.clone(),
+						system_program_account.clone(),
+					],
+				)?;
+			}
 
-		let token_program = next_account_info(account_info_iter)?;
-		let owner_change_ix = spl_token::instruction::set_authority(
-			token_program.key,
-			temp_token_account.key, // set_authority will fail if temp_token_account is not owned by Token program
-			Some(&pda),
-			spl_token::instruction::AuthorityType::AccountOwner,
-			initializer.key,
-			&[&initializer.key],
+			debug_msg!("Calling the system program to deposit SOL into the vault...");
+			invoke(
+				&system_instruction::transfer(initializer.key, temp_token_account.key, deposit),
+				&[
+					initializer.clone(),
+					temp_token_account.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+		} else if let Some((lamports, native_mint)) = wrap_sol {
+			Self::wrap_sol_deposit(
+				initializer,
+				payer,
+				temp_token_account,
+				native_mint,
+				escrow_account,
+				&pda,
+				rent,
+				token_program,
+				system_program_account,
+				lamports,
+				program_id,
+			)?;
+			escrow_info.deposited_amount = lamports;
+		} else if let Some((amount, source, vault_mint)) = vault {
+			Self::create_vault(
+				payer,
+				temp_token_account,
+				vault_mint.mint,
+				escrow_account,
+				&pda,
+				rent,
+				token_program,
+				system_program_account,
+				program_id,
+			)?;
+
+			debug_msg!("Calling the token program to move the deposit into the vault...");
+			// What arrives, a Token-2022 transfer fee comes off it
+			escrow_info.deposited_amount = Self::transfer_tokens(
+				token_program,
+				source,
+				temp_token_account,
+				owner,
+				vault_mint,
+				amount,
+				&[],
+			)?;
+		} else if let Some(NftDeposit::Programmable { source, pda_account, pnft }) = nft_deposit {
+			// The pNFT's token account can't be handed over like a temp token account, since it's frozen.
+			// Token Metadata moves the NFT into the escrow PDA's associated token account instead
+			if !token::is_token_program(token_program.key)
+				|| *pda_account.key != pda
+				|| *temp_token_account.key != get_associated_token_address(&pda, pnft.mint.key, token_program.key)
+			{
+				return Err(ProgramError::InvalidAccountData);
+			}
+
+			debug_msg!("Calling Token Metadata to transfer the pNFT to the escrow...");
+			Self::transfer_pnft(&pnft, token_program, source, initializer, temp_token_account, pda_account, initializer, &[])?;
+			Self::check_nft(temp_token_account, pnft.mint, pnft.metadata)?;
+			escrow_info.is_nft = true.into();
+			escrow_info.is_programmable_nft = true.into();
+			escrow_info.deposited_amount = 1;
+		} else if let Some(NftDeposit::Compressed { leaf, pda_account, leaf_delegate, cnft }) = nft_deposit {
+			// The cNFT only changes owners inside its tree, where the escrow PDA holds it from here on
+			if *pda_account.key != pda {
+				return Err(EscrowError::WrongPda.into());
+			}
+
+			debug_msg!("Calling Bubblegum to transfer the compressed NFT to the escrow...");
+			Self::transfer_cnft(&cnft, temp_token_account, initializer, leaf_delegate, pda_account, &leaf, &[])?;
+			escrow_info.is_compressed_nft = true.into();
+			escrow_info.compressed_nft_data_hash = leaf.data_hash;
+			escrow_info.compressed_nft_creator_hash = leaf.creator_hash;
+			escrow_info.compressed_nft_nonce = leaf.nonce;
+			escrow_info.deposited_amount = 1;
+		} else if let Some(stake_deposit) = stake_deposit {
+			// Whoever holds both authorities has the stake account. Once it's deactivated all it's worth
+			// is its lamports, and with the PDA as staker nobody can delegate it again while it's escrowed
+			let stake_info = stake::unpack_stake(temp_token_account)?;
+			if stake_info.staker != *initializer.key || stake_info.withdrawer != *initializer.key {
+				return Err(ProgramError::InvalidAccountData);
+			}
+			if !stake_info.is_deactivated(&Clock::from_account_info(stake_deposit.clock_sysvar)?) {
+				return Err(EscrowError::StakeNotDeactivated.into());
+			}
+
+			debug_msg!("Calling the stake program to hand the stake account to the escrow...");
+			Self::authorize_stake(&stake_deposit, temp_token_account, initializer, &pda, &[])?;
+			escrow_info.is_stake_account = true.into();
+			escrow_info.deposited_amount = temp_token_account.lamports();
+		} else {
+			if let Some(NftDeposit::Plain { mint, metadata }) = nft_deposit {
+				Self::check_nft(temp_token_account, mint, metadata)?;
+				escrow_info.is_nft = true.into();
+			}
+			// Handing over the account leaves its delegate and close authority in place. A delegate could
+			// still move the deposit and a close authority could close a wSOL account with the SOL in it
+			let temp_token_account_info = token::unpack_account(temp_token_account)?;
+			if temp_token_account_info.delegate.is_some() {
+				return Err(EscrowError::TempAccountHasDelegate.into());
+			}
+			if temp_token_account_info.close_authority.is_some() {
+				return Err(EscrowError::TempAccountHasCloseAuthority.into());
+			}
+			escrow_info.deposited_amount = temp_token_account_info.amount;
+
+			let signers = multisig.map_or(&[][..], |(_, signers)| signers);
+			token::check_token_program_id(token_program.key)?;
+
+			debug_msg!("Calling the token program to transfer token account ownership...");
+			cpi::invoke_signed(
+				token_program.key,
+				&[
+					AccountMeta::new(*temp_token_account.key, false),
+					AccountMeta::new_readonly(*owner.key, signers.is_empty()),
+				],
+				&token::set_owner_data(&pda),
+				&[temp_token_account.clone(), owner.clone(), token_program.clone()],
+				signers,
+				&[],
+			)?;
+		}
+		if escrow_info.deposited_amount == 0 {
+			return Err(EscrowError::EmptyDeposit.into());
+		}
+
+		if listing_fee > 0 {
+			Self::check_treasury(treasury, system_program_account, program_id)?;
+
+			debug_msg!("Calling the system program to pay the listing fee...");
+			invoke(
+				&system_instruction::transfer(initializer.key, treasury.key, listing_fee),
+				&[
+					initializer.clone(),
+					treasury.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+		}
+
+		if let Some(group_account) = group_account {
+			let mut group_info = Self::unpack_group(group_account, initializer.key, program_id)?;
+			group_info.escrow_count = group_info.escrow_count
+				.checked_add(1)
+				.ok_or(EscrowError::AmountOverflow)?;
+			EscrowGroup::pack(group_info, &mut group_account.try_borrow_mut_data()?)?;
+			escrow_info.group_pubkey = *group_account.key;
+		}
+
+		if let Some(registry_page) = trackers.registry_page {
+			Self::update_registry_page(registry_page, |page| page.add(*escrow_account.key))?;
+			escrow_info.is_registered = true.into();
+		}
+
+		if let Some(index) = trackers.index {
+			Self::update_index(index, initializer.key, |index| index.add(*escrow_account.key))?;
+			escrow_info.is_indexed = true.into();
+		}
+
+		if let Some(stats_account) = trackers.stats {
+			escrow_info.is_counted = true.into();
+			Self::update_stats(stats_account, |stats| {
+				stats.escrows_created = stats.escrows_created.saturating_add(1);
+				stats.open_escrows = stats.open_escrows.saturating_add(1);
+			})?;
+		}
+
+		escrow_info.pack_versioned(&mut escrow_account.try_borrow_mut_data()?)?;
+
+		Self::emit(
+			&EscrowCreated {
+				escrow: *escrow_account.key,
+				initializer: escrow_info.initializer_pubkey,
+				deposit_mint: escrow_info.deposit_mint_pubkey,
+				receive_mint: escrow_info.receive_mint_pubkey,
+				deposited_amount: escrow_info.deposited_amount,
+				expected_amount: escrow_info.expected_amount,
+			},
+			trackers,
+		)
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	/// Exchange, or ExchangeV2 with `v2`. They only differ in the accounts they take
+	fn process_exchange(
+		accounts: &[AccountInfo],
+		v2: bool,
+		amount_expected_by_taker: u64,
+		reference: Option<Pubkey>,
+		max_amount_in: Option<u64>,
+		min_amount_out: Option<u64>,
+		unwrap_sol: bool,
+		compressed_nft_proof: Option<CompressedNftProof>,
+		receipt: bool,
+		trackers: Trackers,
+		pair_stats_account: Option<&AccountInfo>,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let ExchangeAccounts {
+			taker,
+			takers_sending_account,
+			takers_token_to_receive_account,
+			pda_temp_token_account,
+			refund_account,
+			initializers_token_to_receive_account,
+			escrow,
+			token_program,
+			pda_account,
+			config_account,
+			sending_token_program,
+		} = match v2 {
+			true => ExchangeAccounts::next_v2(account_info_iter, program_id)?,
+			false => ExchangeAccounts::next(account_info_iter, program_id)?,
+		};
+		let taker = taker.info();
+		let takers_sending_account = takers_sending_account.info();
+		let takers_token_to_receive_account = takers_token_to_receive_account.info();
+		let pda_temp_token_account = pda_temp_token_account.info();
+		let refund_account = refund_account.info();
+		let initializers_token_to_receive_account = initializers_token_to_receive_account.info();
+		let config_account = config_account.info();
+		let ProgramOwned { account: escrow_account, info: escrow_info } = escrow;
+		let bump_seed = escrow_info.authority_bump_seed;
+
+		// Receive accounts that don't exist yet are created as associated token accounts, the taker pays for
+		// them. The associated token account program makes sure they're at the associated address.
+		// The taker only gets a token account for plain token deposits
+		let create_takers_account = escrow_info.holds_tokens()
+			&& !escrow_info.is_programmable_nft.get()
+			&& !unwrap_sol
+			&& takers_token_to_receive_account.data_is_empty();
+		let create_initializers_account = initializers_token_to_receive_account.data_is_empty();
+		let refund_listing_fee = REFUND_LISTING_FEE_ON_EXCHANGE && escrow_info.listing_fee > 0;
+
+		// Exchange takes the system program wherever something calls it, ExchangeV2 once up front
+		let shared_system_program = if !v2 {
+			None
+		} else if escrow_info.is_native_sol.get() {
+			Some(token_program)
+		} else if create_takers_account
+			|| create_initializers_account
+			|| escrow_info.expected_lamports > 0
+			|| refund_listing_fee
+			|| receipt
+		{
+			let system_program_account = next_account_info(account_info_iter)?;
+			if *system_program_account.key != system_program::id() {
+				return Err(ProgramError::IncorrectProgramId);
+			}
+			Some(system_program_account)
+		} else {
+			None
+		};
+
+		if escrow_info.is_frozen.get() {
+			return Err(EscrowError::EscrowFrozen.into());
+		}
+		if escrow_info.prevent_self_trade.get()
+			&& (*taker.key == escrow_info.initializer_pubkey
+				|| *taker.key == escrow_info.refund_pubkey
+				|| *takers_sending_account.key == escrow_info.initializer_token_to_receive_account_pubkey)
+		{
+			return Err(EscrowError::SelfTrade.into());
+		}
+
+		let takers_sending_account_info = token::unpack_account(takers_sending_account)?;
+		let sending_mint = Self::next_mint(&takers_sending_account_info, account_info_iter)?;
+		if escrow_info.is_collection_offer() {
+			Self::check_collection_nft(
+				&escrow_info,
+				takers_sending_account,
+				initializers_token_to_receive_account,
+				sending_token_program,
+				sending_mint.mint,
+				account_info_iter,
+			)?;
+		}
+		// The vault is unpacked once here, nothing moves out of it before the transfer to the taker
+		let (deposit_mint, vault) = Self::next_deposit_mint(&escrow_info, pda_temp_token_account, account_info_iter)?;
+		// Extra lamports in a SOL vault just go back to the initializer when it's closed
+		let surplus = match &vault {
+			Some(vault) => vault.amount
+				.checked_sub(escrow_info.deposited_amount)
+				.ok_or(EscrowError::ExpectedAmountMismatch)?,
+			None => 0,
+		};
+		Self::check_extensions(
+			Self::load_extension_policy(Some(config_account), program_id)?,
+			&[Some(takers_sending_account)],
+			&[Some(sending_mint.mint), deposit_mint.map(|deposit_mint| deposit_mint.mint)],
 		)?;
 
-		msg!("Calling the token program to transfer token account ownership...");
-		invoke(
-			&owner_change_ix,
-			&[
-				temp_token_account.clone(),
-				initializer.clone(),
-				token_program.clone(),
-			]	
+		if create_takers_account || create_initializers_account {
+			let associated_token_program_account = next_account_info(account_info_iter)?;
+			let system_program_account = Self::next_system_program(shared_system_program, account_info_iter)?;
+			if create_initializers_account {
+				// The associated token account program needs the initializer's wallet, which only comes in
+				// as account 4 when it's also the refund address
+				if *refund_account.key != escrow_info.initializer_pubkey {
+					return Err(EscrowError::WrongReceiveAccount.into());
+				}
+				Self::create_associated_token_account(
+					taker,
+					initializers_token_to_receive_account,
+					refund_account,
+					sending_mint.mint,
+					sending_token_program,
+					associated_token_program_account,
+					system_program_account,
+				)?;
+			}
+			if create_takers_account {
+				Self::create_associated_token_account(
+					taker,
+					takers_token_to_receive_account,
+					taker,
+					deposit_mint.ok_or(ProgramError::NotEnoughAccountKeys)?.mint,
+					token_program,
+					associated_token_program_account,
+					system_program_account,
+				)?;
+			}
+		}
+
+		// Each leg goes through the token program of its own mint, or the system program for SOL.
+		// A frozen account or a non-transferable mint would only fail halfway through settlement
+		// The initializer's account may have just been created, it's unpacked after that
+		check_token_program(takers_sending_account, sending_token_program)?;
+		check_token_program(initializers_token_to_receive_account, sending_token_program)?;
+		let initializers_token_to_receive_account_info = token::unpack_account(initializers_token_to_receive_account)?;
+		Self::check_transferable(takers_sending_account, &takers_sending_account_info)?;
+		Self::check_transferable(initializers_token_to_receive_account, &initializers_token_to_receive_account_info)?;
+		if escrow_info.is_native_sol.get() {
+			if *token_program.key != system_program::id() || unwrap_sol {
+				return Err(ProgramError::IncorrectProgramId);
+			}
+		} else if escrow_info.is_compressed_nft.get() || escrow_info.is_stake_account.get() {
+			// next_cnft_accounts and next_stake_accounts check it's Bubblegum or the stake program
+			if unwrap_sol {
+				return Err(ProgramError::IncorrectProgramId);
+			}
+		} else {
+			// pNFT token accounts are always frozen, and Token Metadata creates the taker's if needed
+			let vault = vault.as_ref().ok_or(ProgramError::NotEnoughAccountKeys)?;
+			check_token_program(pda_temp_token_account, token_program)?;
+			if !escrow_info.is_programmable_nft.get() {
+				Self::check_transferable(pda_temp_token_account, vault)?;
+			}
+			if unwrap_sol {
+				// Only spl-token's wSOL, the taker's side is a plain account then
+				if *token_program.key != spl_token::id() || vault.mint != spl_token::native_mint::id() {
+					return Err(EscrowError::TokenMintMismatch.into());
+				}
+			} else if !escrow_info.is_programmable_nft.get() {
+				check_token_program(takers_token_to_receive_account, token_program)?;
+				let takers_token_to_receive_account_info = token::unpack_account(takers_token_to_receive_account)?;
+				Self::check_transferable(takers_token_to_receive_account, &takers_token_to_receive_account_info)?;
+				// The deposit's leg stays within its mint too, see below for the other one
+				if takers_token_to_receive_account_info.mint != vault.mint {
+					return Err(EscrowError::TokenMintMismatch.into());
+				}
+			}
+		}
+
+		// Both legs have to stay within their mint. The token program would refuse a transfer across mints
+		// too, this just says what's wrong before anything moves
+		if escrow_info.receive_mint_pubkey != Pubkey::default() && escrow_info.receive_mint_pubkey != *sending_mint.mint.key {
+			return Err(EscrowError::TokenMintMismatch.into());
+		}
+		if initializers_token_to_receive_account_info.mint != *sending_mint.mint.key {
+			return Err(EscrowError::TokenMintMismatch.into());
+		}
+
+		let deposit_pnft = match deposit_mint {
+			Some(deposit_mint) if escrow_info.is_programmable_nft.get() => {
+				Some(Self::next_escrowed_pnft(deposit_mint.mint, taker, account_info_iter)?)
+			},
+			_ => None,
+		};
+		let deposit_cnft = Self::next_escrowed_cnft(&escrow_info, compressed_nft_proof, token_program, account_info_iter)?;
+		let deposit_stake = Self::next_escrowed_stake(&escrow_info, token_program, pda_temp_token_account, account_info_iter)?;
+
+		// NFT sales pay the creators their royalties out of what the taker pays. There are no more than
+		// MAX_CREATORS, the slots past them hold the token program as a placeholder
+		let mut royalties = [(sending_token_program, 0); metadata::MAX_CREATORS];
+		let mut royalties_len = 0;
+		if escrow_info.is_nft.get() {
+			let nft_metadata = match &deposit_pnft {
+				Some(deposit_pnft) => metadata::unpack_metadata(deposit_pnft.metadata, deposit_pnft.mint.key)?,
+				None => {
+					let nft_mint = deposit_mint.ok_or(ProgramError::NotEnoughAccountKeys)?.mint;
+					let nft_metadata = next_account_info(account_info_iter)?;
+					metadata::unpack_metadata(nft_metadata, nft_mint.key)?
+				},
+			};
+			for (creator, royalty) in nft_metadata.royalties(escrow_info.expected_amount)?.iter() {
+				let creator_token_account = next_account_info(account_info_iter)?;
+				if *creator_token_account.key != get_associated_token_address(creator, sending_mint.mint.key, sending_token_program.key) {
+					return Err(EscrowError::WrongCreatorAccount.into());
+				}
+				royalties[royalties_len] = (creator_token_account, *royalty);
+				royalties_len += 1;
+			}
+		}
+		let royalties = &mut royalties[..royalties_len];
+		let total_royalties = Self::total_royalties(royalties)?;
+		let initializers_share = escrow_info.expected_amount.checked_sub(total_royalties).ok_or(EscrowError::RoyaltyOverflow)?;
+
+		// For the expiry, the transfer fee schedule, the interest on interest-bearing mints and the pair stats
+		let clock = Self::next_clock(account_info_iter)?;
+		if escrow_info.is_expired(clock.unix_timestamp) {
+			return Err(EscrowError::EscrowExpired.into());
+		}
+
+		// Amounts of interest-bearing mints are what wallets show, the raw amount with the interest so far
+		// on it. That's what the taker expects to get and what the escrow expects to be paid, the raw
+		// amounts that actually move are worked back from it
+		let sending_interest = token::get_interest_scale(sending_mint.mint, clock.unix_timestamp)?;
+		let deposit_interest = match deposit_mint {
+			Some(deposit_mint) => token::get_interest_scale(deposit_mint.mint, clock.unix_timestamp)?,
+			None => None,
+		};
+		let with_deposit_interest = |amount| -> Result<u64, ProgramError> {
+			match deposit_interest {
+				Some(scale) => token::apply_interest(amount, scale),
+				None => Ok(amount),
+			}
+		};
+		let without_sending_interest = |amount| -> Result<u64, ProgramError> {
+			match sending_interest {
+				Some(scale) => token::remove_interest(amount, scale),
+				None => Ok(amount),
+			}
+		};
+
+		// Amount validation, prevent frontrunning. The taker gets exactly what was deposited, not whatever
+		// else ended up in the temp account since
+		if amount_expected_by_taker != with_deposit_interest(escrow_info.deposited_amount)? {
+			return Err(EscrowError::ExpectedAmountMismatch.into()); // TODO why do we need .into?
+		}
+
+		let initializers_share = without_sending_interest(initializers_share)?;
+		for (_, royalty) in royalties.iter_mut() {
+			*royalty = without_sending_interest(*royalty)?;
+		}
+		let total_royalties = Self::total_royalties(royalties)?;
+
+		// With a transfer fee on the token the taker sends, the taker has to send enough on top for the
+		// initializer to still get their full share. Creators take the fee on their royalties
+		let transfer_fee = token::get_transfer_fee(sending_mint.mint, clock.epoch)?;
+		let amount_in = match transfer_fee {
+			Some(transfer_fee) => transfer_fee.calculate_gross_amount(initializers_share)?,
+			None => initializers_share,
+		};
+		if matches!(max_amount_in, Some(max) if amount_in.saturating_add(total_royalties) > max) {
+			return Err(EscrowError::SlippageExceeded.into());
+		}
+
+		let lamports_system_program = if escrow_info.expected_lamports > 0 {
+			let system_program_account = Self::next_system_program(shared_system_program, account_info_iter)?;
+			if *system_program_account.key != system_program::id() {
+				return Err(ProgramError::IncorrectProgramId);
+			}
+			Some(system_program_account)
+		} else {
+			None
+		};
+
+		let refund_accounts = if refund_listing_fee {
+			let treasury = next_account_info(account_info_iter)?;
+			let system_program_account = Self::next_system_program(shared_system_program, account_info_iter)?;
+			let treasury_bump_seed = Self::check_treasury(treasury, system_program_account, program_id)?;
+			Some((treasury, system_program_account, treasury_bump_seed))
+		} else {
+			None
+		};
+
+		// A chained escrow only becomes fillable once its predecessor has been filled
+		if escrow_info.predecessor_pubkey != Pubkey::default() {
+			let predecessor_account = next_account_info(account_info_iter)?;
+			if *predecessor_account.key != escrow_info.predecessor_pubkey {
+				return Err(EscrowError::WrongPredecessor.into());
+			}
+			Self::check_predecessor_filled(predecessor_account, program_id)?;
+		}
+
+		// Nothing is done with the reference account, it just has to be in the transaction
+		if let Some(reference) = reference {
+			if *next_account_info(account_info_iter)?.key != reference {
+				return Err(EscrowError::WrongReferenceAccount.into());
+			}
+		}
+
+		// Like Cancel, so the group's count of its open escrows gets down to 0 for CancelGroup
+		Self::leave_group(&escrow_info, account_info_iter, program_id)?;
+
+		let initializers_surplus_token_account = if surplus > 0 {
+			Some(next_account_info(account_info_iter)?)
+		} else {
+			None
+		};
+
+		let receipt_accounts = if receipt {
+			let receipt_account = next_account_info(account_info_iter)?;
+			let system_program_account = Self::next_system_program(shared_system_program, account_info_iter)?;
+			let rent = match v2 {
+				true => Rent::get()?,
+				false => Rent::from_account_info(next_account_info(account_info_iter)?)?,
+			};
+			let clock = Self::next_clock(account_info_iter)?;
+			Some((receipt_account, system_program_account, rent, clock))
+		} else {
+			None
+		};
+
+		// Both legs are checked on what actually arrives, transfer fees come out of the receiving side.
+		// Optional bounds on both legs are for clients where the terms can move between quoting and landing
+		debug_msg!("Calling the token program to transfer tokens to the escrow's initializer...");
+		let received_by_initializer = Self::transfer_tokens(
+			sending_token_program,
+			takers_sending_account,
+			initializers_token_to_receive_account,
+			taker,
+			sending_mint,
+			amount_in,
+			&[],
 		)?;
+		if received_by_initializer < initializers_share {
+			return Err(EscrowError::ExpectedAmountMismatch.into());
+		}
 
-		Ok(())
+		if let Some(system_program_account) = lamports_system_program {
+			debug_msg!("Calling the system program to pay the initializer's SOL...");
+			invoke(
+				&system_instruction::transfer(taker.key, refund_account.key, escrow_info.expected_lamports),
+				&[
+					taker.clone(),
+					refund_account.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+		}
+
+		for (creator_token_account, royalty) in royalties.iter() {
+			debug_msg!("Calling the token program to pay a creator royalty...");
+			Self::transfer_tokens(
+				sending_token_program,
+				takers_sending_account,
+				creator_token_account,
+				taker,
+				sending_mint,
+				*royalty,
+				&[],
+			)?;
+		}
+
+		let received_by_taker = if escrow_info.is_native_sol.get() {
+			debug_msg!("Calling the system program to pay the SOL out to the taker...");
+			Self::transfer_from_sol_vault(
+				pda_temp_token_account,
+				takers_token_to_receive_account,
+				token_program,
+				escrow_account.key,
+				escrow_info.deposited_amount,
+				program_id,
+			)?;
+			escrow_info.deposited_amount
+		} else if unwrap_sol {
+			// Paid out of the lamports the temp account leaves when it's closed below
+			escrow_info.deposited_amount
+		} else if let Some((deposit_cnft, leaf)) = &deposit_cnft {
+			debug_msg!("Calling Bubblegum to transfer the compressed NFT to the taker...");
+			Self::transfer_cnft(
+				deposit_cnft,
+				pda_temp_token_account,
+				pda_account,
+				pda_account,
+				takers_token_to_receive_account,
+				leaf,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+			escrow_info.deposited_amount
+		} else if let Some(deposit_stake) = &deposit_stake {
+			debug_msg!("Calling the stake program to hand the stake account to the taker...");
+			Self::authorize_stake(
+				deposit_stake,
+				pda_temp_token_account,
+				pda_account,
+				takers_token_to_receive_account.key,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+			escrow_info.deposited_amount
+		} else if let Some(deposit_pnft) = &deposit_pnft {
+			debug_msg!("Calling Token Metadata to transfer the pNFT to the taker...");
+			Self::transfer_pnft(
+				deposit_pnft,
+				token_program,
+				pda_temp_token_account,
+				pda_account,
+				takers_token_to_receive_account,
+				taker,
+				pda_account,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+			escrow_info.deposited_amount
+		} else {
+			debug_msg!("Calling the token program to transfer tokens to the taker...");
+			// For authority we pass in the seeds (b"escrow", the escrow and bump_seed) instead of the PDA itself, so the
+			// PDA can be recalculated. If the recalculation and the given PDA keys don't match, the transfer fails
+			Self::transfer_tokens(
+				token_program,
+				pda_temp_token_account,
+				takers_token_to_receive_account,
+				pda_account,
+				deposit_mint.ok_or(ProgramError::NotEnoughAccountKeys)?,
+				escrow_info.deposited_amount,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?
+		};
+		if matches!(min_amount_out, Some(min) if with_deposit_interest(received_by_taker)? < min) {
+			return Err(EscrowError::SlippageExceeded.into());
+		}
+
+		if escrow_info.deposited_lamports > 0 {
+			debug_msg!("Paying the initializer's SOL out to the taker...");
+			**escrow_account.lamports.borrow_mut() = escrow_account.lamports()
+				.checked_sub(escrow_info.deposited_lamports)
+				.ok_or(EscrowError::AmountOverflow)?;
+			**taker.lamports.borrow_mut() = taker.lamports()
+				.checked_add(escrow_info.deposited_lamports)
+				.ok_or(EscrowError::AmountOverflow)?;
+		}
+
+		if let Some(initializers_surplus_token_account) = initializers_surplus_token_account {
+			Self::sweep_surplus(
+				&escrow_info,
+				pda_temp_token_account,
+				initializers_surplus_token_account,
+				token_program,
+				pda_account,
+				deposit_mint.ok_or(ProgramError::NotEnoughAccountKeys)?,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+				surplus,
+			)?;
+		}
+
+		if escrow_info.is_native_sol.get() {
+			debug_msg!("Calling the system program to close the SOL vault...");
+			Self::transfer_from_sol_vault(
+				pda_temp_token_account,
+				refund_account,
+				token_program,
+				escrow_account.key,
+				pda_temp_token_account.lamports(),
+				program_id,
+			)?;
+		} else if unwrap_sol {
+			// Closing a wSOL account unwraps it, all of its lamports go to the destination. That's the escrow
+			// account, so the program can pay the deposit out of it and the rest goes to the refund address
+			// along with the escrow's rent
+			Self::close_temp_token_account(
+				token_program,
+				pda_temp_token_account,
+				escrow_account,
+				pda_account,
+				None,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+
+			debug_msg!("Paying the unwrapped SOL out to the taker...");
+			**escrow_account.lamports.borrow_mut() = escrow_account.lamports()
+				.checked_sub(escrow_info.deposited_amount)
+				.ok_or(EscrowError::AmountOverflow)?;
+			**takers_token_to_receive_account.lamports.borrow_mut() = takers_token_to_receive_account.lamports()
+				.checked_add(escrow_info.deposited_amount)
+				.ok_or(EscrowError::AmountOverflow)?;
+		} else if escrow_info.holds_tokens()
+			&& (!escrow_info.is_programmable_nft.get() || Self::is_pnft_token_account_closable(pda_temp_token_account)?)
+		{
+			Self::close_temp_token_account(
+				token_program,
+				pda_temp_token_account,
+				refund_account,
+				pda_account,
+				deposit_mint,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+		}
+
+		if let Some((treasury, system_program_account, treasury_bump_seed)) = refund_accounts {
+			debug_msg!("Calling the system program to refund the listing fee...");
+			invoke_signed(
+				&system_instruction::transfer(treasury.key, refund_account.key, escrow_info.listing_fee),
+				&[
+					treasury.clone(),
+					refund_account.clone(),
+					system_program_account.clone(),
+				],
+				&[&[TREASURY_SEED, &[treasury_bump_seed]]],
+			)?;
+			Self::release_listing_fee(config_account, escrow_info.listing_fee)?;
+		}
+
+		if let Some((receipt_account, system_program_account, rent, clock)) = receipt_accounts {
+			let (receipt_pubkey, receipt_bump_seed) = TradeReceipt::find_address(escrow_account.key, program_id);
+			Self::create_pda_account(
+				taker,
+				receipt_account,
+				&receipt_pubkey,
+				TradeReceipt::LEN,
+				&rent,
+				system_program_account,
+				&[RECEIPT_SEED, escrow_account.key.as_ref(), &[receipt_bump_seed]],
+				program_id,
+			)?;
+			let receipt_info = TradeReceipt {
+				is_initialized: true,
+				escrow_pubkey: *escrow_account.key,
+				maker_pubkey: escrow_info.initializer_pubkey,
+				taker_pubkey: *taker.key,
+				deposit_mint_pubkey: escrow_info.deposit_mint_pubkey,
+				receive_mint_pubkey: *sending_mint.mint.key,
+				deposit_amount: received_by_taker,
+				receive_amount: received_by_initializer,
+				slot: clock.slot,
+				unix_timestamp: clock.unix_timestamp,
+			};
+			TradeReceipt::pack(receipt_info, &mut receipt_account.try_borrow_mut_data()?)?;
+		}
+
+		if let Some(pair_stats_account) = pair_stats_account {
+			let mut pair_stats = PairStats::unpack(&pair_stats_account.try_borrow_data()?)?;
+			if pair_stats.deposit_mint_pubkey != escrow_info.deposit_mint_pubkey || pair_stats.receive_mint_pubkey != *sending_mint.mint.key {
+				return Err(EscrowError::WrongTrackerAccount.into());
+			}
+			pair_stats.record_fill(
+				received_by_taker,
+				received_by_initializer,
+				escrow_info.normalized_price(),
+				clock.unix_timestamp,
+			);
+			PairStats::pack(pair_stats, &mut pair_stats_account.try_borrow_mut_data()?)?;
+		}
+
+		// Lamports that changed hands, whichever side they were on
+		let mut sol_volume = escrow_info.deposited_lamports.saturating_add(escrow_info.expected_lamports);
+		if escrow_info.deposit_mint_pubkey == spl_token::native_mint::id() {
+			sol_volume = sol_volume.saturating_add(received_by_taker);
+		}
+		if *sending_mint.mint.key == spl_token::native_mint::id() {
+			sol_volume = sol_volume.saturating_add(received_by_initializer);
+		}
+
+		Self::emit(
+			&EscrowFilled {
+				escrow: *escrow_account.key,
+				initializer: escrow_info.initializer_pubkey,
+				taker: *taker.key,
+				deposit_mint: escrow_info.deposit_mint_pubkey,
+				receive_mint: *sending_mint.mint.key,
+				received_by_taker,
+				received_by_initializer,
+			},
+			trackers,
+		)?;
+
+		Self::settle_escrow(
+			&escrow_info,
+			escrow_account,
+			refund_account,
+			EscrowStatus::Completed,
+			trackers,
+			sol_volume,
+		)
 	}
 
-	fn process_exchange(accounts: &[AccountInfo], amount_expected_by_taker: u64, program_id: &Pubkey) -> ProgramResult {
+	fn process_cancel(
+		accounts: &[AccountInfo],
+		compressed_nft_proof: Option<CompressedNftProof>,
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
 		let account_info_iter = &mut accounts.iter();
-		let taker = next_account_info(account_info_iter)?;
+		let CancelAccounts {
+			initializer,
+			pda_temp_token_account,
+			refund_token_account,
+			escrow,
+			token_program,
+			pda_account,
+			refund_account,
+		} = CancelAccounts::next(account_info_iter, program_id)?;
+		let pda_temp_token_account = pda_temp_token_account.info();
+		let refund_token_account = refund_token_account.info();
+		let refund_account = refund_account.info();
+		let ProgramOwned { account: escrow_account, info: escrow_info } = escrow;
+
+		Self::leave_group(&escrow_info, account_info_iter, program_id)?;
+		let (deposit_mint, vault) = Self::next_deposit_mint(&escrow_info, pda_temp_token_account, account_info_iter)?;
+		let deposit_pnft = match deposit_mint {
+			Some(deposit_mint) if escrow_info.is_programmable_nft.get() => {
+				Some(Self::next_escrowed_pnft(deposit_mint.mint, initializer, account_info_iter)?)
+			},
+			_ => None,
+		};
+		let deposit_cnft = Self::next_escrowed_cnft(&escrow_info, compressed_nft_proof, token_program, account_info_iter)?;
+		let deposit_stake = Self::next_escrowed_stake(&escrow_info, token_program, pda_temp_token_account, account_info_iter)?;
+		if let Some(config_account) = Self::next_listing_fee_config(&escrow_info, account_info_iter, program_id)? {
+			Self::release_listing_fee(config_account, escrow_info.listing_fee)?;
+		}
 
-		if !taker.is_signer {
+		// A multisig initializer can't sign itself, its signers are whatever accounts are left
+		if !initializer.is_signer && !token::is_multisig_signed(initializer, account_info_iter.as_slice())? {
 			return Err(ProgramError::MissingRequiredSignature);
-		}	
+		}
 
-		let takers_sending_account = next_account_info(account_info_iter)?;
-		let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+		Self::cancel_escrow(
+			&escrow_info,
+			refund_account,
+			escrow_account,
+			pda_temp_token_account,
+			refund_token_account,
+			token_program,
+			pda_account,
+			deposit_mint,
+			vault,
+			deposit_pnft,
+			deposit_cnft,
+			deposit_stake,
+			EscrowStatus::Cancelled,
+			trackers,
+			program_id,
+		)
+	}
 
-		let pda_temp_token_account = next_account_info(account_info_iter)?;
-		let pda_temp_token_account_info = TokenAccount::unpack(&pda_temp_token_account.try_borrow_data()?)?;
-		let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+	fn process_sweep_surplus(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let SweepSurplusAccounts {
+			pda_temp_token_account,
+			initializers_surplus_token_account,
+			escrow,
+			token_program,
+			pda_account,
+			..
+		} = SweepSurplusAccounts::next(account_info_iter, program_id)?;
+		let initializers_surplus_token_account = initializers_surplus_token_account.info();
+		let ProgramOwned { account: escrow_account, info: escrow_info } = escrow;
+		let TokenAccountOf { account: pda_temp_token_account, info: pda_temp_token_account_info } = pda_temp_token_account;
 
-		// Amount validation, prevent frontrunning
-		if amount_expected_by_taker != pda_temp_token_account_info.amount {
-			return Err(EscrowError::ExpectedAmountMismatch.into()); // TODO why do we need .into?
+		let surplus = pda_temp_token_account_info.amount.saturating_sub(escrow_info.deposited_amount);
+		if surplus == 0 {
+			return Ok(());
 		}
 
-		let initializers_main_account = next_account_info(account_info_iter)?;
-		let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
-		let escrow_account = next_account_info(account_info_iter)?;
+		let deposit_mint = Self::next_mint(&pda_temp_token_account_info, account_info_iter)?;
+
+		Self::sweep_surplus(
+			&escrow_info,
+			pda_temp_token_account,
+			initializers_surplus_token_account,
+			token_program,
+			pda_account,
+			deposit_mint,
+			&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[escrow_info.authority_bump_seed]]],
+			surplus,
+		)
+	}
 
-		let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+	fn process_recover_stranded_assets(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
 
-		// Validate Escrow matches instruction 
-		if escrow_info.temp_token_account_pubkey != *pda_temp_token_account.key {
-			return Err(ProgramError::InvalidAccountData);
+		if !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let stranded_token_account = next_account_info(account_info_iter)?;
+		let destination_token_account = next_account_info(account_info_iter)?;
+		let token_program = next_account_info(account_info_iter)?;
+		let pda_account = next_account_info(account_info_iter)?;
+		let escrow_account = next_account_info(account_info_iter)?;
+
+		let (config, _clock) = Self::load_config(account_info_iter, program_id)?;
+		if config.admin != *admin.key {
+			return Err(ProgramError::MissingRequiredSignature);
 		}
-		if escrow_info.initializer_pubkey != *initializers_main_account.key {
+
+		// A pNFT deposit sits in its escrow PDA's associated token account, so the escrow has to be closed
+		// or settled. Other temp accounts have to be owned by the initializer when InitEscrow hands them to
+		// the PDA, and an associated token account of the PDA is owned by the PDA from the start
+		if Self::is_open_escrow(escrow_account, program_id)? {
 			return Err(ProgramError::InvalidAccountData);
 		}
-		if escrow_info.initializer_token_to_receive_account_pubkey != *initializers_token_to_receive_account.key {
+		let (pda, bump_seed) = Escrow::find_authority_address(escrow_account.key, program_id);
+		check_token_program(stranded_token_account, token_program)?;
+		let stranded_token_account_info = token::unpack_account(stranded_token_account)?;
+		if *stranded_token_account.key != get_associated_token_address(&pda, &stranded_token_account_info.mint, token_program.key) {
 			return Err(ProgramError::InvalidAccountData);
 		}
 
-		let token_program = next_account_info(account_info_iter)?;
+		let mint = Self::next_mint(&stranded_token_account_info, account_info_iter)?;
 
-		let transfer_to_initializer_ix = spl_token::instruction::transfer(  // TODO do the instructions in spl_token::instruction encompass all possible instructions in solana??
-			token_program.key, // token program ID
-        	takers_sending_account.key, // source pubkey
-        	initializers_token_to_receive_account.key, // destination pubkey
-        	taker.key,  // authority pubkey
-        	&[&taker.key],  // signer pubkeys
-        	escrow_info.expected_amount,
+		debug_msg!("Calling the token program to move the stranded tokens...");
+		Self::transfer_tokens(
+			token_program,
+			stranded_token_account,
+			destination_token_account,
+			pda_account,
+			mint,
+			stranded_token_account_info.amount,
+			&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
 		)?;
-		msg!("Calling the token program to transfer tokens to the escrow's initializer...");
-		invoke(
-			&transfer_to_initializer_ix,
-			&[
-				takers_sending_account.clone(),
-				initializers_token_to_receive_account.clone(),
-				taker.clone(),
-				token_program.clone()
-			]
+		Ok(())
+	}
+
+	fn process_crank_expired(
+		accounts: &[AccountInfo],
+		compressed_nft_proof: Option<CompressedNftProof>,
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		Self::force_cancel(accounts, compressed_nft_proof, EscrowStatus::Expired, trackers, program_id, |escrow_info, config, unix_timestamp| {
+			if !escrow_info.is_expired(unix_timestamp) {
+				return Err(EscrowError::EscrowNotExpired.into());
+			}
+			Ok(config.crank_bounty_lamports)
+		})
+	}
+
+	fn process_sweep_dormant(
+		accounts: &[AccountInfo],
+		compressed_nft_proof: Option<CompressedNftProof>,
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		Self::force_cancel(accounts, compressed_nft_proof, EscrowStatus::Cancelled, trackers, program_id, |escrow_info, config, unix_timestamp| {
+			if !escrow_info.is_dormant(config.dormancy_period, unix_timestamp) {
+				return Err(EscrowError::EscrowNotDormant.into());
+			}
+			Ok(0)
+		})
+	}
+
+	/// Cancel on behalf of the initializer, for CrankExpired and SweepDormant. `check` says whether the
+	/// escrow can be closed and returns the bounty for the caller, which comes out of the escrow account's
+	/// rent. The initializer gets the rest back as usual, a kept record is left with `status`. pNFT, cNFT
+	/// and stake account escrows take the same accounts as in Cancel, the caller pays for the pNFT's
+	fn force_cancel<F>(
+		accounts: &[AccountInfo],
+		compressed_nft_proof: Option<CompressedNftProof>,
+		status: EscrowStatus,
+		trackers: Trackers,
+		program_id: &Pubkey,
+		check: F,
+	) -> ProgramResult
+	where
+		F: Fn(&Escrow, &Config, i64) -> Result<u64, ProgramError>,
+	{
+		let account_info_iter = &mut accounts.iter();
+		let caller = next_account_info(account_info_iter)?;
+
+		if !caller.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let pda_temp_token_account = next_account_info(account_info_iter)?;
+		let refund_token_account = next_account_info(account_info_iter)?;
+		let escrow_account = next_account_info(account_info_iter)?;
+		let token_program = next_account_info(account_info_iter)?;
+		let pda_account = next_account_info(account_info_iter)?;
+		let refund_account = next_account_info(account_info_iter)?;
+		let (config_account, config, clock) = Self::load_config_accounts(account_info_iter, program_id)?;
+
+		check_owner(escrow_account, program_id)?;
+		let escrow_info = Escrow::unpack_versioned(&escrow_account.try_borrow_data()?)?;
+		let bounty = check(&escrow_info, &config, clock.unix_timestamp)?;
+		Self::release_listing_fee(config_account, escrow_info.listing_fee)?;
+
+		Self::leave_group(&escrow_info, account_info_iter, program_id)?;
+		let (deposit_mint, vault) = Self::next_deposit_mint(&escrow_info, pda_temp_token_account, account_info_iter)?;
+		let deposit_pnft = match deposit_mint {
+			Some(deposit_mint) if escrow_info.is_programmable_nft.get() => {
+				Some(Self::next_escrowed_pnft(deposit_mint.mint, caller, account_info_iter)?)
+			},
+			_ => None,
+		};
+		let deposit_cnft = Self::next_escrowed_cnft(&escrow_info, compressed_nft_proof, token_program, account_info_iter)?;
+		let deposit_stake = Self::next_escrowed_stake(&escrow_info, token_program, pda_temp_token_account, account_info_iter)?;
+
+		let record_reserve = Self::record_reserve(&escrow_info, escrow_account)?;
+		Self::pay_bounty(&escrow_info, escrow_account, refund_account, caller, bounty, record_reserve)?;
+
+		Self::cancel_escrow(
+			&escrow_info,
+			refund_account,
+			escrow_account,
+			pda_temp_token_account,
+			refund_token_account,
+			token_program,
+			pda_account,
+			deposit_mint,
+			vault,
+			deposit_pnft,
+			deposit_cnft,
+			deposit_stake,
+			status,
+			trackers,
+			program_id,
+		)
+	}
+
+	/// Pays force_cancel's caller up to `bounty` out of the escrow account's rent. The initializer's SOL
+	/// (see `lamports_deposit`) sits in the same account, it goes back to the refund address in full first
+	/// so none of the bounty comes out of it, and a kept record keeps `record_reserve`
+	fn pay_bounty(
+		escrow_info: &Escrow,
+		escrow_account: &AccountInfo,
+		refund_account: &AccountInfo,
+		caller: &AccountInfo,
+		bounty: u64,
+		record_reserve: u64,
+	) -> ProgramResult {
+		if escrow_info.deposited_lamports > 0 {
+			if escrow_info.refund_pubkey != *refund_account.key {
+				return Err(EscrowError::WrongRefundAccount.into());
+			}
+			debug_msg!("Giving the initializer's SOL back to the refund address...");
+			**escrow_account.lamports.borrow_mut() = escrow_account.lamports()
+				.checked_sub(escrow_info.deposited_lamports)
+				.ok_or(EscrowError::AmountOverflow)?;
+			**refund_account.lamports.borrow_mut() = refund_account.lamports()
+				.checked_add(escrow_info.deposited_lamports)
+				.ok_or(EscrowError::AmountOverflow)?;
+		}
+
+		let bounty = bounty.min(escrow_account.lamports().saturating_sub(record_reserve));
+		if bounty > 0 {
+			**caller.lamports.borrow_mut() = caller.lamports()
+				.checked_add(bounty)
+				.ok_or(EscrowError::AmountOverflow)?;
+			**escrow_account.lamports.borrow_mut() -= bounty;
+		}
+		Ok(())
+	}
+
+	fn process_create_group(accounts: &[AccountInfo], name: [u8; 32], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let maker = next_account_info(account_info_iter)?;
+
+		if !maker.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let group_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		let (group_pubkey, group_bump_seed) = EscrowGroup::find_address(maker.key, &name, program_id);
+		Self::create_pda_account(
+			maker,
+			group_account,
+			&group_pubkey,
+			EscrowGroup::LEN,
+			rent,
+			system_program_account,
+			&[GROUP_SEED, maker.key.as_ref(), &name, &[group_bump_seed]],
+			program_id,
+		)?;
+
+		let group_info = EscrowGroup {
+			is_initialized: true,
+			maker_pubkey: *maker.key,
+			name,
+			escrow_count: 0,
+		};
+		EscrowGroup::pack(group_info, &mut group_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_cancel_group(
+		accounts: &[AccountInfo],
+		compressed_nft_proofs: &[CompressedNftProof],
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let maker = next_account_info(account_info_iter)?;
+
+		if !maker.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let group_account = next_account_info(account_info_iter)?;
+		let mut group_info = Self::unpack_group(group_account, maker.key, program_id)?;
+
+		let token_program = next_account_info(account_info_iter)?;
+
+		// Whatever is left are (escrow, temp token account, refund token account, refund address, PDA)
+		// groups, plus the deposit's mint for token escrows and whatever else Cancel takes for the escrow.
+		// The compressed NFT escrows' proofs are used up in order
+		let mut compressed_nft_proofs = compressed_nft_proofs.iter();
+		while let Some(escrow_account) = account_info_iter.next() {
+			let pda_temp_token_account = next_account_info(account_info_iter)?;
+			let refund_token_account = next_account_info(account_info_iter)?;
+			let refund_account = next_account_info(account_info_iter)?;
+			let pda_account = next_account_info(account_info_iter)?;
+
+			check_owner(escrow_account, program_id)?;
+			let escrow_info = Escrow::unpack_versioned(&escrow_account.try_borrow_data()?)?;
+			let (deposit_mint, vault) = Self::next_deposit_mint(&escrow_info, pda_temp_token_account, account_info_iter)?;
+			let deposit_pnft = match deposit_mint {
+				Some(deposit_mint) if escrow_info.is_programmable_nft.get() => {
+					Some(Self::next_escrowed_pnft(deposit_mint.mint, maker, account_info_iter)?)
+				},
+				_ => None,
+			};
+			let compressed_nft_proof = match escrow_info.is_compressed_nft.get() {
+				true => compressed_nft_proofs.next().copied(),
+				false => None,
+			};
+			let deposit_cnft = Self::next_escrowed_cnft(&escrow_info, compressed_nft_proof, token_program, account_info_iter)?;
+			let deposit_stake = Self::next_escrowed_stake(&escrow_info, token_program, pda_temp_token_account, account_info_iter)?;
+			if let Some(config_account) = Self::next_listing_fee_config(&escrow_info, account_info_iter, program_id)? {
+				Self::release_listing_fee(config_account, escrow_info.listing_fee)?;
+			}
+			if escrow_info.group_pubkey != *group_account.key {
+				return Err(EscrowError::WrongGroup.into());
+			}
+			if escrow_info.initializer_pubkey != *maker.key {
+				return Err(EscrowError::WrongInitializer.into());
+			}
+
+			Self::cancel_escrow(
+				&escrow_info,
+				refund_account,
+				escrow_account,
+				pda_temp_token_account,
+				refund_token_account,
+				token_program,
+				pda_account,
+				deposit_mint,
+				vault,
+				deposit_pnft,
+				deposit_cnft,
+				deposit_stake,
+				EscrowStatus::Cancelled,
+				trackers,
+				program_id,
+			)?;
+			group_info.escrow_count = group_info.escrow_count.saturating_sub(1);
+		}
+
+		if group_info.escrow_count == 0 {
+			debug_msg!("Closing the group account...");
+			return Self::close_program_account(group_account, maker);
+		}
+		EscrowGroup::pack(group_info, &mut group_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_initialize_config(
+		accounts: &[AccountInfo],
+		admin: Pubkey,
+		listing_fee_lamports: u64,
+		heartbeat_period: i64,
+		crank_bounty_lamports: u64,
+		dormancy_period: i64,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let upgrade_authority = next_account_info(account_info_iter)?;
+
+		if !upgrade_authority.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let config_account = next_account_info(account_info_iter)?;
+		let program_data_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let clock = Self::next_clock(account_info_iter)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		// Otherwise whoever gets here first after a deploy would own the config
+		Self::check_upgrade_authority(program_data_account, upgrade_authority, program_id)?;
+
+		let (config_pubkey, config_bump_seed) = Config::find_address(program_id);
+		Self::create_pda_account(
+			upgrade_authority,
+			config_account,
+			&config_pubkey,
+			Config::LEN,
+			rent,
+			system_program_account,
+			&[CONFIG_SEED, &[config_bump_seed]],
+			program_id,
+		)?;
+
+		let config_info = Config {
+			is_initialized: true,
+			is_standby: false,
+			admin,
+			listing_fee_lamports,
+			last_heartbeat: clock.unix_timestamp,
+			heartbeat_period,
+			standby_config_pubkey: Pubkey::default(),
+			crank_bounty_lamports,
+			is_paused: false,
+			dormancy_period,
+			extension_policy: ExtensionPolicy::Allow,
+			disabled_features: 0,
+			pending_admin: Pubkey::default(),
+			top_level_exchange_only: false,
+			refundable_listing_fees: 0,
+		};
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_initialize_standby_config(
+		accounts: &[AccountInfo],
+		admin: Pubkey,
+		listing_fee_lamports: u64,
+		crank_bounty_lamports: u64,
+		dormancy_period: i64,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let primary_admin = next_account_info(account_info_iter)?;
+
+		if !primary_admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let config_account = next_account_info(account_info_iter)?;
+		let standby_config_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		let mut config_info = Self::unpack_config(config_account, program_id)?;
+		if config_info.is_standby || *config_account.key != Config::find_address(program_id).0 {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if config_info.admin != *primary_admin.key {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let (standby_config_pubkey, standby_config_bump_seed) = Config::find_standby_address(program_id);
+		Self::create_pda_account(
+			primary_admin,
+			standby_config_account,
+			&standby_config_pubkey,
+			Config::LEN,
+			rent,
+			system_program_account,
+			&[CONFIG_SEED, STANDBY_CONFIG_SEED, &[standby_config_bump_seed]],
+			program_id,
+		)?;
+
+		let standby_config_info = Config {
+			is_initialized: true,
+			is_standby: true,
+			admin,
+			listing_fee_lamports,
+			last_heartbeat: 0,
+			heartbeat_period: 0,
+			standby_config_pubkey: Pubkey::default(),
+			crank_bounty_lamports,
+			is_paused: false,
+			dormancy_period,
+			extension_policy: ExtensionPolicy::Allow,
+			disabled_features: 0,
+			pending_admin: Pubkey::default(),
+			top_level_exchange_only: false,
+			refundable_listing_fees: 0,
+		};
+		Config::pack(standby_config_info, &mut standby_config_account.try_borrow_mut_data()?)?;
+
+		config_info.standby_config_pubkey = standby_config_pubkey;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_update_config(
+		accounts: &[AccountInfo],
+		listing_fee_lamports: u64,
+		crank_bounty_lamports: u64,
+		dormancy_period: i64,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = next_account_info(account_info_iter)?;
+		let clock = Self::next_clock(account_info_iter)?;
+
+		let mut config_info = Self::unpack_admin_config(admin, config_account, program_id)?;
+		config_info.listing_fee_lamports = listing_fee_lamports;
+		config_info.crank_bounty_lamports = crank_bounty_lamports;
+		config_info.dormancy_period = dormancy_period;
+		if !config_info.is_standby {
+			config_info.last_heartbeat = clock.unix_timestamp;
+		}
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_nominate_admin(accounts: &[AccountInfo], new_admin: Pubkey, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = next_account_info(account_info_iter)?;
+
+		let mut config_info = Self::unpack_admin_config(admin, config_account, program_id)?;
+		config_info.pending_admin = new_admin;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		msg!("Nominated admin:");
+		new_admin.log();
+		Ok(())
+	}
+
+	fn process_accept_admin(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let new_admin = next_account_info(account_info_iter)?;
+		let config_account = next_account_info(account_info_iter)?;
+
+		let mut config_info = Self::unpack_config(config_account, program_id)?;
+		// Nobody is nominated while it's Pubkey::default(), which can't sign
+		if config_info.pending_admin != *new_admin.key || !new_admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		config_info.admin = config_info.pending_admin;
+		config_info.pending_admin = Pubkey::default();
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		msg!("New admin:");
+		new_admin.key.log();
+		Ok(())
+	}
+
+	fn process_set_listing_fee(accounts: &[AccountInfo], listing_fee_lamports: u64, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = next_account_info(account_info_iter)?;
+
+		let mut config_info = Self::unpack_admin_config(admin, config_account, program_id)?;
+		config_info.listing_fee_lamports = listing_fee_lamports;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		stack_msg!("Listing fee: {}", listing_fee_lamports);
+		Ok(())
+	}
+
+	fn process_withdraw_treasury(accounts: &[AccountInfo], lamports: u64, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let treasury = next_account_info(account_info_iter)?;
+		let destination = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+		let (config_account, config, _clock) = Self::load_config_accounts(account_info_iter, program_id)?;
+
+		if config.admin != *admin.key || !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let treasury_bump_seed = Self::check_treasury(treasury, system_program_account, program_id)?;
+		// Exchange still has to refund the listing fees of open escrows out of it
+		let refundable_listing_fees = Self::unpack_config(config_account, program_id)?.refundable_listing_fees;
+		let available = Self::withdrawable_lamports(treasury.lamports(), rent.minimum_balance(0), refundable_listing_fees);
+		if lamports > available {
+			return Err(ProgramError::InsufficientFunds);
+		}
+
+		invoke_signed(
+			&system_instruction::transfer(treasury.key, destination.key, lamports),
+			&[
+				treasury.clone(),
+				destination.clone(),
+				system_program_account.clone(),
+			],
+			&[&[TREASURY_SEED, &[treasury_bump_seed]]],
+		)?;
+
+		stack_msg!("Withdrew {} lamports from the treasury", lamports);
+		Ok(())
+	}
+
+	/// What WithdrawTreasury can take out of a treasury holding `lamports`, it keeps its rent and what it
+	/// still owes back
+	fn withdrawable_lamports(lamports: u64, rent_exempt_minimum: u64, refundable_listing_fees: u64) -> u64 {
+		lamports.saturating_sub(rent_exempt_minimum).saturating_sub(refundable_listing_fees)
+	}
+
+	fn process_heartbeat(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = next_account_info(account_info_iter)?;
+		let clock = Self::next_clock(account_info_iter)?;
+
+		let mut config_info = Self::unpack_admin_config(admin, config_account, program_id)?;
+		if config_info.is_standby {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		config_info.last_heartbeat = clock.unix_timestamp;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_set_paused(accounts: &[AccountInfo], paused: bool, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+		let (config, _clock) = Self::load_config(account_info_iter, program_id)?;
+
+		if config.admin != *admin.key || !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let mut config_info = Self::unpack_config(config_account, program_id)?;
+		config_info.is_paused = paused;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		stack_msg!("Paused: {}", paused);
+		Ok(())
+	}
+
+	fn process_set_features(accounts: &[AccountInfo], disabled_features: u16, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+		let (config, _clock) = Self::load_config(account_info_iter, program_id)?;
+
+		if config.admin != *admin.key || !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let mut config_info = Self::unpack_config(config_account, program_id)?;
+		config_info.disabled_features = disabled_features;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		stack_msg!("Disabled features: {:#06x}", disabled_features);
+		Ok(())
+	}
+
+	fn process_set_top_level_exchange(accounts: &[AccountInfo], top_level_only: bool, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+		let (config, _clock) = Self::load_config(account_info_iter, program_id)?;
+
+		if config.admin != *admin.key || !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let mut config_info = Self::unpack_config(config_account, program_id)?;
+		config_info.top_level_exchange_only = top_level_only;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		stack_msg!("Top level exchanges only: {}", top_level_only);
+		Ok(())
+	}
+
+	fn process_set_extension_policy(accounts: &[AccountInfo], policy: ExtensionPolicy, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let admin = next_account_info(account_info_iter)?;
+		let config_account = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+		let (config, _clock) = Self::load_config(account_info_iter, program_id)?;
+
+		if config.admin != *admin.key || !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let mut config_info = Self::unpack_config(config_account, program_id)?;
+		config_info.extension_policy = policy;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+		stack_msg!("Extension policy: {:?}", policy);
+		Ok(())
+	}
+
+	fn process_migrate_escrow(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let payer = next_account_info(account_info_iter)?;
+		let escrow_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		if !payer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		check_owner(escrow_account, program_id)?;
+		match escrow_account.data_len() {
+			Escrow::V0_LEN => {},
+			len if len >= Escrow::LEN => {
+				msg!("Escrow is on the current version already");
+				return Ok(());
+			},
+			_ => return Err(ProgramError::InvalidAccountData),
+		}
+
+		let escrow_info = Self::complete_v0_escrow(escrow_account, account_info_iter, program_id)?;
+		let missing_rent = rent.minimum_balance(Escrow::LEN).saturating_sub(escrow_account.lamports());
+		if missing_rent > 0 {
+			debug_msg!("Calling the system program to top up the escrow's rent...");
+			invoke(
+				&system_instruction::transfer(payer.key, escrow_account.key, missing_rent),
+				&[
+					payer.clone(),
+					escrow_account.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+		}
+		escrow_account.realloc(Escrow::LEN, false)?;
+		escrow_info.pack_versioned(&mut escrow_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	/// A v0 escrow with what it didn't keep filled in from the accounts MigrateEscrow takes after the
+	/// system program for it: the temp token account, the initializer's receive account and their mints.
+	/// The temp token account goes over from the program-wide PDA to the escrow's own, which is the only
+	/// one the rest of the program signs for
+	fn complete_v0_escrow<'a>(
+		escrow_account: &AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'_, AccountInfo<'a>>,
+		program_id: &Pubkey,
+	) -> Result<Escrow, ProgramError> {
+		let mut escrow_info = Escrow::unpack_versioned_unchecked(&escrow_account.try_borrow_data()?)?;
+		if !escrow_info.is_active() {
+			return Err(ProgramError::UninitializedAccount);
+		}
+		let temp_token_account = next_account_info(account_info_iter)?;
+		let token_to_receive_account = next_account_info(account_info_iter)?;
+		if escrow_info.temp_token_account_pubkey != *temp_token_account.key {
+			return Err(EscrowError::WrongVaultAccount.into());
+		}
+		if escrow_info.initializer_token_to_receive_account_pubkey != *token_to_receive_account.key {
+			return Err(EscrowError::WrongReceiveAccount.into());
+		}
+		let vault = token::unpack_account(temp_token_account)?;
+		let deposit_mint = Self::next_mint(&vault, account_info_iter)?;
+		let receive_mint = Self::next_mint(&token::unpack_account(token_to_receive_account)?, account_info_iter)?;
+		let token_program = next_account_info(account_info_iter)?;
+		let legacy_pda_account = next_account_info(account_info_iter)?;
+		check_token_program(temp_token_account, token_program)?;
+		let (legacy_pda, legacy_bump_seed) = Escrow::find_legacy_authority_address(program_id);
+		if *legacy_pda_account.key != legacy_pda || vault.owner != legacy_pda {
+			return Err(EscrowError::WrongPda.into());
+		}
+		let (pda, authority_bump_seed) = Escrow::find_authority_address(escrow_account.key, program_id);
+
+		debug_msg!("Calling the token program to hand the temp token account to the escrow's PDA...");
+		token::check_token_program_id(token_program.key)?;
+		cpi::invoke_signed(
+			token_program.key,
+			&[AccountMeta::new(*temp_token_account.key, false), AccountMeta::new_readonly(legacy_pda, true)],
+			&token::set_owner_data(&pda),
+			&[temp_token_account.clone(), legacy_pda_account.clone(), token_program.clone()],
+			&[],
+			&[&[ESCROW_SEED, &[legacy_bump_seed]]],
+		)?;
+
+		escrow_info.version = ESCROW_VERSION;
+		// v0 deposited all of the temp token account
+		escrow_info.deposited_amount = vault.amount;
+		escrow_info.deposit_mint_pubkey = *deposit_mint.mint.key;
+		escrow_info.deposit_decimals = deposit_mint.decimals;
+		escrow_info.receive_mint_pubkey = *receive_mint.mint.key;
+		escrow_info.expected_decimals = receive_mint.decimals;
+		escrow_info.authority_bump_seed = authority_bump_seed;
+		// When it was made isn't known, so it's dormant a dormancy period from now at the earliest
+		escrow_info.last_active_at = Clock::get()?.unix_timestamp;
+		Ok(escrow_info)
+	}
+
+	fn process_realloc_escrow(accounts: &[AccountInfo], new_len: u32, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let authority = next_account_info(account_info_iter)?;
+		let escrow_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		if !authority.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		check_owner(escrow_account, program_id)?;
+
+		// Older layouts have to be migrated first, load only takes the current one
+		let old_len = escrow_account.data_len();
+		let escrow_info = *Escrow::load(&escrow_account.try_borrow_data()?)?;
+		if escrow_info.initializer_pubkey != *authority.key {
+			let (config, _clock) = Self::load_config(account_info_iter, program_id)?;
+			if config.admin != *authority.key {
+				return Err(ProgramError::MissingRequiredSignature);
+			}
+		}
+
+		let new_len = new_len as usize;
+		if new_len <= old_len || new_len - old_len > MAX_PERMITTED_DATA_INCREASE {
+			return Err(ProgramError::InvalidArgument);
+		}
+		let missing_rent = rent.minimum_balance(new_len).saturating_sub(escrow_account.lamports());
+		if missing_rent > 0 {
+			debug_msg!("Calling the system program to top up the escrow's rent...");
+			invoke(
+				&system_instruction::transfer(authority.key, escrow_account.key, missing_rent),
+				&[
+					authority.clone(),
+					escrow_account.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+		}
+		escrow_account.realloc(new_len, true)?;
+
+		stack_msg!("Escrow account is {} bytes now", new_len);
+		Ok(())
+	}
+
+	fn process_close_escrow_record(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let initializer = next_account_info(account_info_iter)?;
+		let escrow_account = next_account_info(account_info_iter)?;
+		let refund_account = next_account_info(account_info_iter)?;
+
+		// A multisig initializer can't sign itself, its signers are whatever accounts are left
+		if !initializer.is_signer && !token::is_multisig_signed(initializer, account_info_iter.as_slice())? {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		check_owner(escrow_account, program_id)?;
+
+		let escrow_info = Escrow::unpack_versioned_unchecked(&escrow_account.try_borrow_data()?)?;
+		if !matches!(escrow_info.status()?, EscrowStatus::Completed | EscrowStatus::Cancelled | EscrowStatus::Expired) {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if escrow_info.initializer_pubkey != *initializer.key || escrow_info.refund_pubkey != *refund_account.key {
+			return Err(ProgramError::InvalidAccountData);
+		}
+
+		debug_msg!("Closing the escrow record...");
+		Self::close_program_account(escrow_account, refund_account)
+	}
+
+	fn process_initialize_stats(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let payer = next_account_info(account_info_iter)?;
+		let stats_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		if !payer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let (stats_pubkey, stats_bump_seed) = Stats::find_address(program_id);
+		Self::create_pda_account(
+			payer,
+			stats_account,
+			&stats_pubkey,
+			Stats::LEN,
+			rent,
+			system_program_account,
+			&[STATS_SEED, &[stats_bump_seed]],
+			program_id,
+		)?;
+		let stats_info = Stats {
+			is_initialized: true,
+			escrows_created: 0,
+			open_escrows: 0,
+			fills: 0,
+			cancellations: 0,
+			sol_volume: 0,
+		};
+		Stats::pack(stats_info, &mut stats_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_initialize_pair_stats(
+		accounts: &[AccountInfo],
+		deposit_mint: Pubkey,
+		receive_mint: Pubkey,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let payer = next_account_info(account_info_iter)?;
+		let pair_stats_account = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		if !payer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let (pair_stats_pubkey, pair_stats_bump_seed) = PairStats::find_address(&deposit_mint, &receive_mint, program_id);
+		Self::create_pda_account(
+			payer,
+			pair_stats_account,
+			&pair_stats_pubkey,
+			PairStats::LEN,
+			rent,
+			system_program_account,
+			&[PAIR_STATS_SEED, deposit_mint.as_ref(), receive_mint.as_ref(), &[pair_stats_bump_seed]],
+			program_id,
+		)?;
+		let pair_stats_info = PairStats {
+			is_initialized: true,
+			deposit_mint_pubkey: deposit_mint,
+			receive_mint_pubkey: receive_mint,
+			fills: 0,
+			deposit_volume: 0,
+			receive_volume: 0,
+			last_price: 0,
+			last_fill_at: 0,
+			window_start: 0,
+			window_fills: 0,
+			window_deposit_volume: 0,
+			window_receive_volume: 0,
+		};
+		PairStats::pack(pair_stats_info, &mut pair_stats_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_initialize_registry_page(accounts: &[AccountInfo], index: u32, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let payer = next_account_info(account_info_iter)?;
+		let registry_page = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		if !payer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let (registry_page_pubkey, registry_page_bump_seed) = RegistryPage::find_address(index, program_id);
+		Self::create_pda_account(
+			payer,
+			registry_page,
+			&registry_page_pubkey,
+			RegistryPage::LEN,
+			rent,
+			system_program_account,
+			&[REGISTRY_SEED, &index.to_le_bytes(), &[registry_page_bump_seed]],
+			program_id,
+		)?;
+		let page_info = RegistryPage {
+			is_initialized: true,
+			index,
+			escrows: Vec::new(),
+		};
+		RegistryPage::pack(page_info, &mut registry_page.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_initialize_index(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let initializer = next_account_info(account_info_iter)?;
+		let index = next_account_info(account_info_iter)?;
+		let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+		let system_program_account = next_account_info(account_info_iter)?;
+
+		if !initializer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		let (index_pubkey, index_bump_seed) = InitializerIndex::find_address(initializer.key, program_id);
+		Self::create_pda_account(
+			initializer,
+			index,
+			&index_pubkey,
+			InitializerIndex::LEN,
+			rent,
+			system_program_account,
+			&[INDEX_SEED, initializer.key.as_ref(), &[index_bump_seed]],
+			program_id,
+		)?;
+		let index_info = InitializerIndex {
+			is_initialized: true,
+			initializer_pubkey: *initializer.key,
+			escrows: Vec::new(),
+		};
+		InitializerIndex::pack(index_info, &mut index.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	fn process_set_frozen(accounts: &[AccountInfo], frozen: bool, program_id: &Pubkey) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let initializer = next_account_info(account_info_iter)?;
+		let escrow_account = next_account_info(account_info_iter)?;
+		let clock = Self::next_clock(account_info_iter)?;
+
+		if !initializer.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		check_owner(escrow_account, program_id)?;
+
+		let mut escrow_info = Escrow::unpack_versioned(&escrow_account.try_borrow_data()?)?;
+		if escrow_info.initializer_pubkey != *initializer.key {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		escrow_info.is_frozen = frozen.into();
+		escrow_info.last_active_at = clock.unix_timestamp;
+		escrow_info.pack_versioned(&mut escrow_account.try_borrow_mut_data()?)?;
+
+		Ok(())
+	}
+
+	/// Sends the deposit back to the escrow's refund address, then closes the temp token account and the
+	/// escrow account with the rent going there too. Checking the initializer is up to the caller
+	#[allow(clippy::too_many_arguments)]
+	fn cancel_escrow<'a>(
+		escrow_info: &Escrow,
+		refund_account: &AccountInfo<'a>,
+		escrow_account: &AccountInfo<'a>,
+		pda_temp_token_account: &AccountInfo<'a>,
+		refund_token_account: &AccountInfo<'a>,
+		token_program: &AccountInfo<'a>,
+		pda_account: &AccountInfo<'a>,
+		deposit_mint: Option<MintAccounts<'_, 'a>>,
+		vault: Option<TokenAccount>,
+		deposit_pnft: Option<PnftAccounts<'_, 'a>>,
+		deposit_cnft: Option<(CnftAccounts<'_, 'a>, Leaf)>,
+		deposit_stake: Option<StakeAccounts<'_, 'a>>,
+		status: EscrowStatus,
+		trackers: Trackers,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		if escrow_info.temp_token_account_pubkey != *pda_temp_token_account.key {
+			return Err(EscrowError::WrongVaultAccount.into());
+		}
+		check_authority(escrow_info, escrow_account, pda_account, program_id)?;
+		// The refund address goes in as the refund token account for SOL, otherwise these are all different
+		check_distinct(&[pda_temp_token_account, refund_token_account, escrow_account])?;
+		let bump_seed = escrow_info.authority_bump_seed;
+		if escrow_info.refund_pubkey != *refund_account.key {
+			return Err(EscrowError::WrongRefundAccount.into());
+		}
+		// Only the token deposits' vaults can hold more than was deposited, they get all of it back
+		Self::emit(
+			&EscrowCancelled {
+				escrow: *escrow_account.key,
+				initializer: escrow_info.initializer_pubkey,
+				refund: escrow_info.refund_pubkey,
+				deposit_mint: escrow_info.deposit_mint_pubkey,
+				refunded_amount: vault.as_ref().map_or(escrow_info.deposited_amount, |vault| vault.amount),
+				status,
+			},
+			trackers,
+		)?;
+		if escrow_info.is_native_sol.get() {
+			// SOL goes straight back to the refund address, there's no token account on this side
+			if *token_program.key != system_program::id() || refund_token_account.key != refund_account.key {
+				return Err(EscrowError::WrongRefundAccount.into());
+			}
+			debug_msg!("Calling the system program to give the SOL back to the refund address...");
+			Self::transfer_from_sol_vault(
+				pda_temp_token_account,
+				refund_account,
+				token_program,
+				escrow_account.key,
+				pda_temp_token_account.lamports(),
+				program_id,
+			)?;
+
+			return Self::settle_escrow(escrow_info, escrow_account, refund_account, status, trackers, 0);
+		}
+		if escrow_info.is_compressed_nft.get() {
+			// The cNFT goes straight back to the refund address
+			let (deposit_cnft, leaf) = deposit_cnft.ok_or(ProgramError::NotEnoughAccountKeys)?;
+			if refund_token_account.key != refund_account.key {
+				return Err(EscrowError::WrongRefundAccount.into());
+			}
+			debug_msg!("Calling Bubblegum to give the compressed NFT back to the refund address...");
+			Self::transfer_cnft(
+				&deposit_cnft,
+				pda_temp_token_account,
+				pda_account,
+				pda_account,
+				refund_account,
+				&leaf,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+
+			return Self::settle_escrow(escrow_info, escrow_account, refund_account, status, trackers, 0);
+		}
+		if escrow_info.is_stake_account.get() {
+			// The refund address gets both authorities
+			let deposit_stake = deposit_stake.ok_or(ProgramError::NotEnoughAccountKeys)?;
+			if refund_token_account.key != refund_account.key {
+				return Err(EscrowError::WrongRefundAccount.into());
+			}
+			debug_msg!("Calling the stake program to give the stake account back to the refund address...");
+			Self::authorize_stake(
+				&deposit_stake,
+				pda_temp_token_account,
+				pda_account,
+				refund_account.key,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+
+			return Self::settle_escrow(escrow_info, escrow_account, refund_account, status, trackers, 0);
+		}
+		check_token_program(pda_temp_token_account, token_program)?;
+		if escrow_info.is_programmable_nft.get() {
+			// The refund address's token account is its associated token account, Token Metadata creates
+			// it if needed
+			let deposit_pnft = deposit_pnft.ok_or(ProgramError::NotEnoughAccountKeys)?;
+			debug_msg!("Calling Token Metadata to give the pNFT back to the refund address...");
+			Self::transfer_pnft(
+				&deposit_pnft,
+				token_program,
+				pda_temp_token_account,
+				pda_account,
+				refund_token_account,
+				refund_account,
+				pda_account,
+				&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+			)?;
+			if Self::is_pnft_token_account_closable(pda_temp_token_account)? {
+				Self::close_temp_token_account(
+					token_program,
+					pda_temp_token_account,
+					refund_account,
+					pda_account,
+					None,
+					&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+				)?;
+			}
+
+			return Self::settle_escrow(escrow_info, escrow_account, refund_account, status, trackers, 0);
+		}
+		if token::unpack_account(refund_token_account)?.owner != escrow_info.refund_pubkey {
+			return Err(EscrowError::WrongRefundAccount.into());
+		}
+		// Unpacked with the deposit's mint, nothing moved out of it since
+		let vault = vault.ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+		debug_msg!("Calling the token program to give the deposit back to the refund address...");
+		Self::transfer_tokens(
+			token_program,
+			pda_temp_token_account,
+			refund_token_account,
+			pda_account,
+			deposit_mint.ok_or(ProgramError::NotEnoughAccountKeys)?,
+			vault.amount,
+			&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+		)?;
+
+		Self::close_temp_token_account(
+			token_program,
+			pda_temp_token_account,
+			refund_account,
+			pda_account,
+			deposit_mint,
+			&[&[ESCROW_SEED, escrow_account.key.as_ref(), &[bump_seed]]],
+		)?;
+
+		Self::settle_escrow(escrow_info, escrow_account, refund_account, status, trackers, 0)
+	}
+
+	/// Sends `surplus` (anything in the temp account above the recorded deposit) to a token account of the initializer
+	#[allow(clippy::too_many_arguments)]
+	fn sweep_surplus<'a>(
+		escrow_info: &Escrow,
+		pda_temp_token_account: &AccountInfo<'a>,
+		initializers_surplus_token_account: &AccountInfo<'a>,
+		token_program: &AccountInfo<'a>,
+		pda_account: &AccountInfo<'a>,
+		deposit_mint: MintAccounts<'_, 'a>,
+		signer_seeds: &[&[&[u8]]],
+		surplus: u64,
+	) -> ProgramResult {
+		if token::unpack_account(initializers_surplus_token_account)?.owner != escrow_info.initializer_pubkey {
+			return Err(EscrowError::WrongSurplusAccount.into());
+		}
+
+		debug_msg!("Calling the token program to return surplus tokens to the initializer...");
+		Self::transfer_tokens(
+			token_program,
+			pda_temp_token_account,
+			initializers_surplus_token_account,
+			pda_account,
+			deposit_mint,
+			surplus,
+			signer_seeds,
+		)?;
+		Ok(())
+	}
+
+	/// Moves `amount` from `source` to `destination` with transfer_checked and returns how much actually
+	/// arrived there, which is less than `amount` for Token-2022 mints with a transfer fee. Only those have
+	/// the destination read before and after, for any other mint all of it arrives. The authority signs
+	/// with `signer_seeds` if it's the PDA
+	#[allow(clippy::too_many_arguments)]
+	fn transfer_tokens<'a>(
+		token_program: &AccountInfo<'a>,
+		source: &AccountInfo<'a>,
+		destination: &AccountInfo<'a>,
+		authority: &AccountInfo<'a>,
+		MintAccounts { mint, hook_accounts, decimals, has_transfer_fee }: MintAccounts<'_, 'a>,
+		amount: u64,
+		signer_seeds: &[&[&[u8]]],
+	) -> Result<u64, ProgramError> {
+		let balance_before = if has_transfer_fee { token::unpack_account(destination)?.amount } else { 0 };
+
+		token::check_token_program_id(token_program.key)?;
+		cpi::invoke_signed(
+			token_program.key,
+			&[
+				AccountMeta::new(*source.key, false),
+				AccountMeta::new_readonly(*mint.key, false),
+				AccountMeta::new(*destination.key, false),
+				AccountMeta::new_readonly(*authority.key, true),
+			],
+			&token::transfer_checked_data(amount, decimals),
+			&[source.clone(), mint.clone(), destination.clone(), authority.clone(), token_program.clone()],
+			hook_accounts,
+			signer_seeds,
+		)?;
+
+		if !has_transfer_fee {
+			return Ok(amount);
+		}
+		token::unpack_account(destination)?.amount
+			.checked_sub(balance_before)
+			.ok_or_else(|| EscrowError::AmountOverflow.into())
+	}
+
+	/// Pays lamports out of a native SOL escrow's vault, which the program signs for as its PDA
+	fn transfer_from_sol_vault<'a>(
+		sol_vault: &AccountInfo<'a>,
+		destination: &AccountInfo<'a>,
+		system_program_account: &AccountInfo<'a>,
+		escrow: &Pubkey,
+		lamports: u64,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let (sol_vault_pubkey, sol_vault_bump_seed) = Escrow::find_sol_vault_address(escrow, program_id);
+		if *sol_vault.key != sol_vault_pubkey {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		invoke_signed(
+			&system_instruction::transfer(sol_vault.key, destination.key, lamports),
+			&[
+				sol_vault.clone(),
+				destination.clone(),
+				system_program_account.clone(),
+			],
+			&[&[SOL_VAULT_SEED, escrow.as_ref(), &[sol_vault_bump_seed]]],
+		)
+	}
+
+	/// Creates the wSOL account [b"wsol", escrow] already owned by the escrow PDA, then wraps `lamports` of
+	/// the initializer's SOL into it
+	#[allow(clippy::too_many_arguments)]
+	fn wrap_sol_deposit<'a>(
+		initializer: &AccountInfo<'a>,
+		payer: &AccountInfo<'a>,
+		wsol_account: &AccountInfo<'a>,
+		native_mint: &AccountInfo<'a>,
+		escrow_account: &AccountInfo<'a>,
+		pda: &Pubkey,
+		rent: &Rent,
+		token_program: &AccountInfo<'a>,
+		system_program_account: &AccountInfo<'a>,
+		lamports: u64,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let (wsol_pubkey, wsol_bump_seed) = Escrow::find_wsol_address(escrow_account.key, program_id);
+		if *wsol_account.key != wsol_pubkey
+			|| *native_mint.key != spl_token::native_mint::id()
+			|| *token_program.key != spl_token::id()
+		{
+			return Err(ProgramError::InvalidAccountData);
+		}
+
+		debug_msg!("Calling the system program to create the wSOL account...");
+		invoke_signed(
+			&system_instruction::create_account(
+				payer.key,
+				wsol_account.key,
+				rent.minimum_balance(spl_token::state::Account::LEN),
+				spl_token::state::Account::LEN as u64,
+				token_program.key,
+			),
+			&[
+				payer.clone(),
+				wsol_account.clone(),
+				system_program_account.clone(),
+			],
+			&[&[WSOL_SEED, escrow_account.key.as_ref(), &[wsol_bump_seed]]],
+		)?;
+
+		debug_msg!("Calling the token program to initialize the wSOL account...");
+		invoke(
+			&token::initialize_account3(token_program.key, wsol_account.key, native_mint.key, pda)?,
+			&[wsol_account.clone(), native_mint.clone(), token_program.clone()],
+		)?;
+
+		debug_msg!("Calling the system program to wrap the deposit...");
+		invoke(
+			&system_instruction::transfer(initializer.key, wsol_account.key, lamports),
+			&[
+				initializer.clone(),
+				wsol_account.clone(),
+				system_program_account.clone(),
+			],
+		)?;
+		invoke(
+			&token::sync_native(token_program.key, wsol_account.key)?,
+			&[wsol_account.clone(), token_program.clone()],
+		)
+	}
+
+	/// Creates the vault [b"vault", escrow] as a token account of `mint` already owned by the escrow PDA.
+	/// Token-2022 accounts are as big as the mint's extensions need, Token-2022 says how big that is
+	#[allow(clippy::too_many_arguments)]
+	fn create_vault<'a>(
+		payer: &AccountInfo<'a>,
+		vault: &AccountInfo<'a>,
+		mint: &AccountInfo<'a>,
+		escrow_account: &AccountInfo<'a>,
+		pda: &Pubkey,
+		rent: &Rent,
+		token_program: &AccountInfo<'a>,
+		system_program_account: &AccountInfo<'a>,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		let (vault_pubkey, vault_bump_seed) = Escrow::find_vault_address(escrow_account.key, program_id);
+		if *vault.key != vault_pubkey || !token::is_token_program(token_program.key) {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let len = if *token_program.key == token::token_2022::id() {
+			invoke(
+				&token::get_account_data_size(token_program.key, mint.key)?,
+				&[mint.clone(), token_program.clone()],
+			)?;
+			match get_return_data() {
+				Some((program, data)) if program == *token_program.key && data.len() == 8 => {
+					u64::from_le_bytes(data.try_into().unwrap()) as usize
+				},
+				_ => return Err(ProgramError::InvalidAccountData),
+			}
+		} else {
+			spl_token::state::Account::LEN
+		};
+
+		debug_msg!("Calling the system program to create the vault...");
+		invoke_signed(
+			&system_instruction::create_account(
+				payer.key,
+				vault.key,
+				rent.minimum_balance(len),
+				len as u64,
+				token_program.key,
+			),
+			&[
+				payer.clone(),
+				vault.clone(),
+				system_program_account.clone(),
+			],
+			&[&[VAULT_SEED, escrow_account.key.as_ref(), &[vault_bump_seed]]],
+		)?;
+
+		debug_msg!("Calling the token program to initialize the vault...");
+		invoke(
+			&token::initialize_account3(token_program.key, vault.key, mint.key, pda)?,
+			&[vault.clone(), mint.clone(), token_program.clone()],
+		)
+	}
+
+	/// Closes the PDA's temp token account, sending its rent to `destination`. Fees Token-2022 withheld in
+	/// it from the deposit have to be harvested to the mint first or the close fails. The PDA signs with
+	/// `signer_seeds`
+	fn close_temp_token_account<'a>(
+		token_program: &AccountInfo<'a>,
+		pda_temp_token_account: &AccountInfo<'a>,
+		destination: &AccountInfo<'a>,
+		pda_account: &AccountInfo<'a>,
+		deposit_mint: Option<MintAccounts<'_, 'a>>,
+		signer_seeds: &[&[&[u8]]],
+	) -> ProgramResult {
+		if let Some(MintAccounts { mint: deposit_mint, .. }) = deposit_mint {
+			if token::withheld_amount(pda_temp_token_account)? > 0 {
+				debug_msg!("Calling the token program to harvest withheld fees to the mint...");
+				invoke(
+					&token::harvest_withheld_tokens_to_mint(token_program.key, deposit_mint.key, &[pda_temp_token_account.key])?,
+					&[deposit_mint.clone(), pda_temp_token_account.clone(), token_program.clone()],
+				)?;
+			}
+		}
+
+		let close_pdas_temp_acc_ix = token::close_account(
+			token_program.key,
+			pda_temp_token_account.key,
+			destination.key,
+			pda_account.key,
 		)?;
+		debug_msg!("Calling the token program to close pda's temp account...");
+		invoke_signed(
+			&close_pdas_temp_acc_ix,
+			&[
+				pda_temp_token_account.clone(),
+				destination.clone(),
+				pda_account.clone(),
+				token_program.clone(),
+			],
+			signer_seeds,
+		)
+	}
 
-		let pda_account = next_account_info(account_info_iter)?;
-		let transfer_to_taker_ix = spl_token::instruction::transfer(
-		    token_program.key,
-		    pda_temp_token_account.key,
-		    takers_token_to_receive_account.key,
-		    &pda,
-		    &[&pda],
-		    pda_temp_token_account_info.amount,
-		)?;
-		msg!("Calling the token program to transfer tokens to the taker...");
+	/// Reads the PnftAccounts after a pNFT's mint and metadata account. The rule set's accounts are only
+	/// there if the metadata names one
+	fn next_pnft_accounts<'r, 'a>(
+		mint: &'r AccountInfo<'a>,
+		metadata: &'r AccountInfo<'a>,
+		metadata_info: &metadata::Metadata,
+		payer: &'r AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<PnftAccounts<'r, 'a>, ProgramError> {
+		let edition = next_account_info(account_info_iter)?;
+		let owner_token_record = next_account_info(account_info_iter)?;
+		let destination_token_record = next_account_info(account_info_iter)?;
+		let sysvar_instructions = next_account_info(account_info_iter)?;
+		let associated_token_program = next_account_info(account_info_iter)?;
+		let system_program = next_account_info(account_info_iter)?;
+		let token_metadata_program = next_account_info(account_info_iter)?;
+		// Token Metadata checks the rest itself
+		if *token_metadata_program.key != metadata::mpl_token_metadata::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+
+		let authorization_rules = match metadata_info.rule_set {
+			Some(rule_set) => {
+				let authorization_rules_program = next_account_info(account_info_iter)?;
+				let authorization_rules = next_account_info(account_info_iter)?;
+				if *authorization_rules.key != rule_set {
+					return Err(ProgramError::InvalidAccountData);
+				}
+				Some((authorization_rules_program, authorization_rules))
+			},
+			None => None,
+		};
+
+		Ok(PnftAccounts {
+			mint,
+			metadata,
+			edition,
+			owner_token_record,
+			destination_token_record,
+			payer,
+			sysvar_instructions,
+			associated_token_program,
+			system_program,
+			token_metadata_program,
+			authorization_rules,
+		})
+	}
+
+	/// Same as next_pnft_accounts for the pNFT a pNFT escrow's token account holds, starting with its
+	/// metadata account. `mint` is the deposit's mint, already checked against the token account
+	fn next_escrowed_pnft<'r, 'a>(
+		mint: &'r AccountInfo<'a>,
+		payer: &'r AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<PnftAccounts<'r, 'a>, ProgramError> {
+		let metadata = next_account_info(account_info_iter)?;
+		let metadata_info = metadata::unpack_metadata(metadata, mint.key)?;
+		Self::next_pnft_accounts(mint, metadata, &metadata_info, payer, account_info_iter)
+	}
+
+	/// Moves a pNFT with Token Metadata's Transfer. `authority` signs with `signer_seeds` if it's the PDA
+	#[allow(clippy::too_many_arguments)]
+	fn transfer_pnft<'a>(
+		pnft: &PnftAccounts<'_, 'a>,
+		token_program: &AccountInfo<'a>,
+		source: &AccountInfo<'a>,
+		source_owner: &AccountInfo<'a>,
+		destination: &AccountInfo<'a>,
+		destination_owner: &AccountInfo<'a>,
+		authority: &AccountInfo<'a>,
+		signer_seeds: &[&[&[u8]]],
+	) -> ProgramResult {
+		let transfer_ix = metadata::transfer(
+			&metadata::TransferAccounts {
+				token: source.key,
+				token_owner: source_owner.key,
+				destination: destination.key,
+				destination_owner: destination_owner.key,
+				mint: pnft.mint.key,
+				metadata: pnft.metadata.key,
+				edition: pnft.edition.key,
+				owner_token_record: pnft.owner_token_record.key,
+				destination_token_record: pnft.destination_token_record.key,
+				authority: authority.key,
+				payer: pnft.payer.key,
+				token_program: token_program.key,
+				authorization_rules: pnft
+					.authorization_rules
+					.map(|(authorization_rules_program, authorization_rules)| (authorization_rules_program.key, authorization_rules.key)),
+			},
+			1,
+		);
+
+		// Without a rule set the program stands in for its accounts, the CPI doesn't mind account infos it
+		// doesn't use. Token Metadata creates the token records, so this can't go through cpi::invoke_signed
+		let (authorization_rules_program, authorization_rules) =
+			pnft.authorization_rules.unwrap_or((pnft.token_metadata_program, pnft.token_metadata_program));
+		let account_infos = [
+			source.clone(),
+			source_owner.clone(),
+			destination.clone(),
+			destination_owner.clone(),
+			pnft.mint.clone(),
+			pnft.metadata.clone(),
+			pnft.edition.clone(),
+			pnft.owner_token_record.clone(),
+			pnft.destination_token_record.clone(),
+			authority.clone(),
+			pnft.payer.clone(),
+			pnft.system_program.clone(),
+			pnft.sysvar_instructions.clone(),
+			token_program.clone(),
+			pnft.associated_token_program.clone(),
+			pnft.token_metadata_program.clone(),
+			authorization_rules_program.clone(),
+			authorization_rules.clone(),
+		];
+		invoke_signed(&transfer_ix, &account_infos, signer_seeds)
+	}
+
+	/// Reads the CnftAccounts after `bubblegum_program`, with `proof_len` proof accounts at the end
+	fn next_cnft_accounts<'r, 'a>(
+		bubblegum_program: &'r AccountInfo<'a>,
+		proof_len: u8,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<CnftAccounts<'r, 'a>, ProgramError> {
+		// Bubblegum checks the rest itself
+		if *bubblegum_program.key != bubblegum::mpl_bubblegum::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+		let tree_authority = next_account_info(account_info_iter)?;
+		let log_wrapper = next_account_info(account_info_iter)?;
+		let compression_program = next_account_info(account_info_iter)?;
+		let system_program = next_account_info(account_info_iter)?;
+		let proof = account_info_iter
+			.as_slice()
+			.get(..proof_len as usize)
+			.ok_or(ProgramError::NotEnoughAccountKeys)?;
+		if proof_len > 0 {
+			account_info_iter.nth(proof_len as usize - 1);
+		}
+
+		Ok(CnftAccounts {
+			bubblegum_program,
+			tree_authority,
+			log_wrapper,
+			compression_program,
+			system_program,
+			proof,
+		})
+	}
+
+	/// For compressed NFT escrows, reads the CnftAccounts for the proof given with the instruction and
+	/// returns them with the escrowed leaf. None for other escrows
+	fn next_escrowed_cnft<'r, 'a>(
+		escrow_info: &Escrow,
+		compressed_nft_proof: Option<CompressedNftProof>,
+		bubblegum_program: &'r AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<Option<(CnftAccounts<'r, 'a>, Leaf)>, ProgramError> {
+		if !escrow_info.is_compressed_nft.get() {
+			return Ok(None);
+		}
+		let (root, proof_len) = compressed_nft_proof.ok_or(EscrowError::InvalidInstruction)?;
+		let cnft = Self::next_cnft_accounts(bubblegum_program, proof_len, account_info_iter)?;
+		Ok(Some((cnft, escrow_info.compressed_nft_leaf(root))))
+	}
+
+	/// Moves a compressed NFT with Bubblegum's Transfer. `leaf_owner` signs with `signer_seeds` if it's the PDA
+	#[allow(clippy::too_many_arguments)]
+	fn transfer_cnft<'a>(
+		cnft: &CnftAccounts<'_, 'a>,
+		merkle_tree: &AccountInfo<'a>,
+		leaf_owner: &AccountInfo<'a>,
+		leaf_delegate: &AccountInfo<'a>,
+		new_leaf_owner: &AccountInfo<'a>,
+		leaf: &Leaf,
+		signer_seeds: &[&[&[u8]]],
+	) -> ProgramResult {
+		cpi::invoke_signed(
+			&bubblegum::mpl_bubblegum::id(),
+			&[
+				AccountMeta::new_readonly(*cnft.tree_authority.key, false),
+				AccountMeta::new_readonly(*leaf_owner.key, true),
+				AccountMeta::new_readonly(*leaf_delegate.key, false),
+				AccountMeta::new_readonly(*new_leaf_owner.key, false),
+				AccountMeta::new(*merkle_tree.key, false),
+				AccountMeta::new_readonly(bubblegum::spl_noop::id(), false),
+				AccountMeta::new_readonly(bubblegum::spl_account_compression::id(), false),
+				AccountMeta::new_readonly(system_program::id(), false),
+			],
+			&bubblegum::transfer_data(leaf),
+			&[
+				cnft.tree_authority.clone(),
+				leaf_owner.clone(),
+				leaf_delegate.clone(),
+				new_leaf_owner.clone(),
+				merkle_tree.clone(),
+				cnft.log_wrapper.clone(),
+				cnft.compression_program.clone(),
+				cnft.system_program.clone(),
+				cnft.bubblegum_program.clone(),
+			],
+			cnft.proof,
+			signer_seeds,
+		)
+	}
+
+	/// Reads the StakeAccounts of `stake_account` after `stake_program`, the custodian only if the
+	/// stake account's lockup is in force
+	fn next_stake_accounts<'r, 'a>(
+		stake_program: &'r AccountInfo<'a>,
+		stake_account: &AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<StakeAccounts<'r, 'a>, ProgramError> {
+		if *stake_program.key != stake::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+		let clock_sysvar = next_account_info(account_info_iter)?;
+		let clock = Clock::from_account_info(clock_sysvar)?;
+		let stake_info = stake::unpack_stake(stake_account)?;
+		let custodian = if stake_info.is_lockup_in_force(&clock) {
+			let custodian = next_account_info(account_info_iter)?;
+			if *custodian.key != stake_info.lockup_custodian {
+				return Err(ProgramError::InvalidAccountData);
+			}
+			Some(custodian)
+		} else {
+			None
+		};
+
+		Ok(StakeAccounts {
+			stake_program,
+			clock_sysvar,
+			custodian,
+		})
+	}
+
+	/// For stake account escrows, reads the StakeAccounts of the escrowed stake account. None for other escrows
+	fn next_escrowed_stake<'r, 'a>(
+		escrow_info: &Escrow,
+		stake_program: &'r AccountInfo<'a>,
+		stake_account: &AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<Option<StakeAccounts<'r, 'a>>, ProgramError> {
+		if !escrow_info.is_stake_account.get() {
+			return Ok(None);
+		}
+		Ok(Some(Self::next_stake_accounts(stake_program, stake_account, account_info_iter)?))
+	}
+
+	/// Makes `new_authority` the staker and withdrawer of `stake_account`. `authority` holds both now and
+	/// signs with `signer_seeds` if it's the PDA
+	fn authorize_stake<'a>(
+		stake_accounts: &StakeAccounts<'_, 'a>,
+		stake_account: &AccountInfo<'a>,
+		authority: &AccountInfo<'a>,
+		new_authority: &Pubkey,
+		signer_seeds: &[&[&[u8]]],
+	) -> ProgramResult {
+		for stake_authorize in [stake::StakeAuthorize::Staker, stake::StakeAuthorize::Withdrawer] {
+			// The custodian only has a say in who withdraws
+			let custodian = match stake_authorize {
+				stake::StakeAuthorize::Withdrawer => stake_accounts.custodian,
+				stake::StakeAuthorize::Staker => None,
+			};
+			cpi::invoke_signed(
+				&stake::id(),
+				&[
+					AccountMeta::new(*stake_account.key, false),
+					AccountMeta::new_readonly(*stake_accounts.clock_sysvar.key, false),
+					AccountMeta::new_readonly(*authority.key, true),
+				],
+				&stake::authorize_data(new_authority, stake_authorize),
+				&[
+					stake_account.clone(),
+					stake_accounts.clock_sysvar.clone(),
+					authority.clone(),
+					stake_accounts.stake_program.clone(),
+				],
+				custodian.map_or(&[][..], std::slice::from_ref),
+				signer_seeds,
+			)?;
+		}
+		Ok(())
+	}
+
+	/// Whether a pNFT token account emptied by Token Metadata's Transfer is still there to be closed.
+	/// Token Metadata may have closed it itself, or left it frozen
+	fn is_pnft_token_account_closable(token_account: &AccountInfo) -> Result<bool, ProgramError> {
+		Ok(token_account.lamports() > 0 && !token::unpack_account(token_account)?.is_frozen())
+	}
+
+	/// For collection offers, reads the metadata account of the NFT the taker sends and makes sure it's in
+	/// the escrow's collection and goes to the initializer's associated token account for it
+	fn check_collection_nft<'a>(
+		escrow_info: &Escrow,
+		takers_sending_account: &AccountInfo<'a>,
+		initializers_token_to_receive_account: &AccountInfo<'a>,
+		sending_token_program: &AccountInfo<'a>,
+		nft_mint: &AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<AccountInfo<'a>>,
+	) -> ProgramResult {
+		let nft_metadata = next_account_info(account_info_iter)?;
+		Self::check_nft(takers_sending_account, nft_mint, nft_metadata)?;
+
+		// Anyone can put any collection in their metadata, only a verified one means the collection has the NFT
+		let metadata_info = metadata::unpack_metadata(nft_metadata, nft_mint.key)?;
+		if metadata_info.is_programmable()
+			|| !matches!(metadata_info.collection, Some(collection) if collection.verified && collection.key == escrow_info.collection_pubkey)
+		{
+			return Err(EscrowError::NotInCollection.into());
+		}
+
+		let initializers_nft_account = get_associated_token_address(
+			&escrow_info.initializer_pubkey,
+			nft_mint.key,
+			sending_token_program.key,
+		);
+		if *initializers_token_to_receive_account.key != initializers_nft_account {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		Ok(())
+	}
+
+	/// Makes sure `mint` is the mint of `token_account` and returns its decimals
+	fn unpack_mint_of(token_account: &AccountInfo, mint: &AccountInfo) -> Result<u8, ProgramError> {
+		if token::unpack_account(token_account)?.mint != *mint.key {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		Ok(token::unpack_mint(mint)?.decimals)
+	}
+
+	/// Makes sure `token_account` holds exactly one token of a 0 decimals mint with Metaplex metadata
+	fn check_nft(token_account: &AccountInfo, mint: &AccountInfo, metadata: &AccountInfo) -> ProgramResult {
+		let token_account_info = token::unpack_account(token_account)?;
+		if token_account_info.mint != *mint.key {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if token_account_info.amount != 1 || token::unpack_mint(mint)?.decimals != 0 {
+			return Err(EscrowError::NotAnNft.into());
+		}
+		metadata::check_metadata(metadata, mint.key)
+	}
+
+	/// Fails with a clear error for token accounts the token program won't move tokens in or out of,
+	/// `token_account_info` is the account unpacked
+	fn check_transferable(token_account: &AccountInfo, token_account_info: &TokenAccount) -> ProgramResult {
+		if token_account_info.is_frozen() {
+			return Err(EscrowError::TokenAccountFrozen.into());
+		}
+		if token::is_non_transferable(token_account)? {
+			return Err(EscrowError::NonTransferableMint.into());
+		}
+		Ok(())
+	}
+
+	/// Reads the deposit's mint like next_mint, with the vault as it was unpacked for it. Both None for
+	/// escrows that don't hold their deposit in a token account
+	fn next_deposit_mint<'r, 'a>(
+		escrow_info: &Escrow,
+		pda_temp_token_account: &AccountInfo<'a>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<(Option<MintAccounts<'r, 'a>>, Option<TokenAccount>), ProgramError> {
+		if !escrow_info.holds_tokens() {
+			return Ok((None, None));
+		}
+		let vault = token::unpack_account(pda_temp_token_account)?;
+		Ok((Some(Self::next_mint(&vault, account_info_iter)?), Some(vault)))
+	}
+
+	/// Reads the mint of a token account, `token_account_info` is the account unpacked, from
+	/// `account_info_iter`. For mints with a transfer hook the hook program, its extra account metas account
+	/// and the extra accounts listed there come right after the mint
+	fn next_mint<'r, 'a>(
+		token_account_info: &TokenAccount,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<MintAccounts<'r, 'a>, ProgramError> {
+		let mint = next_account_info(account_info_iter)?;
+		if token_account_info.mint != *mint.key {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let decimals = token::unpack_mint(mint)?.decimals;
+		let has_transfer_fee = token::has_transfer_fee(mint)?;
+
+		let hook_program_id = match token::get_transfer_hook_program_id(mint)? {
+			Some(hook_program_id) => hook_program_id,
+			None => {
+				return Ok(MintAccounts {
+					mint,
+					hook_accounts: &[],
+					decimals,
+					has_transfer_fee,
+				})
+			},
+		};
+		let remaining = account_info_iter.as_slice();
+		let (hook_program, extra_account_metas) = match remaining {
+			[hook_program, extra_account_metas, ..] => (hook_program, extra_account_metas),
+			_ => return Err(ProgramError::NotEnoughAccountKeys),
+		};
+		if *hook_program.key != hook_program_id
+			|| *extra_account_metas.key != token::find_extra_account_metas_address(mint.key, &hook_program_id)
+		{
+			return Err(ProgramError::InvalidAccountData);
+		}
+		// Token-2022 checks the extra accounts themselves against the list when it calls the hook
+		let hook_accounts_len = 2 + token::extra_account_metas_count(extra_account_metas)?;
+		let hook_accounts = remaining.get(..hook_accounts_len).ok_or(ProgramError::NotEnoughAccountKeys)?;
+		account_info_iter.nth(hook_accounts_len - 1);
+
+		Ok(MintAccounts {
+			mint,
+			hook_accounts,
+			decimals,
+			has_transfer_fee,
+		})
+	}
+
+	/// Closes a settled escrow's account into `destination`, or with `retain_history` leaves it as a
+	/// record marked `status` that only keeps its rent. A counted escrow is counted as settled with
+	/// `sol_volume` lamports, a registered one taken out of its registry page
+	fn settle_escrow(
+		escrow_info: &Escrow,
+		escrow_account: &AccountInfo,
+		destination: &AccountInfo,
+		status: EscrowStatus,
+		trackers: Trackers,
+		sol_volume: u64,
+	) -> ProgramResult {
+		if escrow_info.is_registered.get() {
+			let registry_page = trackers.registry_page.ok_or(ProgramError::NotEnoughAccountKeys)?;
+			Self::update_registry_page(registry_page, |page| {
+				if !page.remove(escrow_account.key) {
+					return Err(EscrowError::WrongTrackerAccount.into());
+				}
+				Ok(())
+			})?;
+		}
+
+		if escrow_info.is_indexed.get() {
+			let index = trackers.index.ok_or(ProgramError::NotEnoughAccountKeys)?;
+			Self::update_index(index, &escrow_info.initializer_pubkey, |index| {
+				if !index.remove(escrow_account.key) {
+					return Err(EscrowError::WrongTrackerAccount.into());
+				}
+				Ok(())
+			})?;
+		}
+
+		if escrow_info.is_counted.get() {
+			Self::update_stats(trackers.stats.ok_or(ProgramError::NotEnoughAccountKeys)?, |stats| {
+				stats.open_escrows = stats.open_escrows.saturating_sub(1);
+				if status == EscrowStatus::Completed {
+					stats.fills = stats.fills.saturating_add(1);
+				} else {
+					stats.cancellations = stats.cancellations.saturating_add(1);
+				}
+				stats.sol_volume = stats.sol_volume.saturating_add(sol_volume);
+			})?;
+		}
+
+		if !escrow_info.retain_history.get() {
+			debug_msg!("Closing the escrow account...");
+			return Self::close_program_account(escrow_account, destination);
+		}
+
+		debug_msg!("Keeping the escrow account as a record...");
+		let excess = escrow_account.lamports().saturating_sub(Self::record_reserve(escrow_info, escrow_account)?);
+		**destination.lamports.borrow_mut() = destination.lamports()
+			.checked_add(excess)
+			.ok_or(EscrowError::AmountOverflow)?;
+		**escrow_account.lamports.borrow_mut() -= excess;
+
+		let mut record = *escrow_info;
+		record.status = status as u8;
+		record.pack_versioned(&mut escrow_account.try_borrow_mut_data()?)
+	}
+
+	/// Lamports a settled escrow keeps as a record, its rent exemption with `retain_history` and none otherwise
+	fn record_reserve(escrow_info: &Escrow, escrow_account: &AccountInfo) -> Result<u64, ProgramError> {
+		if !escrow_info.retain_history.get() {
+			return Ok(0);
+		}
+		Ok(Rent::get()?.minimum_balance(escrow_account.data_len()))
+	}
+
+	/// The event authority and the program account if they're the last accounts, see event.rs
+	fn split_event_cpi_accounts<'r, 'a>(
+		accounts: &'r [AccountInfo<'a>],
+		program_id: &Pubkey,
+	) -> Result<(&'r [AccountInfo<'a>], Option<EventCpiAccounts<'r, 'a>>), ProgramError> {
+		match accounts {
+			[rest @ .., event_authority, program] if program.key == program_id => {
+				let (event_authority_pubkey, bump_seed) = event::find_event_authority_address(program_id);
+				if *event_authority.key != event_authority_pubkey {
+					return Err(EscrowError::WrongEventAuthority.into());
+				}
+				Ok((rest, Some(EventCpiAccounts { event_authority, program, bump_seed })))
+			},
+			_ => Ok((accounts, None)),
+		}
+	}
+
+	/// Logs `event`, or makes it a CPI if the instruction brought the accounts for that
+	fn emit<E: Event>(event: &E, trackers: Trackers) -> ProgramResult {
+		match trackers.event_cpi {
+			Some(EventCpiAccounts { event_authority, program, bump_seed }) => event.emit_cpi(event_authority, program, bump_seed),
+			None => {
+				event.emit();
+				Ok(())
+			},
+		}
+	}
+
+	/// The program's own event CPI, there's nothing to do but make sure it is one: only the program can
+	/// sign for its event authority, so nobody else can put events in its inner instructions
+	fn process_event_cpi(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+		let event_authority = next_account_info(&mut accounts.iter())?;
+		if !event_authority.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		if *event_authority.key != event::find_event_authority_address(program_id).0 {
+			return Err(EscrowError::WrongEventAuthority.into());
+		}
+		Ok(())
+	}
+
+	/// The last of `accounts` if it's a T account of this program, and the rest. Stats, registry and index
+	/// accounts are only ever created at their PDAs, so that's all there is to check
+	fn split_trailing_account<'r, 'a, T: Discriminator>(
+		accounts: &'r [AccountInfo<'a>],
+		program_id: &Pubkey,
+	) -> (&'r [AccountInfo<'a>], Option<&'r AccountInfo<'a>>) {
+		match accounts.split_last() {
+			Some((last, rest))
+				if last.owner == program_id
+					&& matches!(last.try_borrow_data(), Ok(data) if has_discriminator::<T>(&data)) =>
+			{
+				(rest, Some(last))
+			},
+			_ => (accounts, None),
+		}
+	}
+
+	fn update_registry_page<F: FnOnce(&mut RegistryPage) -> ProgramResult>(registry_page: &AccountInfo, update: F) -> ProgramResult {
+		let mut page = RegistryPage::unpack(&registry_page.try_borrow_data()?)?;
+		update(&mut page)?;
+		RegistryPage::pack(page, &mut registry_page.try_borrow_mut_data()?)
+	}
+
+	/// Updates the index account `index`, which has to be `initializer`'s
+	fn update_index<F: FnOnce(&mut InitializerIndex) -> ProgramResult>(index: &AccountInfo, initializer: &Pubkey, update: F) -> ProgramResult {
+		let mut index_info = InitializerIndex::unpack(&index.try_borrow_data()?)?;
+		if index_info.initializer_pubkey != *initializer {
+			return Err(EscrowError::WrongTrackerAccount.into());
+		}
+		update(&mut index_info)?;
+		InitializerIndex::pack(index_info, &mut index.try_borrow_mut_data()?)
+	}
+
+	fn update_stats<F: FnOnce(&mut Stats)>(stats_account: &AccountInfo, update: F) -> ProgramResult {
+		let mut stats = Stats::unpack(&stats_account.try_borrow_data()?)?;
+		update(&mut stats);
+		Stats::pack(stats, &mut stats_account.try_borrow_mut_data()?)
+	}
+
+	/// Counts `listing_fee` in the primary config's refundable listing fees, so WithdrawTreasury leaves it
+	/// for Exchange to refund. Nothing's written without a refundable fee, so only escrows paying one need
+	/// the primary config writable
+	fn reserve_listing_fee(config_account: &AccountInfo, listing_fee: u64) -> ProgramResult {
+		if !REFUND_LISTING_FEE_ON_EXCHANGE || listing_fee == 0 {
+			return Ok(());
+		}
+		let mut config_info = Config::unpack(&config_account.try_borrow_data()?)?;
+		config_info.refundable_listing_fees = config_info.refundable_listing_fees
+			.checked_add(listing_fee)
+			.ok_or(EscrowError::AmountOverflow)?;
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)
+	}
+
+	/// Takes an escrow's listing fee back out of the refundable ones once it's refunded, or once the
+	/// escrow is cancelled and the treasury keeps it
+	fn release_listing_fee(config_account: &AccountInfo, listing_fee: u64) -> ProgramResult {
+		if !REFUND_LISTING_FEE_ON_EXCHANGE || listing_fee == 0 {
+			return Ok(());
+		}
+		let mut config_info = Config::unpack(&config_account.try_borrow_data()?)?;
+		config_info.refundable_listing_fees = config_info.refundable_listing_fees.saturating_sub(listing_fee);
+		Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)
+	}
+
+	/// The primary config for release_listing_fee, for the instructions that cancel escrows and don't take
+	/// it anyway. Only escrows with a refundable listing fee take it
+	fn next_listing_fee_config<'r, 'a>(
+		escrow_info: &Escrow,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+		program_id: &Pubkey,
+	) -> Result<Option<&'r AccountInfo<'a>>, ProgramError> {
+		if !REFUND_LISTING_FEE_ON_EXCHANGE || escrow_info.listing_fee == 0 {
+			return Ok(None);
+		}
+		let config_account = next_account_info(account_info_iter)?;
+		check_owner(config_account, program_id)?;
+		if *config_account.key != Config::find_address(program_id).0 {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		Ok(Some(config_account))
+	}
+
+	/// Moves all lamports of a program owned account to `destination` and wipes its data. The runtime only
+	/// drops the account after the transaction, until then anyone could send it lamports again, so the
+	/// zeroed data gets the closed discriminator to keep it from being read or initialized again
+	fn close_program_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+		**destination.lamports.borrow_mut() = destination.lamports()
+			.checked_add(account.lamports())
+			.ok_or(EscrowError::AmountOverflow)?;
+		**account.lamports.borrow_mut() = 0;
+		let mut data = account.try_borrow_mut_data()?;
+		data.fill(0);
+		if let Some(discriminator) = data.get_mut(..DISCRIMINATOR_LEN) {
+			discriminator.copy_from_slice(&CLOSED_ACCOUNT_DISCRIMINATOR);
+		}
+		*data = &mut [];
+
+		Ok(())
+	}
+
+	/// Whether `account` still holds an active escrow of this program. Filled and cancelled escrows get
+	/// their data wiped (and are gone entirely after the transaction) or are kept as settled records,
+	/// so they aren't open anymore
+	fn is_open_escrow(account: &AccountInfo, program_id: &Pubkey) -> Result<bool, ProgramError> {
+		let len = account.data_len();
+		if account.owner != program_id
+			|| (len < Escrow::LEN && len != Escrow::V0_LEN)
+		{
+			return Ok(false);
+		}
+		Ok(Escrow::unpack_versioned_unchecked(&account.try_borrow_data()?)?.is_active())
+	}
+
+	/// Fails with PredecessorNotSettled unless `account` is the record of an escrow Exchange filled.
+	/// Cancelled, swept and cranked escrows don't count, and neither do closed accounts, which don't say
+	/// how they were settled. InitEscrow only chains behind escrows that retain their history for this
+	fn check_predecessor_filled(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+		if account.owner != program_id || account.data_len() < Escrow::LEN {
+			return Err(EscrowError::PredecessorNotSettled.into());
+		}
+		match Escrow::unpack_versioned_unchecked(&account.try_borrow_data()?)?.status()? {
+			EscrowStatus::Completed => Ok(()),
+			_ => Err(EscrowError::PredecessorNotSettled.into()),
+		}
+	}
+
+	/// A grouped escrow has to take its group along when it's closed outside of CancelGroup, so the
+	/// member count stays right. Reads the group account from `account_info_iter` if there is one
+	fn leave_group<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+		escrow_info: &Escrow,
+		account_info_iter: &mut I,
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		if escrow_info.group_pubkey == Pubkey::default() {
+			return Ok(());
+		}
+		let group_account = next_account_info(account_info_iter)?;
+		if *group_account.key != escrow_info.group_pubkey {
+			return Err(EscrowError::WrongGroup.into());
+		}
+		let mut group_info = Self::unpack_group(group_account, &escrow_info.initializer_pubkey, program_id)?;
+		group_info.escrow_count = group_info.escrow_count.saturating_sub(1);
+		EscrowGroup::pack(group_info, &mut group_account.try_borrow_mut_data()?)
+	}
+
+	/// Unpacks an EscrowGroup and makes sure it's a real group belonging to `maker`
+	fn unpack_group(group_account: &AccountInfo, maker: &Pubkey, program_id: &Pubkey) -> Result<EscrowGroup, ProgramError> {
+		check_owner(group_account, program_id)?;
+		let group_info = EscrowGroup::unpack(&group_account.try_borrow_data()?)?;
+		if group_info.maker_pubkey != *maker {
+			return Err(EscrowError::WrongGroup.into());
+		}
+		Ok(group_info)
+	}
+
+	/// `shared_system_program` if ExchangeV2 took it up front, otherwise the next account
+	fn next_system_program<'r, 'a>(
+		shared_system_program: Option<&'r AccountInfo<'a>>,
+		account_info_iter: &mut std::slice::Iter<'r, AccountInfo<'a>>,
+	) -> Result<&'r AccountInfo<'a>, ProgramError> {
+		match shared_system_program {
+			Some(system_program_account) => Ok(system_program_account),
+			None => next_account_info(account_info_iter),
+		}
+	}
+
+	/// The clock from Clock::get. Instructions used to take the clock sysvar, it's skipped if it's the next
+	/// account so older clients that still pass it keep working
+	fn next_clock<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(account_info_iter: &mut I) -> Result<Clock, ProgramError> {
+		if matches!(account_info_iter.clone().next(), Some(account) if sysvar::clock::check_id(account.key)) {
+			account_info_iter.next();
+		}
+		Clock::get()
+	}
+
+	/// Reads the config account, plus the standby config right after it if the config names one.
+	/// Returns whichever of the two configs is currently in effect, and the clock
+	fn load_config<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+		account_info_iter: &mut I,
+		program_id: &Pubkey,
+	) -> Result<(Config, Clock), ProgramError> {
+		let (_config_account, config_info, clock) = Self::load_config_accounts(account_info_iter, program_id)?;
+		Ok((config_info, clock))
+	}
+
+	/// load_config that also returns the primary config account, for the instructions that write to it
+	fn load_config_accounts<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+		account_info_iter: &mut I,
+		program_id: &Pubkey,
+	) -> Result<(&'a AccountInfo<'b>, Config, Clock), ProgramError> {
+		let config_account = next_account_info(account_info_iter)?;
+		let clock = Self::next_clock(account_info_iter)?;
+
+		let config_info = Self::unpack_config(config_account, program_id)?;
+		if config_info.is_standby || *config_account.key != Config::find_address(program_id).0 {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if config_info.standby_config_pubkey == Pubkey::default() {
+			return Ok((config_account, config_info, clock));
+		}
+
+		let standby_config_account = next_account_info(account_info_iter)?;
+		if *standby_config_account.key != config_info.standby_config_pubkey {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if config_info.is_heartbeat_expired(clock.unix_timestamp) {
+			msg!("Primary config missed its heartbeat, using the standby config");
+			return Ok((config_account, Self::unpack_config(standby_config_account, program_id)?, clock));
+		}
+		Ok((config_account, config_info, clock))
+	}
+
+	/// Fails if the primary config says the program is paused or has any of `features` turned off.
+	/// Instructions that get checked must pass the primary config, so it can't be left out to get around it
+	fn check_primary_config(config_account: Option<&AccountInfo>, features: u16, program_id: &Pubkey) -> Result<Config, ProgramError> {
+		let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+		if *config_account.key != Config::find_address(program_id).0 {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		let config_info = Self::unpack_config(config_account, program_id)?;
+		if config_info.is_paused {
+			return Err(EscrowError::ProgramPaused.into());
+		}
+		if !config_info.has_features(features) {
+			return Err(EscrowError::FeatureDisabled.into());
+		}
+		Ok(config_info)
+	}
+
+	/// Makes sure the instruction being processed is one of the transaction's own and not a CPI. If
+	/// another program had called us, the transaction's current instruction would be that program's
+	fn check_top_level(instructions_sysvar: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+		let current_index = sysvar::instructions::load_current_index_checked(instructions_sysvar)?;
+		let current = sysvar::instructions::load_instruction_at_checked(current_index as usize, instructions_sysvar)?;
+		if current.program_id != *program_id {
+			return Err(EscrowError::NotTopLevel.into());
+		}
+		Ok(())
+	}
+
+	/// The FEATURE_* bits `instruction` uses
+	fn required_features(instruction: &EscrowInstruction) -> u16 {
+		let feature = |used: bool, feature: u16| if used { feature } else { 0 };
+		match instruction {
+			EscrowInstruction::InitEscrow {
+				native_sol_deposit,
+				wrap_sol_deposit,
+				nft,
+				collection,
+				compressed_nft,
+				lamports_deposit,
+				expected_lamports,
+				stake_account,
+				retain_history,
+				..
+			} => {
+				feature(*nft || collection.is_some() || compressed_nft.is_some(), FEATURE_NFT)
+					| feature(
+						native_sol_deposit.is_some() || wrap_sol_deposit.is_some() || lamports_deposit.is_some() || expected_lamports.is_some(),
+						FEATURE_NATIVE_SOL,
+					)
+					| feature(*stake_account, FEATURE_STAKE)
+					| feature(*retain_history, FEATURE_RETAIN_HISTORY)
+			},
+			EscrowInstruction::BatchInitEscrow { .. } => FEATURE_BATCH_INIT,
+			EscrowInstruction::Exchange { unwrap_sol, receipt, .. } | EscrowInstruction::ExchangeV2 { unwrap_sol, receipt, .. } => {
+				feature(*unwrap_sol, FEATURE_NATIVE_SOL) | feature(*receipt, FEATURE_RECEIPTS)
+			},
+			_ => 0,
+		}
+	}
+
+	/// The extension policy of the primary config, which has to be `config_account`
+	fn load_extension_policy(config_account: Option<&AccountInfo>, program_id: &Pubkey) -> Result<ExtensionPolicy, ProgramError> {
+		let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+		if *config_account.key != Config::find_address(program_id).0 {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		Ok(Self::unpack_config(config_account, program_id)?.extension_policy)
+	}
+
+	/// Applies `policy` to a permanent delegate on any of `mints` and a CPI guard on any of `token_accounts`,
+	/// the token accounts the program moves tokens out of for their owner. The Nones are skipped, so the
+	/// callers don't need a Vec of the ones they have
+	fn check_extensions(policy: ExtensionPolicy, token_accounts: &[Option<&AccountInfo>], mints: &[Option<&AccountInfo>]) -> ProgramResult {
+		if policy == ExtensionPolicy::Allow {
+			return Ok(());
+		}
+		for mint in mints.iter().flatten() {
+			if token::has_permanent_delegate(mint)? {
+				if policy == ExtensionPolicy::Deny {
+					return Err(EscrowError::ExtensionNotAllowed.into());
+				}
+				msg!("Warning: this mint has a permanent delegate");
+				mint.key.log();
+			}
+		}
+		for token_account in token_accounts.iter().flatten() {
+			if token::is_cpi_guarded(token_account)? {
+				if policy == ExtensionPolicy::Deny {
+					return Err(EscrowError::ExtensionNotAllowed.into());
+				}
+				msg!("Warning: this token account has its CPI guard on");
+				token_account.key.log();
+			}
+		}
+		Ok(())
+	}
+
+	fn unpack_config(config_account: &AccountInfo, program_id: &Pubkey) -> Result<Config, ProgramError> {
+		check_owner(config_account, program_id)?;
+		Config::unpack(&config_account.try_borrow_data()?)
+	}
+
+	/// Unpacks a (primary or standby) config, making sure `admin` is its admin and signed
+	fn unpack_admin_config(admin: &AccountInfo, config_account: &AccountInfo, program_id: &Pubkey) -> Result<Config, ProgramError> {
+		let config_info = Self::unpack_config(config_account, program_id)?;
+		if config_info.admin != *admin.key || !admin.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		Ok(config_info)
+	}
+
+	/// Makes sure `upgrade_authority` is the upgrade authority of this program, read from its ProgramData account
+	fn check_upgrade_authority(program_data_account: &AccountInfo, upgrade_authority: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+		let (program_data_pubkey, _) = Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+		if *program_data_account.key != program_data_pubkey {
+			return Err(ProgramError::InvalidAccountData);
+		}
+
+		// UpgradeableLoaderState::ProgramData is a u32 enum tag (3), the u64 deploy slot, then an Option<Pubkey>
+		let data = program_data_account.try_borrow_data()?;
+		let authority = match data.get(..45) {
+			Some([3, 0, 0, 0, _, _, _, _, _, _, _, _, 1, authority @ ..]) => Pubkey::new(authority),
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		if authority != *upgrade_authority.key {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		Ok(())
+	}
+
+	/// Creates `wallet`'s associated token account for `mint`, `associated_token_account` has to be its address
+	#[allow(clippy::too_many_arguments)]
+	fn create_associated_token_account<'a>(
+		payer: &AccountInfo<'a>,
+		associated_token_account: &AccountInfo<'a>,
+		wallet: &AccountInfo<'a>,
+		mint: &AccountInfo<'a>,
+		token_program: &AccountInfo<'a>,
+		associated_token_program_account: &AccountInfo<'a>,
+		system_program_account: &AccountInfo<'a>,
+	) -> ProgramResult {
+		if *associated_token_program_account.key != associated_token_program::id()
+			|| *system_program_account.key != system_program::id()
+			|| !token::is_token_program(token_program.key)
+		{
+			return Err(ProgramError::IncorrectProgramId);
+		}
+
+		debug_msg!("Calling the associated token account program to create a receive account...");
+		invoke(
+			&token::create_associated_token_account(payer.key, wallet.key, mint.key, token_program.key),
+			&[
+				payer.clone(),
+				associated_token_account.clone(),
+				wallet.clone(),
+				mint.clone(),
+				system_program_account.clone(),
+				token_program.clone(),
+				associated_token_program_account.clone(),
+			],
+		)
+	}
+
+	/// Creates a program owned account at a PDA of this program, `seeds` have to include the bump seed.
+	/// Anyone can send lamports to a PDA before it's created, which would make create_account fail, so
+	/// an address that already has some is topped up, allocated and assigned instead
+	#[allow(clippy::too_many_arguments)]
+	fn create_pda_account<'a>(
+		payer: &AccountInfo<'a>,
+		new_account: &AccountInfo<'a>,
+		expected_pubkey: &Pubkey,
+		len: usize,
+		rent: &Rent,
+		system_program_account: &AccountInfo<'a>,
+		seeds: &[&[u8]],
+		program_id: &Pubkey,
+	) -> ProgramResult {
+		if *new_account.key != *expected_pubkey {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if *system_program_account.key != system_program::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+
+		if new_account.lamports() == 0 {
+			debug_msg!("Calling the system program to create a program account...");
+			return invoke_signed(
+				&system_instruction::create_account(
+					payer.key,
+					new_account.key,
+					rent.minimum_balance(len),
+					len as u64,
+					program_id,
+				),
+				&[
+					payer.clone(),
+					new_account.clone(),
+					system_program_account.clone(),
+				],
+				&[seeds],
+			);
+		}
+
+		debug_msg!("Calling the system program to set up a prefunded program account...");
+		let top_up = rent.minimum_balance(len).saturating_sub(new_account.lamports());
+		if top_up > 0 {
+			invoke(
+				&system_instruction::transfer(payer.key, new_account.key, top_up),
+				&[
+					payer.clone(),
+					new_account.clone(),
+					system_program_account.clone(),
+				],
+			)?;
+		}
+		// Both fail unless it's still an empty system account
 		invoke_signed(
-		    &transfer_to_taker_ix,
-		    &[
-		        pda_temp_token_account.clone(),
-		        takers_token_to_receive_account.clone(),
-		        pda_account.clone(),
-		        token_program.clone(),
-		    ],
-		    // This parameter is for authority. In this case, the authority is the PDA. BUT instead of passing in the key for PDA itself, we pass in the seeds (&[b"escrow"] and bump_seed), so that we can recalculate the PDA. If the recalculation and the given PDA keys dont' match, then this instruction fails with AuthenticationError
-		    &[&[&b"escrow"[..], &[bump_seed]]], 
-		)?;
-
-		// Close PDA
-		let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
-		    token_program.key,
-		    pda_temp_token_account.key,
-		    initializers_main_account.key,
-		    &pda,
-		    &[&pda]
-		)?;
-		msg!("Calling the token program to close pda's temp account...");
+			&system_instruction::allocate(new_account.key, len as u64),
+			&[new_account.clone(), system_program_account.clone()],
+			&[seeds],
+		)?;
 		invoke_signed(
-		    &close_pdas_temp_acc_ix,
-		    &[
-		        pda_temp_token_account.clone(),
-		        initializers_main_account.clone(),
-		        pda_account.clone(),
-		        token_program.clone(),
-		    ],
-		    &[&[&b"escrow"[..], &[bump_seed]]],
-		)?;
-
-		msg!("Closing the escrow account...");
-		**initializers_main_account.lamports.borrow_mut() = initializers_main_account.lamports()
-			.checked_add(escrow_account.lamports())
-			.ok_or(EscrowError::AmountOverflow)?;
-		**escrow_account.lamports.borrow_mut() = 0;
-		*escrow_account.try_borrow_mut_data()? = &mut [];
+			&system_instruction::assign(new_account.key, program_id),
+			&[new_account.clone(), system_program_account.clone()],
+			&[seeds],
+		)
+	}
+
+	fn total_royalties(royalties: &[(&AccountInfo, u64)]) -> Result<u64, ProgramError> {
+		royalties
+			.iter()
+			.try_fold(0u64, |total, (_, royalty)| total.checked_add(*royalty))
+			.ok_or_else(|| EscrowError::RoyaltyOverflow.into())
+	}
 
+	/// Escrow accounts have to be exactly as long as InitEscrow needs them, anything else is something else
+	fn check_escrow_len(escrow_account: &AccountInfo, len: usize) -> ProgramResult {
+		if escrow_account.data_len() != len {
+			return Err(EscrowError::InvalidEscrowLength.into());
+		}
 		Ok(())
 	}
-}
\ No newline at end of file
+
+	/// Makes sure the treasury and system program accounts are the real ones, returns the treasury bump seed
+	fn check_treasury(treasury: &AccountInfo, system_program_account: &AccountInfo, program_id: &Pubkey) -> Result<u8, ProgramError> {
+		let (treasury_pubkey, treasury_bump_seed) = find_treasury_address(program_id);
+		if *treasury.key != treasury_pubkey {
+			return Err(ProgramError::InvalidAccountData);
+		}
+		if *system_program_account.key != system_program::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+		Ok(treasury_bump_seed)
+	}
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn total_royalties_near_u64_max() {
+		let key = Pubkey::new_unique();
+		let (mut lamports, mut data) = (0, []);
+		let creator = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &key, false, 0);
+
+		assert_eq!(Processor::total_royalties(&[]), Ok(0));
+		assert_eq!(Processor::total_royalties(&[(&creator, u64::MAX)]), Ok(u64::MAX));
+		assert_eq!(Processor::total_royalties(&[(&creator, u64::MAX - 1), (&creator, 1)]), Ok(u64::MAX));
+		assert_eq!(
+			Processor::total_royalties(&[(&creator, u64::MAX), (&creator, 1)]),
+			Err(EscrowError::RoyaltyOverflow.into())
+		);
+		assert_eq!(
+			Processor::total_royalties(&[(&creator, u64::MAX / 2 + 1), (&creator, u64::MAX / 2 + 1)]),
+			Err(EscrowError::RoyaltyOverflow.into())
+		);
+	}
+
+	#[test]
+	fn only_a_filled_predecessor_unlocks_its_successor() {
+		let (key, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+		let predecessor_status = |status: EscrowStatus| {
+			let mut data = vec![0; Escrow::LEN];
+			let mut escrow = *Escrow::load(&data).unwrap();
+			escrow.version = ESCROW_VERSION;
+			escrow.status = status as u8;
+			escrow.retain_history = true.into();
+			escrow.pack_versioned(&mut data).unwrap();
+			let mut lamports = 0;
+			let predecessor = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+			Processor::check_predecessor_filled(&predecessor, &program_id)
+		};
+
+		assert_eq!(predecessor_status(EscrowStatus::Completed), Ok(()));
+		for status in [EscrowStatus::Active, EscrowStatus::Cancelled, EscrowStatus::Expired] {
+			assert_eq!(predecessor_status(status), Err(EscrowError::PredecessorNotSettled.into()));
+		}
+
+		let (mut lamports, mut data) = (0, []);
+		let closed = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+		assert_eq!(
+			Processor::check_predecessor_filled(&closed, &program_id),
+			Err(EscrowError::PredecessorNotSettled.into())
+		);
+	}
+}
@@ -0,0 +1,29 @@
+//! Off-chain helpers for filling escrows through Solana Pay (https://docs.solanapay.com/spec).
+//!
+//! Filling an escrow calls this program, which a plain Solana Pay transfer request can't do, so escrows
+//! are shared as transaction requests: the wallet fetches the Exchange transaction from `link`. The
+//! reference key passed here should also go into that transaction's Exchange instruction, so the
+//! point-of-sale can find the fill with getSignaturesForAddress(reference).
+
+use solana_program::pubkey::Pubkey;
+
+/// Builds a `solana:` transaction request URL for filling `escrow`, with `escrow` and `reference`
+/// added to the query of `link`
+pub fn transaction_request_url(link: &str, escrow: &Pubkey, reference: &Pubkey) -> String {
+	let separator = if link.contains('?') { '&' } else { '?' };
+	let link = format!("{}{}escrow={}&reference={}", link, separator, escrow, reference);
+
+	// The spec wants the link URL-encoded since it has query parameters
+	format!("solana:{}", percent_encode(&link))
+}
+
+fn percent_encode(input: &str) -> String {
+	let mut encoded = String::with_capacity(input.len());
+	for byte in input.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
@@ -0,0 +1,88 @@
+//! Reading stake accounts. solana-program has the StakeState type but it's bincode, which this crate
+//! doesn't pull in, so the few fields we need are read by hand
+
+use std::convert::TryInto;
+
+use solana_program::{
+	account_info::AccountInfo,
+	clock::Clock,
+	program_error::ProgramError,
+	pubkey::Pubkey,
+};
+
+pub use solana_program::stake::{instruction::authorize, program::id, state::StakeAuthorize};
+
+/// StakeInstruction's u32 variant tag of Authorize
+const INSTRUCTION_AUTHORIZE: u32 = 1;
+
+/// StakeState's u32 variant tags
+const STAKE_STATE_INITIALIZED: u32 = 1;
+const STAKE_STATE_STAKE: u32 = 2;
+
+/// Meta (rent exempt reserve, authorized and lockup) goes right after the tag, the delegation after it
+const AUTHORIZED_OFFSET: usize = 4 + 8;
+const LOCKUP_OFFSET: usize = AUTHORIZED_OFFSET + 64;
+const DEACTIVATION_EPOCH_OFFSET: usize = LOCKUP_OFFSET + 48 + 32 + 8 + 8;
+
+/// The fields of a stake account we use
+pub struct StakeInfo {
+	pub staker: Pubkey,
+	pub withdrawer: Pubkey,
+	pub lockup_unix_timestamp: i64,
+	pub lockup_epoch: u64,
+	pub lockup_custodian: Pubkey,
+	/// The epoch the stake was deactivated in, None if it was never delegated. u64::MAX while it's active
+	pub deactivation_epoch: Option<u64>,
+}
+
+impl StakeInfo {
+	/// Whether it has no stake delegated anymore as of `clock`, so what it's worth is just its lamports
+	pub fn is_deactivated(&self, clock: &Clock) -> bool {
+		match self.deactivation_epoch {
+			Some(epoch) => epoch < clock.epoch,
+			None => true,
+		}
+	}
+
+	/// Whether the lockup still holds, then changing the withdrawer takes the custodian's signature
+	pub fn is_lockup_in_force(&self, clock: &Clock) -> bool {
+		self.lockup_unix_timestamp > clock.unix_timestamp || self.lockup_epoch > clock.epoch
+	}
+}
+
+/// Authorize's data, bincode like the stake program's instructions, for the CPI without an Instruction on
+/// the heap. The custodian goes in the accounts
+pub fn authorize_data(new_authority: &Pubkey, stake_authorize: StakeAuthorize) -> [u8; 4 + 32 + 4] {
+	let mut data = [0; 4 + 32 + 4];
+	data[..4].copy_from_slice(&INSTRUCTION_AUTHORIZE.to_le_bytes());
+	data[4..36].copy_from_slice(new_authority.as_ref());
+	data[36..].copy_from_slice(&(stake_authorize as u32).to_le_bytes());
+	data
+}
+
+/// Reads an initialized or delegated stake account of the stake program
+pub fn unpack_stake(account: &AccountInfo) -> Result<StakeInfo, ProgramError> {
+	if *account.owner != id() {
+		return Err(ProgramError::IncorrectProgramId);
+	}
+	let data = account.try_borrow_data()?;
+	if data.len() < DEACTIVATION_EPOCH_OFFSET + 8 {
+		return Err(ProgramError::InvalidAccountData);
+	}
+	let pubkey = |offset: usize| Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap());
+	let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+	let deactivation_epoch = match u32::from_le_bytes(data[..4].try_into().unwrap()) {
+		STAKE_STATE_INITIALIZED => None,
+		STAKE_STATE_STAKE => Some(u64_at(DEACTIVATION_EPOCH_OFFSET)),
+		_ => return Err(ProgramError::InvalidAccountData),
+	};
+	Ok(StakeInfo {
+		staker: pubkey(AUTHORIZED_OFFSET),
+		withdrawer: pubkey(AUTHORIZED_OFFSET + 32),
+		lockup_unix_timestamp: u64_at(LOCKUP_OFFSET) as i64,
+		lockup_epoch: u64_at(LOCKUP_OFFSET + 8),
+		lockup_custodian: pubkey(LOCKUP_OFFSET + 16),
+		deactivation_epoch,
+	})
+}
@@ -0,0 +1,569 @@
+use std::convert::{TryFrom, TryInto};
+
+use solana_program::{
+	account_info::AccountInfo,
+	entrypoint::ProgramResult,
+	instruction::{AccountMeta, Instruction},
+	program_error::ProgramError,
+	program_pack::{IsInitialized, Pack},
+	pubkey::Pubkey,
+	system_program,
+};
+use spl_token::{
+	instruction::MAX_SIGNERS,
+	state::{Account as TokenAccount, Mint, Multisig},
+};
+
+use crate::error::EscrowError;
+
+/// Tags of the token instructions that are built by hand, Token-2022 has spl-token's
+const INSTRUCTION_SET_AUTHORITY: u8 = 6;
+const INSTRUCTION_TRANSFER_CHECKED: u8 = 12;
+
+/// The associated token account program, for deriving addresses and creating missing ones
+pub mod associated_token_program {
+	solana_program::declare_id!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+}
+
+pub use escrow_interface::token::{check_token_program_id, is_token_program, token_2022};
+
+/// Where Token-2022 puts the account type byte, right after the base account, when there are extensions
+const ACCOUNT_TYPE_INDEX: usize = TokenAccount::LEN;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+/// Token-2022 extension types we look at
+pub const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+pub const EXTENSION_TRANSFER_FEE_AMOUNT: u16 = 2;
+pub const EXTENSION_INTEREST_BEARING_CONFIG: u16 = 10;
+pub const EXTENSION_CPI_GUARD: u16 = 11;
+pub const EXTENSION_PERMANENT_DELEGATE: u16 = 12;
+pub const EXTENSION_NON_TRANSFERABLE_ACCOUNT: u16 = 13;
+pub const EXTENSION_TRANSFER_HOOK: u16 = 14;
+pub const EXTENSION_TRANSFER_HOOK_ACCOUNT: u16 = 15;
+
+/// Token-2022's year for interest, in seconds
+const SECONDS_PER_YEAR: f64 = 60.0 * 60.0 * 24.0 * 365.24;
+
+/// Seed of a transfer hook program's PDA listing the extra accounts its Execute needs, per mint
+pub const EXTRA_ACCOUNT_METAS_SEED: &[u8] = b"extra-account-metas";
+
+/// The associated token account program's Create, which fails if the account already exists
+const ASSOCIATED_TOKEN_INSTRUCTION_CREATE: u8 = 0;
+
+/// Token-2022 instruction tags for the bits spl-token doesn't have
+const INSTRUCTION_GET_ACCOUNT_DATA_SIZE: u8 = 21;
+const INSTRUCTION_TRANSFER_FEE_EXTENSION: u8 = 26;
+const TRANSFER_FEE_HARVEST_WITHHELD_TOKENS_TO_MINT: u8 = 4;
+
+/// Makes sure `token_program` is one of the token programs and is the one owning `account`
+pub fn check_token_program(account: &AccountInfo, token_program: &AccountInfo) -> ProgramResult {
+	check_token_program_id(token_program.key)?;
+	if account.owner != token_program.key {
+		return Err(ProgramError::IncorrectProgramId);
+	}
+	Ok(())
+}
+
+/// Address of `wallet`'s associated token account for `mint` under `token_program_id`
+pub fn get_associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program_id: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(
+		&[wallet.as_ref(), token_program_id.as_ref(), mint.as_ref()],
+		&associated_token_program::id(),
+	).0
+}
+
+/// The associated token account program's Create, `payer` pays for `wallet`'s new account for `mint`
+pub fn create_associated_token_account(payer: &Pubkey, wallet: &Pubkey, mint: &Pubkey, token_program_id: &Pubkey) -> Instruction {
+	Instruction {
+		program_id: associated_token_program::id(),
+		accounts: vec![
+			AccountMeta::new(*payer, true),
+			AccountMeta::new(get_associated_token_address(wallet, mint, token_program_id), false),
+			AccountMeta::new_readonly(*wallet, false),
+			AccountMeta::new_readonly(*mint, false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new_readonly(*token_program_id, false),
+		],
+		data: vec![ASSOCIATED_TOKEN_INSTRUCTION_CREATE],
+	}
+}
+
+/// Unpacks a token account of either token program, ignoring any Token-2022 extensions
+pub fn unpack_account(account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+	let data = account.try_borrow_data()?;
+	unpack_base::<TokenAccount>(account.owner, &data, ACCOUNT_TYPE_ACCOUNT)
+}
+
+/// Unpacks a mint of either token program, ignoring any Token-2022 extensions. Mints with extensions get
+/// padded up to the account length, so the account type byte is at the same spot
+pub fn unpack_mint(account: &AccountInfo) -> Result<Mint, ProgramError> {
+	let data = account.try_borrow_data()?;
+	unpack_base::<Mint>(account.owner, &data, ACCOUNT_TYPE_MINT)
+}
+
+fn unpack_base<T: Pack + IsInitialized>(owner: &Pubkey, data: &[u8], account_type: u8) -> Result<T, ProgramError> {
+	if !is_token_program(owner) {
+		return Err(ProgramError::IncorrectProgramId);
+	}
+	if data.len() < T::LEN {
+		return Err(ProgramError::InvalidAccountData);
+	}
+	// Anything longer than the base layout is a Token-2022 account with extensions
+	if data.len() != T::LEN
+		&& (*owner != token_2022::id() || data.get(ACCOUNT_TYPE_INDEX) != Some(&account_type))
+	{
+		return Err(ProgramError::InvalidAccountData);
+	}
+	let state = T::unpack_from_slice(&data[..T::LEN])?;
+	if !state.is_initialized() {
+		return Err(ProgramError::UninitializedAccount);
+	}
+	Ok(state)
+}
+
+/// Finds the data of a Token-2022 extension in an account's or mint's data. The extensions are
+/// type-length-value entries (u16 type, u16 length) after the account type byte
+pub fn get_extension(data: &[u8], extension_type: u16) -> Option<&[u8]> {
+	let mut tlv = data.get(ACCOUNT_TYPE_INDEX + 1..)?;
+	while tlv.len() >= 4 {
+		let entry_type = u16::from_le_bytes([tlv[0], tlv[1]]);
+		let length = u16::from_le_bytes([tlv[2], tlv[3]]) as usize;
+		// Type 0 is unused space at the end
+		if entry_type == 0 {
+			return None;
+		}
+		let value = tlv.get(4..4 + length)?;
+		if entry_type == extension_type {
+			return Some(value);
+		}
+		tlv = &tlv[4 + length..];
+	}
+	None
+}
+
+/// Whether `mint` is a Token-2022 mint with a transfer fee, which takes the clock to pick the fee schedule
+pub fn has_transfer_fee(mint: &AccountInfo) -> Result<bool, ProgramError> {
+	if *mint.owner != token_2022::id() {
+		return Ok(false);
+	}
+	let data = mint.try_borrow_data()?;
+	Ok(get_extension(&data, EXTENSION_TRANSFER_FEE_CONFIG).is_some())
+}
+
+/// Whether `mint` has a Token-2022 permanent delegate, which can move or burn tokens from any of its accounts
+pub fn has_permanent_delegate(mint: &AccountInfo) -> Result<bool, ProgramError> {
+	if *mint.owner != token_2022::id() {
+		return Ok(false);
+	}
+	let data = mint.try_borrow_data()?;
+	// The delegate can be set to none, then the extension is still there with a zeroed key
+	Ok(matches!(get_extension(&data, EXTENSION_PERMANENT_DELEGATE), Some(delegate) if delegate.iter().any(|&byte| byte != 0)))
+}
+
+/// Whether `account` has its Token-2022 CPI guard on. The owner can't have tokens moved for them
+/// through a CPI then, which is how this program moves them
+pub fn is_cpi_guarded(account: &AccountInfo) -> Result<bool, ProgramError> {
+	if *account.owner != token_2022::id() {
+		return Ok(false);
+	}
+	let data = account.try_borrow_data()?;
+	Ok(matches!(get_extension(&data, EXTENSION_CPI_GUARD), Some([1, ..])))
+}
+
+/// Whether `mint` is a Token-2022 interest-bearing mint
+pub fn is_interest_bearing(mint: &AccountInfo) -> Result<bool, ProgramError> {
+	if *mint.owner != token_2022::id() {
+		return Ok(false);
+	}
+	let data = mint.try_borrow_data()?;
+	Ok(get_extension(&data, EXTENSION_INTEREST_BEARING_CONFIG).is_some())
+}
+
+/// What raw amounts of an interest-bearing mint are worth at `unix_timestamp` relative to their raw value,
+/// the factor Token-2022 applies to get the UI amount wallets show. Interest compounds continuously, first
+/// at the average rate up to the last rate change and then at the current rate. None for other mints
+pub fn get_interest_scale(mint: &AccountInfo, unix_timestamp: i64) -> Result<Option<f64>, ProgramError> {
+	if *mint.owner != token_2022::id() {
+		return Ok(None);
+	}
+	let data = mint.try_borrow_data()?;
+	// Rate authority, then the initialization timestamp, the average rate before the last update, the
+	// last update timestamp and the current rate. Rates are in basis points
+	let config = match get_extension(&data, EXTENSION_INTEREST_BEARING_CONFIG) {
+		Some(config) if config.len() >= 52 => config,
+		Some(_) => return Err(ProgramError::InvalidAccountData),
+		None => return Ok(None),
+	};
+	let initialization_timestamp = i64::from_le_bytes(config[32..40].try_into().unwrap());
+	let pre_update_average_rate = i16::from_le_bytes(config[40..42].try_into().unwrap());
+	let last_update_timestamp = i64::from_le_bytes(config[42..50].try_into().unwrap());
+	let current_rate = i16::from_le_bytes(config[50..52].try_into().unwrap());
+
+	let growth = |rate: i16, seconds: i64| (rate as f64 / 10_000.0 * seconds as f64 / SECONDS_PER_YEAR).exp();
+	let elapsed = |from: i64, to: i64| to.checked_sub(from).ok_or(ProgramError::InvalidAccountData);
+	Ok(Some(
+		growth(pre_update_average_rate, elapsed(initialization_timestamp, last_update_timestamp)?)
+			* growth(current_rate, elapsed(last_update_timestamp, unix_timestamp)?),
+	))
+}
+
+/// `amount` with the interest of `scale` (see get_interest_scale) on it, rounded down. Still in raw
+/// units, so the UI amount times 10^decimals. InterestOverflow if that doesn't fit a u64
+pub fn apply_interest(amount: u64, scale: f64) -> Result<u64, ProgramError> {
+	to_amount(amount as f64 * scale)
+}
+
+/// The raw amount that's worth `amount` with the interest of `scale` on it, rounded up. InterestOverflow
+/// if that doesn't fit a u64
+pub fn remove_interest(amount: u64, scale: f64) -> Result<u64, ProgramError> {
+	to_amount((amount as f64 / scale).ceil())
+}
+
+/// `as u64` would quietly turn anything out of range into 0 or u64::MAX. u64::MAX itself is 2^64 as a
+/// f64, which is already out of range
+fn to_amount(amount: f64) -> Result<u64, ProgramError> {
+	if amount.is_finite() && amount >= 0.0 && amount < u64::MAX as f64 {
+		Ok(amount as u64)
+	} else {
+		Err(EscrowError::InterestOverflow.into())
+	}
+}
+
+/// Whether `account` is a multisig of either token program and at least m of its signers are among
+/// `signers` and signed, the same check the token program does for a multisig authority
+pub fn is_multisig_signed(account: &AccountInfo, signers: &[AccountInfo]) -> Result<bool, ProgramError> {
+	// Token-2022 keeps multisigs at exactly spl-token's length, none of its other accounts have it
+	if !is_token_program(account.owner) || account.data_len() != Multisig::LEN {
+		return Ok(false);
+	}
+	let multisig = Multisig::unpack(&account.try_borrow_data()?)?;
+	let mut matched = [false; MAX_SIGNERS];
+	let mut num_signed = 0;
+	for signer in signers.iter().filter(|signer| signer.is_signer) {
+		for (position, key) in multisig.signers[..multisig.n as usize].iter().enumerate() {
+			if key == signer.key && !matched[position] {
+				matched[position] = true;
+				num_signed += 1;
+				break;
+			}
+		}
+	}
+	Ok(num_signed >= multisig.m)
+}
+
+/// Whether `account` belongs to a Token-2022 non-transferable mint. Every account of such a mint carries
+/// the NonTransferableAccount extension, so the mint isn't needed to tell
+pub fn is_non_transferable(account: &AccountInfo) -> Result<bool, ProgramError> {
+	if *account.owner != token_2022::id() {
+		return Ok(false);
+	}
+	let data = account.try_borrow_data()?;
+	Ok(get_extension(&data, EXTENSION_NON_TRANSFERABLE_ACCOUNT).is_some())
+}
+
+/// The transfer hook program of `mint`, None if it has no hook
+pub fn get_transfer_hook_program_id(mint: &AccountInfo) -> Result<Option<Pubkey>, ProgramError> {
+	if *mint.owner != token_2022::id() {
+		return Ok(None);
+	}
+	let data = mint.try_borrow_data()?;
+	// The hook's authority, then its program id. An all zero program id means no hook
+	let program_id = match get_extension(&data, EXTENSION_TRANSFER_HOOK) {
+		Some(hook) if hook.len() >= 64 => Pubkey::new(&hook[32..64]),
+		Some(_) => return Err(ProgramError::InvalidAccountData),
+		None => return Ok(None),
+	};
+	if program_id == Pubkey::default() {
+		return Ok(None);
+	}
+	Ok(Some(program_id))
+}
+
+pub fn find_extra_account_metas_address(mint: &Pubkey, hook_program_id: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[EXTRA_ACCOUNT_METAS_SEED, mint.as_ref()], hook_program_id).0
+}
+
+/// How many extra accounts the hook's extra account metas account lists for Execute. It's one TLV entry,
+/// an 8 byte discriminator and a u32 length, holding a u32 count and that many 35 byte account metas
+pub fn extra_account_metas_count(extra_account_metas: &AccountInfo) -> Result<usize, ProgramError> {
+	let data = extra_account_metas.try_borrow_data()?;
+	let count = data.get(12..16).ok_or(ProgramError::InvalidAccountData)?;
+	Ok(u32::from_le_bytes(count.try_into().unwrap()) as usize)
+}
+
+/// Fees Token-2022 withheld in `account` from transfers into it. The account can't be closed until
+/// they're harvested to the mint
+pub fn withheld_amount(account: &AccountInfo) -> Result<u64, ProgramError> {
+	if *account.owner != token_2022::id() {
+		return Ok(0);
+	}
+	let data = account.try_borrow_data()?;
+	Ok(get_extension(&data, EXTENSION_TRANSFER_FEE_AMOUNT)
+		.and_then(|value| value.get(..8))
+		.map(|amount| u64::from_le_bytes(amount.try_into().unwrap()))
+		.unwrap_or(0))
+}
+
+/// One of the two fee schedules in a Token-2022 TransferFeeConfig
+pub struct TransferFee {
+	pub epoch: u64,
+	pub maximum_fee: u64,
+	pub transfer_fee_basis_points: u16,
+}
+
+impl TransferFee {
+	fn unpack(src: &[u8]) -> Self {
+		Self {
+			epoch: u64::from_le_bytes(src[..8].try_into().unwrap()),
+			maximum_fee: u64::from_le_bytes(src[8..16].try_into().unwrap()),
+			transfer_fee_basis_points: u16::from_le_bytes(src[16..18].try_into().unwrap()),
+		}
+	}
+
+	/// Fee on a transfer of `amount`, rounded up like Token-2022 does
+	pub fn calculate_fee(&self, amount: u64) -> Option<u64> {
+		if self.transfer_fee_basis_points == 0 || amount == 0 {
+			return Some(0);
+		}
+		let fee = (amount as u128)
+			.checked_mul(self.transfer_fee_basis_points as u128)?
+			.checked_add(9_999)?
+			/ 10_000;
+		Some((fee as u64).min(self.maximum_fee))
+	}
+
+	/// Smallest amount to send so at least `net_amount` arrives after the fee, FeeOverflow if that
+	/// doesn't fit a u64
+	pub fn calculate_gross_amount(&self, net_amount: u64) -> Result<u64, ProgramError> {
+		self.gross_amount(net_amount).ok_or_else(|| EscrowError::FeeOverflow.into())
+	}
+
+	fn gross_amount(&self, net_amount: u64) -> Option<u64> {
+		if self.transfer_fee_basis_points == 0 || net_amount == 0 {
+			return Some(net_amount);
+		}
+		// In u128, a maximum fee of u64::MAX (no maximum) would overflow on any amount otherwise
+		let capped = net_amount as u128 + self.maximum_fee as u128;
+		if self.transfer_fee_basis_points >= 10_000 {
+			return u64::try_from(capped).ok();
+		}
+		let gross = (net_amount as u128)
+			.checked_mul(10_000)?
+			.checked_add(10_000 - self.transfer_fee_basis_points as u128 - 1)?
+			.checked_div(10_000 - self.transfer_fee_basis_points as u128)?;
+		// Capped before it's narrowed, near u64::MAX the uncapped amount doesn't fit even when the capped one does
+		let mut gross = u64::try_from(gross.min(capped)).ok()?;
+		// Rounding the fee up can leave the net a token short
+		while gross.checked_sub(self.calculate_fee(gross)?)? < net_amount {
+			gross = gross.checked_add(1)?;
+		}
+		Some(gross)
+	}
+}
+
+/// The transfer fee schedule of `mint` in effect at `epoch`, None if the mint has no transfer fee
+pub fn get_transfer_fee(mint: &AccountInfo, epoch: u64) -> Result<Option<TransferFee>, ProgramError> {
+	if *mint.owner != token_2022::id() {
+		return Ok(None);
+	}
+	let data = mint.try_borrow_data()?;
+	// Two authorities and the withheld amount, then the older and the newer fee schedule
+	let config = match get_extension(&data, EXTENSION_TRANSFER_FEE_CONFIG) {
+		Some(config) if config.len() >= 108 => config,
+		Some(_) => return Err(ProgramError::InvalidAccountData),
+		None => return Ok(None),
+	};
+	let newer = TransferFee::unpack(&config[90..108]);
+	if epoch >= newer.epoch {
+		return Ok(Some(newer));
+	}
+	Ok(Some(TransferFee::unpack(&config[72..90])))
+}
+
+/// The spl-token instruction builders only take spl-token's id, so build with that and point the
+/// instruction at whichever token program the account belongs to. That has to be one of the two token
+/// programs, the program signs for its PDAs in these so a stand-in program could do anything with them
+fn for_program(instruction: Result<Instruction, ProgramError>, token_program_id: &Pubkey) -> Result<Instruction, ProgramError> {
+	check_token_program_id(token_program_id)?;
+	let mut instruction = instruction?;
+	instruction.program_id = *token_program_id;
+	Ok(instruction)
+}
+
+/// `hook_accounts` are the mint's transfer hook program, its extra account metas account and the extra
+/// accounts listed there, if the mint has a hook. Token-2022 hands them on to the hook
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_checked(
+	token_program_id: &Pubkey,
+	source: &Pubkey,
+	mint: &Pubkey,
+	destination: &Pubkey,
+	authority: &Pubkey,
+	amount: u64,
+	decimals: u8,
+	hook_accounts: &[AccountInfo],
+) -> Result<Instruction, ProgramError> {
+	let mut instruction = for_program(
+		spl_token::instruction::transfer_checked(&spl_token::id(), source, mint, destination, authority, &[], amount, decimals),
+		token_program_id,
+	)?;
+	instruction.accounts.extend(hook_accounts.iter().map(|account| AccountMeta {
+		pubkey: *account.key,
+		is_signer: false,
+		is_writable: account.is_writable,
+	}));
+	Ok(instruction)
+}
+
+/// `signers` are the signers of `owner` if it's a multisig, empty otherwise
+pub fn set_owner(
+	token_program_id: &Pubkey,
+	account: &Pubkey,
+	new_owner: &Pubkey,
+	owner: &Pubkey,
+	signers: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+	for_program(
+		spl_token::instruction::set_authority(
+			&spl_token::id(),
+			account,
+			Some(new_owner),
+			spl_token::instruction::AuthorityType::AccountOwner,
+			owner,
+			signers,
+		),
+		token_program_id,
+	)
+}
+
+/// TransferChecked's data, for transfer_checked as a CPI without an Instruction on the heap
+pub fn transfer_checked_data(amount: u64, decimals: u8) -> [u8; 10] {
+	let mut data = [0; 10];
+	data[0] = INSTRUCTION_TRANSFER_CHECKED;
+	data[1..9].copy_from_slice(&amount.to_le_bytes());
+	data[9] = decimals;
+	data
+}
+
+/// SetAuthority's data for set_owner, as a CPI without an Instruction on the heap
+pub fn set_owner_data(new_owner: &Pubkey) -> [u8; 35] {
+	let mut data = [0; 35];
+	data[0] = INSTRUCTION_SET_AUTHORITY;
+	data[1] = spl_token::instruction::AuthorityType::AccountOwner as u8;
+	// COption's Some
+	data[2] = 1;
+	data[3..].copy_from_slice(new_owner.as_ref());
+	data
+}
+
+/// InitializeAccount3, which takes the owner as data so `owner` doesn't have to be passed in or sign
+pub fn initialize_account3(
+	token_program_id: &Pubkey,
+	account: &Pubkey,
+	mint: &Pubkey,
+	owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+	for_program(
+		spl_token::instruction::initialize_account3(&spl_token::id(), account, mint, owner),
+		token_program_id,
+	)
+}
+
+/// Brings a wSOL account's token amount up to its lamports above rent
+pub fn sync_native(token_program_id: &Pubkey, account: &Pubkey) -> Result<Instruction, ProgramError> {
+	for_program(spl_token::instruction::sync_native(&spl_token::id(), account), token_program_id)
+}
+
+pub fn close_account(
+	token_program_id: &Pubkey,
+	account: &Pubkey,
+	destination: &Pubkey,
+	owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+	for_program(
+		spl_token::instruction::close_account(&spl_token::id(), account, destination, owner, &[]),
+		token_program_id,
+	)
+}
+
+/// Token-2022's GetAccountDataSize, how big a token account of `mint` has to be with the extensions the
+/// mint gives its accounts. The size comes back as a u64 in the return data
+pub fn get_account_data_size(token_program_id: &Pubkey, mint: &Pubkey) -> Result<Instruction, ProgramError> {
+	check_token_program_id(token_program_id)?;
+	Ok(Instruction {
+		program_id: *token_program_id,
+		accounts: vec![AccountMeta::new_readonly(*mint, false)],
+		data: vec![INSTRUCTION_GET_ACCOUNT_DATA_SIZE],
+	})
+}
+
+/// Token-2022's HarvestWithheldTokensToMint, moves the fees withheld in `sources` into the mint.
+/// Anyone can call it, no signature needed
+pub fn harvest_withheld_tokens_to_mint(token_program_id: &Pubkey, mint: &Pubkey, sources: &[&Pubkey]) -> Result<Instruction, ProgramError> {
+	check_token_program_id(token_program_id)?;
+	let mut accounts = vec![AccountMeta::new(*mint, false)];
+	accounts.extend(sources.iter().map(|source| AccountMeta::new(**source, false)));
+	Ok(Instruction {
+		program_id: *token_program_id,
+		accounts,
+		data: vec![INSTRUCTION_TRANSFER_FEE_EXTENSION, TRANSFER_FEE_HARVEST_WITHHELD_TOKENS_TO_MINT],
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn transfer_fee(transfer_fee_basis_points: u16, maximum_fee: u64) -> TransferFee {
+		TransferFee { epoch: 0, maximum_fee, transfer_fee_basis_points }
+	}
+
+	#[test]
+	fn instruction_data_matches_spl_token() {
+		let (account, mint, owner, new_owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+		assert_eq!(
+			transfer_checked_data(u64::MAX - 1, 9)[..],
+			transfer_checked(&spl_token::id(), &account, &mint, &account, &owner, u64::MAX - 1, 9, &[]).unwrap().data[..]
+		);
+		assert_eq!(set_owner_data(&new_owner)[..], set_owner(&spl_token::id(), &account, &new_owner, &owner, &[]).unwrap().data[..]);
+	}
+
+	#[test]
+	fn gross_amount_covers_the_fee() {
+		assert_eq!(transfer_fee(100, u64::MAX).calculate_gross_amount(9_900), Ok(10_000));
+		assert_eq!(transfer_fee(100, 50).calculate_gross_amount(9_900), Ok(9_950));
+		assert_eq!(transfer_fee(0, 50).calculate_gross_amount(u64::MAX), Ok(u64::MAX));
+	}
+
+	#[test]
+	fn gross_amount_near_u64_max() {
+		assert_eq!(transfer_fee(100, 0).calculate_gross_amount(u64::MAX), Ok(u64::MAX));
+		assert_eq!(transfer_fee(100, 1).calculate_gross_amount(u64::MAX - 1), Ok(u64::MAX));
+		assert_eq!(transfer_fee(10_000, 5).calculate_gross_amount(u64::MAX - 5), Ok(u64::MAX));
+		assert_eq!(transfer_fee(100, 1).calculate_gross_amount(u64::MAX), Err(EscrowError::FeeOverflow.into()));
+		assert_eq!(transfer_fee(10_000, 5).calculate_gross_amount(u64::MAX - 4), Err(EscrowError::FeeOverflow.into()));
+		assert_eq!(transfer_fee(100, u64::MAX).calculate_gross_amount(u64::MAX - 1), Err(EscrowError::FeeOverflow.into()));
+	}
+
+	#[test]
+	fn interest_near_u64_max() {
+		assert_eq!(apply_interest(1_000, 1.5), Ok(1_500));
+		assert_eq!(remove_interest(1_500, 1.5), Ok(1_000));
+		// The largest f64 under 2^64 is 2^64 - 2048
+		assert_eq!(apply_interest(u64::MAX - 2047, 1.0), Ok(u64::MAX - 2047));
+		assert_eq!(remove_interest(u64::MAX - 2047, 1.0), Ok(u64::MAX - 2047));
+		assert_eq!(apply_interest(u64::MAX, 1.0), Err(EscrowError::InterestOverflow.into()));
+		assert_eq!(apply_interest(u64::MAX - 1, 1.0), Err(EscrowError::InterestOverflow.into()));
+		assert_eq!(apply_interest(u64::MAX / 2 + 1, 2.0), Err(EscrowError::InterestOverflow.into()));
+		assert_eq!(remove_interest(u64::MAX, 1.0), Err(EscrowError::InterestOverflow.into()));
+		assert_eq!(remove_interest(u64::MAX / 2 + 1, 0.5), Err(EscrowError::InterestOverflow.into()));
+	}
+
+	#[test]
+	fn interest_out_of_range_scales() {
+		assert_eq!(remove_interest(1, 0.0), Err(EscrowError::InterestOverflow.into()));
+		assert_eq!(apply_interest(1, f64::NAN), Err(EscrowError::InterestOverflow.into()));
+		assert_eq!(apply_interest(1, -1.0), Err(EscrowError::InterestOverflow.into()));
+	}
+}